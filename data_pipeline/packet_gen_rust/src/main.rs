@@ -1,7 +1,5 @@
-use crate::pio_helpers::get_seq_frame_bytes;
-
-mod packet;
-mod pio_helpers;
+use pico_qpsk_core::frame_gen::get_seq_frame_bytes;
+use pico_qpsk_core::to_max_frame_size;
 
 const DEFAULT_PAYLOAD_SIZE: u32 = 4;
 const MAX_PAYLOAD_SIZE: usize = 1000;
@@ -0,0 +1,559 @@
+//! Campaign files, steps the CLI runs one after the other
+//!
+//! TOML and YAML files are read into the same [Campaign]. A step only lists what it changes, the rest
+//! comes from the firmware's config at the start of the campaign. Fields that take a list are swept, a
+//! step runs once for every combination of them.
+//!
+//! ```toml
+//! [[step]]
+//! name = "offsets"
+//! offset_mhz = [2, 4, 8]
+//! number_packets = 100
+//! interval_ms = 10
+//!
+//! [[step]]
+//! name = "random payloads"
+//! payload = "random"
+//! payload_length = [4, 16, 64]
+//! seed = 42
+//! destination = { kind = "short", pan_id = 0x2222, address = 0x1234 }
+//! sequence_number = { mode = "increment", start = 0 }
+//! schedule = { mode = "poisson", seed = 7 }
+//! sideband = "usb"
+//! phy = "oqpsk915"
+//!
+//! [[step]]
+//! name = "fsk range"
+//! phy = "fsk"
+//! keying = { bit_rate = 4800, coding = "nrz", deviation_khz = 50, sync_word = 0x2DD4 }
+//! ```
+use crate::device::MAX_FRAME_SIZE;
+use pico_qpsk_core::frame_gen::{get_hex_string_as_bytes, HexStringError};
+use pico_qpsk_core::keying::{Keying, KeyingConfig, KeyingError, LineCoding};
+use pico_qpsk_core::offset_planner::PlanError;
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::protocol::TransmitSource;
+use pico_qpsk_core::schedule::ScheduleMode;
+use pico_qpsk_core::session_config::{
+    Address, ExtendedAddress, PanId, PayloadMode, SequenceNumberMode, SessionConfig, ShortAddress,
+};
+use pico_qpsk_core::sub_ghz::SubGhzPhy;
+use pico_qpsk_core::transmit_option::TransmitOption;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// One value or a list of values to sweep
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Sweep<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T: Copy> Sweep<T> {
+    fn values(&self) -> Vec<T> {
+        match self {
+            Sweep::One(value) => vec![*value],
+            Sweep::Many(values) => values.clone(),
+        }
+    }
+}
+
+/// The values of an optional sweep, `default` when it isn't set
+fn sweep_values<T: Copy>(sweep: &Option<Sweep<T>>, default: T) -> Vec<T> {
+    sweep.as_ref().map_or_else(|| vec![default], Sweep::values)
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadKind {
+    Sequential,
+    Random,
+}
+
+/// `{ kind = "none" }`, `{ kind = "short", pan_id = .., address = .. }` or the same with `"extended"`
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum AddressConfig {
+    None,
+    Short { pan_id: u16, address: u16 },
+    Extended { pan_id: u16, address: u64 },
+}
+
+impl From<AddressConfig> for Option<Address> {
+    fn from(address: AddressConfig) -> Self {
+        match address {
+            AddressConfig::None => None,
+            AddressConfig::Short { pan_id, address } => {
+                Some(Address::Short(PanId(pan_id), ShortAddress(address)))
+            }
+            AddressConfig::Extended { pan_id, address } => {
+                Some(Address::Extended(PanId(pan_id), ExtendedAddress(address)))
+            }
+        }
+    }
+}
+
+/// `{ mode = "fixed", sequence_number = .. }`, `{ mode = "increment", start = .. }` or
+/// `{ mode = "wrap", start = .., end = .. }`
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SequenceNumberConfig {
+    Fixed { sequence_number: u8 },
+    Increment { start: u8 },
+    Wrap { start: u8, end: u8 },
+}
+
+impl From<SequenceNumberConfig> for SequenceNumberMode {
+    fn from(mode: SequenceNumberConfig) -> Self {
+        match mode {
+            SequenceNumberConfig::Fixed { sequence_number } => SequenceNumberMode::Fixed(sequence_number),
+            SequenceNumberConfig::Increment { start } => SequenceNumberMode::Increment { start },
+            SequenceNumberConfig::Wrap { start, end } => SequenceNumberMode::Wrap { start, end },
+        }
+    }
+}
+
+/// `{ mode = "fixed" }`, `{ mode = "poisson", seed = .. }` or `{ mode = "burst", length = .., gap_ms = .. }`
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ScheduleConfig {
+    Fixed,
+    Poisson {
+        #[serde(default)]
+        seed: u64,
+    },
+    Burst {
+        length: u16,
+        gap_ms: u32,
+    },
+}
+
+impl From<ScheduleConfig> for ScheduleMode {
+    fn from(mode: ScheduleConfig) -> Self {
+        match mode {
+            ScheduleConfig::Fixed => ScheduleMode::Fixed,
+            ScheduleConfig::Poisson { seed } => ScheduleMode::Poisson { seed },
+            ScheduleConfig::Burst { length, gap_ms } => ScheduleMode::Burst { length, gap_ms },
+        }
+    }
+}
+
+/// `"dsb"` on the I pin only, `"usb"` or `"lsb"` on the I and Q pins
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SidebandConfig {
+    Dsb,
+    Usb,
+    Lsb,
+}
+
+impl From<SidebandConfig> for Option<Sideband> {
+    fn from(sideband: SidebandConfig) -> Self {
+        match sideband {
+            SidebandConfig::Dsb => None,
+            SidebandConfig::Usb => Some(Sideband::Upper),
+            SidebandConfig::Lsb => Some(Sideband::Lower),
+        }
+    }
+}
+
+/// `"2450"` for the 2.4GHz O-QPSK PHY, the sub-GHz PHYs or `"ook"` and `"fsk"` with the step's `keying`
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PhyConfig {
+    #[serde(rename = "2450")]
+    OQpsk2450,
+    Bpsk868,
+    Bpsk915,
+    Oqpsk868,
+    Oqpsk915,
+    Ook,
+    Fsk,
+}
+
+impl PhyConfig {
+    /// The sub-GHz PHY, `None` for the 2.4GHz one and the keyed ones
+    fn sub_ghz(self) -> Option<SubGhzPhy> {
+        match self {
+            PhyConfig::Bpsk868 => Some(SubGhzPhy::Bpsk868MHz),
+            PhyConfig::Bpsk915 => Some(SubGhzPhy::Bpsk915MHz),
+            PhyConfig::Oqpsk868 => Some(SubGhzPhy::OQpsk868MHz),
+            PhyConfig::Oqpsk915 => Some(SubGhzPhy::OQpsk915MHz),
+            PhyConfig::OQpsk2450 | PhyConfig::Ook | PhyConfig::Fsk => None,
+        }
+    }
+}
+
+/// `"nrz"` or `"manchester"`
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CodingConfig {
+    Nrz,
+    Manchester,
+}
+
+impl From<CodingConfig> for LineCoding {
+    fn from(coding: CodingConfig) -> Self {
+        match coding {
+            CodingConfig::Nrz => LineCoding::Nrz,
+            CodingConfig::Manchester => LineCoding::Manchester,
+        }
+    }
+}
+
+/// The OOK and 2-FSK settings a step changes, the others stay
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct KeyingSettings {
+    pub bit_rate: Option<u32>,
+    pub coding: Option<CodingConfig>,
+    /// the FSK tones are this far above and below the offset, `phy = "fsk"` needs one unless the
+    /// firmware already sends FSK
+    pub deviation_khz: Option<u32>,
+    pub preamble_bytes: Option<u8>,
+    pub sync_word: Option<u16>,
+}
+
+/// One step of a campaign, every field is optional
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub name: Option<String>,
+    /// frequency offset in MHz, 2, 4, 6 and 8 are the standard options, the others are planned
+    pub offset_mhz: Option<Sweep<f64>>,
+    pub interval_ms: Option<Sweep<u32>>,
+    pub number_packets: Option<Sweep<u32>>,
+    pub payload: Option<PayloadKind>,
+    pub payload_length: Option<Sweep<u16>>,
+    /// setting a seed without a payload kind sends random payloads
+    pub seed: Option<Sweep<u64>>,
+    /// the same payload in every packet, as hex
+    pub payload_hex: Option<String>,
+    /// a PHY frame sent as is, as hex
+    pub frame_hex: Option<String>,
+    pub source: Option<AddressConfig>,
+    pub destination: Option<AddressConfig>,
+    pub sequence_number: Option<SequenceNumberConfig>,
+    /// how the packets of a run are spread around `interval_ms`
+    pub schedule: Option<ScheduleConfig>,
+    pub sideband: Option<SidebandConfig>,
+    pub phy: Option<PhyConfig>,
+    /// the bit rate, coding and framing of `phy = "ook"` and `"fsk"`
+    pub keying: Option<KeyingSettings>,
+    /// wait after every run of the step
+    #[serde(default)]
+    pub pause_ms: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Campaign {
+    #[serde(default, alias = "step")]
+    pub steps: Vec<Step>,
+}
+
+/// What a [Run] sends, the owned version of a [TransmitSource]
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunSource {
+    /// the payload mode of the run's config
+    Defaults,
+    Payload(Vec<u8>),
+    RawFrame(Vec<u8>),
+}
+
+impl RunSource {
+    pub fn transmit_source(&self) -> TransmitSource<'_> {
+        match self {
+            RunSource::Defaults => TransmitSource::Defaults,
+            RunSource::Payload(payload) => TransmitSource::Payload(payload),
+            RunSource::RawFrame(frame) => TransmitSource::RawFrame(frame),
+        }
+    }
+}
+
+/// One combination of the swept values of a step
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    pub config: SessionConfig,
+    pub source: RunSource,
+    pub pause_ms: u64,
+}
+
+/// A type to hold the possible errors that occur when a campaign is read
+#[derive(Debug)]
+pub enum CampaignError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
+    /// not a .toml, .yaml or .yml file
+    Extension,
+    /// `step` counts from 1
+    Step {
+        step: usize,
+        reason: StepError,
+    },
+}
+
+/// Why a step can't be run
+#[derive(Debug, PartialEq)]
+pub enum StepError {
+    OffsetMHz(f64, PlanError),
+    Hex {
+        field: &'static str,
+        error: HexStringError,
+    },
+    /// two fields that can't be used together
+    Conflict(&'static str, &'static str),
+    /// a burst needs at least one packet
+    BurstLength,
+    /// `keying` without `phy = "ook"` or `"fsk"` in the step or the firmware
+    KeyingWithoutPhy,
+    /// `phy = "fsk"` without `keying.deviation_khz` or a deviation from the firmware
+    NoDeviation,
+    /// the keying can't be sent at the offset in Hz
+    Keying(u32, KeyingError),
+}
+
+impl Display for CampaignError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CampaignError::Io(error) => write!(f, "can't read the campaign: {error}"),
+            CampaignError::Toml(error) => write!(f, "bad campaign: {error}"),
+            CampaignError::Yaml(error) => write!(f, "bad campaign: {error}"),
+            CampaignError::Extension => write!(f, "campaigns are .toml, .yaml or .yml files"),
+            CampaignError::Step { step, reason } => match reason {
+                StepError::OffsetMHz(offset, PlanError::OutOfRange) => {
+                    write!(f, "step {step}: no {offset}MHz offset, use 1.5 to 9")
+                }
+                StepError::OffsetMHz(offset, PlanError::ChipRate) => {
+                    write!(
+                        f,
+                        "step {step}: no clock setup sends a {offset}MHz offset at the right chip rate"
+                    )
+                }
+                StepError::Hex { field, error } => write!(f, "step {step}: {field} is not hex: {error:?}"),
+                StepError::Conflict(first, second) => {
+                    write!(f, "step {step}: {first} and {second} can't be used together")
+                }
+                StepError::BurstLength => write!(f, "step {step}: a burst needs a length of at least 1"),
+                StepError::KeyingWithoutPhy => {
+                    write!(f, "step {step}: keying is for phy = \"ook\" or \"fsk\"")
+                }
+                StepError::NoDeviation => write!(f, "step {step}: phy = \"fsk\" needs keying.deviation_khz"),
+                StepError::Keying(offset_hz, error) => {
+                    write!(
+                        f,
+                        "step {step}: the keying can't be sent at {offset_hz}Hz: {error:?}"
+                    )
+                }
+            },
+        }
+    }
+}
+
+impl std::error::Error for CampaignError {}
+
+/// The transmit option for a frequency offset, see [TransmitOption::for_offset]
+pub fn transmit_option_for_offset(offset_mhz: f64) -> Result<TransmitOption, PlanError> {
+    // anything that doesn't fit is out of range, negative offsets and NaN end up as 0
+    TransmitOption::for_offset((offset_mhz * 1e6).round() as u32)
+}
+
+fn parse_hex(field: &'static str, hex: &str) -> Result<Vec<u8>, StepError> {
+    get_hex_string_as_bytes::<MAX_FRAME_SIZE>(hex)
+        .map(|bytes| bytes.to_vec())
+        .map_err(|error| StepError::Hex { field, error })
+}
+
+impl Campaign {
+    /// Read a campaign, the extension picks TOML or YAML
+    pub fn from_path(path: &Path) -> Result<Self, CampaignError> {
+        let text = std::fs::read_to_string(path).map_err(CampaignError::Io)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            _ => Err(CampaignError::Extension),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, CampaignError> {
+        toml::from_str(text).map_err(CampaignError::Toml)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, CampaignError> {
+        serde_yaml::from_str(text).map_err(CampaignError::Yaml)
+    }
+
+    /// Every run of every step, checked before anything is sent
+    ///
+    /// returns: Result<(step index, runs of the step), [CampaignError]>
+    pub fn runs(&self, base: &SessionConfig) -> Result<Vec<(usize, Vec<Run>)>, CampaignError> {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(idx, step)| {
+                step.runs(base)
+                    .map(|runs| (idx, runs))
+                    .map_err(|reason| CampaignError::Step {
+                        step: idx + 1,
+                        reason,
+                    })
+            })
+            .collect()
+    }
+}
+
+impl Step {
+    /// Name for logs, `step <n>` when the step has none
+    pub fn name(&self, idx: usize) -> String {
+        self.name.clone().unwrap_or_else(|| format!("step {}", idx + 1))
+    }
+
+    /// The runs of the step, on top of the `base` config
+    pub fn runs(&self, base: &SessionConfig) -> Result<Vec<Run>, StepError> {
+        let source = match (&self.payload_hex, &self.frame_hex) {
+            (Some(_), Some(_)) => return Err(StepError::Conflict("payload_hex", "frame_hex")),
+            (Some(payload), None) => RunSource::Payload(parse_hex("payload_hex", payload)?),
+            (None, Some(frame)) => RunSource::RawFrame(parse_hex("frame_hex", frame)?),
+            (None, None) => RunSource::Defaults,
+        };
+        if source != RunSource::Defaults {
+            let hex_field = if self.frame_hex.is_some() {
+                "frame_hex"
+            } else {
+                "payload_hex"
+            };
+            if self.payload.is_some() {
+                return Err(StepError::Conflict(hex_field, "payload"));
+            }
+            if self.payload_length.is_some() {
+                return Err(StepError::Conflict(hex_field, "payload_length"));
+            }
+            if self.seed.is_some() {
+                return Err(StepError::Conflict(hex_field, "seed"));
+            }
+        }
+
+        let mut config = *base;
+        if let Some(source) = self.source {
+            config.frame.source = source.into();
+        }
+        if let Some(destination) = self.destination {
+            config.frame.destination = destination.into();
+        }
+        if let Some(sequence_number) = self.sequence_number {
+            config.frame.sequence_number = sequence_number.into();
+        }
+        if let Some(schedule) = self.schedule {
+            if let ScheduleConfig::Burst { length: 0, .. } = schedule {
+                return Err(StepError::BurstLength);
+            }
+            config.send.schedule = schedule.into();
+        }
+        if let Some(sideband) = self.sideband {
+            config.sideband = sideband.into();
+        }
+        if let Some(phy) = self.phy {
+            config.sub_ghz = phy.sub_ghz();
+            config.keying = match phy {
+                PhyConfig::Ook => Some(Keying::Ook),
+                PhyConfig::Fsk => match base.keying {
+                    Some(KeyingConfig {
+                        keying: keying @ Keying::Fsk { .. },
+                        ..
+                    }) => Some(keying),
+                    _ => Some(Keying::Fsk { deviation_hz: 0 }),
+                },
+                _ => None,
+            }
+            .map(|keying| KeyingConfig {
+                keying,
+                ..base.keying.unwrap_or_default()
+            });
+        }
+        if let Some(settings) = self.keying {
+            let keying = config.keying.as_mut().ok_or(StepError::KeyingWithoutPhy)?;
+            if let Some(bit_rate) = settings.bit_rate {
+                keying.bit_rate = bit_rate;
+            }
+            if let Some(coding) = settings.coding {
+                keying.coding = coding.into();
+            }
+            if let Some(preamble_bytes) = settings.preamble_bytes {
+                keying.preamble_bytes = preamble_bytes;
+            }
+            if let Some(sync_word) = settings.sync_word {
+                keying.sync_word = sync_word;
+            }
+            match (&mut keying.keying, settings.deviation_khz) {
+                (Keying::Fsk { deviation_hz }, Some(deviation_khz)) => *deviation_hz = deviation_khz * 1000,
+                (Keying::Ook, Some(_)) => return Err(StepError::Conflict("keying.deviation_khz", "OOK")),
+                (_, None) => {}
+            }
+        }
+        if let Some(KeyingConfig {
+            keying: Keying::Fsk { deviation_hz: 0 },
+            ..
+        }) = config.keying
+        {
+            return Err(StepError::NoDeviation);
+        }
+
+        let (base_kind, base_length, base_seed) = match base.send.payload {
+            PayloadMode::Sequential { length } => (PayloadKind::Sequential, length, 0),
+            PayloadMode::Random { length, seed } => (PayloadKind::Random, length, seed),
+        };
+        let kind = match (self.payload, &self.seed) {
+            (Some(kind), _) => kind,
+            (None, Some(_)) => PayloadKind::Random,
+            (None, None) => base_kind,
+        };
+        let seeds = match kind {
+            PayloadKind::Sequential => vec![0],
+            PayloadKind::Random => sweep_values(&self.seed, base_seed),
+        };
+
+        let mut runs = Vec::new();
+        let offsets = self.offset_mhz.as_ref().map_or_else(
+            || vec![None],
+            |offsets| offsets.values().into_iter().map(Some).collect(),
+        );
+        for offset_mhz in offsets {
+            let transmit_option = match offset_mhz {
+                None => base.transmit_option,
+                Some(offset_mhz) => transmit_option_for_offset(offset_mhz)
+                    .map_err(|error| StepError::OffsetMHz(offset_mhz, error))?,
+            };
+            if let Some(keying) = config.keying {
+                let (quarter_cycles, quarters_per_chip) = transmit_option.quarter_timing();
+                keying
+                    .check(quarter_cycles, quarters_per_chip)
+                    .map_err(|error| StepError::Keying(transmit_option.offset_hz(), error))?;
+            }
+            for interval_ms in sweep_values(&self.interval_ms, base.send.interval_ms) {
+                for number_packets in sweep_values(&self.number_packets, base.send.number_packets) {
+                    for length in sweep_values(&self.payload_length, base_length) {
+                        for seed in &seeds {
+                            let mut config = config;
+                            config.transmit_option = transmit_option;
+                            config.send.interval_ms = interval_ms;
+                            config.send.number_packets = number_packets;
+                            config.send.payload = match kind {
+                                PayloadKind::Sequential => PayloadMode::Sequential { length },
+                                PayloadKind::Random => PayloadMode::Random { length, seed: *seed },
+                            };
+                            runs.push(Run {
+                                config,
+                                source: source.clone(),
+                                pause_ms: self.pause_ms,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(runs)
+    }
+}
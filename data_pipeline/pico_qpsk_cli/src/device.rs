@@ -0,0 +1,282 @@
+//! Talking to the firmware over its USB serial port with the binary protocol
+//!
+//! The firmware switches a session to the binary protocol when the first byte it receives is 0x00, every
+//! encoded message starts with one so opening the port and sending a request is enough.
+use pico_qpsk_core::protocol::{
+    decode_message, encode_message, max_encoded_size, message_size, ErrorCode, ProtocolError, Request,
+    Response, Status, TransmitSource,
+};
+use pico_qpsk_core::schedule::{PeriodStats, ScheduleMode};
+use pico_qpsk_core::session_config::SessionConfig;
+use pico_qpsk_core::to_max_frame_size;
+use serialport::{ClearBuffer, SerialPort, SerialPortType};
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// USB vendor ID of the firmware
+pub const USB_VID: u16 = 0x16c0;
+/// USB product ID of the firmware
+pub const USB_PID: u16 = 0x27dd;
+
+/// The largest frame the firmware builds, from its MAX_PAYLOAD_SIZE of 1000
+pub const MAX_FRAME_SIZE: usize = to_max_frame_size!(1000);
+/// A transmit request with a raw frame is the longest request
+const MAX_REQUEST_SIZE: usize = message_size(MAX_FRAME_SIZE + 9);
+const MAX_ENCODED_REQUEST: usize = max_encoded_size(MAX_REQUEST_SIZE);
+
+/// How long a read waits for bytes before the deadline is checked again
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// A [Response] that owns its frame, so it can outlive the bytes it was decoded from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    Ok,
+    Error(ErrorCode),
+    Status(Status),
+    PacketSent {
+        packet_number: u32,
+        frame: Vec<u8>,
+    },
+    Done {
+        packets_sent: u32,
+        aborted: bool,
+        period: PeriodStats,
+    },
+}
+
+impl From<Response<'_>> for Reply {
+    fn from(response: Response) -> Self {
+        match response {
+            Response::Ok => Reply::Ok,
+            Response::Error(code) => Reply::Error(code),
+            Response::Status(status) => Reply::Status(status),
+            Response::PacketSent { packet_number, frame } => Reply::PacketSent {
+                packet_number,
+                frame: frame.to_vec(),
+            },
+            Response::Done {
+                packets_sent,
+                aborted,
+                period,
+            } => Reply::Done {
+                packets_sent,
+                aborted,
+                period,
+            },
+        }
+    }
+}
+
+/// A type to hold the possible errors that occur when talking to the firmware
+#[derive(Debug)]
+pub enum DeviceError {
+    /// no serial port has the firmware's VID and PID
+    NotFound,
+    Serial(serialport::Error),
+    Io(std::io::Error),
+    /// a request couldn't be encoded
+    Protocol(ProtocolError),
+    /// the firmware answered the request with an error
+    Rejected(ErrorCode),
+    /// no response before the timeout
+    Timeout,
+    /// a response that doesn't answer the request, boxed as a status holds a whole config
+    Unexpected(Box<Reply>),
+}
+
+impl Display for DeviceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceError::NotFound => write!(
+                f,
+                "no serial port with USB ID {USB_VID:04x}:{USB_PID:04x}, is the pico plugged in?"
+            ),
+            DeviceError::Serial(error) => write!(f, "serial port error: {error}"),
+            DeviceError::Io(error) => write!(f, "serial port error: {error}"),
+            DeviceError::Protocol(error) => write!(f, "bad message: {error:?}"),
+            DeviceError::Rejected(code) => write!(f, "the firmware answered with {code:?}"),
+            DeviceError::Timeout => write!(f, "the firmware didn't answer in time"),
+            DeviceError::Unexpected(reply) => write!(f, "unexpected response {reply:?}"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl From<serialport::Error> for DeviceError {
+    fn from(error: serialport::Error) -> Self {
+        DeviceError::Serial(error)
+    }
+}
+
+impl From<std::io::Error> for DeviceError {
+    fn from(error: std::io::Error) -> Self {
+        DeviceError::Io(error)
+    }
+}
+
+fn unexpected(reply: Reply) -> DeviceError {
+    match reply {
+        Reply::Error(code) => DeviceError::Rejected(code),
+        reply => DeviceError::Unexpected(Box::new(reply)),
+    }
+}
+
+/// The serial ports with the firmware's VID and PID
+pub fn find_ports() -> Result<Vec<String>, DeviceError> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .filter(|port| {
+            matches!(&port.port_type, SerialPortType::UsbPort(usb) if usb.vid == USB_VID && usb.pid == USB_PID)
+        })
+        .map(|port| port.port_name)
+        .collect())
+}
+
+/// The firmware on the other end of a serial port
+pub struct Device<P> {
+    port: P,
+    next_request_id: u16,
+    /// bytes received after the last complete message
+    received: Vec<u8>,
+    /// how long to wait for a response, a transmit request waits the packet interval on top
+    pub timeout: Duration,
+}
+
+impl Device<Box<dyn SerialPort>> {
+    /// Open `path`, or the first port with the firmware's VID and PID when it is `None`
+    pub fn open(path: Option<&str>) -> Result<Self, DeviceError> {
+        let path = match path {
+            Some(path) => path.to_string(),
+            None => find_ports()?.into_iter().next().ok_or(DeviceError::NotFound)?,
+        };
+        // the baud rate doesn't matter for USB serial
+        let mut port = serialport::new(path, 115_200).timeout(READ_TIMEOUT).open()?;
+        // the firmware goes back to the text console when DTR drops
+        port.write_data_terminal_ready(true)?;
+        port.clear(ClearBuffer::Input)?;
+        Ok(Device::new(port))
+    }
+}
+
+impl<P: Read + Write> Device<P> {
+    pub fn new(port: P) -> Self {
+        Device {
+            port,
+            // the firmware answers messages it can't read with request ID 0, so it is never used
+            next_request_id: 1,
+            received: Vec::new(),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    pub fn port(&self) -> &P {
+        &self.port
+    }
+
+    /// Send a request without waiting for the response
+    ///
+    /// returns: Result<the request ID, [DeviceError]>
+    pub fn send(&mut self, request: Request) -> Result<u16, DeviceError> {
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.checked_add(1).unwrap_or(1);
+
+        let mut out = [0u8; MAX_ENCODED_REQUEST];
+        let len = encode_message::<_, MAX_REQUEST_SIZE>(request_id, request, &mut out)
+            .map_err(DeviceError::Protocol)?;
+        self.port.write_all(&out[..len])?;
+        self.port.flush()?;
+        Ok(request_id)
+    }
+
+    /// The next response to `request_id`, responses to older requests are dropped
+    ///
+    /// An error with request ID 0 is also returned, the firmware couldn't read the request. Frames that
+    /// can't be decoded, cut short or garbled on the way, are reported on stderr and skipped
+    pub fn receive(&mut self, request_id: u16, timeout: Duration) -> Result<Reply, DeviceError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            while let Some(end) = self.received.iter().position(|byte| *byte == 0x00) {
+                let mut frame: Vec<u8> = self.received.drain(..=end).collect();
+                frame.pop();
+                // the delimiter in front of a message
+                if frame.is_empty() {
+                    continue;
+                }
+                let (id, response) = match decode_message::<Response>(&mut frame) {
+                    Ok(message) => message,
+                    Err(error) => {
+                        eprintln!("skipped a bad message: {error:?}");
+                        continue;
+                    }
+                };
+                if id == request_id || (id == 0 && matches!(response, Response::Error(_))) {
+                    return Ok(response.into());
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return Err(DeviceError::Timeout);
+            }
+            let mut buffer = [0u8; 256];
+            match self.port.read(&mut buffer) {
+                Ok(len) => self.received.extend_from_slice(&buffer[..len]),
+                Err(error) if matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => (),
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    fn request(&mut self, request: Request) -> Result<Reply, DeviceError> {
+        let request_id = self.send(request)?;
+        self.receive(request_id, self.timeout)
+    }
+
+    /// Replace the firmware's session config
+    pub fn configure(&mut self, config: SessionConfig) -> Result<(), DeviceError> {
+        match self.request(Request::Configure(config))? {
+            Reply::Ok => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    pub fn status(&mut self) -> Result<Status, DeviceError> {
+        match self.request(Request::Status)? {
+            Reply::Status(status) => Ok(status),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    /// Send `number_packets` packets `interval_ms` apart, `on_packet` gets the packet number and PHY frame
+    /// of every packet as the firmware sends it. `schedule` is the firmware's schedule mode, it only
+    /// decides how long to wait for the next packet
+    ///
+    /// returns: Result<(packets sent, aborted, the periods achieved), [DeviceError]>
+    pub fn transmit(
+        &mut self,
+        interval_ms: u32,
+        schedule: ScheduleMode,
+        number_packets: u32,
+        source: TransmitSource,
+        mut on_packet: impl FnMut(u32, &[u8]),
+    ) -> Result<(u32, bool, PeriodStats), DeviceError> {
+        let request_id = self.send(Request::Transmit {
+            interval_ms,
+            number_packets,
+            source,
+        })?;
+        let timeout = self.timeout + Duration::from_millis(schedule.longest_gap_ms(interval_ms));
+        loop {
+            match self.receive(request_id, timeout)? {
+                Reply::PacketSent { packet_number, frame } => on_packet(packet_number, &frame),
+                Reply::Done {
+                    packets_sent,
+                    aborted,
+                    period,
+                } => return Ok((packets_sent, aborted, period)),
+                reply => return Err(unexpected(reply)),
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! A log of every transmitted frame, as CSV or a JSON array
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// One transmitted frame
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FrameRecord {
+    /// when the firmware reported the packet, RFC 3339 in UTC
+    pub timestamp: String,
+    pub step: String,
+    /// counts the runs of the step from 1
+    pub run: usize,
+    pub transmit_option: String,
+    pub interval_ms: u32,
+    pub packet_number: u32,
+    pub number_packets: u32,
+    /// the PHY frame as hex
+    pub frame: String,
+}
+
+/// A type to hold the possible errors that occur when frames are logged
+#[derive(Debug)]
+pub enum LogError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    /// not a .csv or .json file
+    Extension,
+}
+
+impl Display for LogError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogError::Io(error) => write!(f, "can't write the frame log: {error}"),
+            LogError::Csv(error) => write!(f, "can't write the frame log: {error}"),
+            LogError::Json(error) => write!(f, "can't write the frame log: {error}"),
+            LogError::Extension => write!(f, "frame logs are .csv or .json files"),
+        }
+    }
+}
+
+impl std::error::Error for LogError {}
+
+/// The frame bytes as upper case hex, the way the firmware prints them
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
+
+/// Writes the records as they come in, so a cut off campaign still leaves a log behind
+pub enum FrameLog<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    /// `first` is true until the first record is written
+    Json {
+        writer: W,
+        first: bool,
+    },
+}
+
+impl FrameLog<BufWriter<File>> {
+    /// Create the log, the extension picks CSV or JSON
+    pub fn create(path: &Path) -> Result<Self, LogError> {
+        let json = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => false,
+            Some("json") => true,
+            _ => return Err(LogError::Extension),
+        };
+        let writer = BufWriter::new(File::create(path).map_err(LogError::Io)?);
+        if json {
+            FrameLog::json(writer)
+        } else {
+            Ok(FrameLog::csv(writer))
+        }
+    }
+}
+
+impl<W: Write> FrameLog<W> {
+    pub fn csv(writer: W) -> Self {
+        FrameLog::Csv(Box::new(csv::Writer::from_writer(writer)))
+    }
+
+    pub fn json(mut writer: W) -> Result<Self, LogError> {
+        writer.write_all(b"[").map_err(LogError::Io)?;
+        Ok(FrameLog::Json { writer, first: true })
+    }
+
+    pub fn write(&mut self, record: &FrameRecord) -> Result<(), LogError> {
+        match self {
+            FrameLog::Csv(writer) => writer.serialize(record).map_err(LogError::Csv),
+            FrameLog::Json { writer, first } => {
+                let separator: &[u8] = if *first { b"\n  " } else { b",\n  " };
+                *first = false;
+                writer.write_all(separator).map_err(LogError::Io)?;
+                serde_json::to_writer(&mut *writer, record).map_err(LogError::Json)
+            }
+        }
+    }
+
+    /// Close the JSON array and flush
+    ///
+    /// returns: the writer
+    pub fn finish(self) -> Result<W, LogError> {
+        match self {
+            FrameLog::Csv(writer) => {
+                let mut writer = writer
+                    .into_inner()
+                    .map_err(|error| LogError::Io(error.into_error()))?;
+                writer.flush().map_err(LogError::Io)?;
+                Ok(writer)
+            }
+            FrameLog::Json { mut writer, .. } => {
+                writer.write_all(b"\n]\n").map_err(LogError::Io)?;
+                writer.flush().map_err(LogError::Io)?;
+                Ok(writer)
+            }
+        }
+    }
+}
@@ -0,0 +1,7 @@
+//! Host side of the firmware's binary protocol, used by the `pico_qpsk_cli` binary
+//!
+//! `device` finds the pico by its USB ID and sends requests, `campaign` reads the TOML and YAML
+//! campaign files and `frame_log` writes the transmitted frames to CSV or JSON.
+pub mod campaign;
+pub mod device;
+pub mod frame_log;
@@ -0,0 +1,306 @@
+use chrono::{SecondsFormat, Utc};
+use clap::{Parser, Subcommand};
+use pico_qpsk_cli::campaign::{transmit_option_for_offset, Campaign, RunSource};
+use pico_qpsk_cli::device::{find_ports, Device};
+use pico_qpsk_cli::frame_log::{to_hex, FrameLog, FrameRecord};
+use pico_qpsk_core::frame_gen::get_hex_string_as_bytes;
+use pico_qpsk_core::protocol::Status;
+use pico_qpsk_core::session_config::SessionConfig;
+use pico_qpsk_core::transmit_option::TransmitOption;
+use serialport::SerialPort;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Drive the pico transmitter over its USB serial port
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// serial port to use, by default the first one with the pico's USB ID (16c0:27dd)
+    #[arg(long, global = true)]
+    port: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the serial ports with the pico's USB ID
+    List,
+    /// Show whether the pico is transmitting and its config
+    Status,
+    /// Send packets with the pico's config
+    Send {
+        /// milliseconds between packets, by default the pico's `def` setting
+        #[arg(long)]
+        interval_ms: Option<u32>,
+        /// by default the pico's `def` setting
+        #[arg(long)]
+        count: Option<u32>,
+        /// the same payload in every packet, as hex, by default the pico's `payload` setting
+        #[arg(long, conflicts_with = "frame")]
+        payload: Option<String>,
+        /// a PHY frame sent as is, as hex
+        #[arg(long)]
+        frame: Option<String>,
+        /// log every frame to a .csv or .json file
+        #[arg(long)]
+        log: Option<PathBuf>,
+    },
+    /// Show the clocks the pico uses for a frequency offset, planned when it isn't a standard option
+    Plan {
+        /// the offset in MHz, from 1.5 to 9
+        offset_mhz: f64,
+    },
+    /// Run the steps of a TOML or YAML campaign file
+    Run {
+        campaign: PathBuf,
+        /// log every frame to a .csv or .json file
+        #[arg(long)]
+        log: Option<PathBuf>,
+    },
+}
+
+/// Where the frames of a transmit go, printed and maybe logged
+struct Transmission<'a> {
+    log: Option<&'a mut FrameLog<BufWriter<File>>>,
+    step: String,
+    run: usize,
+    config: SessionConfig,
+    number_packets: u32,
+}
+
+fn transmit(
+    device: &mut Device<Box<dyn SerialPort>>,
+    transmission: Transmission,
+    source: &RunSource,
+) -> Result<(), Box<dyn Error>> {
+    let Transmission {
+        mut log,
+        step,
+        run,
+        config,
+        number_packets,
+    } = transmission;
+    let mut log_error = None;
+    let (packets_sent, aborted, period) = device.transmit(
+        config.send.interval_ms,
+        config.send.schedule,
+        number_packets,
+        source.transmit_source(),
+        |packet_number, frame| {
+            let frame = to_hex(frame);
+            println!("packet {packet_number}/{number_packets}: {frame}");
+            let Some(log) = log.as_deref_mut() else {
+                return;
+            };
+            let record = FrameRecord {
+                timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+                step: step.clone(),
+                run,
+                transmit_option: format!("{:?}", config.transmit_option),
+                interval_ms: config.send.interval_ms,
+                packet_number,
+                number_packets,
+                frame,
+            };
+            if let Err(error) = log.write(&record) {
+                log_error.get_or_insert(error);
+            }
+        },
+    )?;
+    if let Some(error) = log_error {
+        return Err(error.into());
+    }
+    if aborted {
+        println!("aborted after {packets_sent}/{number_packets} packets");
+    } else {
+        println!("done, {packets_sent} packets sent");
+    }
+    if let Some(mean_us) = period.mean_us() {
+        println!(
+            "period: mean {mean_us}us, min {}us, max {}us, {}/{} late",
+            period.min_us, period.max_us, period.late, period.count
+        );
+    }
+    Ok(())
+}
+
+fn print_status(status: &Status) {
+    if status.transmitting {
+        println!(
+            "transmitting: {}/{} packets sent",
+            status.packets_sent, status.number_packets
+        );
+    } else {
+        println!(
+            "idle, the last transmit sent {}/{} packets",
+            status.packets_sent, status.number_packets
+        );
+    }
+    let config = &status.config;
+    print_transmit_option(&config.transmit_option);
+    println!("source: {:?}", config.frame.source);
+    println!("destination: {:?}", config.frame.destination);
+    println!("sequence number: {:?}", config.frame.sequence_number);
+    println!(
+        "send: interval_ms: {}, number_packets: {}, payload: {:?}",
+        config.send.interval_ms, config.send.number_packets, config.send.payload
+    );
+    println!("schedule: {:?}", config.send.schedule);
+    println!("sideband: {:?}", config.sideband);
+    println!("phy: {:?}", config.sub_ghz);
+    println!("keying: {:?}", config.keying);
+    println!("pins: {:?}", config.pins);
+}
+
+fn print_transmit_option(option: &TransmitOption) {
+    let offset_mhz = option.offset_millihertz() as f64 / 1e9;
+    match option {
+        TransmitOption::Standard(option) => println!("transmit option: {option:?}, {offset_mhz}MHz offset"),
+        TransmitOption::Planned(plan) => {
+            let pll = plan.clock.pll();
+            let divider = plan.divider.to_256ths();
+            println!("transmit option: planned {offset_mhz}MHz offset");
+            println!(
+                "system clock: {}Hz, VCO {}Hz, refdiv {}, post dividers {} and {}",
+                plan.clock.hz(),
+                pll.vco_hz(),
+                pll.refdiv,
+                pll.post_div1,
+                pll.post_div2
+            );
+            println!(
+                "PIO divider: {} {}/256, {} cycles per quarter, {} quarters per chip",
+                divider >> 8,
+                divider & 0xFF,
+                plan.quarter_cycles,
+                plan.quarters_per_chip
+            );
+            println!("chip rate off by {}ppm", plan.chip_rate_error_ppm());
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    const MAX_FRAME_SIZE: usize = pico_qpsk_cli::device::MAX_FRAME_SIZE;
+    get_hex_string_as_bytes::<MAX_FRAME_SIZE>(hex)
+        .map(|bytes| bytes.to_vec())
+        .map_err(|error| format!("{hex} is not hex: {error:?}").into())
+}
+
+fn finish_log(log: Option<FrameLog<BufWriter<File>>>) -> Result<(), Box<dyn Error>> {
+    if let Some(log) = log {
+        log.finish()?;
+    }
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::List => {
+            for port in find_ports()? {
+                println!("{port}");
+            }
+        }
+        Command::Status => {
+            let mut device = Device::open(cli.port.as_deref())?;
+            print_status(&device.status()?);
+        }
+        Command::Send {
+            interval_ms,
+            count,
+            payload,
+            frame,
+            log,
+        } => {
+            let mut log = log.as_deref().map(FrameLog::create).transpose()?;
+            let mut device = Device::open(cli.port.as_deref())?;
+            let mut config = device.status()?.config;
+            if let Some(interval_ms) = interval_ms {
+                config.send.interval_ms = interval_ms;
+            }
+            let source = match (payload, frame) {
+                (Some(payload), _) => RunSource::Payload(parse_hex(&payload)?),
+                (None, Some(frame)) => RunSource::RawFrame(parse_hex(&frame)?),
+                (None, None) => RunSource::Defaults,
+            };
+            let transmission = Transmission {
+                log: log.as_mut(),
+                step: "send".to_string(),
+                run: 1,
+                config,
+                number_packets: count.unwrap_or(config.send.number_packets),
+            };
+            let result = transmit(&mut device, transmission, &source);
+            finish_log(log)?;
+            result?;
+        }
+        Command::Plan { offset_mhz } => {
+            let option = transmit_option_for_offset(offset_mhz)
+                .map_err(|error| format!("no plan for a {offset_mhz}MHz offset: {error:?}"))?;
+            print_transmit_option(&option);
+            let error_hz = option.offset_millihertz() as f64 / 1e3 - offset_mhz * 1e6;
+            println!("offset error: {error_hz:.3}Hz");
+        }
+        Command::Run { campaign, log } => {
+            let campaign = Campaign::from_path(&campaign)?;
+            let mut log = log.as_deref().map(FrameLog::create).transpose()?;
+            let mut device = Device::open(cli.port.as_deref())?;
+            let base = device.status()?.config;
+            let steps = campaign.runs(&base)?;
+
+            let mut result = Ok(());
+            'steps: for (idx, runs) in steps {
+                let step = campaign.steps[idx].name(idx);
+                for (run_idx, run) in runs.iter().enumerate() {
+                    println!(
+                        "{step}: run {}/{}, {:?}, interval_ms: {}, number_packets: {}, payload: {:?}",
+                        run_idx + 1,
+                        runs.len(),
+                        run.config.transmit_option,
+                        run.config.send.interval_ms,
+                        run.config.send.number_packets,
+                        run.config.send.payload
+                    );
+                    let transmission = Transmission {
+                        log: log.as_mut(),
+                        step: step.clone(),
+                        run: run_idx + 1,
+                        config: run.config,
+                        number_packets: run.config.send.number_packets,
+                    };
+                    result = device
+                        .configure(run.config)
+                        .map_err(Into::into)
+                        .and_then(|()| transmit(&mut device, transmission, &run.source));
+                    if result.is_err() {
+                        break 'steps;
+                    }
+                    std::thread::sleep(Duration::from_millis(run.pause_ms));
+                }
+            }
+            // leave the pico with the config it had before the campaign, a failed run too, its error
+            // comes first
+            let restored = device.configure(base);
+            finish_log(log)?;
+            result?;
+            restored?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    // the errors are printed with Display, returning them from main would print them with Debug
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
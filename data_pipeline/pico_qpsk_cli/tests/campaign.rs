@@ -0,0 +1,210 @@
+//! Check that campaign files are read and swept the same from TOML and YAML
+
+use pico_qpsk_cli::campaign::{Campaign, CampaignError, RunSource, StepError};
+use pico_qpsk_core::keying::{Keying, KeyingConfig, KeyingError, LineCoding};
+use pico_qpsk_core::offset_planner::PlanError;
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::schedule::ScheduleMode;
+use pico_qpsk_core::session_config::{
+    Address, PanId, PayloadMode, SequenceNumberMode, SessionConfig, ShortAddress,
+};
+use pico_qpsk_core::sub_ghz::SubGhzPhy;
+use pico_qpsk_core::transmit_option::{StandardTransmitOption, TransmitOption};
+use std::path::Path;
+
+const TOML: &str = r#"
+[[step]]
+name = "offsets"
+offset_mhz = [2, 4.5, 8]
+number_packets = [10, 100]
+
+[[step]]
+payload_length = [4, 16]
+seed = 42
+destination = { kind = "short", pan_id = 0x2222, address = 0x1234 }
+sequence_number = { mode = "wrap", start = 0, end = 9 }
+pause_ms = 500
+
+[[step]]
+frame_hex = "00000000A70F019801222234124444CDAB01027652"
+interval_ms = 10
+schedule = { mode = "burst", length = 5, gap_ms = 200 }
+sideband = "lsb"
+phy = "bpsk915"
+"#;
+
+const YAML: &str = r#"
+step:
+  - name: offsets
+    offset_mhz: [2, 4.5, 8]
+    number_packets: [10, 100]
+  - payload_length: [4, 16]
+    seed: 42
+    destination: { kind: short, pan_id: 0x2222, address: 0x1234 }
+    sequence_number: { mode: wrap, start: 0, end: 9 }
+    pause_ms: 500
+  - frame_hex: 00000000A70F019801222234124444CDAB01027652
+    interval_ms: 10
+    schedule: { mode: burst, length: 5, gap_ms: 200 }
+    sideband: lsb
+    phy: bpsk915
+"#;
+
+#[test]
+fn toml_and_yaml_campaigns_match() {
+    let campaign = Campaign::from_toml(TOML).unwrap();
+    assert_eq!(Campaign::from_yaml(YAML).unwrap(), campaign);
+    assert_eq!(campaign.steps[0].name(0), "offsets");
+    assert_eq!(campaign.steps[1].name(1), "step 2");
+}
+
+#[test]
+fn every_combination_is_run() {
+    let base = SessionConfig::default();
+    let steps = Campaign::from_toml(TOML).unwrap().runs(&base).unwrap();
+    let runs: Vec<usize> = steps.iter().map(|(_, runs)| runs.len()).collect();
+    assert_eq!(runs, [6, 2, 1]);
+
+    let offsets = &steps[0].1;
+    assert_eq!(
+        offsets[0].config.transmit_option,
+        TransmitOption::Standard(StandardTransmitOption::Clk128MHzOffset2MHz)
+    );
+    assert_eq!(offsets[0].config.send.number_packets, 10);
+    assert_eq!(offsets[1].config.send.number_packets, 100);
+    assert_eq!(
+        offsets[2].config.transmit_option,
+        TransmitOption::for_offset(4_500_000).unwrap()
+    );
+    assert!(matches!(
+        offsets[2].config.transmit_option,
+        TransmitOption::Planned(_)
+    ));
+    assert_eq!(
+        offsets[5].config.transmit_option,
+        TransmitOption::Standard(StandardTransmitOption::Clk128MHzOffset8MHz)
+    );
+    // what the step doesn't set comes from the base config
+    assert_eq!(offsets[0].config.send.payload, base.send.payload);
+    assert_eq!(offsets[0].source, RunSource::Defaults);
+
+    let payloads = &steps[1].1;
+    // a seed alone means random payloads
+    assert_eq!(
+        payloads[1].config.send.payload,
+        PayloadMode::Random { length: 16, seed: 42 }
+    );
+    assert_eq!(
+        payloads[1].config.frame.destination,
+        Some(Address::Short(PanId(0x2222), ShortAddress(0x1234)))
+    );
+    assert_eq!(
+        payloads[1].config.frame.sequence_number,
+        SequenceNumberMode::Wrap { start: 0, end: 9 }
+    );
+    assert_eq!(payloads[1].pause_ms, 500);
+
+    let frame = &steps[2].1[0];
+    assert_eq!(frame.config.send.interval_ms, 10);
+    assert_eq!(
+        frame.config.send.schedule,
+        ScheduleMode::Burst {
+            length: 5,
+            gap_ms: 200
+        }
+    );
+    assert_eq!(frame.config.sideband, Some(Sideband::Lower));
+    assert_eq!(steps[0].1[0].config.sideband, None);
+    assert_eq!(frame.config.sub_ghz, Some(SubGhzPhy::Bpsk915MHz));
+    assert_eq!(steps[0].1[0].config.sub_ghz, None);
+    assert!(matches!(&frame.source, RunSource::RawFrame(bytes) if bytes.len() == 21));
+}
+
+#[test]
+fn bad_steps_are_rejected_before_anything_is_sent() {
+    let base = SessionConfig::default();
+    let errors = [
+        (
+            "offset_mhz = 12",
+            StepError::OffsetMHz(12.0, PlanError::OutOfRange),
+        ),
+        (
+            "payload_hex = \"01\"\nframe_hex = \"02\"",
+            StepError::Conflict("payload_hex", "frame_hex"),
+        ),
+        (
+            "payload_hex = \"01\"\nseed = 1",
+            StepError::Conflict("payload_hex", "seed"),
+        ),
+        (
+            "schedule = { mode = \"burst\", length = 0, gap_ms = 10 }",
+            StepError::BurstLength,
+        ),
+        ("keying = { bit_rate = 4800 }", StepError::KeyingWithoutPhy),
+        ("phy = \"fsk\"", StepError::NoDeviation),
+        (
+            "phy = \"ook\"\nkeying = { bit_rate = 4000000 }",
+            StepError::Keying(8_000_000, KeyingError::TooFast(2_000_000)),
+        ),
+    ];
+    for (step, expected) in errors {
+        let campaign = Campaign::from_toml(&format!("[[step]]\n[[step]]\n{step}")).unwrap();
+        match campaign.runs(&base) {
+            Err(CampaignError::Step { step: 2, reason }) => assert_eq!(reason, expected),
+            other => panic!("{step}: {other:?}"),
+        }
+    }
+    assert!(Campaign::from_toml("[[step]]\nofset_mhz = 2").is_err());
+}
+
+#[test]
+fn keyed_steps_build_on_the_firmware_keying() {
+    let campaign = Campaign::from_toml(
+        r#"
+[[step]]
+phy = "fsk"
+keying = { bit_rate = 4800, coding = "nrz", deviation_khz = 50, sync_word = 0x2DD4 }
+
+[[step]]
+keying = { preamble_bytes = 8 }
+"#,
+    )
+    .unwrap();
+    let fsk = KeyingConfig {
+        keying: Keying::Fsk { deviation_hz: 50_000 },
+        coding: LineCoding::Nrz,
+        bit_rate: 4800,
+        sync_word: 0x2DD4,
+        ..KeyingConfig::default()
+    };
+    let runs = campaign.steps[0].runs(&SessionConfig::default()).unwrap();
+    assert_eq!(runs[0].config.keying, Some(fsk));
+    // the second step only has the firmware's config to change
+    assert!(matches!(
+        campaign.steps[1].runs(&SessionConfig::default()),
+        Err(StepError::KeyingWithoutPhy)
+    ));
+    let base = SessionConfig {
+        keying: Some(fsk),
+        ..SessionConfig::default()
+    };
+    let runs = campaign.steps[1].runs(&base).unwrap();
+    assert_eq!(
+        runs[0].config.keying,
+        Some(KeyingConfig {
+            preamble_bytes: 8,
+            ..fsk
+        })
+    );
+    // a PHY without keying turns it off
+    let step = Campaign::from_toml("[[step]]\nphy = \"2450\"").unwrap();
+    assert_eq!(step.steps[0].runs(&base).unwrap()[0].config.keying, None);
+}
+
+#[test]
+fn example_campaigns_are_valid() {
+    for path in ["campaigns/offset_sweep.toml", "campaigns/random_payloads.yaml"] {
+        let campaign = Campaign::from_path(Path::new(path)).unwrap();
+        assert!(campaign.runs(&SessionConfig::default()).is_ok(), "{path}");
+    }
+}
@@ -0,0 +1,205 @@
+//! Check the requests and responses against a fake serial port
+
+use pico_qpsk_cli::device::{Device, DeviceError};
+use pico_qpsk_cli::frame_log::{FrameLog, FrameRecord};
+use pico_qpsk_core::protocol::{
+    decode_message, encode_message, ErrorCode, Request, Response, TransmitSource,
+};
+use pico_qpsk_core::schedule::{PeriodStats, ScheduleMode};
+use pico_qpsk_core::session_config::SessionConfig;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// Answers are queued up front, everything written is kept
+#[derive(Default)]
+struct FakePort {
+    to_host: VecDeque<u8>,
+    written: Vec<u8>,
+}
+
+impl FakePort {
+    fn respond(&mut self, request_id: u16, response: Response) {
+        let mut out = [0u8; 128];
+        let len = encode_message::<_, 64>(request_id, response, &mut out).unwrap();
+        self.to_host.extend(&out[..len]);
+    }
+
+    /// The requests written to the port
+    fn requests(&self) -> Vec<(u16, String)> {
+        self.written
+            .split(|byte| *byte == 0x00)
+            .filter(|frame| !frame.is_empty())
+            .map(|frame| {
+                let mut frame = frame.to_vec();
+                let (request_id, request) = decode_message::<Request>(&mut frame).unwrap();
+                (request_id, format!("{request:?}"))
+            })
+            .collect()
+    }
+}
+
+impl Read for FakePort {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // a few bytes at a time, like USB packets
+        let len = buf.len().min(self.to_host.len()).min(7);
+        for (byte, received) in buf.iter_mut().zip(self.to_host.drain(..len)) {
+            *byte = received;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for FakePort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn transmit_reports_every_packet() {
+    let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x05, 0x41, 0x88, 0x01, 0x76, 0x52];
+    let mut port = FakePort::default();
+    // a late answer to an earlier request is skipped
+    port.respond(7, Response::Ok);
+    for packet_number in 1..=3 {
+        port.respond(
+            1,
+            Response::PacketSent {
+                packet_number,
+                frame: &frame,
+            },
+        );
+    }
+    port.respond(
+        1,
+        Response::Done {
+            packets_sent: 3,
+            aborted: false,
+            period: PeriodStats {
+                count: 2,
+                min_us: 5_000,
+                max_us: 5_001,
+                total_us: 10_001,
+                late: 0,
+            },
+        },
+    );
+
+    let mut device = Device::new(port);
+    let mut packets = Vec::new();
+    let (packets_sent, aborted, period) = device
+        .transmit(
+            5,
+            ScheduleMode::Fixed,
+            3,
+            TransmitSource::Defaults,
+            |packet_number, frame| {
+                packets.push((packet_number, frame.to_vec()));
+            },
+        )
+        .unwrap();
+    assert_eq!((packets_sent, aborted), (3, false));
+    assert_eq!(period.mean_us(), Some(5_000));
+    assert_eq!(
+        packets,
+        (1..=3).map(|number| (number, frame.to_vec())).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        device.port().requests(),
+        [(
+            1,
+            format!(
+                "{:?}",
+                Request::Transmit {
+                    interval_ms: 5,
+                    number_packets: 3,
+                    source: TransmitSource::Defaults
+                }
+            )
+        )]
+    );
+}
+
+#[test]
+fn undecodable_frames_are_skipped() {
+    let mut port = FakePort::default();
+    // a response that lost a byte on the way, then a frame of noise
+    let mut out = [0u8; 128];
+    let len = encode_message::<_, 64>(1, Response::Error(ErrorCode::Busy), &mut out).unwrap();
+    port.to_host.extend(
+        out[..len]
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != 3)
+            .map(|(_, byte)| byte),
+    );
+    port.to_host.extend([0x00, 0x13, 0x37, 0x00]);
+    port.respond(1, Response::Ok);
+
+    let mut device = Device::new(port);
+    device.timeout = Duration::from_millis(20);
+    device.configure(SessionConfig::default()).unwrap();
+}
+
+#[test]
+fn errors_and_silence_are_reported() {
+    let mut port = FakePort::default();
+    port.respond(1, Response::Error(ErrorCode::Busy));
+    // the firmware couldn't read the request ID
+    port.respond(0, Response::Error(ErrorCode::BadMessage));
+
+    let mut device = Device::new(port);
+    device.timeout = Duration::from_millis(20);
+    assert!(matches!(
+        device.configure(SessionConfig::default()),
+        Err(DeviceError::Rejected(ErrorCode::Busy))
+    ));
+    assert!(matches!(
+        device.status(),
+        Err(DeviceError::Rejected(ErrorCode::BadMessage))
+    ));
+    assert!(matches!(device.status(), Err(DeviceError::Timeout)));
+}
+
+fn record(packet_number: u32) -> FrameRecord {
+    FrameRecord {
+        timestamp: "2024-05-01T12:00:00.000000Z".to_string(),
+        step: "offsets".to_string(),
+        run: 1,
+        transmit_option: "Clk128MHzOffset8MHz".to_string(),
+        interval_ms: 10,
+        packet_number,
+        number_packets: 2,
+        frame: "00000000A7".to_string(),
+    }
+}
+
+#[test]
+fn frame_logs_are_csv_or_json() {
+    let mut log = FrameLog::csv(Vec::new());
+    log.write(&record(1)).unwrap();
+    log.write(&record(2)).unwrap();
+    let csv = String::from_utf8(log.finish().unwrap()).unwrap();
+    assert_eq!(
+        csv.lines().collect::<Vec<_>>(),
+        [
+            "timestamp,step,run,transmit_option,interval_ms,packet_number,number_packets,frame",
+            "2024-05-01T12:00:00.000000Z,offsets,1,Clk128MHzOffset8MHz,10,1,2,00000000A7",
+            "2024-05-01T12:00:00.000000Z,offsets,1,Clk128MHzOffset8MHz,10,2,2,00000000A7",
+        ]
+    );
+
+    let mut log = FrameLog::json(Vec::new()).unwrap();
+    log.write(&record(1)).unwrap();
+    log.write(&record(2)).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&log.finish().unwrap()).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert_eq!(json[1]["packet_number"], 2);
+    assert_eq!(json[0]["frame"], "00000000A7");
+}
@@ -0,0 +1,11 @@
+//! IQ samples of the pico's packets, used by the `pico_qpsk_iq` binary
+//!
+//! `npy` reads and writes the `.npy` captures of `data_pipeline/data_recording` and `simulator` builds the
+//! same kind of capture from the pio bytecode the firmware sends, without an SDR. `receiver` finds the
+//! O-QPSK frames in a capture and decodes them. `sigmf` reads and writes SigMF recordings, the
+//! samples with their sample rate, center frequency, hardware, the `send` they came from and the frames in
+//! them.
+pub mod npy;
+pub mod receiver;
+pub mod sigmf;
+pub mod simulator;
@@ -0,0 +1,297 @@
+use chrono::{SecondsFormat, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::session_config::SessionConfig;
+use pico_qpsk_core::transmit_option::TransmitOption;
+use pico_qpsk_iq::npy;
+use pico_qpsk_iq::receiver::{Receiver, ReceiverConfig};
+use pico_qpsk_iq::sigmf::{self, Meta, Payload, Transmit, TransmitSideband};
+use pico_qpsk_iq::simulator::{session_packets, simulate, Capture, Transmitter};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Work with IQ captures of the pico's packets
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write the capture an SDR would make of a `send`, as a .npy of complex64 samples or a SigMF recording
+    Simulate {
+        /// the .npy file to write, any other name is a SigMF recording
+        out: PathBuf,
+        #[command(flatten)]
+        send: SendArgs,
+        #[command(flatten)]
+        recording: RecordingArgs,
+        /// the carrier's frequency minus the SDR's, in MHz, by default minus the offset so the upper
+        /// sideband is on 0Hz
+        #[arg(long, allow_negative_numbers = true)]
+        carrier_offset_mhz: Option<f64>,
+        #[arg(long, default_value_t = 4.0)]
+        sample_rate_mhz: f64,
+        /// the sideband's power over the noise in the sample rate's bandwidth, no noise by default
+        #[arg(long, allow_negative_numbers = true)]
+        snr_db: Option<f64>,
+        #[arg(long, default_value_t = 0)]
+        noise_seed: u64,
+    },
+    /// Decode the O-QPSK frames of a .npy capture or a SigMF recording
+    Receive {
+        /// the .npy file to read, any other name is a SigMF recording
+        capture: PathBuf,
+        /// a multiple of 2MHz, by default the SigMF recording's or 4MHz
+        #[arg(long)]
+        sample_rate_mhz: Option<f64>,
+        /// where the sideband is in the capture, in MHz from the SDR's center frequency
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        sideband_mhz: f64,
+        /// the sideband below the carrier, its spectrum is mirrored
+        #[arg(long)]
+        lower: bool,
+        /// the preamble correlation from 0 to 1 that starts looking for a frame
+        #[arg(long, default_value_t = 0.5)]
+        threshold: f32,
+        /// write the capture as a SigMF recording with an annotation for every frame
+        #[arg(long)]
+        sigmf: Option<PathBuf>,
+        /// the `send` recorded in the SigMF metadata, when --offset-mhz is given
+        #[command(flatten)]
+        send: SendArgs,
+        #[command(flatten)]
+        recording: RecordingArgs,
+    },
+}
+
+/// The `send` the packets come from
+#[derive(Args)]
+struct SendArgs {
+    /// the frequency offset in MHz, from 1.5 to 9, 8 when simulating
+    #[arg(long)]
+    offset_mhz: Option<f64>,
+    /// dsb on the antenna pin alone, usb or lsb on the I and Q pins
+    #[arg(long, value_enum, default_value_t = SidebandArg::Dsb)]
+    sideband: SidebandArg,
+    #[arg(long, default_value_t = 10)]
+    count: u32,
+    /// milliseconds from the start of one packet to the start of the next
+    #[arg(long, default_value_t = 10)]
+    interval_ms: u32,
+    #[arg(long, default_value_t = 4)]
+    payload_length: u16,
+    /// random payloads from this seed, like `srp`, instead of the sequential one
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl SendArgs {
+    fn transmit(&self, offset_mhz: f64) -> Transmit {
+        Transmit {
+            offset_hz: offset_mhz * 1e6,
+            sideband: self.sideband.into(),
+            interval_ms: self.interval_ms,
+            number_packets: self.count,
+            payload: match self.seed {
+                Some(seed) => Payload::Random {
+                    length: self.payload_length,
+                    seed,
+                },
+                None => Payload::Sequential {
+                    length: self.payload_length,
+                },
+            },
+        }
+    }
+}
+
+/// What the SigMF metadata says about the recording
+#[derive(Args)]
+struct RecordingArgs {
+    /// the SDR's center frequency in MHz, 2460 like data_recording.py when simulating
+    #[arg(long)]
+    center_freq_mhz: Option<f64>,
+    /// notes on the hardware, the SDR, its gain and antenna, where the pico was
+    #[arg(long)]
+    hw: Option<String>,
+    #[arg(long)]
+    description: Option<String>,
+}
+
+impl RecordingArgs {
+    /// Set what was given on `meta`
+    fn apply(self, meta: &mut Meta) {
+        if let Some(mhz) = self.center_freq_mhz {
+            for capture in &mut meta.captures {
+                capture.frequency = Some(mhz * 1e6);
+            }
+        }
+        meta.global.hw = self.hw.or(meta.global.hw.take());
+        meta.global.description = self.description.or(meta.global.description.take());
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum SidebandArg {
+    Dsb,
+    Usb,
+    Lsb,
+}
+
+impl From<SidebandArg> for TransmitSideband {
+    fn from(sideband: SidebandArg) -> Self {
+        match sideband {
+            SidebandArg::Dsb => TransmitSideband::Dsb,
+            SidebandArg::Usb => TransmitSideband::Usb,
+            SidebandArg::Lsb => TransmitSideband::Lsb,
+        }
+    }
+}
+
+/// A .npy file, anything else is a SigMF recording
+fn is_npy(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "npy")
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Simulate {
+            out,
+            send,
+            recording,
+            carrier_offset_mhz,
+            sample_rate_mhz,
+            snr_db,
+            noise_seed,
+        } => {
+            let offset_mhz = send.offset_mhz.unwrap_or(8.0);
+            let transmit = send.transmit(offset_mhz);
+            let transmit_option = TransmitOption::for_offset(transmit.offset_hz.round() as u32)
+                .map_err(|error| format!("no {offset_mhz}MHz offset: {error:?}"))?;
+            let mut config = SessionConfig {
+                transmit_option,
+                sideband: transmit.sideband.into(),
+                ..SessionConfig::default()
+            };
+            config.send.interval_ms = transmit.interval_ms;
+            config.send.number_packets = transmit.number_packets;
+            config.send.payload = transmit.payload.into();
+            let offset_hz = transmit_option.offset_millihertz() as f64 / 1e3;
+            let capture = Capture {
+                sample_rate_hz: (sample_rate_mhz * 1e6).round() as u32,
+                carrier_offset_hz: carrier_offset_mhz.map_or(-offset_hz, |mhz| mhz * 1e6),
+                snr_db,
+                noise_seed,
+            };
+            // half an interval before the first packet and after the last one
+            let lead_us = u64::from(transmit.interval_ms) * 500;
+            let packets = session_packets(&config, lead_us)
+                .map_err(|error| format!("can't build the frames: {error:?}"))?;
+            let duration_us = lead_us + packets.last().map_or(0, |packet| packet.start_us);
+            let len = (duration_us * u64::from(capture.sample_rate_hz) / 1_000_000) as usize;
+            let samples = simulate(
+                &Transmitter::new(&transmit_option, config.phy()),
+                &packets,
+                &capture,
+                len,
+            )?;
+            if is_npy(&out) {
+                npy::save(&out, &samples)?;
+            } else {
+                let mut meta = Meta::new(capture.sample_rate_hz, Some(2460e6));
+                meta.captures[0].datetime = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true));
+                meta.global.hw = Some(format!(
+                    "none, simulated by pico_qpsk_iq with the carrier {}Hz from the center frequency, {}",
+                    capture.carrier_offset_hz,
+                    match capture.snr_db {
+                        Some(snr_db) => format!("{snr_db}dB SNR from noise seed {noise_seed}"),
+                        None => "no noise".to_string(),
+                    }
+                ));
+                meta.global.transmit = Some(transmit);
+                recording.apply(&mut meta);
+                sigmf::save(&out, &meta, &samples)?;
+            }
+            println!(
+                "{} packets in {} samples at {}Hz, the sidebands are at {}Hz and {}Hz",
+                packets.len(),
+                samples.len(),
+                capture.sample_rate_hz,
+                capture.carrier_offset_hz + offset_hz,
+                capture.carrier_offset_hz - offset_hz,
+            );
+        }
+        Command::Receive {
+            capture,
+            sample_rate_mhz,
+            sideband_mhz,
+            lower,
+            threshold,
+            sigmf,
+            send,
+            recording,
+        } => {
+            let (meta, samples) = if is_npy(&capture) {
+                (None, npy::load(&capture)?)
+            } else {
+                let (meta, samples) = sigmf::load(&capture)?;
+                (Some(meta), samples)
+            };
+            let sample_rate_hz = match (
+                sample_rate_mhz,
+                meta.as_ref().and_then(|meta| meta.global.sample_rate),
+            ) {
+                (Some(mhz), _) => (mhz * 1e6).round() as u32,
+                (None, Some(hz)) => hz.round() as u32,
+                (None, None) => 4_000_000,
+            };
+            let config = ReceiverConfig {
+                sample_rate_hz,
+                sideband_hz: sideband_mhz * 1e6,
+                sideband: if lower { Sideband::Lower } else { Sideband::Upper },
+                threshold,
+            };
+            let frames = Receiver::new(config)?.receive(&samples);
+            for frame in &frames {
+                let psdu: String = frame.psdu.iter().map(|byte| format!("{byte:02x}")).collect();
+                println!(
+                    "{:.6}s sample {} {} SNR {} {psdu}",
+                    frame.time_s(config.sample_rate_hz),
+                    frame.start_sample,
+                    if frame.fcs_ok { "FCS ok" } else { "bad FCS" },
+                    frame
+                        .snr_db
+                        .map_or("?".to_string(), |snr_db| format!("{snr_db:.1}dB")),
+                );
+            }
+            let good = frames.iter().filter(|frame| frame.fcs_ok).count();
+            println!("{} frames, {good} with a good FCS", frames.len());
+            if let Some(out) = sigmf {
+                let mut meta = meta.unwrap_or_else(|| Meta::new(sample_rate_hz, None));
+                meta.global.sample_rate = Some(f64::from(sample_rate_hz));
+                if let Some(offset_mhz) = send.offset_mhz {
+                    meta.global.transmit = Some(send.transmit(offset_mhz));
+                }
+                recording.apply(&mut meta);
+                meta.annotate(&frames, config.sideband_hz);
+                sigmf::save(&out, &meta, &samples)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    // the errors are printed with Display, returning them from main would print them with Debug
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! `.npy` files of complex samples, the format `data_recording.py` saves its USRP captures in
+//!
+//! `usrp.recv_num_samps` returns one row of complex64 samples per channel, so a capture has the shape
+//! `(1, n)`. That is what [write_npy] writes, [read_npy] takes it or a flat `(n,)` array, of complex64 or
+//! complex128.
+use num_complex::{Complex32, Complex64};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// numpy pads the header so the data starts on a multiple of this
+const ALIGNMENT: usize = 64;
+
+/// A type to hold the possible errors that occur when a `.npy` file is read
+#[derive(Debug)]
+pub enum NpyError {
+    Io(std::io::Error),
+    /// the file doesn't start with the `.npy` magic
+    Magic,
+    /// the header isn't a dict this reader understands, names the part that is missing or wrong
+    Header(&'static str),
+    /// the dtype isn't little endian complex64 or complex128
+    Dtype(String),
+    /// more than one channel, or more than 2 dimensions
+    Shape(Vec<usize>),
+    /// the file ends before all the samples of the shape
+    Truncated,
+}
+
+impl Display for NpyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NpyError::Io(error) => write!(f, "can't read the samples: {error}"),
+            // the captures are kept in git LFS, without `git lfs pull` the file is a short text pointer
+            NpyError::Magic => write!(f, "not a .npy file, is it a git LFS pointer?"),
+            NpyError::Header(part) => write!(f, "bad .npy header: {part}"),
+            NpyError::Dtype(dtype) => write!(f, "the samples are {dtype}, not complex64 or complex128"),
+            NpyError::Shape(shape) => write!(f, "the samples have the shape {shape:?}, not one channel"),
+            NpyError::Truncated => write!(f, "the file ends before the last sample"),
+        }
+    }
+}
+
+impl std::error::Error for NpyError {}
+
+impl From<std::io::Error> for NpyError {
+    fn from(error: std::io::Error) -> Self {
+        NpyError::Io(error)
+    }
+}
+
+/// Write `samples` as a version 1.0 `.npy` of complex64 with the shape `(1, n)`
+pub fn write_npy<W: Write>(mut writer: W, samples: &[Complex32]) -> std::io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<c8', 'fortran_order': False, 'shape': (1, {}), }}",
+        samples.len()
+    );
+    // magic, version and header length come first, the header ends with a newline
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.extend(core::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(ALIGNMENT) - unpadded,
+    ));
+    header.push('\n');
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for sample in samples {
+        writer.write_all(&sample.re.to_le_bytes())?;
+        writer.write_all(&sample.im.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Read the samples of a one channel `.npy`, complex128 samples are rounded to complex64
+///
+/// # Examples
+///
+/// ```
+/// use num_complex::Complex32;
+/// use pico_qpsk_iq::npy::{read_npy, write_npy};
+///
+/// let samples = [Complex32::new(0.5, -0.25), Complex32::new(-1.0, 1.0)];
+/// let mut file = Vec::new();
+/// write_npy(&mut file, &samples).unwrap();
+/// assert_eq!(file.len(), 128 + 16);
+/// assert_eq!(read_npy(file.as_slice()).unwrap(), samples);
+/// ```
+pub fn read_npy<R: Read>(mut reader: R) -> Result<Vec<Complex32>, NpyError> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble).map_err(|_| NpyError::Magic)?;
+    if preamble[..6] != MAGIC[..] {
+        return Err(NpyError::Magic);
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            usize::from(u16::from_le_bytes(len))
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        _ => return Err(NpyError::Header("version")),
+    };
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8(header).map_err(|_| NpyError::Header("not text"))?;
+
+    let descr = field(&header, "descr").ok_or(NpyError::Header("descr"))?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|descr| descr.split('\'').next())
+        .ok_or(NpyError::Header("descr"))?;
+    let sample_size = match descr {
+        "<c8" => 8,
+        "<c16" => 16,
+        _ => return Err(NpyError::Dtype(descr.to_string())),
+    };
+    let fortran_order = field(&header, "fortran_order").ok_or(NpyError::Header("fortran_order"))?;
+    let shape = field(&header, "shape").ok_or(NpyError::Header("shape"))?;
+    let shape: Vec<usize> = shape
+        .strip_prefix('(')
+        .and_then(|shape| shape.split(')').next())
+        .ok_or(NpyError::Header("shape"))?
+        .split(',')
+        .map(str::trim)
+        .filter(|len| !len.is_empty())
+        .map(|len| len.parse().map_err(|_| NpyError::Header("shape")))
+        .collect::<Result<_, _>>()?;
+    // one row is the same in both orders
+    let len = match shape[..] {
+        [len] | [1, len] => len,
+        _ => return Err(NpyError::Shape(shape)),
+    };
+    if !fortran_order.starts_with("False") && !fortran_order.starts_with("True") {
+        return Err(NpyError::Header("fortran_order"));
+    }
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if data.len() < len * sample_size {
+        return Err(NpyError::Truncated);
+    }
+    let samples = data[..len * sample_size].chunks_exact(sample_size);
+    Ok(match sample_size {
+        8 => samples
+            .map(|sample| {
+                let (re, im) = sample.split_at(4);
+                Complex32::new(
+                    f32::from_le_bytes(re.try_into().expect("split at 4")),
+                    f32::from_le_bytes(im.try_into().expect("split at 4")),
+                )
+            })
+            .collect(),
+        _ => samples
+            .map(|sample| {
+                let (re, im) = sample.split_at(8);
+                let sample = Complex64::new(
+                    f64::from_le_bytes(re.try_into().expect("split at 8")),
+                    f64::from_le_bytes(im.try_into().expect("split at 8")),
+                );
+                Complex32::new(sample.re as f32, sample.im as f32)
+            })
+            .collect(),
+    })
+}
+
+/// The text after `'key':` in a header dict, up to the end of the header
+fn field<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{key}':"))? + key.len() + 3;
+    Some(header[start..].trim_start())
+}
+
+/// [write_npy] to a file
+pub fn save(path: &Path, samples: &[Complex32]) -> std::io::Result<()> {
+    write_npy(BufWriter::new(File::create(path)?), samples)
+}
+
+/// [read_npy] from a file
+pub fn load(path: &Path) -> Result<Vec<Complex32>, NpyError> {
+    read_npy(BufReader::new(File::open(path)?))
+}
@@ -0,0 +1,344 @@
+//! An 802.15.4 O-QPSK receiver for IQ captures of the pico's packets
+//!
+//! The capture is shifted so the sideband the frames are on is at 0Hz. Every subcarrier chip of the pico
+//! holds one of 4 phases for half a microsecond, so a symbol is 32 of them, the 16 (I, Q) chips of
+//! [CHIP_ARRAY] with the middle chips in between. The receiver correlates the capture with those as
+//! rectangular pulses, the matched filter of the pico's waveform, and takes the magnitude so the phase of
+//! the carrier doesn't matter.
+//!
+//! A run of 0 symbols over [ReceiverConfig::threshold] starts a search for the SFD, the symbols after it
+//! are the PHR and the PSDU. Every symbol is looked for a sample early and late as well, so the clocks of
+//! the pico and the SDR can drift apart over a frame. The FCS of the PSDU is checked, frames with a bad one
+//! are reported too.
+use num_complex::Complex32;
+use pico_qpsk_core::packet::fcs;
+use pico_qpsk_core::pio_bytecode_gen::{Sideband, CHIP_ARRAY};
+use std::f64::consts::TAU;
+use std::fmt::{Display, Formatter};
+
+/// 2 chips of the 2.4GHz PHY per microsecond, a subcarrier chip of the pico is one of them
+const CHIP_RATE: u32 = 2_000_000;
+
+/// The subcarrier chips of a symbol
+const SYMBOL_CHIPS: usize = 32;
+
+/// The 0 symbols of the preamble that have to be over the threshold to look for the SFD
+const PREAMBLE_DETECT: usize = 4;
+
+/// The symbols of the preamble, the 4 0x00 octets
+const PREAMBLE_SYMBOLS: usize = 8;
+
+/// The SFD 0xA7, low nibble first
+const SFD: [u8; 2] = [0x7, 0xA];
+
+/// The noise is measured over this many symbols before the preamble, a symbol away from it
+const NOISE_SYMBOLS: usize = 4;
+
+/// How the frames are found in a capture
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReceiverConfig {
+    /// a multiple of 2MHz, from 4MHz up
+    pub sample_rate_hz: u32,
+    /// the center of the sideband in the capture, the SDR's center frequency minus the carrier's plus or
+    /// minus the offset, 0 for the upper sideband of an 8MHz offset with the carrier 8MHz below the SDR
+    pub sideband_hz: f64,
+    /// which side of the carrier the sideband is on, the lower one has its spectrum mirrored
+    pub sideband: Sideband,
+    /// the mean normalised correlation of [PREAMBLE_DETECT] preamble symbols that makes the receiver look
+    /// for an SFD, from 0 to 1
+    pub threshold: f32,
+}
+
+impl Default for ReceiverConfig {
+    fn default() -> Self {
+        ReceiverConfig {
+            sample_rate_hz: 4_000_000,
+            sideband_hz: 0.0,
+            sideband: Sideband::Upper,
+            threshold: 0.5,
+        }
+    }
+}
+
+/// A type to hold the possible errors that occur when a receiver is set up
+#[derive(Debug, PartialEq)]
+pub enum ReceiverError {
+    /// the sample rate isn't a multiple of the 2MHz chip rate from 4MHz up
+    SampleRate(u32),
+}
+
+impl Display for ReceiverError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiverError::SampleRate(sample_rate_hz) => write!(
+                f,
+                "no {sample_rate_hz}Hz sample rate, it has to be a multiple of 2MHz from 4MHz up"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReceiverError {}
+
+/// A frame found in a capture
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedFrame {
+    /// the sample the preamble starts at
+    pub start_sample: usize,
+    /// the samples from the preamble to the end of the PSDU
+    pub len: usize,
+    /// the PSDU, the PHR is its length
+    pub psdu: Vec<u8>,
+    /// the FCS at the end of the PSDU matches the rest of it
+    pub fcs_ok: bool,
+    /// the mean normalised correlation of the symbols after the SFD, 1 for a clean frame
+    pub quality: f32,
+    /// the power of the frame over the power of the noise before it, in dB, `None` when there is no
+    /// quiet stretch before the frame to measure the noise in or there is no noise in it
+    pub snr_db: Option<f64>,
+}
+
+impl ReceivedFrame {
+    /// When the preamble starts, in seconds from the start of the capture
+    pub fn time_s(&self, sample_rate_hz: u32) -> f64 {
+        self.start_sample as f64 / f64::from(sample_rate_hz)
+    }
+
+    /// The PHY frame, `[PREAMBLE][SFD][PHR][PSDU]` like the pico sends it
+    pub fn phy_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0x00, 0x00, 0x00, 0x00, 0xA7, self.psdu.len() as u8];
+        bytes.extend(&self.psdu);
+        bytes
+    }
+}
+
+/// The best symbol near a position
+struct Symbol {
+    symbol: u8,
+    /// the sample the symbol starts at
+    start: usize,
+    /// its normalised correlation
+    correlation: f32,
+}
+
+/// Finds and decodes the frames of a capture, see the [module docs](self)
+pub struct Receiver {
+    config: ReceiverConfig,
+    /// the samples of every subcarrier chip
+    samples_per_chip: usize,
+    /// every symbol's waveform at the sample rate
+    templates: Vec<Vec<Complex32>>,
+}
+
+impl Receiver {
+    pub fn new(config: ReceiverConfig) -> Result<Self, ReceiverError> {
+        if config.sample_rate_hz < 2 * CHIP_RATE || !config.sample_rate_hz.is_multiple_of(CHIP_RATE) {
+            return Err(ReceiverError::SampleRate(config.sample_rate_hz));
+        }
+        let samples_per_chip = (config.sample_rate_hz / CHIP_RATE) as usize;
+        // a 0 bit is +1, the I bit is the real part and the Q bit the imaginary one
+        let level = |bit: u8| if bit == 0 { 1.0 } else { -1.0 };
+        let templates = CHIP_ARRAY
+            .iter()
+            .map(|chips| {
+                (0..SYMBOL_CHIPS)
+                    .map(|chip| {
+                        let current = chips[chip / 2];
+                        let chip = match (chip % 2, chips.get(chip / 2 + 1)) {
+                            (0, _) => Complex32::new(level(current >> 1), level(current & 1)),
+                            // a middle chip has the Q bit of the chip before and the I bit of the one
+                            // after, which is in the next symbol for the last one
+                            (_, Some(next)) => Complex32::new(level(next >> 1), level(current & 1)),
+                            (_, None) => Complex32::new(0.0, level(current & 1)),
+                        };
+                        match config.sideband {
+                            Sideband::Upper => chip,
+                            Sideband::Lower => chip.conj(),
+                        }
+                    })
+                    .flat_map(|chip| std::iter::repeat_n(chip, samples_per_chip))
+                    .collect()
+            })
+            .collect();
+        Ok(Receiver {
+            config,
+            samples_per_chip,
+            templates,
+        })
+    }
+
+    /// The samples of a symbol
+    fn symbol_len(&self) -> usize {
+        SYMBOL_CHIPS * self.samples_per_chip
+    }
+
+    /// Every frame in `samples`, in the order they start
+    pub fn receive(&self, samples: &[Complex32]) -> Vec<ReceivedFrame> {
+        let samples = shift(samples, -self.config.sideband_hz, self.config.sample_rate_hz);
+        let symbol_len = self.symbol_len();
+        // the energy of samples[..n] is energy[n]
+        let energy: Vec<f64> = std::iter::once(0.0)
+            .chain(samples.iter().scan(0.0, |energy, sample| {
+                *energy += f64::from(sample.norm_sqr());
+                Some(*energy)
+            }))
+            .collect();
+        let zeros: Vec<f32> = (0..samples.len().saturating_sub(symbol_len))
+            .map(|start| self.correlation(&samples, &energy, start, 0))
+            .collect();
+        let preamble = |start: usize| {
+            (0..PREAMBLE_DETECT)
+                .map(|symbol| zeros.get(start + symbol * symbol_len).copied().unwrap_or(0.0))
+                .sum::<f32>()
+                / PREAMBLE_DETECT as f32
+        };
+
+        let mut frames: Vec<ReceivedFrame> = Vec::new();
+        let mut start = 0;
+        while start < zeros.len() {
+            if preamble(start) < self.config.threshold {
+                start += 1;
+                continue;
+            }
+            // the preamble repeats every symbol, the best start within one is where the symbols are
+            let best = (start..start + symbol_len)
+                .max_by(|a, b| preamble(*a).total_cmp(&preamble(*b)))
+                .expect("a symbol isn't empty");
+            let quiet_from = frames.last().map_or(0, |frame| frame.start_sample + frame.len);
+            match self.decode(&samples, &energy, best, quiet_from) {
+                Some(frame) => {
+                    start = frame.start_sample + frame.len;
+                    frames.push(frame);
+                }
+                None => start = best + symbol_len,
+            }
+        }
+        frames
+    }
+
+    /// The normalised correlation of `symbol` with the samples from `start`, 0 past the end
+    fn correlation(&self, samples: &[Complex32], energy: &[f64], start: usize, symbol: u8) -> f32 {
+        let template = &self.templates[usize::from(symbol)];
+        let Some(window) = samples.get(start..start + template.len()) else {
+            return 0.0;
+        };
+        let correlation: Complex32 = window
+            .iter()
+            .zip(template)
+            .map(|(sample, chip)| sample * chip.conj())
+            .sum();
+        let window_energy = energy[start + template.len()] - energy[start];
+        let template_energy: f32 = template.iter().map(Complex32::norm_sqr).sum();
+        if window_energy <= 0.0 {
+            return 0.0;
+        }
+        correlation.norm() / (window_energy as f32 * template_energy).sqrt()
+    }
+
+    /// The symbol with the best correlation a sample around `start`
+    fn symbol(&self, samples: &[Complex32], energy: &[f64], start: usize) -> Symbol {
+        (start.saturating_sub(1)..=start + 1)
+            .flat_map(|start| (0..16).map(move |symbol| (start, symbol)))
+            .map(|(start, symbol)| Symbol {
+                symbol,
+                start,
+                correlation: self.correlation(samples, energy, start, symbol),
+            })
+            .max_by(|a, b| a.correlation.total_cmp(&b.correlation))
+            .expect("there are 16 symbols")
+    }
+
+    /// Look for the SFD in the symbols from `start`, and decode the frame after it
+    fn decode(
+        &self,
+        samples: &[Complex32],
+        energy: &[f64],
+        start: usize,
+        quiet_from: usize,
+    ) -> Option<ReceivedFrame> {
+        let symbol_len = self.symbol_len();
+        let mut position = start;
+        // the preamble can be detected late, it is looked for up to its full length
+        let mut sfd = None;
+        for _ in 0..=PREAMBLE_SYMBOLS {
+            let symbol = self.symbol(samples, energy, position);
+            match symbol.symbol {
+                0 => position = symbol.start + symbol_len,
+                symbol if symbol == SFD[0] => {
+                    let next = self.symbol(samples, energy, position + symbol_len);
+                    if next.symbol != SFD[1] {
+                        return None;
+                    }
+                    sfd = Some(position);
+                    position = next.start + symbol_len;
+                    break;
+                }
+                _ => return None,
+            }
+        }
+        // a frame at the start of the capture can be found a sample early
+        let start_sample = sfd?.saturating_sub(PREAMBLE_SYMBOLS * symbol_len);
+
+        let mut correlations = 0.0;
+        let mut symbols = 0;
+        let mut next_byte = |position: &mut usize| {
+            let mut byte = 0;
+            // low nibble first
+            for shift in [0, 4] {
+                let symbol = self.symbol(samples, energy, *position);
+                byte |= symbol.symbol << shift;
+                correlations += symbol.correlation;
+                symbols += 1;
+                *position = symbol.start + symbol_len;
+            }
+            byte
+        };
+        // the 7 bit length of the PSDU, the top bit is reserved
+        let psdu_len = next_byte(&mut position) & 0x7F;
+        if position + 2 * usize::from(psdu_len) * symbol_len > samples.len() {
+            return None;
+        }
+        let psdu: Vec<u8> = (0..psdu_len).map(|_| next_byte(&mut position)).collect();
+        let quality = correlations / symbols as f32;
+        let fcs_ok = psdu.len() >= 2 && {
+            let (mac, footer) = psdu.split_at(psdu.len() - 2);
+            fcs(mac).to_le_bytes() == footer
+        };
+
+        // the noise before the preamble, a symbol away from it so the filter of the SDR doesn't matter
+        let frame_power = (energy[position] - energy[start_sample]) / (position - start_sample) as f64;
+        let snr_db = start_sample.checked_sub(symbol_len).and_then(|noise_end| {
+            let noise_start = noise_end
+                .saturating_sub(NOISE_SYMBOLS * symbol_len)
+                .max(quiet_from);
+            if noise_start >= noise_end {
+                return None;
+            }
+            let noise = (energy[noise_end] - energy[noise_start]) / (noise_end - noise_start) as f64;
+            // a capture with nothing before the frame, or less power in the frame than before it
+            (noise > 0.0 && frame_power > noise).then(|| 10.0 * ((frame_power - noise) / noise).log10())
+        });
+        Some(ReceivedFrame {
+            start_sample,
+            len: position - start_sample,
+            psdu,
+            fcs_ok,
+            quality,
+            snr_db,
+        })
+    }
+}
+
+/// `samples` moved by `shift_hz`
+fn shift(samples: &[Complex32], shift_hz: f64, sample_rate_hz: u32) -> Vec<Complex32> {
+    let turns_per_sample = shift_hz / f64::from(sample_rate_hz);
+    samples
+        .iter()
+        .enumerate()
+        .map(|(idx, sample)| {
+            let turns = (idx as f64 * turns_per_sample).fract();
+            let (sin, cos) = (TAU * turns).sin_cos();
+            sample * Complex32::new(cos as f32, sin as f32)
+        })
+        .collect()
+}
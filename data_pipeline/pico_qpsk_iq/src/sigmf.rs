@@ -0,0 +1,435 @@
+//! SigMF recordings, the samples in a `.sigmf-data` file and what they are in the `.sigmf-meta` next to it
+//!
+//! The samples are written as `cf32_le`, the complex64 of the `.npy` captures, and `cf64_le` is read as
+//! well. The metadata holds the sample rate and the SDR's center frequency, notes on the hardware and an
+//! annotation for every frame the [receiver](crate::receiver) decodes. The `send` the packets came from is
+//! `pico_qpsk:transmit` in the global object, the decoded PSDUs are `pico_qpsk:` fields of the
+//! annotations. Fields of other namespaces are kept when a recording is read and written again.
+//!
+//! ```json
+//! {
+//!   "global": {
+//!     "core:datatype": "cf32_le",
+//!     "core:sample_rate": 4000000.0,
+//!     "core:version": "1.2.0",
+//!     "core:hw": "USRP B210, 50dB gain, the pico 1m away",
+//!     "core:extensions": [{ "name": "pico_qpsk", "version": "0.1.0", "optional": true }],
+//!     "pico_qpsk:transmit": {
+//!       "offset_hz": 8000000.0,
+//!       "sideband": "dsb",
+//!       "interval_ms": 10,
+//!       "number_packets": 10,
+//!       "payload": { "mode": "random", "length": 4, "seed": 42 }
+//!     }
+//!   },
+//!   "captures": [{ "core:sample_start": 0, "core:frequency": 2460000000.0 }],
+//!   "annotations": [{
+//!     "core:sample_start": 20001,
+//!     "core:sample_count": 2944,
+//!     "core:freq_lower_edge": 2459000000.0,
+//!     "core:freq_upper_edge": 2461000000.0,
+//!     "core:label": "802.15.4",
+//!     "core:comment": "FCS ok",
+//!     "pico_qpsk:psdu": "019801222234124444CDAB5AC389A3835C",
+//!     "pico_qpsk:fcs_ok": true,
+//!     "pico_qpsk:quality": 0.89,
+//!     "pico_qpsk:snr_db": 9.5
+//!   }]
+//! }
+//! ```
+use crate::receiver::ReceivedFrame;
+use num_complex::Complex32;
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::session_config::PayloadMode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The SigMF version the metadata is written for
+pub const VERSION: &str = "1.2.0";
+
+/// The namespace of the fields that aren't SigMF's
+pub const NAMESPACE: &str = "pico_qpsk";
+
+/// The label of the annotations of decoded frames
+pub const FRAME_LABEL: &str = "802.15.4";
+
+/// Half the width of the O-QPSK main lobe around the sideband, the annotations' frequency edges
+const HALF_BANDWIDTH_HZ: f64 = 1e6;
+
+/// A type to hold the possible errors that occur when a SigMF recording is read or written
+#[derive(Debug)]
+pub enum SigmfError {
+    Io(std::io::Error),
+    /// the `.sigmf-meta` isn't SigMF metadata
+    Json(serde_json::Error),
+    /// the samples read aren't `cf32_le` or `cf64_le`
+    Datatype(String),
+    /// the samples written aren't `cf32_le`
+    WriteDatatype(String),
+    /// the `.sigmf-data` ends in the middle of a sample
+    Truncated,
+}
+
+impl Display for SigmfError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SigmfError::Io(error) => write!(f, "can't read or write the recording: {error}"),
+            SigmfError::Json(error) => write!(f, "bad .sigmf-meta: {error}"),
+            SigmfError::Datatype(datatype) => write!(f, "the samples are {datatype}, not cf32_le or cf64_le"),
+            SigmfError::WriteDatatype(datatype) => write!(f, "can't write {datatype} samples, only cf32_le"),
+            SigmfError::Truncated => write!(f, "the .sigmf-data ends in the middle of a sample"),
+        }
+    }
+}
+
+impl std::error::Error for SigmfError {}
+
+impl From<std::io::Error> for SigmfError {
+    fn from(error: std::io::Error) -> Self {
+        SigmfError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SigmfError {
+    fn from(error: serde_json::Error) -> Self {
+        SigmfError::Json(error)
+    }
+}
+
+/// The contents of a `.sigmf-meta` file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Meta {
+    pub global: Global,
+    pub captures: Vec<CaptureSegment>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+/// What holds for the whole recording
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Global {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+    #[serde(rename = "core:sample_rate", default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f64>,
+    #[serde(rename = "core:version")]
+    pub version: String,
+    #[serde(rename = "core:description", default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "core:author", default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// the SDR, its antenna and gain, where the pico was
+    #[serde(rename = "core:hw", default, skip_serializing_if = "Option::is_none")]
+    pub hw: Option<String>,
+    #[serde(rename = "core:recorder", default, skip_serializing_if = "Option::is_none")]
+    pub recorder: Option<String>,
+    #[serde(rename = "core:extensions", default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<Extension>,
+    /// the `send` the packets in the recording came from
+    #[serde(
+        rename = "pico_qpsk:transmit",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub transmit: Option<Transmit>,
+    /// the fields of other namespaces
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// A namespace the metadata uses besides `core`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Extension {
+    pub name: String,
+    pub version: String,
+    /// a reader that doesn't know the namespace can still use the recording
+    pub optional: bool,
+}
+
+impl Extension {
+    /// The [NAMESPACE] of this crate's version
+    pub fn pico_qpsk() -> Self {
+        Extension {
+            name: NAMESPACE.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            optional: true,
+        }
+    }
+}
+
+/// The SDR's settings from a sample on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CaptureSegment {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    /// the SDR's center frequency in Hz
+    #[serde(rename = "core:frequency", default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    /// when the segment's first sample was taken, ISO 8601 in UTC
+    #[serde(rename = "core:datetime", default, skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Something in a stretch of samples, a decoded frame when it has a `psdu`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(
+        rename = "core:sample_count",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sample_count: Option<u64>,
+    #[serde(
+        rename = "core:freq_lower_edge",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub freq_lower_edge: Option<f64>,
+    #[serde(
+        rename = "core:freq_upper_edge",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub freq_upper_edge: Option<f64>,
+    #[serde(rename = "core:label", default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(rename = "core:comment", default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// the PSDU as upper case hex
+    #[serde(rename = "pico_qpsk:psdu", default, skip_serializing_if = "Option::is_none")]
+    pub psdu: Option<String>,
+    #[serde(rename = "pico_qpsk:fcs_ok", default, skip_serializing_if = "Option::is_none")]
+    pub fcs_ok: Option<bool>,
+    /// see [ReceivedFrame::quality]
+    #[serde(
+        rename = "pico_qpsk:quality",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub quality: Option<f32>,
+    #[serde(rename = "pico_qpsk:snr_db", default, skip_serializing_if = "Option::is_none")]
+    pub snr_db: Option<f64>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl Annotation {
+    /// The annotation of a decoded frame, `sideband_hz` is where its sideband is, `None` when the
+    /// recording has no center frequency
+    pub fn frame(frame: &ReceivedFrame, sideband_hz: Option<f64>) -> Self {
+        Annotation {
+            sample_start: frame.start_sample as u64,
+            sample_count: Some(frame.len as u64),
+            freq_lower_edge: sideband_hz.map(|hz| hz - HALF_BANDWIDTH_HZ),
+            freq_upper_edge: sideband_hz.map(|hz| hz + HALF_BANDWIDTH_HZ),
+            label: Some(FRAME_LABEL.to_string()),
+            comment: Some(if frame.fcs_ok { "FCS ok" } else { "bad FCS" }.to_string()),
+            psdu: Some(frame.psdu.iter().map(|byte| format!("{byte:02X}")).collect()),
+            fcs_ok: Some(frame.fcs_ok),
+            quality: Some(frame.quality),
+            snr_db: frame.snr_db,
+            other: Map::new(),
+        }
+    }
+}
+
+/// The sidebands the pico sent
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransmitSideband {
+    /// both, on the antenna pin alone
+    Dsb,
+    /// the upper one, on the I and Q pins
+    Usb,
+    /// the lower one, on the I and Q pins
+    Lsb,
+}
+
+impl From<Option<Sideband>> for TransmitSideband {
+    fn from(sideband: Option<Sideband>) -> Self {
+        match sideband {
+            None => TransmitSideband::Dsb,
+            Some(Sideband::Upper) => TransmitSideband::Usb,
+            Some(Sideband::Lower) => TransmitSideband::Lsb,
+        }
+    }
+}
+
+impl From<TransmitSideband> for Option<Sideband> {
+    fn from(sideband: TransmitSideband) -> Self {
+        match sideband {
+            TransmitSideband::Dsb => None,
+            TransmitSideband::Usb => Some(Sideband::Upper),
+            TransmitSideband::Lsb => Some(Sideband::Lower),
+        }
+    }
+}
+
+/// [PayloadMode] with the mode named, `{ "mode": "random", "length": 4, "seed": 42 }`
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Payload {
+    Sequential { length: u16 },
+    Random { length: u16, seed: u64 },
+}
+
+impl From<PayloadMode> for Payload {
+    fn from(payload: PayloadMode) -> Self {
+        match payload {
+            PayloadMode::Sequential { length } => Payload::Sequential { length },
+            PayloadMode::Random { length, seed } => Payload::Random { length, seed },
+        }
+    }
+}
+
+impl From<Payload> for PayloadMode {
+    fn from(payload: Payload) -> Self {
+        match payload {
+            Payload::Sequential { length } => PayloadMode::Sequential { length },
+            Payload::Random { length, seed } => PayloadMode::Random { length, seed },
+        }
+    }
+}
+
+/// The `send` of a recording's packets
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transmit {
+    /// the subcarrier's frequency offset from the carrier
+    pub offset_hz: f64,
+    pub sideband: TransmitSideband,
+    pub interval_ms: u32,
+    pub number_packets: u32,
+    pub payload: Payload,
+}
+
+impl Meta {
+    /// The metadata of `cf32_le` samples at `sample_rate_hz` from an SDR at `frequency_hz`, with one
+    /// capture segment
+    pub fn new(sample_rate_hz: u32, frequency_hz: Option<f64>) -> Self {
+        Meta {
+            global: Global {
+                datatype: "cf32_le".to_string(),
+                sample_rate: Some(f64::from(sample_rate_hz)),
+                version: VERSION.to_string(),
+                description: None,
+                author: None,
+                hw: None,
+                recorder: Some(concat!("pico_qpsk_iq ", env!("CARGO_PKG_VERSION")).to_string()),
+                extensions: vec![Extension::pico_qpsk()],
+                transmit: None,
+                other: Map::new(),
+            },
+            captures: vec![CaptureSegment {
+                sample_start: 0,
+                frequency: frequency_hz,
+                datetime: None,
+                other: Map::new(),
+            }],
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Replace the annotations of frames with `frames`, `sideband_hz` is where the receiver looked for them
+    /// relative to the center frequency of the first capture segment
+    pub fn annotate(&mut self, frames: &[ReceivedFrame], sideband_hz: f64) {
+        if !self
+            .global
+            .extensions
+            .iter()
+            .any(|extension| extension.name == NAMESPACE)
+        {
+            self.global.extensions.push(Extension::pico_qpsk());
+        }
+        let frequency = self.captures.first().and_then(|capture| capture.frequency);
+        let sideband_hz = frequency.map(|frequency| frequency + sideband_hz);
+        self.annotations.retain(|annotation| annotation.psdu.is_none());
+        self.annotations
+            .extend(frames.iter().map(|frame| Annotation::frame(frame, sideband_hz)));
+        // SigMF wants them in the order they start
+        self.annotations.sort_by_key(|annotation| annotation.sample_start);
+    }
+}
+
+/// The `.sigmf-meta` and `.sigmf-data` of a recording, from either of them or the name without an extension
+pub fn paths(path: &Path) -> (PathBuf, PathBuf) {
+    let base = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("sigmf-meta" | "sigmf-data" | "sigmf") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    };
+    let with = |extension: &str| {
+        let mut path = base.clone().into_os_string();
+        path.push(extension);
+        PathBuf::from(path)
+    };
+    (with(".sigmf-meta"), with(".sigmf-data"))
+}
+
+/// Write `samples` as `cf32_le`
+pub fn write_data<W: Write>(mut writer: W, samples: &[Complex32]) -> std::io::Result<()> {
+    for sample in samples {
+        writer.write_all(&sample.re.to_le_bytes())?;
+        writer.write_all(&sample.im.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Read `datatype` samples, `cf64_le` ones are narrowed to complex64
+pub fn read_data<R: Read>(mut reader: R, datatype: &str) -> Result<Vec<Complex32>, SigmfError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    match datatype {
+        "cf32_le" => {
+            if !bytes.len().is_multiple_of(8) {
+                return Err(SigmfError::Truncated);
+            }
+            let part = |bytes: &[u8]| f32::from_le_bytes(bytes.try_into().expect("4 bytes"));
+            Ok(bytes
+                .chunks_exact(8)
+                .map(|sample| Complex32::new(part(&sample[..4]), part(&sample[4..])))
+                .collect())
+        }
+        "cf64_le" => {
+            if !bytes.len().is_multiple_of(16) {
+                return Err(SigmfError::Truncated);
+            }
+            let part = |bytes: &[u8]| f64::from_le_bytes(bytes.try_into().expect("8 bytes")) as f32;
+            Ok(bytes
+                .chunks_exact(16)
+                .map(|sample| Complex32::new(part(&sample[..8]), part(&sample[8..])))
+                .collect())
+        }
+        datatype => Err(SigmfError::Datatype(datatype.to_string())),
+    }
+}
+
+/// Write the recording at [paths] of `path`, `meta` has to say `cf32_le`
+pub fn save(path: &Path, meta: &Meta, samples: &[Complex32]) -> Result<(), SigmfError> {
+    if meta.global.datatype != "cf32_le" {
+        return Err(SigmfError::WriteDatatype(meta.global.datatype.clone()));
+    }
+    let (meta_path, data_path) = paths(path);
+    write_data(BufWriter::new(File::create(data_path)?), samples)?;
+    let mut writer = BufWriter::new(File::create(meta_path)?);
+    serde_json::to_writer_pretty(&mut writer, meta)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read the recording at [paths] of `path`, the metadata says `cf32_le` like the samples it returns so the
+/// recording can be [saved](save) again
+pub fn load(path: &Path) -> Result<(Meta, Vec<Complex32>), SigmfError> {
+    let (meta_path, data_path) = paths(path);
+    let mut meta: Meta = serde_json::from_reader(BufReader::new(File::open(meta_path)?))?;
+    let samples = read_data(BufReader::new(File::open(data_path)?), &meta.global.datatype)?;
+    meta.global.datatype = "cf32_le".to_string();
+    Ok((meta, samples))
+}
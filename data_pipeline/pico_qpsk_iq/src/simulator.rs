@@ -0,0 +1,428 @@
+//! The baseband an SDR near the carrier captures from the pico, so demodulators can be developed without
+//! the hardware
+//!
+//! The pio bytecode is turned back into the pin levels at the system clock, a state machine cycle lasts the
+//! system clock cycles its divider gives it. The antenna pin switches the reflection of the carrier between
+//! +1 and -1, the I and Q pins of the single sideband program between the 4 states `(±1 ± j) / √2`, and the
+//! pins are low between the packets. The reflection is mixed with the offset of the carrier from the
+//! SDR's center frequency, low pass filtered and decimated to the SDR's sample rate, and white gaussian
+//! noise can be added. The carrier that reaches the SDR directly isn't modelled, only its reflection.
+//!
+//! The samples are complex64 like the ones `data_recording.py` saves, see [crate::npy].
+use num_complex::{Complex32, Complex64};
+use pico_qpsk_core::frame_gen::{get_seq_payload, random_payloads};
+use pico_qpsk_core::packet::FrameConstructionError;
+use pico_qpsk_core::pio_bytecode_decode::{pio_words_to_square_wave, quadrature_words_to_pins};
+use pico_qpsk_core::prng::SplitMix64;
+use pico_qpsk_core::schedule::Schedule;
+use pico_qpsk_core::session_config::{PayloadMode, SessionConfig};
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::{Phy, TransmitOption};
+use std::f64::consts::{PI, TAU};
+use std::fmt::{Display, Formatter};
+
+/// The firmware's MAX_PAYLOAD_SIZE
+const MAX_PAYLOAD_SIZE: usize = 1000;
+const MAX_FRAME_SIZE: usize = to_max_frame_size!(MAX_PAYLOAD_SIZE);
+
+/// Half the length of the low pass filter, in samples at the SDR's rate
+const HALF_WIDTH_SAMPLES: u64 = 16;
+
+/// The low pass filter is 6dB down at this fraction of the sample rate on either side of 0Hz
+const CUTOFF: f64 = 0.4;
+
+/// The most phases of the low pass filter, when the system clock isn't a whole number of samples the
+/// samples are put on the nearest 1/64th of a system clock cycle
+const MAX_PHASES: u64 = 64;
+
+/// The mixer's oscillator is worked out from scratch this often, in between it is rotated
+const OSCILLATOR_RESYNC: u64 = 4096;
+
+/// How the pico sends the packets
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transmitter {
+    pub sys_hz: u32,
+    /// the state machine's clock divider in 1/256 steps, see
+    /// [StateMachineClockDividerSetting::to_256ths](pico_qpsk_core::transmit_option::StateMachineClockDividerSetting::to_256ths)
+    pub divider_256ths: u32,
+    /// the I and Q pins of the single sideband program instead of the antenna pin alone
+    pub two_pins: bool,
+}
+
+impl Transmitter {
+    /// The clocks and pins `option` sends `phy` with
+    pub fn new(option: &TransmitOption, phy: Phy) -> Self {
+        Transmitter {
+            sys_hz: option.processor_clock().hz(),
+            divider_256ths: option.state_machine_clock_for(phy).to_256ths(),
+            two_pins: phy.two_pins(),
+        }
+    }
+
+    /// The power of one sideband of the subcarrier's fundamental, the one the I and Q pins keep
+    pub fn sideband_power(&self) -> f64 {
+        // a ±1 square wave has 2/π of each sideband, the Q pin a quarter period apart doubles one and
+        // cancels the other, over the √2 the states are scaled by
+        if self.two_pins {
+            8.0 / (PI * PI)
+        } else {
+            4.0 / (PI * PI)
+        }
+    }
+
+    /// The reflection while the pins are low
+    fn idle(&self) -> Complex64 {
+        if self.two_pins {
+            Complex64::new(-1.0, -1.0) / 2f64.sqrt()
+        } else {
+            Complex64::new(-1.0, 0.0)
+        }
+    }
+
+    /// The reflection of every state machine cycle of `words`
+    fn reflection<'a>(&self, words: &'a [u32]) -> Box<dyn Iterator<Item = Complex64> + 'a> {
+        let level = |high: bool| if high { 1.0 } else { -1.0 };
+        if self.two_pins {
+            Box::new(
+                quadrature_words_to_pins(words)
+                    .map(move |(i, q)| Complex64::new(level(i), level(q)) / 2f64.sqrt()),
+            )
+        } else {
+            // a Level::High drives the pin low
+            Box::new(pio_words_to_square_wave(words).map(move |high| Complex64::new(level(!high), 0.0)))
+        }
+    }
+}
+
+/// A packet of a capture
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    /// when the state machine starts on it, in microseconds from the start of the capture
+    pub start_us: u64,
+    /// the PHY frame
+    pub frame: Vec<u8>,
+    /// the pio bytecode of the frame
+    pub words: Vec<u32>,
+}
+
+impl Packet {
+    /// The packet `option` sends `frame` in with `phy`
+    pub fn new(start_us: u64, frame: &[u8], option: &TransmitOption, phy: Phy) -> Self {
+        Packet {
+            start_us,
+            frame: frame.to_vec(),
+            words: option.convert_phy(frame, phy).collect(),
+        }
+    }
+}
+
+/// The packets the firmware's `send` sends with the settings of `config`, the first one starts at
+/// `start_us`
+///
+/// The payloads, sequence numbers and start times are the ones the firmware uses, so a capture of a real
+/// run and a simulated one line up.
+pub fn session_packets(config: &SessionConfig, start_us: u64) -> Result<Vec<Packet>, FrameConstructionError> {
+    let phy = config.phy();
+    let send = config.send;
+    let (length, mut random) = match send.payload {
+        PayloadMode::Sequential { length } => (length, None),
+        PayloadMode::Random { length, seed } => (
+            length,
+            Some(random_payloads::<MAX_PAYLOAD_SIZE>(
+                seed,
+                usize::from(length).min(MAX_PAYLOAD_SIZE),
+            )),
+        ),
+    };
+    let sequential = get_seq_payload::<MAX_PAYLOAD_SIZE>(usize::from(length).min(MAX_PAYLOAD_SIZE));
+    Schedule::new(send.interval_ms, send.schedule)
+        .zip(0..send.number_packets)
+        .map(|(offset_us, packet_index)| {
+            let frame = match random.as_mut() {
+                Some(payloads) => config.frame.frame_bytes::<MAX_FRAME_SIZE>(
+                    packet_index,
+                    &payloads.next().expect("random payloads never end"),
+                ),
+                None => config
+                    .frame
+                    .frame_bytes::<MAX_FRAME_SIZE>(packet_index, &sequential),
+            }?;
+            Ok(Packet::new(
+                start_us + offset_us,
+                &frame,
+                &config.transmit_option,
+                phy,
+            ))
+        })
+        .collect()
+}
+
+/// How the SDR captures the packets
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Capture {
+    pub sample_rate_hz: u32,
+    /// the carrier's frequency minus the SDR's center frequency, a carrier at 2452MHz and the SDR at 2460MHz
+    /// is -8MHz, the upper sideband of an 8MHz offset is then on 0Hz
+    pub carrier_offset_hz: f64,
+    /// [Transmitter::sideband_power] over the noise power in the sample rate's bandwidth, in dB, `None`
+    /// leaves the noise out
+    pub snr_db: Option<f64>,
+    pub noise_seed: u64,
+}
+
+/// A type to hold the possible errors that occur when a capture is simulated
+#[derive(Debug, PartialEq)]
+pub enum SimulationError {
+    /// the sample rate is 0 or above the system clock
+    SampleRate(u32),
+    /// the packet, counting from 0, starts before the one before it is over
+    Overlap(usize),
+}
+
+impl Display for SimulationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationError::SampleRate(sample_rate_hz) => {
+                write!(
+                    f,
+                    "no {sample_rate_hz}Hz sample rate, it has to be up to the system clock"
+                )
+            }
+            SimulationError::Overlap(packet) => {
+                write!(f, "packet {packet} starts before the one before it is over")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+/// Simulate the first `len` samples of a capture of `packets`
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::transmit_option::{Phy, StandardTransmitOption, TransmitOption};
+/// use pico_qpsk_iq::simulator::{simulate, Capture, Packet, Transmitter};
+///
+/// let option = TransmitOption::Standard(StandardTransmitOption::Clk128MHzOffset8MHz);
+/// let phy = Phy::OQpsk(None);
+/// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+/// let capture = Capture {
+///     sample_rate_hz: 4_000_000,
+///     carrier_offset_hz: -8e6,
+///     snr_db: None,
+///     noise_seed: 0,
+/// };
+/// let packets = [Packet::new(100, &frame, &option, phy)];
+/// let samples = simulate(&Transmitter::new(&option, phy), &packets, &capture, 1200).unwrap();
+/// // the idle carrier is filtered out, the packet starts after 400 samples
+/// assert!(samples[..380].iter().all(|sample| sample.norm() < 0.01));
+/// assert!(samples[420..].iter().all(|sample| sample.norm() > 0.1));
+/// ```
+pub fn simulate(
+    transmitter: &Transmitter,
+    packets: &[Packet],
+    capture: &Capture,
+    len: usize,
+) -> Result<Vec<Complex32>, SimulationError> {
+    let sys_hz = u64::from(transmitter.sys_hz);
+    let sample_rate_hz = u64::from(capture.sample_rate_hz);
+    if sample_rate_hz == 0 || sample_rate_hz > sys_hz {
+        return Err(SimulationError::SampleRate(capture.sample_rate_hz));
+    }
+    let noise_power = capture
+        .snr_db
+        .map(|snr_db| transmitter.sideband_power() / 10f64.powf(snr_db / 10.0));
+    let mut baseband = Baseband {
+        resampler: Resampler::new(sys_hz, sample_rate_hz),
+        phase_step: capture.carrier_offset_hz / sys_hz as f64,
+        rotation: Complex64::from_polar(1.0, TAU * capture.carrier_offset_hz / sys_hz as f64),
+        oscillator: Complex64::new(1.0, 0.0),
+        cycle: 0,
+        noise: noise_power.map(|power| Noise {
+            rng: SplitMix64::new(capture.noise_seed),
+            sigma: (power / 2.0).sqrt(),
+        }),
+        samples: Vec::with_capacity(len),
+        len,
+    };
+
+    let divider = u64::from(transmitter.divider_256ths);
+    let idle = transmitter.idle();
+    let lead = baseband.resampler.half_width;
+    for (idx, packet) in packets.iter().enumerate() {
+        let start = lead + packet.start_us * sys_hz / 1_000_000;
+        let Some(lead) = start.checked_sub(baseband.cycle) else {
+            return Err(SimulationError::Overlap(idx));
+        };
+        if !baseband.push(idle, lead) {
+            return Ok(baseband.samples);
+        }
+        // a fractional divider gives the state machine cycles a whole number of system clock cycles each,
+        // on average the divider
+        for (sm_cycle, reflection) in (0u64..).zip(transmitter.reflection(&packet.words)) {
+            let cycles = (sm_cycle + 1) * divider / 256 - sm_cycle * divider / 256;
+            if !baseband.push(reflection, cycles) {
+                return Ok(baseband.samples);
+            }
+        }
+    }
+    while baseband.push(idle, u64::MAX) {}
+    Ok(baseband.samples)
+}
+
+/// The mixer, filter and noise between the reflection and the samples
+struct Baseband {
+    resampler: Resampler,
+    /// the oscillator's turns per system clock cycle
+    phase_step: f64,
+    rotation: Complex64,
+    oscillator: Complex64,
+    /// the system clock cycles pushed so far
+    cycle: u64,
+    noise: Option<Noise>,
+    samples: Vec<Complex32>,
+    len: usize,
+}
+
+impl Baseband {
+    /// Push `reflection` for `cycles` system clock cycles, false once all the samples are there
+    fn push(&mut self, reflection: Complex64, cycles: u64) -> bool {
+        for _ in 0..cycles {
+            if self.samples.len() == self.len {
+                return false;
+            }
+            if self.cycle.is_multiple_of(OSCILLATOR_RESYNC) {
+                let turns = (self.cycle as f64 * self.phase_step).fract();
+                self.oscillator = Complex64::from_polar(1.0, TAU * turns);
+            }
+            if let Some(mut sample) = self.resampler.push(reflection * self.oscillator) {
+                if let Some(noise) = self.noise.as_mut() {
+                    sample += noise.next();
+                }
+                self.samples
+                    .push(Complex32::new(sample.re as f32, sample.im as f32));
+            }
+            self.oscillator *= self.rotation;
+            self.cycle += 1;
+        }
+        self.samples.len() < self.len
+    }
+}
+
+/// Complex white gaussian noise
+struct Noise {
+    rng: SplitMix64,
+    /// the standard deviation of the real and the imaginary part
+    sigma: f64,
+}
+
+impl Noise {
+    /// A sample from the Box-Muller transform of two uniform numbers
+    fn next(&mut self) -> Complex64 {
+        // 53 random bits, the most an f64 holds exactly, as uniform numbers in (0, 1] and [0, 1)
+        let uniform = |rng: &mut SplitMix64| (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        let radius = (-2.0 * (1.0 - uniform(&mut self.rng)).ln()).sqrt();
+        Complex64::from_polar(self.sigma * radius, TAU * uniform(&mut self.rng))
+    }
+}
+
+/// A windowed sinc low pass filter that decimates from the system clock to the sample rate
+///
+/// Sample `n` is centred on system clock cycle `n * up / down`, when that isn't a whole cycle the filter of
+/// its phase is used. The first [Resampler::half_width] inputs are a lead in before cycle 0, so the
+/// filter of the first samples is already full.
+struct Resampler {
+    /// system clock cycles per sample is `up / down`, in lowest terms
+    up: u64,
+    down: u64,
+    /// half the taps of a phase
+    half_width: u64,
+    /// the taps of every phase, phase `p` of `phases` is centred `p / phases` of a cycle later than 0
+    phases: Vec<Vec<f64>>,
+    /// the last `2 * half_width` inputs, input `n` is at `n % (2 * half_width)`
+    history: Vec<Complex64>,
+    /// the inputs pushed so far
+    pushed: u64,
+    /// the next sample
+    sample: u64,
+}
+
+impl Resampler {
+    fn new(sys_hz: u64, sample_rate_hz: u64) -> Self {
+        let gcd = gcd(sys_hz, sample_rate_hz);
+        let (up, down) = (sys_hz / gcd, sample_rate_hz / gcd);
+        let half_width = (HALF_WIDTH_SAMPLES * up).div_ceil(down);
+        let phase_count = down.min(MAX_PHASES);
+        let phases = (0..phase_count)
+            .map(|phase| {
+                let delay = phase as f64 / phase_count as f64;
+                let taps: Vec<f64> = (0..2 * half_width)
+                    .map(|tap| {
+                        // the time from the centre, in samples
+                        let t = (tap as f64 + 1.0 - half_width as f64 - delay) * down as f64 / up as f64;
+                        sinc(2.0 * CUTOFF * t) * blackman(t / HALF_WIDTH_SAMPLES as f64)
+                    })
+                    .collect();
+                // a gain of 1 at 0Hz
+                let sum: f64 = taps.iter().sum();
+                taps.into_iter().map(|tap| tap / sum).collect()
+            })
+            .collect();
+        Resampler {
+            up,
+            down,
+            half_width,
+            phases,
+            history: vec![Complex64::new(0.0, 0.0); 2 * half_width as usize],
+            pushed: 0,
+            sample: 0,
+        }
+    }
+
+    /// Push the next input, returns the next sample once its last input is in
+    fn push(&mut self, input: Complex64) -> Option<Complex64> {
+        let len = self.history.len() as u64;
+        self.history[(self.pushed % len) as usize] = input;
+        self.pushed += 1;
+        // the lead in puts every sample half a filter later
+        let centre = self.sample * self.up / self.down + self.half_width;
+        if self.pushed - 1 < centre + self.half_width {
+            return None;
+        }
+        let phase = (self.sample * self.up % self.down) * self.phases.len() as u64 / self.down;
+        let first = centre + 1 - self.half_width;
+        let sample = self.phases[phase as usize]
+            .iter()
+            .zip(first..)
+            .map(|(tap, input)| self.history[(input % len) as usize] * tap)
+            .sum();
+        self.sample += 1;
+        Some(sample)
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The Blackman window over -1 to 1, 0 outside
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.42 + 0.5 * (PI * x).cos() + 0.08 * (TAU * x).cos()
+    }
+}
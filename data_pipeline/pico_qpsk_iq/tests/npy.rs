@@ -0,0 +1,91 @@
+//! The .npy files numpy writes for a capture and the ones it doesn't
+
+use num_complex::Complex32;
+use pico_qpsk_iq::npy::{read_npy, write_npy, NpyError};
+
+/// A .npy file with `header` padded the way numpy pads it, followed by `data`
+fn npy_file(header: &str, data: &[u8]) -> Vec<u8> {
+    let mut header = header.to_string();
+    while !(10 + header.len() + 1).is_multiple_of(64) {
+        header.push(' ');
+    }
+    header.push('\n');
+    let mut file = b"\x93NUMPY\x01\x00".to_vec();
+    file.extend((header.len() as u16).to_le_bytes());
+    file.extend(header.as_bytes());
+    file.extend(data);
+    file
+}
+
+#[test]
+fn written_files_are_aligned_like_numpy_writes_them() {
+    for len in [0, 1, 7, 1000, 123_456] {
+        let samples: Vec<Complex32> = (0..len)
+            .map(|idx| Complex32::new(idx as f32, -(idx as f32)))
+            .collect();
+        let mut file = Vec::new();
+        write_npy(&mut file, &samples).unwrap();
+        let header_len = file.len() - 8 * len;
+        assert_eq!(header_len % 64, 0, "{len} samples");
+        let header = std::str::from_utf8(&file[10..header_len]).unwrap();
+        assert_eq!(
+            header.trim_end(),
+            format!("{{'descr': '<c8', 'fortran_order': False, 'shape': (1, {len}), }}")
+        );
+        assert!(header.ends_with('\n'));
+        assert_eq!(read_npy(file.as_slice()).unwrap(), samples);
+    }
+}
+
+#[test]
+fn flat_and_complex128_arrays_are_read() {
+    let data: Vec<u8> = [0.5f32, -0.25, 1.0, 2.0]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    let flat = npy_file("{'descr': '<c8', 'fortran_order': False, 'shape': (2,), }", &data);
+    let expected = [Complex32::new(0.5, -0.25), Complex32::new(1.0, 2.0)];
+    assert_eq!(read_npy(flat.as_slice()).unwrap(), expected);
+
+    let data: Vec<u8> = [0.5f64, -0.25, 1.0, 2.0]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    let wide = npy_file(
+        "{'descr': '<c16', 'fortran_order': True, 'shape': (1, 2), }",
+        &data,
+    );
+    assert_eq!(read_npy(wide.as_slice()).unwrap(), expected);
+}
+
+#[test]
+fn other_files_are_rejected() {
+    let lfs_pointer = "version https://git-lfs.github.com/spec/v1\n\
+                       oid sha256:1ddecb3edceb4a520e8da68837b432c3d37c177f45bcf02be815d15d5fa86a11\n\
+                       size 160000128\n";
+    assert!(matches!(read_npy(lfs_pointer.as_bytes()), Err(NpyError::Magic)));
+
+    let real = npy_file(
+        "{'descr': '<f4', 'fortran_order': False, 'shape': (2,), }",
+        &[0; 8],
+    );
+    assert!(matches!(read_npy(real.as_slice()), Err(NpyError::Dtype(dtype)) if dtype == "<f4"));
+
+    let two_channels = npy_file(
+        "{'descr': '<c8', 'fortran_order': False, 'shape': (2, 1), }",
+        &[0; 16],
+    );
+    assert!(matches!(read_npy(two_channels.as_slice()), Err(NpyError::Shape(shape)) if shape == [2, 1]));
+
+    let short = npy_file(
+        "{'descr': '<c8', 'fortran_order': False, 'shape': (1, 3), }",
+        &[0; 16],
+    );
+    assert!(matches!(read_npy(short.as_slice()), Err(NpyError::Truncated)));
+
+    let no_shape = npy_file("{'descr': '<c8', 'fortran_order': False, }", &[]);
+    assert!(matches!(
+        read_npy(no_shape.as_slice()),
+        Err(NpyError::Header("shape"))
+    ));
+}
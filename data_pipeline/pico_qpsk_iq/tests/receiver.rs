@@ -0,0 +1,231 @@
+//! The frames the receiver decodes from simulated captures
+
+use num_complex::Complex32;
+use pico_qpsk_core::packet::fcs;
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::transmit_option::{Phy, StandardTransmitOption, TransmitOption};
+use pico_qpsk_iq::receiver::{ReceivedFrame, Receiver, ReceiverConfig, ReceiverError};
+use pico_qpsk_iq::simulator::{simulate, Capture, Packet, Transmitter};
+
+/// A PHY frame with a good FCS and `payload` after the sequence number `seq`
+fn frame(seq: u8, payload: &[u8]) -> Vec<u8> {
+    let mut mac = vec![0x41, 0x88, seq, 0x34, 0x12, 0xFF, 0xFF, 0x01, 0x00];
+    mac.extend(payload);
+    let footer = fcs(&mac).to_le_bytes();
+    mac.extend(footer);
+    let mut frame = vec![0x00, 0x00, 0x00, 0x00, 0xA7, mac.len() as u8];
+    frame.extend(mac);
+    frame
+}
+
+/// `frames` sent 2ms apart from 300us on
+fn capture(option: TransmitOption, phy: Phy, capture: &Capture, frames: &[Vec<u8>]) -> Vec<Complex32> {
+    let packets: Vec<Packet> = frames
+        .iter()
+        .enumerate()
+        .map(|(idx, frame)| Packet::new(300 + 2000 * idx as u64, frame, &option, phy))
+        .collect();
+    let len = (2 * frames.len() + 1) * capture.sample_rate_hz as usize / 1000;
+    simulate(&Transmitter::new(&option, phy), &packets, capture, len).unwrap()
+}
+
+fn check(frames: &[Vec<u8>], received: &[ReceivedFrame], sample_rate_hz: u32, snr_db: Option<f64>) {
+    assert_eq!(received.len(), frames.len(), "{received:?}");
+    for (idx, (frame, received)) in frames.iter().zip(received).enumerate() {
+        assert_eq!(&received.phy_bytes(), frame);
+        assert!(received.fcs_ok);
+        let expected_s = (300 + 2000 * idx) as f64 * 1e-6;
+        let time_s = received.time_s(sample_rate_hz);
+        assert!((time_s - expected_s).abs() < 1e-6, "{time_s} {expected_s}");
+        match snr_db {
+            Some(snr_db) => {
+                let received_db = received.snr_db.unwrap();
+                assert!((received_db - snr_db).abs() < 2.0, "{received_db}dB {snr_db}dB");
+            }
+            None => assert!(received.quality > 0.9, "{received:?}"),
+        }
+    }
+}
+
+#[test]
+fn frames_are_decoded_on_either_sideband() {
+    let frames = [frame(1, &[1, 2, 3, 4]), frame(2, &[0xAB; 40]), frame(3, &[])];
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset8MHz.into();
+    for (phy, sideband, sample_rate_hz) in [
+        (Phy::OQpsk(None), Sideband::Upper, 4_000_000),
+        (Phy::OQpsk(None), Sideband::Lower, 4_000_000),
+        (Phy::OQpsk(None), Sideband::Upper, 8_000_000),
+        (Phy::OQpsk(Some(Sideband::Upper)), Sideband::Upper, 4_000_000),
+        (Phy::OQpsk(Some(Sideband::Lower)), Sideband::Lower, 6_000_000),
+    ] {
+        // the carrier 8MHz below the SDR puts the upper sideband on 0Hz, 8MHz above the lower one
+        let carrier_offset_hz = match sideband {
+            Sideband::Upper => -8e6,
+            Sideband::Lower => 8e6,
+        };
+        let samples = capture(
+            option,
+            phy,
+            &Capture {
+                sample_rate_hz,
+                carrier_offset_hz,
+                snr_db: None,
+                noise_seed: 0,
+            },
+            &frames,
+        );
+        let receiver = Receiver::new(ReceiverConfig {
+            sample_rate_hz,
+            sideband,
+            ..ReceiverConfig::default()
+        })
+        .unwrap();
+        check(&frames, &receiver.receive(&samples), sample_rate_hz, None);
+    }
+}
+
+#[test]
+fn the_wrong_sideband_decodes_nothing() {
+    let frames = [frame(1, &[1, 2, 3, 4])];
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset8MHz.into();
+    let samples = capture(
+        option,
+        Phy::OQpsk(None),
+        &Capture {
+            sample_rate_hz: 4_000_000,
+            carrier_offset_hz: -8e6,
+            snr_db: None,
+            noise_seed: 0,
+        },
+        &frames,
+    );
+    let receiver = Receiver::new(ReceiverConfig {
+        sideband: Sideband::Lower,
+        ..ReceiverConfig::default()
+    })
+    .unwrap();
+    assert_eq!(receiver.receive(&samples), []);
+}
+
+#[test]
+fn noisy_frames_are_decoded_with_their_snr() {
+    let frames: Vec<Vec<u8>> = (0..5).map(|seq| frame(seq, &[seq; 20])).collect();
+    let option: TransmitOption = StandardTransmitOption::Clk144MHzOffset6MHz.into();
+    for (snr_db, sample_rate_hz) in [(10.0, 4_000_000), (3.0, 8_000_000)] {
+        let samples = capture(
+            option,
+            Phy::OQpsk(None),
+            &Capture {
+                sample_rate_hz,
+                carrier_offset_hz: -6e6 + 5e5,
+                snr_db: Some(snr_db),
+                noise_seed: 3,
+            },
+            &frames,
+        );
+        // the sideband is 500kHz above the SDR's center frequency
+        let receiver = Receiver::new(ReceiverConfig {
+            sample_rate_hz,
+            sideband_hz: 5e5,
+            ..ReceiverConfig::default()
+        })
+        .unwrap();
+        check(&frames, &receiver.receive(&samples), sample_rate_hz, Some(snr_db));
+    }
+}
+
+#[test]
+fn noise_alone_decodes_nothing() {
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset8MHz.into();
+    let noise = capture(
+        option,
+        Phy::OQpsk(None),
+        &Capture {
+            sample_rate_hz: 4_000_000,
+            carrier_offset_hz: -8e6,
+            snr_db: Some(0.0),
+            noise_seed: 1,
+        },
+        &[],
+    );
+    let receiver = Receiver::new(ReceiverConfig::default()).unwrap();
+    assert_eq!(receiver.receive(&noise), []);
+    assert_eq!(receiver.receive(&[]), []);
+}
+
+#[test]
+fn a_bad_fcs_is_reported() {
+    let mut bad = frame(7, &[1, 2, 3, 4]);
+    *bad.last_mut().unwrap() ^= 0x01;
+    let frames = [bad, frame(8, &[1, 2, 3, 4])];
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset8MHz.into();
+    let samples = capture(
+        option,
+        Phy::OQpsk(None),
+        &Capture {
+            sample_rate_hz: 4_000_000,
+            carrier_offset_hz: -8e6,
+            snr_db: Some(20.0),
+            noise_seed: 0,
+        },
+        &frames,
+    );
+    let received = Receiver::new(ReceiverConfig::default())
+        .unwrap()
+        .receive(&samples);
+    let fcs_ok: Vec<bool> = received.iter().map(|frame| frame.fcs_ok).collect();
+    assert_eq!(fcs_ok, [false, true]);
+    assert_eq!(received[0].phy_bytes(), frames[0]);
+}
+
+#[test]
+fn sample_rates_off_the_chip_rate_are_rejected() {
+    for sample_rate_hz in [0, 2_000_000, 5_000_000] {
+        assert_eq!(
+            Receiver::new(ReceiverConfig {
+                sample_rate_hz,
+                ..ReceiverConfig::default()
+            })
+            .err(),
+            Some(ReceiverError::SampleRate(sample_rate_hz))
+        );
+    }
+}
+
+#[test]
+fn frames_without_noise_before_them_have_no_snr() {
+    let frame = frame(9, &[1, 2, 3, 4]);
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset8MHz.into();
+    let transmitter = Transmitter::new(&option, Phy::OQpsk(None));
+    let capture = Capture {
+        sample_rate_hz: 4_000_000,
+        carrier_offset_hz: -8e6,
+        snr_db: Some(20.0),
+        noise_seed: 0,
+    };
+    let receiver = Receiver::new(ReceiverConfig::default()).unwrap();
+    // less than a symbol of 16us before the frame
+    for start_us in [0, 5, 15] {
+        let packets = [Packet::new(start_us, &frame, &option, Phy::OQpsk(None))];
+        let samples = simulate(&transmitter, &packets, &capture, 4000).unwrap();
+        let received = receiver.receive(&samples);
+        assert_eq!(received.len(), 1, "{start_us}us");
+        assert_eq!(received[0].phy_bytes(), frame);
+        assert!(received[0].fcs_ok);
+        assert!(received[0].start_sample.abs_diff(4 * start_us as usize) <= 4);
+        assert_eq!(received[0].snr_db, None, "{start_us}us");
+    }
+
+    // a stretch of nothing before the frame, like a capture padded with zeros
+    let packets = [Packet::new(0, &frame, &option, Phy::OQpsk(None))];
+    let noiseless = Capture {
+        snr_db: None,
+        ..capture
+    };
+    let mut samples = vec![Complex32::new(0.0, 0.0); 1000];
+    samples.extend(simulate(&transmitter, &packets, &noiseless, 4000).unwrap());
+    let received = receiver.receive(&samples);
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].phy_bytes(), frame);
+    assert_eq!(received[0].snr_db, None);
+}
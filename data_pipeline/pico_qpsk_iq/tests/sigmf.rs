@@ -0,0 +1,189 @@
+//! SigMF recordings written and read back, with the frames of the receiver and other tools' fields
+
+use num_complex::Complex32;
+use pico_qpsk_core::session_config::PayloadMode;
+use pico_qpsk_iq::receiver::ReceivedFrame;
+use pico_qpsk_iq::sigmf::{
+    load, paths, read_data, save, Annotation, Meta, Payload, SigmfError, Transmit, TransmitSideband,
+};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+fn received(start_sample: usize, psdu: &[u8], fcs_ok: bool) -> ReceivedFrame {
+    ReceivedFrame {
+        start_sample,
+        len: 1000,
+        psdu: psdu.to_vec(),
+        fcs_ok,
+        quality: 0.9,
+        snr_db: Some(12.5),
+    }
+}
+
+#[test]
+fn recordings_are_read_back_as_written() {
+    let samples: Vec<Complex32> = (0..1000)
+        .map(|idx| Complex32::new(idx as f32 * 0.5, -(idx as f32)))
+        .collect();
+    let mut meta = Meta::new(4_000_000, Some(2460e6));
+    meta.global.hw = Some("USRP B210, 50dB gain".to_string());
+    meta.global.transmit = Some(Transmit {
+        offset_hz: 8e6,
+        sideband: TransmitSideband::Usb,
+        interval_ms: 10,
+        number_packets: 100,
+        payload: PayloadMode::Random { length: 4, seed: 42 }.into(),
+    });
+    meta.annotate(&[received(10, &[0x41, 0x88], true)], 0.0);
+    let path = std::env::temp_dir().join(format!("pico_qpsk_iq_{}", std::process::id()));
+    save(&path, &meta, &samples).unwrap();
+    let (meta_path, data_path) = paths(&path);
+    assert_eq!(std::fs::metadata(&data_path).unwrap().len(), 8000);
+    assert_eq!(load(&meta_path).unwrap(), (meta.clone(), samples.clone()));
+    assert_eq!(load(&data_path).unwrap(), (meta, samples));
+    std::fs::remove_file(meta_path).unwrap();
+    std::fs::remove_file(data_path).unwrap();
+}
+
+#[test]
+fn the_data_file_is_next_to_the_meta_file() {
+    for path in ["rec", "rec.sigmf-meta", "rec.sigmf-data", "rec.sigmf"] {
+        assert_eq!(
+            paths(Path::new(path)),
+            (PathBuf::from("rec.sigmf-meta"), PathBuf::from("rec.sigmf-data")),
+            "{path}"
+        );
+    }
+    assert_eq!(
+        paths(Path::new("data/run.2")),
+        (
+            PathBuf::from("data/run.2.sigmf-meta"),
+            PathBuf::from("data/run.2.sigmf-data")
+        )
+    );
+}
+
+#[test]
+fn the_transmit_configuration_names_its_modes() {
+    let transmit = Transmit {
+        offset_hz: 4e6,
+        sideband: TransmitSideband::Dsb,
+        interval_ms: 5,
+        number_packets: 20,
+        payload: Payload::Sequential { length: 16 },
+    };
+    assert_eq!(
+        serde_json::to_value(transmit).unwrap(),
+        json!({
+            "offset_hz": 4e6,
+            "sideband": "dsb",
+            "interval_ms": 5,
+            "number_packets": 20,
+            "payload": { "mode": "sequential", "length": 16 },
+        })
+    );
+    let random: Payload =
+        serde_json::from_value(json!({ "mode": "random", "length": 8, "seed": 7 })).unwrap();
+    assert_eq!(
+        PayloadMode::from(random),
+        PayloadMode::Random { length: 8, seed: 7 }
+    );
+}
+
+#[test]
+fn annotating_keeps_other_annotations_and_fields() {
+    let mut meta: Meta = serde_json::from_value(json!({
+        "global": {
+            "core:datatype": "cf32_le",
+            "core:sample_rate": 8e6,
+            "core:version": "1.0.0",
+            "core:geolocation": { "type": "Point", "coordinates": [4.9, 52.4] },
+        },
+        "captures": [{ "core:sample_start": 0, "core:frequency": 2.46e9, "other:gain": 50 }],
+        "annotations": [
+            { "core:sample_start": 500, "core:label": "wifi" },
+            { "core:sample_start": 100, "pico_qpsk:psdu": "00", "pico_qpsk:fcs_ok": false },
+        ],
+    }))
+    .unwrap();
+    meta.annotate(
+        &[received(2000, &[0xAB, 0xCD], true), received(20, &[0x01], false)],
+        -2e5,
+    );
+    let starts: Vec<u64> = meta
+        .annotations
+        .iter()
+        .map(|annotation| annotation.sample_start)
+        .collect();
+    assert_eq!(starts, [20, 500, 2000]);
+    let frame = &meta.annotations[2];
+    assert_eq!(frame.psdu.as_deref(), Some("ABCD"));
+    assert_eq!(frame.fcs_ok, Some(true));
+    assert_eq!(frame.sample_count, Some(1000));
+    assert_eq!(frame.freq_lower_edge, Some(2.4588e9));
+    assert_eq!(frame.freq_upper_edge, Some(2.4608e9));
+    assert_eq!(meta.annotations[0].comment.as_deref(), Some("bad FCS"));
+    assert_eq!(meta.global.extensions[0].name, "pico_qpsk");
+
+    let written = serde_json::to_value(&meta).unwrap();
+    assert_eq!(
+        written["global"]["core:geolocation"]["coordinates"],
+        json!([4.9, 52.4])
+    );
+    assert_eq!(written["captures"][0]["other:gain"], 50);
+    assert_eq!(
+        written["annotations"][1],
+        json!({ "core:sample_start": 500, "core:label": "wifi" })
+    );
+    assert_eq!(serde_json::from_value::<Meta>(written).unwrap(), meta);
+
+    // without a center frequency the frames aren't placed in frequency
+    let unplaced = Annotation::frame(&received(0, &[], false), None);
+    assert_eq!((unplaced.freq_lower_edge, unplaced.freq_upper_edge), (None, None));
+}
+
+#[test]
+fn other_data_files_are_rejected() {
+    let wide: Vec<u8> = [0.5f64, -0.25].iter().flat_map(|x| x.to_le_bytes()).collect();
+    assert_eq!(
+        read_data(wide.as_slice(), "cf64_le").unwrap(),
+        [Complex32::new(0.5, -0.25)]
+    );
+    assert!(
+        matches!(read_data(&[0u8; 4][..], "ci16_le"), Err(SigmfError::Datatype(datatype)) if datatype == "ci16_le")
+    );
+    assert!(matches!(
+        read_data(&[0u8; 12][..], "cf32_le"),
+        Err(SigmfError::Truncated)
+    ));
+    let mut meta = Meta::new(4_000_000, None);
+    meta.global.datatype = "ci16_le".to_string();
+    assert!(matches!(
+        save(Path::new("unwritten"), &meta, &[]),
+        Err(SigmfError::WriteDatatype(_))
+    ));
+}
+
+#[test]
+fn cf64_recordings_are_annotated_and_written_back_as_cf32() {
+    let path = std::env::temp_dir().join(format!("pico_qpsk_iq_cf64_{}", std::process::id()));
+    let (meta_path, data_path) = paths(&path);
+    let mut meta = Meta::new(8_000_000, Some(2460e6));
+    meta.global.datatype = "cf64_le".to_string();
+    std::fs::write(&meta_path, serde_json::to_vec(&meta).unwrap()).unwrap();
+    let data: Vec<u8> = [0.5f64, -0.25, 1.0, 2.0]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    std::fs::write(&data_path, data).unwrap();
+
+    let (mut meta, samples) = load(&path).unwrap();
+    assert_eq!(meta.global.datatype, "cf32_le");
+    assert_eq!(samples, [Complex32::new(0.5, -0.25), Complex32::new(1.0, 2.0)]);
+    meta.annotate(&[received(1, &[0x41, 0x88], true)], 0.0);
+    save(&path, &meta, &samples).unwrap();
+    assert_eq!(std::fs::metadata(&data_path).unwrap().len(), 16);
+    assert_eq!(load(&path).unwrap(), (meta, samples));
+    std::fs::remove_file(meta_path).unwrap();
+    std::fs::remove_file(data_path).unwrap();
+}
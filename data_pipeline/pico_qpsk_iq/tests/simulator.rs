@@ -0,0 +1,160 @@
+//! Where the simulated packets land in frequency and time, the noise and the packets of a session
+
+use num_complex::Complex32;
+use pico_qpsk_core::frame_gen::random_payloads;
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::schedule::ScheduleMode;
+use pico_qpsk_core::session_config::{PayloadMode, SequenceNumberMode, SessionConfig};
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::{Phy, StandardTransmitOption, TransmitOption};
+use pico_qpsk_iq::simulator::{session_packets, simulate, Capture, Packet, SimulationError, Transmitter};
+use std::f64::consts::TAU;
+
+const FRAME: [u8; 12] = [
+    0x00, 0x00, 0x00, 0x00, 0xA7, 0x07, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+];
+
+fn capture(sample_rate_hz: u32, carrier_offset_hz: f64) -> Capture {
+    Capture {
+        sample_rate_hz,
+        carrier_offset_hz,
+        snr_db: None,
+        noise_seed: 0,
+    }
+}
+
+/// The samples of `FRAME` sent 50us into the capture, only the ones well inside the packet
+fn packet_samples(option: TransmitOption, phy: Phy, capture: &Capture) -> Vec<Complex32> {
+    let packets = [Packet::new(50, &FRAME, &option, phy)];
+    let per_us = capture.sample_rate_hz as usize / 1_000_000;
+    // 24 symbols of 16us
+    let samples = simulate(&Transmitter::new(&option, phy), &packets, capture, 500 * per_us).unwrap();
+    samples[60 * per_us..400 * per_us].to_vec()
+}
+
+fn power(samples: &[Complex32]) -> f64 {
+    samples
+        .iter()
+        .map(|sample| f64::from(sample.norm_sqr()))
+        .sum::<f64>()
+        / samples.len() as f64
+}
+
+/// The power weighted mean frequency, from the phase steps between samples
+fn mean_frequency(samples: &[Complex32], sample_rate_hz: u32) -> f64 {
+    let step: Complex32 = samples.windows(2).map(|pair| pair[1] * pair[0].conj()).sum();
+    f64::from(step.arg()) / TAU * f64::from(sample_rate_hz)
+}
+
+#[test]
+fn the_sidebands_land_around_the_carrier_offset() {
+    let options = [
+        (StandardTransmitOption::Clk128MHzOffset8MHz.into(), 4_000_000),
+        (StandardTransmitOption::Clk144MHzOffset6MHz.into(), 8_000_000),
+        // 128MHz isn't a whole number of 6MHz samples
+        (StandardTransmitOption::Clk128MHzOffset4MHz.into(), 6_000_000),
+        (TransmitOption::for_offset(3_000_000).unwrap(), 4_000_000),
+    ];
+    for (option, sample_rate_hz) in options {
+        let offset_hz = option.offset_millihertz() as f64 / 1e3;
+        for (carrier_offset_hz, expected) in [(-offset_hz + 5e5, 5e5), (offset_hz - 3e5, -3e5)] {
+            let capture = capture(sample_rate_hz, carrier_offset_hz);
+            let samples = packet_samples(option, Phy::OQpsk(None), &capture);
+            let frequency = mean_frequency(&samples, sample_rate_hz);
+            assert!(
+                (frequency - expected).abs() < 1e5,
+                "{option:?} at {sample_rate_hz}Hz: {frequency}Hz, expected {expected}Hz"
+            );
+            // the other sideband is at least 3MHz away, outside the filter
+            let expected_power = Transmitter::new(&option, Phy::OQpsk(None)).sideband_power();
+            assert!((power(&samples) / expected_power - 1.0).abs() < 0.2, "{option:?}");
+        }
+    }
+}
+
+#[test]
+fn a_single_sideband_leaves_out_the_image() {
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset8MHz.into();
+    // the upper sideband on 0Hz
+    let capture = capture(4_000_000, -8e6);
+    let upper = power(&packet_samples(
+        option,
+        Phy::OQpsk(Some(Sideband::Upper)),
+        &capture,
+    ));
+    let lower = power(&packet_samples(
+        option,
+        Phy::OQpsk(Some(Sideband::Lower)),
+        &capture,
+    ));
+    let double = power(&packet_samples(option, Phy::OQpsk(None), &capture));
+    assert!(upper > 1.5 * double, "{upper} {double}");
+    assert!(upper > 20.0 * lower, "{upper} {lower}");
+}
+
+#[test]
+fn the_noise_has_the_power_of_the_snr() {
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset8MHz.into();
+    let transmitter = Transmitter::new(&option, Phy::OQpsk(None));
+    let noisy = Capture {
+        snr_db: Some(10.0),
+        noise_seed: 7,
+        ..capture(8_000_000, -8e6)
+    };
+    // the idle pin is a tone at -8MHz, it doesn't get through the filter
+    let noise = simulate(&transmitter, &[], &noisy, 20_000).unwrap();
+    let expected = transmitter.sideband_power() / 10.0;
+    assert!((power(&noise) / expected - 1.0).abs() < 0.05);
+    assert_eq!(simulate(&transmitter, &[], &noisy, 20_000).unwrap(), noise);
+    let other_seed = Capture {
+        noise_seed: 8,
+        ..noisy
+    };
+    assert_ne!(simulate(&transmitter, &[], &other_seed, 20_000).unwrap(), noise);
+}
+
+#[test]
+fn bad_captures_are_rejected() {
+    let option: TransmitOption = StandardTransmitOption::Clk128MHzOffset2MHz.into();
+    let transmitter = Transmitter::new(&option, Phy::OQpsk(None));
+    for sample_rate_hz in [0, 200_000_000] {
+        assert_eq!(
+            simulate(&transmitter, &[], &capture(sample_rate_hz, 0.0), 10),
+            Err(SimulationError::SampleRate(sample_rate_hz))
+        );
+    }
+    // the frame lasts 384us
+    let packets = [
+        Packet::new(0, &FRAME, &option, Phy::OQpsk(None)),
+        Packet::new(300, &FRAME, &option, Phy::OQpsk(None)),
+    ];
+    assert_eq!(
+        simulate(&transmitter, &packets, &capture(4_000_000, 0.0), 4000),
+        Err(SimulationError::Overlap(1))
+    );
+}
+
+#[test]
+fn session_packets_are_the_ones_the_firmware_sends() {
+    let mut config = SessionConfig::default();
+    config.frame.sequence_number = SequenceNumberMode::Increment { start: 10 };
+    config.send.number_packets = 5;
+    config.send.interval_ms = 2;
+    config.send.payload = PayloadMode::Random { length: 6, seed: 42 };
+    config.send.schedule = ScheduleMode::Burst { length: 2, gap_ms: 7 };
+    let packets = session_packets(&config, 100).unwrap();
+    let starts: Vec<u64> = packets.iter().map(|packet| packet.start_us).collect();
+    assert_eq!(starts, [100, 2100, 9100, 11_100, 18_100]);
+    for ((idx, packet), payload) in packets.iter().enumerate().zip(random_payloads::<6>(42, 6)) {
+        let frame = config
+            .frame
+            .frame_bytes::<{ to_max_frame_size!(6) }>(idx as u32, &payload)
+            .unwrap();
+        assert_eq!(packet.frame, frame.as_slice());
+        assert_eq!(packet.frame[8], 10 + idx as u8);
+        assert_eq!(
+            packet,
+            &Packet::new(packet.start_us, &frame, &config.transmit_option, config.phy())
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! The firmware side of the binary protocol in [pico_qpsk_core::protocol]
+//!
+//! [executor](crate::serial_executor::executor) hands the port over to [run] when the first byte it
+//! receives is 0x00, the session lasts until the host closes the port.
+use crate::packet_timer::PacketTimer;
+use crate::pio_dma::PioDma;
+use crate::pio_helpers::PioControl;
+use crate::serial_executor::{apply_session, send_packets, MAX_FRAME_SIZE, MAX_PAYLOAD_SIZE};
+use crate::sys_clock::SysClock;
+use crate::usb_serial::USBSerial;
+use core::cell::Cell;
+use defmt::{info, warn};
+use heapless::Vec;
+use pico_qpsk_core::frame_gen::{get_seq_payload, random_payloads, RandomPayloads};
+use pico_qpsk_core::protocol::{
+    decode_frame, encode_message, max_encoded_size, message_size, ErrorCode, ProtocolError, Request,
+    Response, Status, TransmitSource,
+};
+use pico_qpsk_core::schedule::Schedule;
+use pico_qpsk_core::session_config::{PayloadMode, SessionConfig};
+use rp_pico::hal::gpio::PullDown;
+use rp_pico::pac::PIO0;
+
+/// A transmit request with a raw frame of [MAX_FRAME_SIZE] bytes is the longest request
+const MAX_ENCODED_REQUEST: usize = max_encoded_size(message_size(MAX_FRAME_SIZE + 9));
+/// A packet sent response with a frame of [MAX_FRAME_SIZE] bytes is the longest response
+const MAX_RESPONSE_SIZE: usize = message_size(MAX_FRAME_SIZE + 4);
+const MAX_ENCODED_RESPONSE: usize = max_encoded_size(MAX_RESPONSE_SIZE);
+
+/// Splits the received bytes into messages
+struct MessageReader {
+    /// the COBS bytes of the message being received
+    frame: Vec<u8, MAX_ENCODED_REQUEST>,
+    /// the message was longer than `frame`, the rest of it is dropped
+    overflowed: bool,
+    /// `frame` holds the last message returned, it is cleared on the next read
+    complete: bool,
+}
+
+impl MessageReader {
+    fn new() -> Self {
+        MessageReader {
+            frame: Vec::new(),
+            overflowed: false,
+            complete: false,
+        }
+    }
+
+    /// The next complete message, `None` until the delimiter after it was received
+    ///
+    /// returns: (request ID, the request or why it could not be read), the request ID is 0 when the
+    /// message is too damaged to read it
+    fn next_message(&mut self, serial: &mut USBSerial) -> Option<(u16, Result<Request<'_>, ProtocolError>)> {
+        if self.complete {
+            self.frame.clear();
+            self.overflowed = false;
+            self.complete = false;
+        }
+        loop {
+            let byte = serial.read_byte()?;
+            if byte != 0x00 {
+                if self.frame.push(byte).is_err() {
+                    self.overflowed = true;
+                }
+                continue;
+            }
+            // the delimiter in front of a message, or between two messages
+            if !self.frame.is_empty() || self.overflowed {
+                break;
+            }
+        }
+        self.complete = true;
+
+        if self.overflowed {
+            return Some((0, Err(ProtocolError::TooLong)));
+        }
+        match decode_frame(&mut self.frame) {
+            Ok(message) => Some((message.request_id, message.read_body())),
+            Err(error) => Some((0, Err(error))),
+        }
+    }
+}
+
+fn respond(serial: &mut USBSerial, request_id: u16, response: Response) {
+    let mut out = [0u8; MAX_ENCODED_RESPONSE];
+    let len = encode_message::<_, MAX_RESPONSE_SIZE>(request_id, response, &mut out)
+        .expect("responses fit in MAX_RESPONSE_SIZE");
+    serial.write_bytes(&out[..len]).expect("write error:respond");
+}
+
+/// The packets of a transmit request, copied out of the request so the next requests can be read while
+/// transmitting
+enum Packets {
+    /// the same payload in every packet
+    Payload(Vec<u8, MAX_PAYLOAD_SIZE>),
+    Random(RandomPayloads<MAX_PAYLOAD_SIZE>),
+    RawFrame(Vec<u8, MAX_FRAME_SIZE>),
+}
+
+impl Packets {
+    fn new(source: TransmitSource, session: &SessionConfig) -> Result<Self, ErrorCode> {
+        match source {
+            TransmitSource::Defaults => Ok(match session.send.payload {
+                // a saved config is checked against the MAX_PAYLOAD_SIZE of the firmware that saved it
+                PayloadMode::Sequential { length } => {
+                    Packets::Payload(get_seq_payload(usize::from(length).min(MAX_PAYLOAD_SIZE)))
+                }
+                PayloadMode::Random { length, seed } => {
+                    Packets::Random(random_payloads(seed, usize::from(length).min(MAX_PAYLOAD_SIZE)))
+                }
+            }),
+            TransmitSource::Payload(payload) => Vec::from_slice(payload)
+                .map(Packets::Payload)
+                .map_err(|_| ErrorCode::FrameConstruction),
+            TransmitSource::RawFrame(frame) => Vec::from_slice(frame)
+                .map(Packets::RawFrame)
+                .map_err(|_| ErrorCode::PacketTooLong),
+        }
+    }
+}
+
+/// Answer requests until the host closes the port
+pub fn run(
+    serial: &mut USBSerial,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    sys_clock: &mut SysClock,
+    session: &mut SessionConfig,
+) {
+    info!("binary protocol session started");
+    let mut reader = MessageReader::new();
+    // packets sent and asked for by the last transmit request
+    let mut last_transmit = (0, 0);
+    loop {
+        let Some((request_id, request)) = reader.next_message(serial) else {
+            if !serial.dtr() {
+                info!("binary protocol session ended");
+                return;
+            }
+            continue;
+        };
+        match request {
+            Ok(Request::Configure(config)) => {
+                match apply_session(sys_clock, pio_ctrl, pio_dma.extras(), session, config) {
+                    Ok(failed) => {
+                        for sm in failed {
+                            warn!(
+                                "extra output {} can't send its offset at {}Hz",
+                                sm,
+                                sys_clock.clock().hz()
+                            );
+                        }
+                        respond(serial, request_id, Response::Ok);
+                    }
+                    Err(error) => {
+                        warn!("configuration rejected: {}", error);
+                        respond(serial, request_id, Response::Error(ErrorCode::BadBody));
+                    }
+                }
+            }
+            Ok(Request::Transmit {
+                interval_ms,
+                number_packets,
+                source,
+            }) => match Packets::new(source, session) {
+                Ok(packets) => {
+                    let packets_sent = transmit(
+                        serial,
+                        timer,
+                        pio_dma,
+                        pio_ctrl,
+                        &mut reader,
+                        session,
+                        request_id,
+                        interval_ms,
+                        number_packets,
+                        packets,
+                    );
+                    last_transmit = (packets_sent, number_packets);
+                }
+                Err(code) => respond(serial, request_id, Response::Error(code)),
+            },
+            Ok(Request::Status) => {
+                let (packets_sent, number_packets) = last_transmit;
+                let status = Status {
+                    transmitting: false,
+                    packets_sent,
+                    number_packets,
+                    config: *session,
+                };
+                respond(serial, request_id, Response::Status(status));
+            }
+            Ok(Request::Abort) => respond(serial, request_id, Response::Error(ErrorCode::NotTransmitting)),
+            Err(error) => {
+                warn!("bad request {}: {}", request_id, error);
+                respond(serial, request_id, Response::Error(error.error_code()));
+            }
+        }
+    }
+}
+
+/// Send the packets of a transmit request, the requests received meanwhile are answered in between packets
+///
+/// returns: the number of packets sent
+#[allow(clippy::too_many_arguments)]
+fn transmit(
+    serial: &mut USBSerial,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    reader: &mut MessageReader,
+    session: &SessionConfig,
+    request_id: u16,
+    interval_ms: u32,
+    number_packets: u32,
+    mut packets: Packets,
+) -> u32 {
+    let packets_sent = Cell::new(0);
+    // the transmit request was already answered with an error, it doesn't get a done
+    let failed = Cell::new(false);
+    let mut frame: Vec<u8, MAX_FRAME_SIZE> = Vec::new();
+
+    send_packets(
+        serial,
+        timer,
+        Schedule::new(interval_ms, session.send.schedule),
+        number_packets,
+        pio_dma,
+        pio_ctrl,
+        &mut |serial, packet_number, pio_dma| {
+            let next_frame = match &mut packets {
+                Packets::Payload(payload) => session.frame.frame_bytes(packet_number - 1, payload),
+                Packets::Random(payloads) => session.frame.frame_bytes(
+                    packet_number - 1,
+                    &payloads.next().expect("random payloads never end"),
+                ),
+                Packets::RawFrame(raw_frame) => Ok(raw_frame.clone()),
+            };
+            let Ok(next_frame) = next_frame else {
+                respond(serial, request_id, Response::Error(ErrorCode::FrameConstruction));
+                failed.set(true);
+                return false;
+            };
+            // the bytecode is only rebuilt when the frame changes
+            if !pio_dma.is_loaded() || next_frame != frame {
+                frame = next_frame;
+                if !pio_dma
+                    .load(session.transmit_option, session.phy(), &frame)
+                    .is_empty()
+                {
+                    respond(serial, request_id, Response::Error(ErrorCode::ExtraOutputTooLong));
+                    failed.set(true);
+                    return false;
+                }
+            }
+            respond(
+                serial,
+                request_id,
+                Response::PacketSent {
+                    packet_number,
+                    frame: &frame,
+                },
+            );
+            true
+        },
+        &mut |_, packet_number| {
+            info!("sending packet {}/{} ", packet_number, number_packets);
+            packets_sent.set(packet_number);
+        },
+        &mut |serial, packet_number| {
+            warn!("packet {} was cut short", packet_number);
+            respond(serial, request_id, Response::Error(ErrorCode::Underrun));
+            failed.set(true);
+        },
+        &mut |serial, packets_sent, period| {
+            if !failed.get() {
+                let done = Response::Done {
+                    packets_sent,
+                    aborted: true,
+                    period: *period,
+                };
+                respond(serial, request_id, done);
+            }
+        },
+        &mut |serial, period| {
+            info!("stopped sending packets");
+            let done = Response::Done {
+                packets_sent: number_packets,
+                aborted: false,
+                period: *period,
+            };
+            respond(serial, request_id, done);
+        },
+        &mut |serial| {
+            let Some((other_request_id, request)) = reader.next_message(serial) else {
+                return false;
+            };
+            match request {
+                Ok(Request::Abort) => {
+                    respond(serial, other_request_id, Response::Ok);
+                    true
+                }
+                Ok(Request::Status) => {
+                    let status = Status {
+                        transmitting: true,
+                        packets_sent: packets_sent.get(),
+                        number_packets,
+                        config: *session,
+                    };
+                    respond(serial, other_request_id, Response::Status(status));
+                    false
+                }
+                Ok(_) => {
+                    respond(serial, other_request_id, Response::Error(ErrorCode::Busy));
+                    false
+                }
+                Err(error) => {
+                    respond(serial, other_request_id, Response::Error(error.error_code()));
+                    false
+                }
+            }
+        },
+    );
+    packets_sent.get()
+}
@@ -1,14 +1,18 @@
+use crate::sys_clock::SysClock;
 use crate::usb_serial::init_usb_bus;
-use cortex_m::delay::Delay;
 use defmt::info;
+use pico_qpsk_core::session_config::GPIO_COUNT;
+use pico_qpsk_core::transmit_option::ProcessorClock;
 use rp_pico as bsp;
 use rp_pico::hal::clocks::ClocksManager;
 use rp_pico::hal::fugit::RateExtU32;
-use rp_pico::hal::pll::PLLConfig;
+use rp_pico::hal::gpio::{DynPinId, FunctionNull, Pin, Pins, PullDown};
+use rp_pico::hal::pll::{Locked, PLLConfig, PhaseLockedLoop};
+use rp_pico::hal::sio::SioFifo;
 use rp_pico::hal::usb::UsbBus;
-use rp_pico::hal::{Clock, Sio, Watchdog};
-use rp_pico::pac::{Peripherals, PIO0, RESETS};
-use rp_pico::{pac, Pins};
+use rp_pico::hal::{Sio, Timer, Watchdog};
+use rp_pico::pac;
+use rp_pico::pac::{Peripherals, DMA, PIO0, PLL_SYS, PPB, PSM, RESETS};
 use usb_device::bus::UsbBusAllocator;
 
 /// Sets the system clock to 128MHz
@@ -22,7 +26,7 @@ use usb_device::bus::UsbBusAllocator;
 /// * `pac_pll_usb`: pac::Peripherals arg for init
 /// * `pac_resets`: pac::Peripherals arg for init
 ///
-/// returns: ClocksManager
+/// returns: (ClocksManager, the system PLL)
 ///
 /// # Examples
 ///
@@ -44,7 +48,7 @@ fn setup_clocks(
     pac_xosc_dev: pac::XOSC,
     pac_pll_usb: pac::PLL_USB,
     pac_resets: &mut RESETS,
-) -> ClocksManager {
+) -> (ClocksManager, PhaseLockedLoop<Locked, PLL_SYS>) {
     info!("Setting up clocks...");
 
     // set up custom clock frequency of 128MHz
@@ -121,7 +125,41 @@ fn setup_clocks(
     info!("Clocks OK");
 
     // ^^^ from NCC ^^^
-    clocks
+    (clocks, pll_sys)
+}
+
+/// A GPIO the PIO can take, whatever its number
+pub type GpioPin = Pin<DynPinId, FunctionNull, PullDown>;
+
+/// Every GPIO by its number, so the pins the PIO uses can come from the saved
+/// [PinConfig](pico_qpsk_core::session_config::PinConfig)
+pub struct GpioBank([Option<GpioPin>; GPIO_COUNT as usize]);
+
+impl GpioBank {
+    fn new(pins: Pins) -> Self {
+        macro_rules! dyn_pins {
+            ($($gpio:ident),*) => {
+                [$(Some(pins.$gpio.into_dyn_pin())),*]
+            };
+        }
+        GpioBank(dyn_pins!(
+            gpio0, gpio1, gpio2, gpio3, gpio4, gpio5, gpio6, gpio7, gpio8, gpio9, gpio10, gpio11, gpio12,
+            gpio13, gpio14, gpio15, gpio16, gpio17, gpio18, gpio19, gpio20, gpio21, gpio22, gpio23, gpio24,
+            gpio25, gpio26, gpio27, gpio28, gpio29
+        ))
+    }
+
+    /// Take GPIO`gpio` out of the bank
+    ///
+    /// # Panics
+    ///
+    /// when there is no such GPIO or it was taken before, a validated `PinConfig` rules both out
+    pub fn take(&mut self, gpio: u8) -> GpioPin {
+        self.0
+            .get_mut(usize::from(gpio))
+            .and_then(Option::take)
+            .expect("a validated PinConfig uses a pin once")
+    }
 }
 
 ///
@@ -131,53 +169,61 @@ fn setup_clocks(
 /// * `pac_resets`: pac::Peripherals arg for init
 /// * `io_bank0`: pac::Peripherals arg for init
 /// * `pads_bank0`: pac::Peripherals arg for init
-/// * `system_clock_freq_hz`: the clock frequency you set up
 /// * `pac_sio`:
 ///
-/// returns: (Pins, Delay)
+/// returns: ([GpioBank], the inter-core FIFO)
 ///
 /// # Examples
 ///
 /// ```
-///  let (pins, mut delay) = setup_pins_delay(
+///  let (pins, fifo) = setup_pins(
 ///         &mut pp.RESETS,
 ///         pp.IO_BANK0,
 ///         pp.PADS_BANK0,
-///         clocks.system_clock.freq().to_Hz(),
 ///         pp.SIO,
 ///     );
 ///
 ///
 /// ```
-fn setup_pins_delay(
+fn setup_pins(
     pac_resets: &mut RESETS,
     io_bank0: pac::IO_BANK0,
     pads_bank0: pac::PADS_BANK0,
-    system_clock_freq_hz: u32,
     pac_sio: pac::SIO,
-) -> (Pins, Delay) {
-    info!("setting up pins and delay...");
-    // let mut pp = pac::Peripherals::take().unwrap();
-    let pc = pac::CorePeripherals::take().unwrap();
-
+) -> (GpioBank, SioFifo) {
+    info!("setting up pins...");
     let sio = Sio::new(pac_sio);
 
-    let delay = Delay::new(pc.SYST, system_clock_freq_hz);
-
     let pins = Pins::new(io_bank0, pads_bank0, sio.gpio_bank0, pac_resets);
-    info!("pins and delay OK");
-    (pins, delay)
+    info!("pins OK");
+    (GpioBank::new(pins), sio.fifo)
+}
+
+/// What starting core1 takes, see [crate::packet_generator::spawn]
+pub struct Core1Peripherals {
+    pub psm: PSM,
+    pub ppb: PPB,
+    /// core0's end of the inter-core FIFO
+    pub fifo: SioFifo,
 }
 
 pub fn setup(
-    processor_clk_config: ProcessorClockConfig,
-) -> (Pins, Delay, RESETS, UsbBusAllocator<UsbBus>, PIO0) {
+    processor_clock: ProcessorClock,
+) -> (
+    GpioBank,
+    Timer,
+    SysClock,
+    UsbBusAllocator<UsbBus>,
+    PIO0,
+    DMA,
+    Core1Peripherals,
+) {
     // get the hardware peripherals
     let mut pp = Peripherals::take().unwrap();
 
     // set up the correct clock speed (128MHz)
-    let clocks = setup_clocks(
-        processor_clk_config.pll(),
+    let (clocks, pll_sys) = setup_clocks(
+        ProcessorClockConfig::from(processor_clock).pll(),
         pp.WATCHDOG,
         pp.PLL_SYS,
         pp.CLOCKS,
@@ -186,14 +232,11 @@ pub fn setup(
         &mut pp.RESETS,
     );
 
-    // set up GPIO and Delay function
-    let (pins, delay) = setup_pins_delay(
-        &mut pp.RESETS,
-        pp.IO_BANK0,
-        pp.PADS_BANK0,
-        clocks.system_clock.freq().to_Hz(),
-        pp.SIO,
-    );
+    // set up GPIO
+    let (pins, fifo) = setup_pins(&mut pp.RESETS, pp.IO_BANK0, pp.PADS_BANK0, pp.SIO);
+
+    // the 1MHz timer the packets are scheduled with, it runs from the watchdog tick set up with the clocks
+    let timer = Timer::new(pp.TIMER, &mut pp.RESETS, &clocks);
 
     let bus = init_usb_bus(
         pp.USBCTRL_REGS,
@@ -202,16 +245,40 @@ pub fn setup(
         &mut pp.RESETS,
     );
 
-    (pins, delay, pp.RESETS, bus, pp.PIO0)
+    let core1 = Core1Peripherals {
+        psm: pp.PSM,
+        ppb: pp.PPB,
+        fifo,
+    };
+
+    // the system clock can change later on, see [crate::sys_clock]
+    let sys_clock = SysClock::new(clocks.system_clock, pll_sys, pp.RESETS, processor_clock);
+
+    (pins, timer, sys_clock, bus, pp.PIO0, pp.DMA, core1)
 }
 
 pub enum ProcessorClockConfig {
-    #[allow(dead_code)]
     Custom(PLLConfig),
     F128MHz,
     F144MHz,
 }
 
+impl From<ProcessorClock> for ProcessorClockConfig {
+    fn from(value: ProcessorClock) -> Self {
+        match value {
+            ProcessorClock::F128MHz => ProcessorClockConfig::F128MHz,
+            ProcessorClock::F144MHz => ProcessorClockConfig::F144MHz,
+            // a plan from pico_qpsk_core::offset_planner, the planner only gives valid settings
+            ProcessorClock::Custom(pll) => ProcessorClockConfig::Custom(PLLConfig {
+                vco_freq: pll.vco_hz().Hz(),
+                refdiv: pll.refdiv,
+                post_div1: pll.post_div1,
+                post_div2: pll.post_div2,
+            }),
+        }
+    }
+}
+
 impl ProcessorClockConfig {
     pub fn pll(self) -> PLLConfig {
         match self {
@@ -0,0 +1,367 @@
+//! Extra backscatter outputs on SM1 to SM3, more tags or antennas from one pico
+//!
+//! Every extra output runs the single pin program on its own pin (see
+//! [crate::pio_helpers::initialize_pio]) at its own offset and sends its own frame or the one
+//! SM0 sends. The outputs share the system clock with SM0, so an offset only gets a PIO divider
+//! ([TransmitOption::for_offset_on_clock]) and most offsets only work at one of the system clocks.
+//! The frames are short enough to generate on core0 into one buffer per output before the packet, a DMA
+//! channel per output copies it into the output's FIFO. All state machines wait for the trigger pin, they
+//! start in the same cycle. The outputs without a pin in the
+//! [PinConfig](pico_qpsk_core::session_config::PinConfig) aren't there.
+use crate::packet_generator::take_once;
+use crate::pio_helpers::{ExtraTx, PioControl};
+use crate::serial_executor::MAX_FRAME_SIZE;
+use crate::usb_serial::USBSerial;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::AtomicBool;
+use defmt::{warn, Format};
+use heapless::Vec;
+use pico_qpsk_core::offset_planner::PlanError;
+use pico_qpsk_core::transmit_option::{ProcessorClock, TransmitOption};
+use rp_pico::hal::dma::{single_buffer, Channel, ChannelIndex, CH2, CH3, CH4};
+use rp_pico::hal::gpio::PullDown;
+use rp_pico::hal::pio::{StateMachineIndex, Tx, SM1, SM2, SM3};
+use rp_pico::pac::PIO0;
+
+pub use pico_qpsk_core::session_config::EXTRA_OUTPUTS;
+/// u32 words of bytecode an extra output has room for, a 60 byte frame at 144MHz/6MHz
+const EXTRA_OUTPUT_WORDS: usize = 4096;
+
+/// The bytecode of every output's packet, generated whole before the packet as there is only one DMA
+/// channel per output and no ring to stream it through like SM0's. 3 x 16K is 48K of the 256K of RAM in
+/// memory.x, with SM0's 32K ring and core1's 8K stack that still leaves core0 168K
+static mut WORDS: [[u32; EXTRA_OUTPUT_WORDS]; EXTRA_OUTPUTS] = [[0; EXTRA_OUTPUT_WORDS]; EXTRA_OUTPUTS];
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// What an extra output sends
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtraOutputConfig {
+    pub offset_hz: u32,
+    /// `None` sends the frame SM0 sends
+    pub frame: Option<Vec<u8, MAX_FRAME_SIZE>>,
+}
+
+/// A type to hold the possible errors that occur when an extra output is set up
+#[derive(Debug, Format, PartialEq)]
+pub enum ExtraOutputError {
+    /// the state machine isn't 1, 2 or 3 or it has no pin
+    NoSuchOutput,
+    /// the offset can't be sent at the system clock SM0 runs at
+    Plan(PlanError),
+    /// the bytecode of the frame is longer than [EXTRA_OUTPUT_WORDS]
+    TooLong,
+}
+
+/// Write the bytecode of `frame` into `words`
+///
+/// returns: the number of words
+fn generate(words: &mut [u32], option: TransmitOption, frame: &[u8]) -> Result<usize, ExtraOutputError> {
+    let mut len = 0;
+    for word in option.convert(frame) {
+        *words.get_mut(len).ok_or(ExtraOutputError::TooLong)? = word;
+        len += 1;
+    }
+    Ok(len)
+}
+
+/// One extra output, its bytecode buffer and the DMA channel that feeds it
+struct ExtraOutput<SM: StateMachineIndex, CH: ChannelIndex> {
+    config: Option<ExtraOutputConfig>,
+    /// the plan for the offset at the current system clock, `None` when there is none
+    option: Option<TransmitOption>,
+    words: &'static mut [u32; EXTRA_OUTPUT_WORDS],
+    /// words of the loaded packet, 0 when nothing is loaded
+    len: usize,
+    /// `None` only while a packet is sent
+    dma: Option<(Channel<CH>, Tx<(PIO0, SM)>)>,
+    transfer: Option<single_buffer::Transfer<Channel<CH>, &'static [u32], Tx<(PIO0, SM)>>>,
+}
+
+impl<SM: StateMachineIndex, CH: ChannelIndex> ExtraOutput<SM, CH> {
+    fn new(channel: Channel<CH>, tx: Tx<(PIO0, SM)>, words: &'static mut [u32; EXTRA_OUTPUT_WORDS]) -> Self {
+        ExtraOutput {
+            config: None,
+            option: None,
+            words,
+            len: 0,
+            dma: Some((channel, tx)),
+            transfer: None,
+        }
+    }
+
+    fn set(
+        &mut self,
+        config: Option<ExtraOutputConfig>,
+        clock: ProcessorClock,
+    ) -> Result<(), ExtraOutputError> {
+        self.config = None;
+        self.option = None;
+        self.len = 0;
+        let Some(config) = config else {
+            return Ok(());
+        };
+        let option =
+            TransmitOption::for_offset_on_clock(config.offset_hz, clock).map_err(ExtraOutputError::Plan)?;
+        if let Some(frame) = &config.frame {
+            self.len = generate(self.words, option, frame)?;
+        }
+        self.config = Some(config);
+        self.option = Some(option);
+        Ok(())
+    }
+
+    fn is_on(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// Plan the offset again if the system clock changed
+    ///
+    /// returns: false when the offset can't be sent at `clock`
+    fn replan(&mut self, clock: ProcessorClock) -> bool {
+        let Some(config) = &self.config else {
+            return true;
+        };
+        if self
+            .option
+            .is_some_and(|option| option.processor_clock() == clock)
+        {
+            return true;
+        }
+        self.len = 0;
+        self.option = TransmitOption::for_offset_on_clock(config.offset_hz, clock).ok();
+        if let (Some(option), Some(frame)) = (self.option, &config.frame) {
+            // a plan with longer quarters makes more bytecode
+            match generate(self.words, option, frame) {
+                Ok(len) => self.len = len,
+                Err(_) => self.option = None,
+            }
+        }
+        self.option.is_some()
+    }
+
+    /// Generate the packet that goes out with SM0's next one
+    ///
+    /// returns: [ExtraOutputError::TooLong] when SM0's frame doesn't fit, the output stays quiet
+    fn load(&mut self, clock: ProcessorClock, sm0_frame: Option<&[u8]>) -> Result<(), ExtraOutputError> {
+        if !self.replan(clock) {
+            warn!(
+                "extra output {} can't send its offset at {}Hz",
+                SM::id(),
+                clock.hz()
+            );
+            return Ok(());
+        }
+        let (Some(config), Some(option)) = (&self.config, self.option) else {
+            return Ok(());
+        };
+        if config.frame.is_none() {
+            self.len = 0;
+            if let Some(frame) = sm0_frame {
+                self.len = generate(self.words, option, frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start the DMA into the FIFO, the packet goes out once the trigger pin goes low
+    fn start(&mut self, pio_ctrl: &mut PioControl<PIO0, PullDown>) {
+        let (Some(option), true) = (self.option, self.len > 0) else {
+            return;
+        };
+        pio_ctrl.change_extra_clock_divider(SM::id(), option.state_machine_clock());
+        let (channel, tx) = self
+            .dma
+            .take()
+            .expect("the DMA channel is only away while a packet is sent");
+        tx.clear_stalled_flag();
+        // Safety: the buffer only changes in generate, which doesn't run before finish gave the channel back
+        let words = unsafe { core::slice::from_raw_parts(self.words.as_ptr(), self.len) };
+        self.transfer = Some(single_buffer::Config::new(channel, words, tx).start());
+    }
+
+    /// Wait for the DMA to copy the last word into the FIFO
+    fn finish(&mut self, serial: &mut USBSerial) {
+        if let Some(transfer) = self.transfer.take() {
+            while !transfer.is_done() {
+                serial.service();
+            }
+            let (channel, _, tx) = transfer.wait();
+            self.dma = Some((channel, tx));
+        }
+    }
+
+    /// true once the state machine sent the whole packet, only after [ExtraOutput::finish]
+    fn is_drained(&self) -> bool {
+        self.dma
+            .as_ref()
+            .is_none_or(|(_, tx)| self.len == 0 || (tx.is_empty() && tx.has_stalled()))
+    }
+}
+
+/// The extra outputs on SM1 to SM3, with DMA channels 2 to 4, `None` for the state machines without a pin
+pub struct ExtraOutputs {
+    sm1: Option<ExtraOutput<SM1, CH2>>,
+    sm2: Option<ExtraOutput<SM2, CH3>>,
+    sm3: Option<ExtraOutput<SM3, CH4>>,
+}
+
+impl ExtraOutputs {
+    pub fn new(tx: ExtraTx<PIO0>, channels: (Channel<CH2>, Channel<CH3>, Channel<CH4>)) -> Self {
+        // see take_once for why this doesn't need a compare and swap
+        take_once(&TAKEN, "the extra outputs are only set up once");
+        // Safety: this is the only place WORDS is used and it only runs once
+        let [words1, words2, words3] = unsafe { &mut *addr_of_mut!(WORDS) }.each_mut();
+        let (tx1, tx2, tx3) = tx;
+        let (ch2, ch3, ch4) = channels;
+        ExtraOutputs {
+            sm1: tx1.map(|tx| ExtraOutput::new(ch2, tx, words1)),
+            sm2: tx2.map(|tx| ExtraOutput::new(ch3, tx, words2)),
+            sm3: tx3.map(|tx| ExtraOutput::new(ch4, tx, words3)),
+        }
+    }
+
+    /// Send `config` on the extra output of SM`sm`, `None` turns it off
+    ///
+    /// `clock` is the system clock SM0 runs at, the offset is planned for it
+    pub fn set(
+        &mut self,
+        sm: usize,
+        config: Option<ExtraOutputConfig>,
+        clock: ProcessorClock,
+    ) -> Result<(), ExtraOutputError> {
+        match (sm, self) {
+            (
+                1,
+                ExtraOutputs {
+                    sm1: Some(output), ..
+                },
+            ) => output.set(config, clock),
+            (
+                2,
+                ExtraOutputs {
+                    sm2: Some(output), ..
+                },
+            ) => output.set(config, clock),
+            (
+                3,
+                ExtraOutputs {
+                    sm3: Some(output), ..
+                },
+            ) => output.set(config, clock),
+            _ => Err(ExtraOutputError::NoSuchOutput),
+        }
+    }
+
+    /// true when SM`sm` has a pin, the output can be turned on
+    pub fn exists(&self, sm: usize) -> bool {
+        match sm {
+            1 => self.sm1.is_some(),
+            2 => self.sm2.is_some(),
+            3 => self.sm3.is_some(),
+            _ => false,
+        }
+    }
+
+    /// What the extra output of SM`sm` sends and the plan for its offset, `None` when it is off
+    pub fn get(&self, sm: usize) -> Option<(&ExtraOutputConfig, Option<TransmitOption>)> {
+        let (config, option) = match (sm, self) {
+            (
+                1,
+                ExtraOutputs {
+                    sm1: Some(output), ..
+                },
+            ) => (&output.config, output.option),
+            (
+                2,
+                ExtraOutputs {
+                    sm2: Some(output), ..
+                },
+            ) => (&output.config, output.option),
+            (
+                3,
+                ExtraOutputs {
+                    sm3: Some(output), ..
+                },
+            ) => (&output.config, output.option),
+            _ => return None,
+        };
+        config.as_ref().map(|config| (config, option))
+    }
+
+    /// Plan the offsets for the system clock `clock`
+    ///
+    /// returns: the state machines of the outputs that can't send their offset at `clock`
+    pub fn replan(&mut self, clock: ProcessorClock) -> Vec<usize, EXTRA_OUTPUTS> {
+        let mut failed = Vec::new();
+        for (sm, ok) in [
+            (1, self.sm1.as_mut().is_none_or(|output| output.replan(clock))),
+            (2, self.sm2.as_mut().is_none_or(|output| output.replan(clock))),
+            (3, self.sm3.as_mut().is_none_or(|output| output.replan(clock))),
+        ] {
+            if !ok {
+                failed.push(sm).expect("there are EXTRA_OUTPUTS outputs");
+            }
+        }
+        failed
+    }
+
+    /// true when an output is on
+    pub fn is_active(&self) -> bool {
+        self.sm1.as_ref().is_some_and(ExtraOutput::is_on)
+            || self.sm2.as_ref().is_some_and(ExtraOutput::is_on)
+            || self.sm3.as_ref().is_some_and(ExtraOutput::is_on)
+    }
+
+    /// Generate the packets that go out with SM0's next one, `sm0_frame` is its frame, `None` when it
+    /// isn't an 802.15.4 one and the outputs without their own frame stay quiet
+    ///
+    /// returns: the state machines of the outputs `sm0_frame` is too long for
+    pub(crate) fn load(
+        &mut self,
+        clock: ProcessorClock,
+        sm0_frame: Option<&[u8]>,
+    ) -> Vec<usize, EXTRA_OUTPUTS> {
+        let mut too_long = Vec::new();
+        for (sm, result) in [
+            (1, self.sm1.as_mut().map(|output| output.load(clock, sm0_frame))),
+            (2, self.sm2.as_mut().map(|output| output.load(clock, sm0_frame))),
+            (3, self.sm3.as_mut().map(|output| output.load(clock, sm0_frame))),
+        ] {
+            if let Some(Err(error)) = result {
+                warn!("extra output {}: {}", sm, error);
+                too_long.push(sm).expect("there are EXTRA_OUTPUTS outputs");
+            }
+        }
+        too_long
+    }
+
+    pub(crate) fn start(&mut self, pio_ctrl: &mut PioControl<PIO0, PullDown>) {
+        if let Some(output) = &mut self.sm1 {
+            output.start(pio_ctrl);
+        }
+        if let Some(output) = &mut self.sm2 {
+            output.start(pio_ctrl);
+        }
+        if let Some(output) = &mut self.sm3 {
+            output.start(pio_ctrl);
+        }
+    }
+
+    pub(crate) fn finish(&mut self, serial: &mut USBSerial) {
+        if let Some(output) = &mut self.sm1 {
+            output.finish(serial);
+        }
+        if let Some(output) = &mut self.sm2 {
+            output.finish(serial);
+        }
+        if let Some(output) = &mut self.sm3 {
+            output.finish(serial);
+        }
+    }
+
+    pub(crate) fn is_drained(&self) -> bool {
+        self.sm1.as_ref().is_none_or(ExtraOutput::is_drained)
+            && self.sm2.as_ref().is_none_or(ExtraOutput::is_drained)
+            && self.sm3.as_ref().is_none_or(ExtraOutput::is_drained)
+    }
+}
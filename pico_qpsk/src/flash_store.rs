@@ -0,0 +1,132 @@
+//! [Flash] on the pico's own QSPI flash, used to keep the session config across reboots
+//!
+//! The program runs from the same flash (XIP), so erasing and programming is done by the boot ROM
+//! from a function in RAM with interrupts off. The ROM functions are looked up before XIP is turned off
+//! and boot2 (the fast XIP setup) is copied to RAM first so it can be run again afterwards.
+//! Core1 waits for its next packet in RAM too, see [crate::packet_generator], the flash is only written
+//! while nothing is sent.
+use pico_qpsk_core::config_store::{ConfigStore, Flash, RECORD_SIZE, STORE_SECTORS};
+use rp_pico::hal::rom_data;
+
+/// Start of the flash in the address map
+const XIP_BASE: usize = 0x1000_0000;
+/// The Pico has 2MB of flash
+const FLASH_SIZE: usize = 2048 * 1024;
+const SECTOR_SIZE: usize = 4096;
+const PAGE_SIZE: usize = 256;
+/// The store is the last sectors of the flash, `memory.x` keeps the program out of them
+const STORE_OFFSET: usize = FLASH_SIZE - STORE_SECTORS * SECTOR_SIZE;
+/// 4KB sector erase command
+const SECTOR_ERASE_COMMAND: u8 = 0x20;
+/// boot2 is the first 256 bytes of flash
+const BOOT2_SIZE_WORDS: usize = 64;
+
+pub type FlashConfigStore = ConfigStore<RomFlash>;
+
+/// The ROM functions and boot2, everything the RAM function calls
+struct FlashFunctions {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+    boot2: unsafe extern "C" fn(),
+}
+
+/// The pico's flash, only the store at the end of it can be changed
+pub struct RomFlash {
+    boot2: [u32; BOOT2_SIZE_WORDS],
+}
+
+impl RomFlash {
+    pub fn new() -> Self {
+        let mut boot2 = [0u32; BOOT2_SIZE_WORDS];
+        for (idx, word) in boot2.iter_mut().enumerate() {
+            // safety: boot2 is always mapped, it is where the chip started
+            *word = unsafe { core::ptr::read_volatile((XIP_BASE as *const u32).add(idx)) };
+        }
+        RomFlash { boot2 }
+    }
+
+    /// Erase and/or program with XIP off
+    ///
+    /// # Arguments
+    ///
+    /// * `offset`: offset from the start of the store
+    /// * `erase_len`: bytes to erase first, 0 for none
+    /// * `data`: page aligned bytes to program, empty for none
+    fn write(&mut self, offset: usize, erase_len: usize, data: &[u8]) {
+        let functions = FlashFunctions {
+            connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+            flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+            flash_range_erase: rom_data::flash_range_erase::ptr(),
+            flash_range_program: rom_data::flash_range_program::ptr(),
+            flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+            // safety: the thumb bit is set, boot2 is position independent code that returns to its caller
+            boot2: unsafe {
+                core::mem::transmute::<usize, unsafe extern "C" fn()>(self.boot2.as_ptr() as usize + 1)
+            },
+        };
+        cortex_m::interrupt::free(|_| {
+            // safety: the address is in the store, nothing else runs while XIP is off
+            unsafe {
+                write_with_xip_off(
+                    &functions,
+                    (STORE_OFFSET + offset) as u32,
+                    erase_len,
+                    data.as_ptr(),
+                    data.len(),
+                )
+            }
+        });
+    }
+}
+
+/// Runs from RAM, it can't call anything in flash
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_with_xip_off(
+    functions: &FlashFunctions,
+    address: u32,
+    erase_len: usize,
+    data: *const u8,
+    data_len: usize,
+) {
+    (functions.connect_internal_flash)();
+    (functions.flash_exit_xip)();
+    if erase_len > 0 {
+        (functions.flash_range_erase)(address, erase_len, SECTOR_SIZE as u32, SECTOR_ERASE_COMMAND);
+    }
+    if data_len > 0 {
+        (functions.flash_range_program)(address, data, data_len);
+    }
+    (functions.flash_flush_cache)();
+    (functions.boot2)();
+}
+
+impl Flash for RomFlash {
+    const SECTOR_SIZE: usize = SECTOR_SIZE;
+
+    fn read(&self, offset: usize, bytes: &mut [u8]) {
+        let store = (XIP_BASE + STORE_OFFSET + offset) as *const u8;
+        for (idx, byte) in bytes.iter_mut().enumerate() {
+            // safety: the store is at the end of the flash, which is always mapped
+            *byte = unsafe { core::ptr::read_volatile(store.add(idx)) };
+        }
+    }
+
+    fn erase_sector(&mut self, offset: usize) {
+        self.write(offset, SECTOR_SIZE, &[]);
+    }
+
+    fn program(&mut self, offset: usize, bytes: &[u8]) {
+        // the ROM only programs whole pages, 0xFF leaves the rest of the page as it is
+        let mut page = [0xFFu8; PAGE_SIZE];
+        let page_offset = offset % PAGE_SIZE;
+        page[page_offset..page_offset + bytes.len()].copy_from_slice(bytes);
+        self.write(offset - page_offset, 0, &page);
+    }
+}
+
+// a record never crosses a page
+const _: () = assert!(PAGE_SIZE.is_multiple_of(RECORD_SIZE));
@@ -1,7 +1,11 @@
 #![no_std]
 #![no_main]
 
-use crate::pio_helpers::{initialize_pio, StandardTransmitOption};
+use crate::extra_outputs::ExtraOutputs;
+use crate::flash_store::RomFlash;
+use crate::packet_timer::PacketTimer;
+use crate::pio_dma::PioDma;
+use crate::pio_helpers::initialize_pio;
 use crate::serial_executor::executor;
 use crate::usb_serial::USBSerial;
 use bsp::entry;
@@ -10,36 +14,64 @@ use defmt_rtt as _;
 // this allows panic handling
 #[allow(unused_imports)]
 use panic_probe as _;
+use pico_qpsk_core::config_store::ConfigStore;
 
 use rp_pico as bsp;
+use rp_pico::hal::dma::DMAExt;
 
+mod binary_executor;
 mod board_setup;
-mod data_array;
 mod error;
-mod packet;
-mod pio_bytecode_gen;
+mod extra_outputs;
+mod flash_store;
+mod packet_generator;
+mod packet_timer;
+mod pio_dma;
 mod pio_helpers;
 mod serial_executor;
+mod sys_clock;
 mod usb_serial;
 
 #[entry]
 fn main() -> ! {
-    let transmission_type = StandardTransmitOption::Clk128MHzOffset8MHz;
+    // the saved settings decide the clocks so they are read first, flash reads work before the clocks are set up
+    let mut config_store = ConfigStore::new(RomFlash::new());
+    let mut session = config_store.load().unwrap_or_default();
+    let transmission_type = session.transmit_option;
 
-    let (pins, mut delay, mut resets, bus, pio) = board_setup::setup(transmission_type.processor_clock());
+    let (mut pins, timer, mut sys_clock, bus, pio, dma, mut core1) =
+        board_setup::setup(transmission_type.processor_clock());
 
     let mut serial = USBSerial::new(&bus);
 
-    // Set up PIO to control transmission
-    let (mut tx, mut pio_ctrl) = initialize_pio(pins.gpio3, pins.gpio6, pio, &mut resets);
+    // Set up PIO to control transmission on the saved pins, GPIO6 is the antenna pin and the I pin, GPIO7
+    // the Q pin and GPIO8 to GPIO10 the antenna pins of the extra outputs by default
+    let (tx, extra_tx, mut pio_ctrl) = initialize_pio(&session.pins, &mut pins, pio, sys_clock.resets());
 
-    // set the correct clock divider
+    // core1 generates the pio bytecode, two chained DMA channels feed it to the PIO, one more channel
+    // for each extra output
+    let consumer = packet_generator::spawn(&mut core1);
+    let dma = dma.split(sys_clock.resets());
+    let extras = ExtraOutputs::new(extra_tx, (dma.ch2, dma.ch3, dma.ch4));
+    let mut pio_dma = PioDma::new(tx, (dma.ch0, dma.ch1), consumer, core1.fifo, extras);
+    let mut timer = PacketTimer::new(timer);
+
+    // set the correct clock divider and the program for one or two antenna pins
     pio_ctrl.change_clock_divider(transmission_type.state_machine_clock());
+    serial_executor::apply_sideband(&mut pio_ctrl, &mut session);
 
     // let generated_frame_bytes:Vec<u8, crate::pio_helpers::MAX_FRAME_SIZE>  = get_testing_generated_frame_bytes();
     // let waves = generate_waves::<16>();
 
-    executor(&mut serial, &mut delay, &mut tx, &mut pio_ctrl, transmission_type);
+    executor(
+        &mut serial,
+        &mut timer,
+        &mut pio_dma,
+        &mut pio_ctrl,
+        session,
+        &mut sys_clock,
+        &mut config_store,
+    );
     //
     //
     // // generate a frame on the pico
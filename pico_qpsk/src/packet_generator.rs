@@ -0,0 +1,99 @@
+//! Core1 generates the pio bytecode of the packets core0 sends
+//!
+//! Core0 pushes a job on the inter-core FIFO, where the transmit option, the [Phy] and the frame are,
+//! and core1 converts the frame into the chunks of a [ChunkRing] that core0's DMA drains into
+//! the PIO.
+//! Core1 waits for jobs in RAM so core0 can turn XIP off to write the flash while it is idle.
+use crate::board_setup::Core1Peripherals;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, Ordering};
+use pico_qpsk_core::chunk_ring::{ChunkRing, Consumer, Producer};
+use pico_qpsk_core::transmit_option::{Phy, TransmitOption};
+use rp_pico::hal::multicore::{Multicore, Stack};
+use rp_pico::hal::sio::SioFifo;
+
+/// u32 words in a chunk
+const CHUNK_WORDS: usize = 1024;
+/// The bytecode of the longest 802.15.4 frame, 7980 words at 144MHz/6MHz, fits in the ring so it is
+/// generated completely before it is sent
+pub const CHUNKS: usize = 8;
+
+pub type PioConsumer = Consumer<'static, CHUNKS, CHUNK_WORDS>;
+type PioProducer = Producer<'static, CHUNKS, CHUNK_WORDS>;
+
+static mut RING: ChunkRing<CHUNKS, CHUNK_WORDS> = ChunkRing::new();
+static mut CORE1_STACK: Stack<2048> = Stack::new();
+static SPAWNED: AtomicBool = AtomicBool::new(false);
+
+/// The SIO FIFO registers, each core sees its own end of the FIFO at the same address
+const SIO_FIFO_ST: *const u32 = 0xD000_0050 as *const u32;
+const SIO_FIFO_RD: *const u32 = 0xD000_0058 as *const u32;
+/// FIFO_ST bit set when there is a word to read
+const FIFO_ST_VLD: u32 = 1;
+
+/// Panic with `message` if `taken` is already set, then set it, for the statics that are handed out once
+///
+/// The M0+ cores have no atomic swap, but a load and a store are enough as only core0 ever hands the
+/// statics out.
+pub fn take_once(taken: &AtomicBool, message: &str) {
+    assert!(!taken.load(Ordering::Relaxed), "{}", message);
+    taken.store(true, Ordering::Relaxed);
+}
+
+/// Start core1 generating the packets [send_job] asks for
+///
+/// returns: the end of the ring core0 reads the bytecode from
+pub fn spawn(core1: &mut Core1Peripherals) -> PioConsumer {
+    take_once(&SPAWNED, "core1 is only spawned once");
+    // Safety: this is the only place the statics are used and it only runs once
+    let (ring, stack) = unsafe { (&mut *addr_of_mut!(RING), &mut (*addr_of_mut!(CORE1_STACK)).mem) };
+    let (producer, consumer) = ring.split();
+
+    let mut multicore = Multicore::new(&mut core1.psm, &mut core1.ppb, &mut core1.fifo);
+    multicore.cores()[1]
+        .spawn(stack, move || generate(producer))
+        .expect("core1 failed to start");
+    consumer
+}
+
+/// Have core1 generate the pio bytecode of `frame` into the ring
+///
+/// # Safety
+///
+/// core1 reads `transmit_option`, `phy` and `frame` until it commits the last chunk of the packet, they
+/// must not change or move before
+pub unsafe fn send_job(fifo: &mut SioFifo, transmit_option: &TransmitOption, phy: &Phy, frame: &[u8]) {
+    fifo.write_blocking(transmit_option as *const TransmitOption as u32);
+    fifo.write_blocking(phy as *const Phy as u32);
+    fifo.write_blocking(frame.as_ptr() as u32);
+    fifo.write_blocking(frame.len() as u32);
+}
+
+fn generate(mut producer: PioProducer) -> ! {
+    loop {
+        let transmit_option = read_fifo() as *const TransmitOption;
+        let phy = read_fifo() as *const Phy;
+        let frame = read_fifo() as *const u8;
+        let len = read_fifo() as usize;
+        // Safety: see send_job
+        let (transmit_option, phy, frame) =
+            unsafe { (&*transmit_option, *phy, core::slice::from_raw_parts(frame, len)) };
+        let mut words = transmit_option.convert_phy(frame, phy).peekable();
+        // the ring is full until core0's DMA sends the oldest chunk
+        while producer.push_from(&mut words) != Some(true) {}
+    }
+}
+
+/// The next word from core0, runs from RAM, it can't call anything in flash
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+fn read_fifo() -> u32 {
+    // Safety: core1 only reads its own end of the FIFO
+    unsafe {
+        while core::ptr::read_volatile(SIO_FIFO_ST) & FIFO_ST_VLD == 0 {
+            // a write to the FIFO wakes the other core
+            core::arch::asm!("wfe");
+        }
+        core::ptr::read_volatile(SIO_FIFO_RD)
+    }
+}
@@ -0,0 +1,48 @@
+//! Starting packets at absolute times of the RP2040 timer
+//!
+//! The 1MHz timer counts from boot in 64 bits so it never wraps, alarm 0 tells when a start time was
+//! reached while USB is polled. See [pico_qpsk_core::schedule] for the start times of a run.
+use crate::usb_serial::USBSerial;
+use rp_pico::hal::timer::{Alarm, Alarm0, Instant};
+use rp_pico::hal::Timer;
+
+/// The alarm reaches at most this far ahead, a longer wait is split up
+const MAX_ALARM_US: u64 = u32::MAX as u64;
+
+pub struct PacketTimer {
+    timer: Timer,
+    alarm: Alarm0,
+}
+
+impl PacketTimer {
+    pub fn new(mut timer: Timer) -> Self {
+        let alarm = timer.alarm_0().expect("alarm 0 is only taken here");
+        PacketTimer { timer, alarm }
+    }
+
+    /// Microseconds since boot
+    pub fn now_us(&self) -> u64 {
+        self.timer.get_counter().ticks()
+    }
+
+    /// Wait until the timer reaches `at_us`, USB is polled meanwhile
+    ///
+    /// returns: false when `at_us` had already passed
+    pub fn wait_until(&mut self, serial: &mut USBSerial, at_us: u64) -> bool {
+        if self.now_us() >= at_us {
+            return false;
+        }
+        loop {
+            let alarm_us = at_us.min(self.now_us() + MAX_ALARM_US);
+            self.alarm
+                .schedule_at(Instant::from_ticks(alarm_us))
+                .expect("the alarm is never more than MAX_ALARM_US ahead");
+            while !self.alarm.finished() {
+                serial.service();
+            }
+            if alarm_us == at_us {
+                return true;
+            }
+        }
+    }
+}
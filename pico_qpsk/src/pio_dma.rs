@@ -0,0 +1,205 @@
+//! Feeding the TX FIFO of SM0 with DMA
+//!
+//! Core1 generates the bytecode of a packet into a ring of chunks (see [crate::packet_generator]), a
+//! DMA channel copies the oldest chunk into the FIFO and the second channel is chained after it with the
+//! next one. The words reach the PIO at its own pace whatever core0 is doing and USB is polled while a
+//! packet is sent. The packet starts once it was generated completely or the ring is full, the rest of a
+//! longer packet is generated while it is sent.
+//!
+//! The [ExtraOutputs] that are on send their packets with SM0's, each packet then waits until every
+//! state machine sent the whole of it and they all start the next one together. An external trigger
+//! (see [PinConfig](pico_qpsk_core::session_config::PinConfig)) parks them after every packet too, a
+//! packet doesn't start before the other device pulls the trigger pin low.
+use crate::extra_outputs::{ExtraOutputs, EXTRA_OUTPUTS};
+use crate::packet_generator::{self, PioConsumer, CHUNKS};
+use crate::pio_helpers::PioControl;
+use crate::serial_executor::MAX_FRAME_SIZE;
+use crate::usb_serial::USBSerial;
+use defmt::Format;
+use heapless::Vec;
+use pico_qpsk_core::transmit_option::{Phy, TransmitOption};
+use rp_pico::hal::dma::{double_buffer, Channel, CH0, CH1};
+use rp_pico::hal::gpio::PullDown;
+use rp_pico::hal::pio::{Tx, SM0};
+use rp_pico::hal::sio::SioFifo;
+use rp_pico::pac::PIO0;
+
+const SENDING_ERROR_MESSAGE: &str = "the DMA channels are only away during PioDma::send";
+
+/// The packet core1 generates on the next send
+struct LoadedPacket {
+    transmit_option: TransmitOption,
+    phy: Phy,
+    frame: Vec<u8, MAX_FRAME_SIZE>,
+}
+
+/// The bytecode of a long packet wasn't generated as fast as the PIO sent it, the packet was cut short
+#[derive(Format)]
+pub struct Underrun;
+
+/// The TX FIFO of SM0, the two DMA channels that feed it and the ring they read from, and the extra outputs
+pub struct PioDma {
+    /// `None` only while [PioDma::send] has them in a transfer
+    dma: Option<(Channel<CH0>, Channel<CH1>, Tx<(PIO0, SM0)>)>,
+    consumer: PioConsumer,
+    /// core0's end of the inter-core FIFO, the jobs for core1 go through it
+    fifo: SioFifo,
+    packet: Option<LoadedPacket>,
+    extras: ExtraOutputs,
+}
+
+impl PioDma {
+    pub fn new(
+        tx: Tx<(PIO0, SM0)>,
+        channels: (Channel<CH0>, Channel<CH1>),
+        consumer: PioConsumer,
+        fifo: SioFifo,
+        extras: ExtraOutputs,
+    ) -> Self {
+        let (ch0, ch1) = channels;
+        PioDma {
+            dma: Some((ch0, ch1, tx)),
+            consumer,
+            fifo,
+            packet: None,
+            extras,
+        }
+    }
+
+    /// The outputs on SM1 to SM3 that send with SM0
+    pub fn extras(&mut self) -> &mut ExtraOutputs {
+        &mut self.extras
+    }
+
+    /// true once a packet was loaded, it stays loaded until the next [PioDma::load] or [PioDma::clear]
+    pub fn is_loaded(&self) -> bool {
+        self.packet.is_some()
+    }
+
+    /// Forget the loaded packet
+    pub fn clear(&mut self) {
+        self.packet = None;
+    }
+
+    /// Keep a frame to send, its bytecode is generated on every send
+    ///
+    /// `phy` has to match the program [PioControl::use_two_pins] picked and the divider of
+    /// [TransmitOption::state_machine_clock_for]. The extra outputs get their packets here too, their
+    /// bytecode is generated right away, the ones that send SM0's frame only do for an O-QPSK one.
+    ///
+    /// returns: the state machines of the extra outputs the frame is too long for
+    pub fn load(
+        &mut self,
+        transmit_option: TransmitOption,
+        phy: Phy,
+        frame_bytes: &[u8],
+    ) -> Vec<usize, EXTRA_OUTPUTS> {
+        let sm0_frame = matches!(phy, Phy::OQpsk(_)).then_some(frame_bytes);
+        let too_long = self.extras.load(transmit_option.processor_clock(), sm0_frame);
+        self.packet = Some(LoadedPacket {
+            transmit_option,
+            phy,
+            frame: Vec::from_slice(frame_bytes).expect("frames are never longer than MAX_FRAME_SIZE"),
+        });
+        too_long
+    }
+
+    /// Send the loaded packet, USB is polled until the last word is in the FIFO
+    ///
+    /// `wait_for_start` is called once the packet is ready to go and returns when it should start, so
+    /// building the packet doesn't delay its start
+    pub fn send(
+        &mut self,
+        serial: &mut USBSerial,
+        pio_ctrl: &mut PioControl<PIO0, PullDown>,
+        wait_for_start: &mut (impl FnMut(&mut USBSerial) + Sized),
+    ) -> Result<(), Underrun> {
+        let packet = self
+            .packet
+            .as_ref()
+            .expect("a packet is loaded before it is sent");
+        // Safety: send only returns after the last chunk was consumed, the packet doesn't change before
+        unsafe {
+            packet_generator::send_job(
+                &mut self.fifo,
+                &packet.transmit_option,
+                &packet.phy,
+                &packet.frame,
+            )
+        };
+        while self.consumer.len() < CHUNKS && !last_committed(&self.consumer) {
+            serial.service();
+        }
+        wait_for_start(serial);
+
+        let (ch0, ch1, tx) = self.dma.take().expect(SENDING_ERROR_MESSAGE);
+        tx.clear_stalled_flag();
+        let (first, mut last) = dma_chunk(&self.consumer, 0);
+        let mut transfer = double_buffer::Config::new((ch0, ch1), first, tx).start();
+        // with the extra outputs on or another device starting the packets, every packet waits for the trigger
+        let park = self.extras.is_active() || pio_ctrl.has_external_trigger();
+        self.extras.start(pio_ctrl);
+        // the DMA fills the 4 word FIFOs long before the trigger pin change reaches the PIO
+        pio_ctrl.start();
+        let mut result = Ok(());
+        while !last {
+            // the next chunk has to be chained before this one runs out
+            while self.consumer.len() < 2 && !transfer.is_done() {
+                serial.service();
+            }
+            if self.consumer.len() < 2 || transfer.is_done() {
+                result = Err(Underrun);
+                break;
+            }
+            let (next, next_last) = dma_chunk(&self.consumer, 1);
+            let chained = transfer.read_next(next);
+            while !chained.is_done() {
+                serial.service();
+            }
+            let (_, next_transfer) = chained.wait();
+            self.consumer.release();
+            transfer = next_transfer;
+            last = next_last;
+        }
+        while !transfer.is_done() {
+            serial.service();
+        }
+        let (ch0, ch1, _, tx) = transfer.wait();
+        self.consumer.release();
+        self.extras.finish(serial);
+        if park {
+            // SM0 stalls on the empty FIFO once it sent its packet, the others do the same
+            while !(tx.is_empty() && tx.has_stalled() && self.extras.is_drained()) {
+                serial.service();
+            }
+            pio_ctrl.stop();
+        }
+        self.dma = Some((ch0, ch1, tx));
+
+        // after an underrun the rest of the packet is still generated, it is dropped
+        while !last {
+            while self.consumer.is_empty() {
+                serial.service();
+            }
+            last = dma_chunk(&self.consumer, 0).1;
+            self.consumer.release();
+        }
+        result
+    }
+}
+
+fn last_committed(consumer: &PioConsumer) -> bool {
+    consumer
+        .len()
+        .checked_sub(1)
+        .and_then(|newest| consumer.chunk(newest))
+        .is_some_and(|chunk| chunk.last)
+}
+
+/// The `n`th chunk of the ring and whether it is the last of the packet
+fn dma_chunk(consumer: &PioConsumer, n: usize) -> (&'static [u32], bool) {
+    let chunk = consumer.chunk(n).expect("the chunk was committed");
+    // Safety: a chunk is only released once its transfer is done, core1 doesn't change it before
+    let words = unsafe { core::slice::from_raw_parts(chunk.words.as_ptr(), chunk.words.len()) };
+    (words, chunk.last)
+}
@@ -1,36 +1,36 @@
-use crate::pio_bytecode_gen::ConvertIterType;
-use crate::pio_helpers::{get_seq_frame_bytes, PioControl, StandardTransmitOption};
+use crate::binary_executor;
+use crate::extra_outputs::{ExtraOutputConfig, ExtraOutputError, ExtraOutputs, EXTRA_OUTPUTS};
+use crate::flash_store::FlashConfigStore;
+use crate::packet_timer::PacketTimer;
+use crate::pio_dma::PioDma;
+use crate::pio_helpers::PioControl;
 use crate::serial_executor::CommandError::ArgsError;
-use crate::to_max_frame_size;
+use crate::sys_clock::SysClock;
 use crate::usb_serial::USBSerial;
 use core::fmt::Write;
-use cortex_m::delay::Delay;
 use defmt::{info, warn};
 use heapless::{String, Vec};
 use owo_colors::{colors::*, OwoColorize, XtermColors};
+use pico_qpsk_core::ble::{self, Advertisement, AdvertisingChannel, AdvertisingData, MAX_ADV_DATA};
+use pico_qpsk_core::frame_gen::{get_hex_string_as_bytes, get_seq_payload, random_payloads, HexStringError};
+use pico_qpsk_core::fsk::FskError;
+use pico_qpsk_core::keying::{Keying, KeyingConfig, KeyingError, LineCoding};
+use pico_qpsk_core::offset_planner::PlanError;
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::schedule::{PeriodStats, Schedule, ScheduleMode};
+use pico_qpsk_core::session_config::{
+    Address, ExtendedAddress, PanId, PayloadMode, PinConfig, PinError, SequenceNumberMode, SessionConfig,
+    ShortAddress, GPIO_COUNT,
+};
+use pico_qpsk_core::sub_ghz::SubGhzPhy;
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::{Phy, ProcessorClock, TransmitOption};
 use rp_pico::hal::gpio::PullDown;
-use rp_pico::hal::pio::{Tx, SM0};
 use rp_pico::hal::reset;
 use rp_pico::pac::PIO0;
 
-#[allow(clippy::enum_variant_names)]
-enum FrequencyOffsetCommandOption {
-    F2MHz,
-    F4MHz,
-    F8MHz,
-}
-
-impl FrequencyOffsetCommandOption {
-    fn parse_from_str<'a>(value: &str, possible_error: CommandError<'a>) -> Result<Self, CommandError<'a>> {
-        Ok(match value {
-            "2" | "2mhz" | "2MHz" => Self::F2MHz,
-            "4" | "4mhz" | "4MHz" => Self::F4MHz,
-            "8" | "8mhz" | "8MHz" => Self::F8MHz,
-            _ => Err(possible_error)?,
-        })
-    }
-}
-
+// there is no allocator to box the frame, only one command is alive at a time
+#[allow(clippy::large_enum_variant)]
 enum Command {
     Restart,
     Help,
@@ -39,91 +39,639 @@ enum Command {
         number_packets: u32,
         payload_length: Option<u32>,
     },
+    SendRandomPacket {
+        interval_ms: u32,
+        number_packets: u32,
+        payload_length: Option<u32>,
+        seed: u64,
+    },
+    /// a raw PHY frame, sent as is
+    SendRawFrame {
+        interval_ms: u32,
+        number_packets: u32,
+        frame: Vec<u8, MAX_FRAME_SIZE>,
+    },
+    /// a user payload, the frame around it comes from the session config
+    SendPayload {
+        interval_ms: u32,
+        number_packets: u32,
+        payload: Vec<u8, MAX_PAYLOAD_SIZE>,
+    },
+    /// a BLE advertisement from [BLE_ADDRESS]
+    SendAdvertisement {
+        interval_ms: u32,
+        number_packets: u32,
+        channel: AdvertisingChannel,
+        data: AdvertisingData,
+    },
     SetFrequencyOffset {
-        frequency: FrequencyOffsetCommandOption,
+        offset_hz: u32,
     },
+    SetSource(Option<Address>),
+    SetDestination(Option<Address>),
+    SetSequenceNumberMode(SequenceNumberMode),
+    SetSendDefaults {
+        interval_ms: u32,
+        number_packets: u32,
+    },
+    SetPayloadMode(PayloadMode),
+    SetSchedule(ScheduleMode),
+    /// `None` for both sidebands on the antenna pin
+    SetSideband(Option<Sideband>),
+    /// `None` for the 2.4GHz O-QPSK PHY, either turns OOK and 2-FSK off
+    SetSubGhzPhy(Option<SubGhzPhy>),
+    /// OOK or 2-FSK, the preamble and sync word stay
+    SetKeying {
+        keying: Keying,
+        coding: LineCoding,
+        bit_rate: u32,
+    },
+    SetSync {
+        sync_word: u16,
+        preamble_bytes: u8,
+    },
+    /// `None` turns the output off
+    SetExtraOutput {
+        sm: usize,
+        config: Option<ExtraOutputConfig>,
+    },
+    SetPins(PinChange),
+    /// send with the session's defaults
+    Send,
+    ShowConfig,
+    Save,
+    Load,
+    FactoryReset,
+}
+
+/// What `pins` changes in the session's [PinConfig], the PIO uses it from the next start up on
+enum PinChange {
+    Trigger {
+        gpio: u8,
+        external: bool,
+    },
+    Antenna {
+        gpio: u8,
+        quadrature: bool,
+    },
+    /// `None` leaves the state machine without a pin
+    Extra {
+        sm: usize,
+        gpio: Option<u8>,
+    },
+    Default,
 }
 
 enum CommandError<'a> {
     UnknownCommand(&'a str),
     UnknownError,
-    ArgsError { arg_name: &'static str },
+    ArgsError {
+        arg_name: &'static str,
+    },
+    InvalidHex {
+        arg_name: &'static str,
+        error: HexStringError,
+    },
+    TooLong {
+        arg_name: &'static str,
+        max_len: usize,
+    },
 }
 
 impl Command {
-    fn from_str<const SIZE: usize>(input: &String<SIZE>) -> Result<Command, CommandError> {
+    fn from_str<const SIZE: usize>(input: &String<SIZE>) -> Result<Command, CommandError<'_>> {
         let mut iter = input.split_whitespace();
         match iter.next().ok_or(CommandError::UnknownError)? {
             "restart" => Ok(Self::Restart),
             "help" => Ok(Self::Help),
             "ssp" => {
-                let interval_ms_str = iter.next().ok_or(CommandError::UnknownError)?;
-
-                let interval_ms = if interval_ms_str.ends_with("ms") {
-                    let slice = &interval_ms_str[0..interval_ms_str.len() - 2];
-                    slice.parse().map_err(|_| ArgsError {
-                        arg_name: "interval_ms ms",
-                    })?
-                } else if interval_ms_str.ends_with("s") {
-                    let slice = &interval_ms_str[0..interval_ms_str.len() - 1];
-                    let secs: u32 = slice.parse().map_err(|_| ArgsError {
-                        arg_name: "interval_ms s",
-                    })?;
-                    secs * 1000
-                } else {
-                    interval_ms_str.parse().map_err(|_| ArgsError {
-                        arg_name: "interval_ms",
-                    })?
-                };
+                let (interval_ms, number_packets, payload_length) = parse_packet_args(&mut iter)?;
 
-                let number_packets =
-                    iter.next()
-                        .ok_or(CommandError::UnknownError)?
-                        .parse()
-                        .map_err(|_| ArgsError {
-                            arg_name: "number_packets",
-                        })?;
+                Ok(Self::SendSequentialPacket {
+                    interval_ms,
+                    number_packets,
+                    payload_length,
+                })
+            }
+            "srp" => {
+                let (interval_ms, number_packets, payload_length) = parse_packet_args(&mut iter)?;
+                let seed = parse_seed(iter.next())?;
 
-                let payload_length = {
-                    let option = iter.next().map(|v| {
-                        v.parse::<u32>().map_err(|_| ArgsError {
-                            arg_name: "number_packets",
-                        })
-                    });
-                    if let Some(result) = option {
-                        Some(result?)
-                    } else {
-                        None
-                    }
-                };
+                Ok(Self::SendRandomPacket {
+                    interval_ms,
+                    number_packets,
+                    payload_length,
+                    seed,
+                })
+            }
+            "srf" => {
+                let (interval_ms, number_packets) = parse_interval_and_count(&mut iter)?;
+                let frame = parse_frame(iter.next().ok_or(CommandError::UnknownError)?)?;
+
+                Ok(Self::SendRawFrame {
+                    interval_ms,
+                    number_packets,
+                    frame,
+                })
+            }
+            "shp" => {
+                let (interval_ms, number_packets) = parse_interval_and_count(&mut iter)?;
+                let payload_str = iter.next().ok_or(CommandError::UnknownError)?;
+                let payload: Vec<u8, MAX_PAYLOAD_SIZE> =
+                    get_hex_string_as_bytes(payload_str).map_err(|error| match error {
+                        HexStringError::TooLong => CommandError::TooLong {
+                            arg_name: "payload",
+                            max_len: MAX_PAYLOAD_SIZE,
+                        },
+                        error => CommandError::InvalidHex {
+                            arg_name: "payload",
+                            error,
+                        },
+                    })?;
 
-                if payload_length > Some(MAX_PAYLOAD_SIZE as u32) {
-                    return Err(ArgsError {
-                        arg_name: "payload_length",
+                Ok(Self::SendPayload {
+                    interval_ms,
+                    number_packets,
+                    payload,
+                })
+            }
+            "sap" => {
+                // the text can have spaces so only the first 3 words are split off
+                let (_, rest) = split_first_word(input);
+                let (interval_str, rest) = split_first_word(rest);
+                let (number_packets_str, rest) = split_first_word(rest);
+                let (interval_ms, number_packets) = parse_interval_and_count(
+                    &mut [interval_str, number_packets_str]
+                        .into_iter()
+                        .filter(|word| !word.is_empty()),
+                )?;
+                let text = rest.trim_start();
+                if text.is_empty() || !text.is_ascii() {
+                    return Err(ArgsError { arg_name: "text" });
+                }
+                if text.len() > MAX_PAYLOAD_SIZE {
+                    return Err(CommandError::TooLong {
+                        arg_name: "text",
+                        max_len: MAX_PAYLOAD_SIZE,
                     });
                 }
 
-                Ok(Self::SendSequentialPacket {
+                Ok(Self::SendPayload {
                     interval_ms,
                     number_packets,
-                    payload_length,
+                    payload: Vec::from_slice(text.as_bytes()).expect("text length was checked"),
+                })
+            }
+            "sba" => {
+                // the name can have spaces like the text of sap
+                let (_, rest) = split_first_word(input);
+                let (interval_str, rest) = split_first_word(rest);
+                let (number_packets_str, rest) = split_first_word(rest);
+                let (channel_str, rest) = split_first_word(rest);
+                let (interval_ms, number_packets) = parse_interval_and_count(
+                    &mut [interval_str, number_packets_str]
+                        .into_iter()
+                        .filter(|word| !word.is_empty()),
+                )?;
+                let channel = parse_ble_channel(channel_str)?;
+                let name = rest.trim_start();
+                if name.is_empty() {
+                    return Err(ArgsError { arg_name: "name" });
+                }
+
+                Ok(Self::SendAdvertisement {
+                    interval_ms,
+                    number_packets,
+                    channel,
+                    data: AdvertisingData::with_name(name.as_bytes()),
+                })
+            }
+            "sbr" => {
+                let (interval_ms, number_packets) = parse_interval_and_count(&mut iter)?;
+                let channel = parse_ble_channel(iter.next().ok_or(CommandError::UnknownError)?)?;
+                let data: Vec<u8, MAX_ADV_DATA> = get_hex_string_as_bytes(
+                    iter.next().ok_or(CommandError::UnknownError)?,
+                )
+                .map_err(|error| match error {
+                    HexStringError::TooLong => CommandError::TooLong {
+                        arg_name: "ad_data",
+                        max_len: MAX_ADV_DATA,
+                    },
+                    error => CommandError::InvalidHex {
+                        arg_name: "ad_data",
+                        error,
+                    },
+                })?;
+
+                Ok(Self::SendAdvertisement {
+                    interval_ms,
+                    number_packets,
+                    channel,
+                    data: AdvertisingData::from_bytes(&data)
+                        .map_err(|_| ArgsError { arg_name: "ad_data" })?,
                 })
             }
             "freq" => {
-                let frequency_option_str = iter.next().ok_or(CommandError::UnknownError)?;
-                let frequency = FrequencyOffsetCommandOption::parse_from_str(
-                    frequency_option_str,
-                    ArgsError {
+                let offset_hz =
+                    parse_mhz(iter.next().ok_or(CommandError::UnknownError)?).ok_or(ArgsError {
                         arg_name: "frequency",
+                    })?;
+                Ok(Self::SetFrequencyOffset { offset_hz })
+            }
+            "src" => Ok(Self::SetSource(parse_address(&mut iter)?)),
+            "dst" => Ok(Self::SetDestination(parse_address(&mut iter)?)),
+            "seq" => {
+                let mode = match iter.next().ok_or(CommandError::UnknownError)? {
+                    "fixed" => {
+                        SequenceNumberMode::Fixed(parse_sequence_number(iter.next(), "sequence_number")?)
+                    }
+                    "inc" => SequenceNumberMode::Increment {
+                        start: match iter.next() {
+                            Some(start) => parse_sequence_number(Some(start), "start")?,
+                            None => 0,
+                        },
                     },
-                )?;
-                Ok(Self::SetFrequencyOffset { frequency })
+                    "wrap" => SequenceNumberMode::Wrap {
+                        start: parse_sequence_number(iter.next(), "start")?,
+                        end: parse_sequence_number(iter.next(), "end")?,
+                    },
+                    _ => Err(ArgsError { arg_name: "mode" })?,
+                };
+                Ok(Self::SetSequenceNumberMode(mode))
+            }
+            "def" => {
+                let (interval_ms, number_packets) = parse_interval_and_count(&mut iter)?;
+                Ok(Self::SetSendDefaults {
+                    interval_ms,
+                    number_packets,
+                })
+            }
+            "payload" => {
+                let mode = iter.next().ok_or(CommandError::UnknownError)?;
+                let length = match iter.next() {
+                    Some(length) => length
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|length| usize::from(*length) <= MAX_PAYLOAD_SIZE)
+                        .ok_or(ArgsError {
+                            arg_name: "payload_length",
+                        })?,
+                    None => DEFAULT_PAYLOAD_SIZE as u16,
+                };
+                let payload = match mode {
+                    "seq" => PayloadMode::Sequential { length },
+                    "rand" => PayloadMode::Random {
+                        length,
+                        seed: parse_seed(iter.next())?,
+                    },
+                    _ => Err(ArgsError { arg_name: "mode" })?,
+                };
+                Ok(Self::SetPayloadMode(payload))
+            }
+            "sched" => {
+                let mode = match iter.next().ok_or(CommandError::UnknownError)? {
+                    "fixed" => ScheduleMode::Fixed,
+                    "poisson" => ScheduleMode::Poisson {
+                        seed: parse_seed(iter.next())?,
+                    },
+                    "burst" => ScheduleMode::Burst {
+                        length: iter
+                            .next()
+                            .and_then(|length| length.parse().ok())
+                            .filter(|length| *length > 0)
+                            .ok_or(ArgsError { arg_name: "length" })?,
+                        gap_ms: iter
+                            .next()
+                            .and_then(parse_duration_ms)
+                            .ok_or(ArgsError { arg_name: "gap" })?,
+                    },
+                    _ => Err(ArgsError { arg_name: "mode" })?,
+                };
+                Ok(Self::SetSchedule(mode))
+            }
+            "sideband" => {
+                let sideband = match iter.next().ok_or(CommandError::UnknownError)? {
+                    "dsb" => None,
+                    "usb" => Some(Sideband::Upper),
+                    "lsb" => Some(Sideband::Lower),
+                    _ => Err(ArgsError { arg_name: "sideband" })?,
+                };
+                Ok(Self::SetSideband(sideband))
+            }
+            "phy" => {
+                let phy = match iter.next().ok_or(CommandError::UnknownError)? {
+                    keying @ ("ook" | "fsk") => {
+                        let bit_rate = iter
+                            .next()
+                            .and_then(parse_bit_rate)
+                            .filter(|bit_rate| *bit_rate > 0)
+                            .ok_or(ArgsError { arg_name: "bit_rate" })?;
+                        let keying = match keying {
+                            "ook" => Keying::Ook,
+                            _ => Keying::Fsk {
+                                deviation_hz: iter
+                                    .next()
+                                    .and_then(|khz| khz.parse::<u32>().ok())
+                                    .and_then(|khz| khz.checked_mul(1000))
+                                    .ok_or(ArgsError {
+                                        arg_name: "deviation",
+                                    })?,
+                            },
+                        };
+                        let coding = match iter.next() {
+                            None | Some("manchester") => LineCoding::Manchester,
+                            Some("nrz") => LineCoding::Nrz,
+                            Some(_) => Err(ArgsError { arg_name: "coding" })?,
+                        };
+                        return Ok(Self::SetKeying {
+                            keying,
+                            coding,
+                            bit_rate,
+                        });
+                    }
+                    "2450" => None,
+                    "bpsk868" => Some(SubGhzPhy::Bpsk868MHz),
+                    "bpsk915" => Some(SubGhzPhy::Bpsk915MHz),
+                    "oqpsk868" => Some(SubGhzPhy::OQpsk868MHz),
+                    "oqpsk915" => Some(SubGhzPhy::OQpsk915MHz),
+                    _ => Err(ArgsError { arg_name: "phy" })?,
+                };
+                Ok(Self::SetSubGhzPhy(phy))
+            }
+            "sync" => {
+                let sync_word = iter
+                    .next()
+                    .and_then(parse_hex_number)
+                    .and_then(|(sync_word, _)| u16::try_from(sync_word).ok())
+                    .ok_or(ArgsError {
+                        arg_name: "sync_word",
+                    })?;
+                let preamble_bytes =
+                    iter.next()
+                        .ok_or(CommandError::UnknownError)?
+                        .parse()
+                        .map_err(|_| ArgsError {
+                            arg_name: "preamble_bytes",
+                        })?;
+                Ok(Self::SetSync {
+                    sync_word,
+                    preamble_bytes,
+                })
+            }
+            "extra" => {
+                let sm = iter
+                    .next()
+                    .ok_or(CommandError::UnknownError)?
+                    .parse()
+                    .ok()
+                    .filter(|sm| (1..=EXTRA_OUTPUTS).contains(sm))
+                    .ok_or(ArgsError { arg_name: "output" })?;
+                let config = match iter.next().ok_or(CommandError::UnknownError)? {
+                    "off" => None,
+                    offset => Some(ExtraOutputConfig {
+                        offset_hz: parse_mhz(offset).ok_or(ArgsError {
+                            arg_name: "frequency",
+                        })?,
+                        frame: iter.next().map(parse_frame).transpose()?,
+                    }),
+                };
+                Ok(Self::SetExtraOutput { sm, config })
             }
+            "pins" => {
+                let change = match iter.next().ok_or(CommandError::UnknownError)? {
+                    "trigger" => PinChange::Trigger {
+                        gpio: parse_gpio(iter.next(), "trigger")?,
+                        external: match iter.next() {
+                            None => false,
+                            Some("ext") => true,
+                            Some(_) => Err(ArgsError { arg_name: "ext" })?,
+                        },
+                    },
+                    "antenna" => PinChange::Antenna {
+                        gpio: parse_gpio(iter.next(), "antenna")?,
+                        quadrature: match iter.next() {
+                            None => true,
+                            Some("noq") => false,
+                            Some(_) => Err(ArgsError { arg_name: "noq" })?,
+                        },
+                    },
+                    "extra" => PinChange::Extra {
+                        sm: iter
+                            .next()
+                            .ok_or(CommandError::UnknownError)?
+                            .parse()
+                            .ok()
+                            .filter(|sm| (1..=EXTRA_OUTPUTS).contains(sm))
+                            .ok_or(ArgsError { arg_name: "output" })?,
+                        gpio: match iter.next() {
+                            Some("none") => None,
+                            gpio => Some(parse_gpio(gpio, "gpio")?),
+                        },
+                    },
+                    "default" => PinChange::Default,
+                    _ => Err(ArgsError { arg_name: "pin" })?,
+                };
+                Ok(Self::SetPins(change))
+            }
+            "send" => Ok(Self::Send),
+            "cfg" => Ok(Self::ShowConfig),
+            "save" => Ok(Self::Save),
+            "load" => Ok(Self::Load),
+            "factory-reset" => Ok(Self::FactoryReset),
             _ => Err(CommandError::UnknownCommand(input.as_str())),
         }
     }
 }
 
+/// parse a PHY frame given as hex
+fn parse_frame(frame_str: &str) -> Result<Vec<u8, MAX_FRAME_SIZE>, CommandError<'_>> {
+    get_hex_string_as_bytes(frame_str).map_err(|error| match error {
+        HexStringError::TooLong => CommandError::TooLong {
+            arg_name: "frame",
+            max_len: MAX_FRAME_SIZE,
+        },
+        error => CommandError::InvalidHex {
+            arg_name: "frame",
+            error,
+        },
+    })
+}
+
+/// split off the first word, returns (word, the rest of the input)
+fn split_first_word(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    input.split_at(input.find(char::is_whitespace).unwrap_or(input.len()))
+}
+
+/// parse `<interval> <number_packets> [payload_length]`, shared by the packet sending commands
+fn parse_packet_args<'a>(
+    iter: &mut impl Iterator<Item = &'a str>,
+) -> Result<(u32, u32, Option<u32>), CommandError<'a>> {
+    let (interval_ms, number_packets) = parse_interval_and_count(iter)?;
+
+    let payload_length = {
+        let option = iter.next().map(|v| {
+            v.parse::<u32>().map_err(|_| ArgsError {
+                arg_name: "payload_length",
+            })
+        });
+        if let Some(result) = option {
+            Some(result?)
+        } else {
+            None
+        }
+    };
+
+    if payload_length > Some(MAX_PAYLOAD_SIZE as u32) {
+        return Err(ArgsError {
+            arg_name: "payload_length",
+        });
+    }
+
+    Ok((interval_ms, number_packets, payload_length))
+}
+
+/// parse `<pan_id> <address>` or `none`, both are hex with an optional 0x,
+/// an address of up to 4 digits is short and a longer one is extended
+fn parse_address<'a>(iter: &mut impl Iterator<Item = &'a str>) -> Result<Option<Address>, CommandError<'a>> {
+    let pan_id_str = iter.next().ok_or(CommandError::UnknownError)?;
+    if pan_id_str == "none" {
+        return Ok(None);
+    }
+    let Some((pan_id, 1..=4)) = parse_hex_number(pan_id_str) else {
+        return Err(ArgsError { arg_name: "pan_id" });
+    };
+    let pan_id = PanId(pan_id as u16);
+
+    match parse_hex_number(iter.next().ok_or(CommandError::UnknownError)?) {
+        Some((address, 1..=4)) => Ok(Some(Address::Short(pan_id, ShortAddress(address as u16)))),
+        Some((address, _)) => Ok(Some(Address::Extended(pan_id, ExtendedAddress(address)))),
+        None => Err(ArgsError { arg_name: "address" }),
+    }
+}
+
+/// parse up to 16 hex digits with an optional 0x, returns (number, number of digits)
+fn parse_hex_number(value: &str) -> Option<(u64, usize)> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    if digits.is_empty() || digits.len() > 16 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    u64::from_str_radix(digits, 16)
+        .ok()
+        .map(|number| (number, digits.len()))
+}
+
+/// parse an optional seed, decimal or hex (42/0x2A)
+fn parse_seed<'a>(value: Option<&str>) -> Result<u64, CommandError<'a>> {
+    let Some(value) = value else {
+        return Ok(DEFAULT_SEED);
+    };
+    let seed = if let Some(hex) = value.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else {
+        value.parse()
+    };
+    seed.map_err(|_| ArgsError { arg_name: "seed" })
+}
+
+/// parse a sequence number, decimal or hex (42/0x2A)
+fn parse_sequence_number<'a>(value: Option<&str>, arg_name: &'static str) -> Result<u8, CommandError<'a>> {
+    let value = value.ok_or(CommandError::UnknownError)?;
+    let sequence_number = if let Some(hex) = value.strip_prefix("0x") {
+        u8::from_str_radix(hex, 16)
+    } else {
+        value.parse()
+    };
+    sequence_number.map_err(|_| ArgsError { arg_name })
+}
+
+/// parse a BLE advertising channel, 37, 38 or 39
+fn parse_ble_channel(value: &str) -> Result<AdvertisingChannel, CommandError<'_>> {
+    value
+        .parse()
+        .ok()
+        .and_then(AdvertisingChannel::from_index)
+        .ok_or(ArgsError { arg_name: "channel" })
+}
+
+/// parse a GPIO number, 0 to 29
+fn parse_gpio<'a>(value: Option<&str>, arg_name: &'static str) -> Result<u8, CommandError<'a>> {
+    value
+        .ok_or(CommandError::UnknownError)?
+        .parse()
+        .ok()
+        .filter(|gpio| *gpio < GPIO_COUNT)
+        .ok_or(ArgsError { arg_name })
+}
+
+/// parse `<interval> <number_packets>`
+fn parse_interval_and_count<'a>(
+    iter: &mut impl Iterator<Item = &'a str>,
+) -> Result<(u32, u32), CommandError<'a>> {
+    let interval_ms_str = iter.next().ok_or(CommandError::UnknownError)?;
+    let interval_ms = parse_duration_ms(interval_ms_str).ok_or(ArgsError {
+        arg_name: "interval_ms",
+    })?;
+
+    let number_packets = iter
+        .next()
+        .ok_or(CommandError::UnknownError)?
+        .parse()
+        .map_err(|_| ArgsError {
+            arg_name: "number_packets",
+        })?;
+
+    Ok((interval_ms, number_packets))
+}
+
+/// parse a duration in milliseconds or seconds (1s/1000ms/1000)
+fn parse_duration_ms(value: &str) -> Option<u32> {
+    if let Some(ms) = value.strip_suffix("ms") {
+        ms.parse().ok()
+    } else if let Some(secs) = value.strip_suffix("s") {
+        secs.parse::<u32>().ok()?.checked_mul(1000)
+    } else {
+        value.parse().ok()
+    }
+}
+
+/// parse a bit rate in b/s, k for kb/s (4800/38k4/10k)
+fn parse_bit_rate(value: &str) -> Option<u32> {
+    let Some((kilo, fraction)) = value.split_once('k') else {
+        return value.parse().ok();
+    };
+    if fraction.len() > 3 || !fraction.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let fraction = fraction
+        .bytes()
+        .chain(core::iter::repeat(b'0'))
+        .take(3)
+        .fold(0, |bits, digit| bits * 10 + u32::from(digit - b'0'));
+    kilo.parse::<u32>().ok()?.checked_mul(1000)?.checked_add(fraction)
+}
+
+/// parse a frequency in MHz, up to 6 decimals (2.5/2.5mhz/2.5MHz)
+fn parse_mhz(value: &str) -> Option<u32> {
+    let value = value
+        .strip_suffix("MHz")
+        .or_else(|| value.strip_suffix("mhz"))
+        .unwrap_or(value);
+    let (mhz, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > 6 || !fraction.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let fraction_hz = fraction
+        .bytes()
+        .chain(core::iter::repeat(b'0'))
+        .take(6)
+        .fold(0, |hz, digit| hz * 10 + u32::from(digit - b'0'));
+    mhz.parse::<u32>()
+        .ok()?
+        .checked_mul(1_000_000)?
+        .checked_add(fraction_hz)
+}
+
 fn help(serial: &mut USBSerial) {
     const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:help";
 
@@ -147,20 +695,130 @@ fn help(serial: &mut USBSerial) {
     \n\r\t Example: ssp 1s 1000 5\
     \n\r\t This will send 1000 packets with an interval of 1 second with a payload of 5 bytes\
 \n\
-    \n\r- srp <interval> <number_packets> <payload_length=4>\
-    \n\r\t (unimplemented) send a packet with with a different payload each time,\
-    the bytes will be sort of random each packet but will be the same each time this is run\
+    \n\r- srp <interval> <number_packets> <payload_length=4> <seed=0>\
+    \n\r\t send a packet with a different random payload each time,\
+    the payloads only depend on the seed so a run can be repeated and checked on the receiver,\
+    each payload is printed as hex before it is sent\
     \n\r\t- interval: interval between packets in millisecond or seconds (1s/1000ms/1000)\
     \n\r\t- number_packets: number of packets to send\
     \n\r\t- payload_length: how long the payload should be (optional, default:4)\
-    \n\r\t Example: srp 10 1000\
-    \n\r\t This will send 1000 packets with an interval of 10 milliseconds\
+    \n\r\t- seed: seed for the payloads, decimal or hex (optional, default:0, 42/0x2A)\
+    \n\r\t Example: srp 10 1000 8 42\
+    \n\r\t This will send 1000 packets with an interval of 10 milliseconds with 8 byte payloads from seed 42\
+\n\
+    \n\r- srf <interval> <number_packets> <frame>\
+    \n\r\t send a raw PHY frame as is, preamble, SFD, length and FCS included\
+    \n\r\t- frame: the frame as hex without spaces or 0x\
+    \n\r\t Example: srf 1s 10 00000000A70F019801222234124444CDAB01027652\
+\n\
+    \n\r- shp <interval> <number_packets> <payload>\
+    \n\r\t send a frame with the payload given as hex, the MAC header (see cfg) and FCS are added\
+    \n\r\t- payload: the payload as hex without spaces or 0x\
+    \n\r\t Example: shp 100ms 10 0102AABB\
+\n\
+    \n\r- sap <interval> <number_packets> <text>\
+    \n\r\t send a frame with an ASCII text payload, the text is the rest of the line\
+    \n\r\t Example: sap 1s 5 hello world\
+\n\
+    \n\r- sba <interval> <number_packets> <channel> <name>\
+    \n\r\t send a BLE advertisement with the name, the rest of the line, as 2-FSK on the antenna pin,\
+    a phone sees it from C0:FF:EE:12:34:56 with the carrier one offset below the channel\
+    \n\r\t- channel: the advertising channel, 37 (2402MHz), 38 (2426MHz) or 39 (2480MHz)\
+    \n\r\t Example: sba 100ms 100 37 pico tag\
+\n\
+    \n\r- sbr <interval> <number_packets> <channel> <ad_data>\
+    \n\r\t send a BLE advertisement with raw AD structures like sba\
+    \n\r\t- ad_data: up to 31 bytes of AD structures as hex without spaces or 0x\
+    \n\r\t Example: sbr 100ms 100 38 020106050950494330\
 \n\
     \n\r- freq <frequency>\
     \n\r\t change the frequency offset, starts at 8MHz\
-    \n\r\t- frequency: the offset frequecy of 2/4/8 MHz (2/2mhz/2MHz)\
-    \n\r\t Example: freq 2\
-    \n\r\t This will set the offset frequency to 2Mhz\
+    \n\r\t- frequency: the offset in MHz from 1.5 to 9 (2/2.5/2mhz/2MHz), 2, 4, 6 and 8 are the\
+    standard options, the clocks for the others are planned and the plan is shown\
+    \n\r\t Example: freq 5\
+    \n\r\t This will set the offset frequency to 5MHz\
+    \n\r\t the system clock changes right away when the offset needs another one\
+\n\
+    \n\r- src <pan_id> <address> / src none\
+    \n\r- dst <pan_id> <address> / dst none\
+    \n\r\t set the source/destination of the frames ssp, srp, shp and sap build, none leaves it out\
+    \n\r\t- pan_id: the PAN ID as hex (0x2222/2222)\
+    \n\r\t- address: the address as hex, up to 4 digits is a short address, more is an extended one\
+    \n\r\t Example: dst 0x1AAA 0x0011223344556677\
+\n\
+    \n\r- seq fixed <n> / seq inc <start=0> / seq wrap <start> <end>\
+    \n\r\t set the sequence number, the same every packet, one more every packet or\
+    counting from start to end then starting again, starts as fixed 1\
+    \n\r\t Example: seq wrap 0 9\
+\n\
+    \n\r- def <interval> <number_packets>\
+    \n\r- payload seq <payload_length=4> / payload rand <payload_length=4> <seed=0>\
+    \n\r- send\
+    \n\r\t send with the interval, number of packets and payload set by def and payload,\
+    payload seq is like ssp and payload rand is like srp, starts as 1s 10 seq 4\
+    \n\r\t Example: def 10ms 500 then payload rand 16 42 then send\
+\n\
+    \n\r- sched fixed / sched poisson <seed=0> / sched burst <length> <gap>\
+    \n\r\t how every command that sends spreads its packets, the interval is from the start of one\
+    packet to the start of the next whatever the airtime of the packets, starts as fixed\
+    \n\r\t- fixed: a packet every interval\
+    \n\r\t- poisson: random gaps averaging the interval, the gaps only depend on the seed\
+    \n\r\t- burst: trains of length packets an interval apart, gap from the last packet of a train\
+    to the first of the next\
+    \n\r\t Example: sched burst 5 1s\
+    \n\r\t the periods achieved are shown when sending is done\
+\n\
+    \n\r- sideband dsb / sideband usb / sideband lsb\
+    \n\r\t dsb toggles the antenna pin (GPIO6) and makes both sidebands, usb and lsb drive the antenna\
+    pin and the Q pin after it (GPIO7) a quarter subcarrier period apart so only the upper or lower\
+    sideband is left, for a 4 state RF switch, starts as dsb\
+\n\
+    \n\r- phy 2450 / phy bpsk868 / phy bpsk915 / phy oqpsk868 / phy oqpsk915\
+    \n\r\t the 802.15.4 PHY of the frames ssp, srp, srf, shp, sap and send send, 2450 is the 2.4GHz\
+    O-QPSK, the others are the 868MHz and 915MHz BPSK and O-QPSK PHYs on the antenna pin alone\
+    whatever the sideband, starts as 2450\
+    \n\r\t the subcarrier is the frequency offset, the chips get slower so a frame takes longer\
+    \n\r\t Example: phy bpsk915\
+\n\
+    \n\r- phy ook <bit_rate> <coding> / phy fsk <bit_rate> <deviation> <coding>\
+    \n\r\t send the frames with on-off keying or 2-FSK of the subcarrier instead, for simple receivers,\
+    the preamble and SFD are swapped for 0xAA bytes and a sync word, the rest goes most significant bit\
+    first, phy 2450 or a sub-GHz PHY turns it off\
+    \n\r\t- bit_rate: bits per second, k for kb/s (4800/38k4/10k)\
+    \n\r\t- deviation: the FSK tones are this many kHz above and below the frequency offset\
+    \n\r\t- coding: manchester or nrz (optional, default:manchester)\
+    \n\r\t Example: phy fsk 10k 100\
+\n\
+    \n\r- sync <sync_word> <preamble_bytes>\
+    \n\r\t the sync word and number of 0xAA preamble bytes of phy ook and fsk, starts as 0xD391 4\
+    \n\r\t Example: sync 0x2DD4 8\
+\n\
+    \n\r- extra <output> <frequency> <frame> / extra <output> off\
+    \n\r\t send on another antenna pin with every packet, started in the same cycle, outputs 1, 2 and 3\
+    are GPIO8, GPIO9 and GPIO10 unless pins extra moved them, they send both sidebands and aren't saved\
+    \n\r\t- frequency: the offset in MHz, all outputs share the system clock so only the PIO divider is\
+    planned for it, at 128MHz (2, 4 and 8MHz) only 2, 4 and 8MHz work\
+    \n\r\t- frame: a raw PHY frame as hex like srf (optional, default: the frame of every packet)\
+    \n\r\t Example: extra 1 4 then extra 2 2 00000000A70F019801222234124444CDAB01027652\
+\n\
+    \n\r- pins trigger <gpio> <ext> / pins antenna <gpio> <noq> / pins extra <output> <gpio>\
+    / pins extra <output> none / pins default\
+    \n\r\t set the pins of the board, they are used once they are saved and the pico restarts,\
+    starts as trigger GPIO3, antenna GPIO6 and Q GPIO7, extra outputs GPIO8 to GPIO10\
+    \n\r\t- trigger: every packet starts when this pin goes low, the pico drives it, with ext another\
+    device does and the pico only pulls it up, the packets then wait for it\
+    \n\r\t- antenna: the antenna pin, the pin after it is the Q pin, noq leaves the Q pin out\
+    \n\r\t- extra: the pin of an extra output, none leaves the output out\
+    \n\r\t Example: pins trigger 15 ext then pins antenna 2 then save then restart\
+\n\
+    \n\r- cfg > show the frequency offset, sideband, PHY, addresses, sequence number mode, send settings,\
+    schedule, pins and extra outputs\
+\n\
+    \n\r- save > keep the settings shown by cfg in flash, they are loaded when the pico starts\
+\n\
+    \n\r- load > go back to the saved settings\
+\n\
+    \n\r- factory-reset > erase the saved settings and go back to the defaults\
     "
         .fg::<Green>()
     )
@@ -170,67 +828,252 @@ fn restart() {
     reset();
 }
 
-fn check_packet_size(packet_pio_iter: ConvertIterType, serial: &mut USBSerial) {
-    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:check_packet_size";
-    let (len, _) = packet_pio_iter
-        .enumerate()
-        .last()
-        .expect("packet iterator should have a defined size");
-    info!("Iterator is {} u32 long", len);
-    if len > MAX_PACKET_PIO_BUFFER {
-        warn!(
-            "Packet pio iterator length is {} longer than {} item buffer!\n\
-       this means the whole packet will not be transmitted",
-            len - MAX_PACKET_PIO_BUFFER,
-            MAX_PACKET_PIO_BUFFER
-        );
+const DEFAULT_PAYLOAD_SIZE: u32 = 4;
+pub(crate) const MAX_PAYLOAD_SIZE: usize = 1000;
+const DEFAULT_SEED: u64 = 0;
+/// The static random address the advertisements are sent from, C0:FF:EE:12:34:56
+const BLE_ADDRESS: [u8; 6] = [0xC0, 0xFF, 0xEE, 0x12, 0x34, 0x56];
+pub(crate) const MAX_FRAME_SIZE: usize = to_max_frame_size!(MAX_PAYLOAD_SIZE);
+/// long enough for a hex string of the largest frame and the command in front of it
+const MAX_COMMAND_LENGTH: usize = 2 * MAX_FRAME_SIZE + 32;
+
+struct UserPacketOptions {
+    payload_length: Option<u32>,
+    interval_ms: u32,
+    number_packets: u32,
+}
+
+fn send_generic_packet(
+    serial: &mut USBSerial,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    session: &SessionConfig,
+    user_options: UserPacketOptions,
+) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:send_generic_packet";
+
+    let UserPacketOptions {
+        payload_length,
+        interval_ms,
+        number_packets,
+    } = user_options;
+
+    let payload_size = payload_length.unwrap_or(DEFAULT_PAYLOAD_SIZE);
+
+    writeln!(serial, "sending generic packet...").expect(SERIAL_PANIC_ERROR_MESSAGE);
+    writeln!(
+        serial,
+        "interval_ms: {}, number_packets: {}, payload_size:{}",
+        interval_ms, number_packets, payload_size
+    )
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+
+    let payload = get_seq_payload::<MAX_PAYLOAD_SIZE>(payload_size as usize);
+
+    send_payload_packets(
+        serial,
+        timer,
+        pio_dma,
+        pio_ctrl,
+        session,
+        interval_ms,
+        number_packets,
+        &mut |_, packet_number, current_payload| same_payload(&payload, packet_number, current_payload),
+    );
+}
+
+/// `next_payload` for [send_payload_packets] when every packet has the same payload
+fn same_payload(payload: &[u8], packet_number: u32, current_payload: &mut Vec<u8, MAX_PAYLOAD_SIZE>) -> bool {
+    if packet_number != 1 {
+        return false;
+    }
+    current_payload.clear();
+    current_payload
+        .extend_from_slice(payload)
+        .expect("payload is never longer than MAX_PAYLOAD_SIZE");
+    true
+}
+
+/// Send the same raw frame `number_packets` times as `phy`
+#[allow(clippy::too_many_arguments)]
+fn send_frame(
+    serial: &mut USBSerial,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    session: &SessionConfig,
+    interval_ms: u32,
+    number_packets: u32,
+    phy: Phy,
+    frame_bytes: &[u8],
+) {
+    send_packets_with_progress(
+        serial,
+        timer,
+        Schedule::new(interval_ms, session.send.schedule),
+        number_packets,
+        pio_dma,
+        pio_ctrl,
+        &mut |serial, _, pio_dma| {
+            // the same packet every time
+            pio_dma.is_loaded()
+                || extra_outputs_loaded(serial, pio_dma.load(session.transmit_option, phy, frame_bytes))
+        },
+    )
+}
+
+/// Send `number_packets` frames built by the session's [FrameConfig](pico_qpsk_core::session_config::FrameConfig),
+/// `next_payload` is called before every packet with the packet number and the last payload,
+/// it returns true when it changed the payload
+#[allow(clippy::too_many_arguments)]
+fn send_payload_packets(
+    serial: &mut USBSerial,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    session: &SessionConfig,
+    interval_ms: u32,
+    number_packets: u32,
+    next_payload: &mut (impl FnMut(&mut USBSerial, u32, &mut Vec<u8, MAX_PAYLOAD_SIZE>) -> bool + Sized),
+) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:send_payload_packets";
+
+    let mut payload = Vec::new();
+
+    send_packets_with_progress(
+        serial,
+        timer,
+        Schedule::new(interval_ms, session.send.schedule),
+        number_packets,
+        pio_dma,
+        pio_ctrl,
+        &mut |serial, packet_number, pio_dma| {
+            let payload_changed = next_payload(serial, packet_number, &mut payload);
+            // the last frame can be sent again if nothing in it changes
+            if !payload_changed && pio_dma.is_loaded() && session.frame.sequence_number.is_fixed() {
+                return true;
+            }
+            match session
+                .frame
+                .frame_bytes::<MAX_FRAME_SIZE>(packet_number - 1, &payload)
+            {
+                Ok(frame_bytes) => extra_outputs_loaded(
+                    serial,
+                    pio_dma.load(session.transmit_option, session.phy(), &frame_bytes),
+                ),
+                Err(error) => {
+                    writeln!(
+                        serial,
+                        "{} {:?}",
+                        "payload does not fit in a frame:".fg::<Red>(),
+                        error
+                    )
+                    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+                    false
+                }
+            }
+        },
+    )
+}
+
+/// Report the extra outputs a frame is too long for, the packet isn't sent without them
+///
+/// returns: true when every extra output has its packet
+fn extra_outputs_loaded(serial: &mut USBSerial, too_long: Vec<usize, EXTRA_OUTPUTS>) -> bool {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:extra_outputs_loaded";
+    for sm in &too_long {
         writeln!(
             serial,
-            "{}{}{}{}{}{}",
-            "Packet pio iterator length is "
-                .color(XtermColors::White)
-                .on_color(XtermColors::BlazeOrange)
-                .italic(),
-            len - MAX_PACKET_PIO_BUFFER,
-            " longer than "
-                .color(XtermColors::White)
-                .on_color(XtermColors::BlazeOrange)
-                .italic(),
-            MAX_PACKET_PIO_BUFFER,
-            " item buffer!\n"
-                .color(XtermColors::White)
-                .on_color(XtermColors::BlazeOrange)
-                .italic(),
-            "this means the whole packet will not be transmitted"
-                .color(XtermColors::White)
-                .on_color(XtermColors::BlazeOrange)
-                .italic(),
+            "{} {}",
+            "the frame is too long for the bytecode buffer of extra output".fg::<Red>(),
+            sm
         )
         .expect(SERIAL_PANIC_ERROR_MESSAGE);
     }
+    too_long.is_empty()
 }
 
-const DEFAULT_PAYLOAD_SIZE: u32 = 4;
-const MAX_PAYLOAD_SIZE: usize = 1000;
-
-struct UserPacketOptions {
-    transmit_option: StandardTransmitOption,
-    payload_length: Option<u32>,
+/// Send `number_packets` BLE advertisements of `data` on `channel`
+///
+/// The state machine runs at the system clock on the antenna pin alone for them, the session's divider
+/// and sideband are back once they are sent
+#[allow(clippy::too_many_arguments)]
+fn send_advertisement(
+    serial: &mut USBSerial,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    session: &SessionConfig,
     interval_ms: u32,
     number_packets: u32,
+    channel: AdvertisingChannel,
+    data: AdvertisingData,
+) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:send_advertisement";
+
+    let option = session.transmit_option;
+    let offset_hz = option.offset_hz();
+    if let Err(error) = ble::fsk_timing(option.processor_clock().hz(), offset_hz).check() {
+        warn!("no BLE at {}Hz: {}", offset_hz, error);
+        writeln!(
+            serial,
+            "{}",
+            "the offset is too high for the 250kHz deviation at this clock".fg::<Red>()
+        )
+        .expect(SERIAL_PANIC_ERROR_MESSAGE);
+        return;
+    }
+    let advertisement = Advertisement {
+        address: BLE_ADDRESS,
+        random_address: true,
+        data,
+    };
+    let packet = ble::air_packet(&advertisement, channel);
+    // the upper sideband lands on the channel
+    let carrier_khz = u32::from(channel.mhz()) * 1000 - offset_hz / 1000;
+    writeln!(serial, "sending BLE advertisement...").expect(SERIAL_PANIC_ERROR_MESSAGE);
+    writeln!(
+        serial,
+        "interval_ms: {}, number_packets: {}, channel: {} ({}MHz), carrier: {}.{:03}MHz",
+        interval_ms,
+        number_packets,
+        channel.index(),
+        channel.mhz(),
+        carrier_khz / 1000,
+        carrier_khz % 1000
+    )
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+
+    pio_ctrl.use_two_pins(Phy::Ble1M.two_pins());
+    pio_ctrl.change_clock_divider(option.state_machine_clock_for(Phy::Ble1M));
+    send_frame(
+        serial,
+        timer,
+        pio_dma,
+        pio_ctrl,
+        session,
+        interval_ms,
+        number_packets,
+        Phy::Ble1M,
+        &packet,
+    );
+    pio_ctrl.change_clock_divider(option.state_machine_clock());
+    pio_ctrl.use_two_pins(session.phy().two_pins());
 }
 
-fn send_generic_packet(
+fn send_random_packet(
     serial: &mut USBSerial,
-    delay: &mut Delay,
-    tx: &mut Tx<(PIO0, SM0)>,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
     pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    session: &SessionConfig,
     user_options: UserPacketOptions,
+    seed: u64,
 ) {
-    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:send_generic_packet";
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:send_random_packet";
 
     let UserPacketOptions {
-        transmit_option,
         payload_length,
         interval_ms,
         number_packets,
@@ -238,29 +1081,58 @@ fn send_generic_packet(
 
     let payload_size = payload_length.unwrap_or(DEFAULT_PAYLOAD_SIZE);
 
-    writeln!(serial, "sending generic packet...").expect(SERIAL_PANIC_ERROR_MESSAGE);
+    writeln!(serial, "sending random packet...").expect(SERIAL_PANIC_ERROR_MESSAGE);
     writeln!(
         serial,
-        "interval_ms: {}, number_packets: {}, payload_size:{}",
-        interval_ms, number_packets, payload_size
+        "interval_ms: {}, number_packets: {}, payload_size:{}, seed:{}",
+        interval_ms, number_packets, payload_size, seed
     )
     .expect(SERIAL_PANIC_ERROR_MESSAGE);
 
-    let frame_bytes = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, { to_max_frame_size!(MAX_PAYLOAD_SIZE) }>(
-        payload_size as usize,
+    let mut payloads = random_payloads::<MAX_PAYLOAD_SIZE>(seed, payload_size as usize);
+
+    send_payload_packets(
+        serial,
+        timer,
+        pio_dma,
+        pio_ctrl,
+        session,
+        interval_ms,
+        number_packets,
+        &mut |serial, packet_number, payload| {
+            *payload = payloads.next().expect("random payloads never end");
+            // echo the payload so the receiver can check it
+            write!(serial, "payload {}/{}: ", packet_number, number_packets)
+                .expect(SERIAL_PANIC_ERROR_MESSAGE);
+            for byte in payload.iter() {
+                write!(serial, "{:02X}", byte).expect(SERIAL_PANIC_ERROR_MESSAGE);
+            }
+            writeln!(serial).expect(SERIAL_PANIC_ERROR_MESSAGE);
+            true
+        },
     );
-    let packet_pio_iter = transmit_option.convert(&frame_bytes);
+}
 
-    check_packet_size(packet_pio_iter.clone(), serial);
+/// [send_packets] with the usual progress messages
+fn send_packets_with_progress(
+    serial: &mut USBSerial,
+    timer: &mut PacketTimer,
+    schedule: Schedule,
+    number_packets: u32,
+    pio_dma: &mut PioDma,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    fill_buffer: &mut (impl FnMut(&mut USBSerial, u32, &mut PioDma) -> bool + Sized),
+) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:send_packets_with_progress";
 
     send_packets(
         serial,
-        delay,
-        interval_ms,
+        timer,
+        schedule,
         number_packets,
-        tx,
+        pio_dma,
         pio_ctrl,
-        packet_pio_iter,
+        fill_buffer,
         &mut |serial, packets_sent| {
             info!("sending packet {}/{} ", packets_sent, number_packets);
             writeln!(
@@ -272,7 +1144,17 @@ fn send_generic_packet(
             )
             .expect(SERIAL_PANIC_ERROR_MESSAGE);
         },
-        &mut |serial, packets_sent| {
+        &mut |serial, packet_number| {
+            warn!("packet {} was cut short", packet_number);
+            writeln!(
+                serial,
+                "{} {}",
+                "the pio bytecode couldn't be generated fast enough, cut short packet".fg::<Red>(),
+                packet_number
+            )
+            .expect(SERIAL_PANIC_ERROR_MESSAGE);
+        },
+        &mut |serial, packets_sent, stats| {
             writeln!(
                 serial,
                 "{} {}/{} packets sent",
@@ -284,68 +1166,557 @@ fn send_generic_packet(
                 number_packets
             )
             .expect(SERIAL_PANIC_ERROR_MESSAGE);
+            write_period_stats(serial, stats);
         },
-        &mut |serial| {
+        &mut |serial, stats| {
             info!("stopped sending packets");
             writeln!(serial, "{}", "Done!".fg::<Green>()).expect(SERIAL_PANIC_ERROR_MESSAGE);
+            write_period_stats(serial, stats);
         },
+        &mut |serial| serial.poll_is_etx(),
     )
 }
 
-const MAX_PACKET_PIO_BUFFER: usize = 4000;
-
+/// Send `number_packets` packets, `fill_buffer` is called before every packet with the packet number
+/// and the [PioDma] to load it into, the last packet stays loaded so it only needs to be loaded when
+/// the packet changes, returning false stops early without sending the packet.
+/// The first packet starts once it is ready, the next ones at the start times of `schedule` counted
+/// from it, the periods achieved are passed to `on_exit_early` and `on_exit_normal`.
+/// `on_underrun` is called when a streamed packet was cut short, sending stops there.
+/// `should_stop` is called after every packet, returning true stops early
 #[allow(clippy::too_many_arguments)]
-fn send_packets(
+pub(crate) fn send_packets(
     serial: &mut USBSerial,
-    delay: &mut Delay,
-    interval_ms: u32,
+    timer: &mut PacketTimer,
+    schedule: Schedule,
     number_packets: u32,
-    tx: &mut Tx<(PIO0, SM0)>,
+    pio_dma: &mut PioDma,
     pio_ctrl: &mut PioControl<PIO0, PullDown>,
-    packet_pio_iter: ConvertIterType,
+    fill_buffer: &mut (impl FnMut(&mut USBSerial, u32, &mut PioDma) -> bool + Sized),
     on_send_packet: &mut (impl FnMut(&mut USBSerial, u32) + Sized),
-    on_exit_early: &mut (impl FnMut(&mut USBSerial, u32) + Sized),
-    on_exit_normal: &mut (impl FnMut(&mut USBSerial) + Sized),
+    on_underrun: &mut (impl FnMut(&mut USBSerial, u32) + Sized),
+    on_exit_early: &mut (impl FnMut(&mut USBSerial, u32, &PeriodStats) + Sized),
+    on_exit_normal: &mut (impl FnMut(&mut USBSerial, &PeriodStats) + Sized),
+    should_stop: &mut (impl FnMut(&mut USBSerial) -> bool + Sized),
 ) {
-    let mut buffer: Vec<u32, MAX_PACKET_PIO_BUFFER> = Vec::new();
-    let iter = packet_pio_iter.take(MAX_PACKET_PIO_BUFFER);
-    buffer.extend(iter);
+    // whatever the last command loaded is not this packet
+    pio_dma.clear();
+    let mut stats = PeriodStats::default();
+    // timer times of the first packet's start and the last one's
+    let mut first_start_us = None;
+    let mut last_start_us = 0;
+
+    for (i, start_us) in (0..number_packets).zip(schedule) {
+        if !fill_buffer(serial, i + 1, pio_dma) {
+            pio_ctrl.stop();
+            on_exit_early(serial, i, &stats);
 
-    for i in 0..number_packets {
-        let mut started = false;
+            return;
+        }
         on_send_packet(serial, i + 1);
-        for i in &buffer {
-            while tx.is_full() {
-                if !started {
-                    started = true;
-                    pio_ctrl.start();
-                }
+        let sent = pio_dma.send(serial, pio_ctrl, &mut |serial| {
+            if let Some(first_start_us) = first_start_us {
+                let late = !timer.wait_until(serial, first_start_us + start_us);
+                stats.record(timer.now_us() - last_start_us, late);
             }
-            tx.write(*i);
+            last_start_us = timer.now_us();
+            first_start_us.get_or_insert(last_start_us);
+        });
+        if sent.is_err() {
+            pio_ctrl.stop();
+            on_underrun(serial, i + 1);
+            on_exit_early(serial, i, &stats);
+
+            return;
         }
-        delay.delay_ms(interval_ms);
-        if serial.poll_is_etx() {
+        if should_stop(serial) {
             pio_ctrl.stop();
-            on_exit_early(serial, i + 1);
+            on_exit_early(serial, i + 1, &stats);
 
             return;
         }
     }
     pio_ctrl.stop();
-    on_exit_normal(serial);
+    on_exit_normal(serial, &stats);
+}
+
+fn write_address(serial: &mut USBSerial, name: &str, address: Option<Address>) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_address";
+    match address {
+        Some(Address::Short(pan_id, address)) => {
+            writeln!(serial, "{name}: PAN 0x{:04X} short 0x{:04X}", pan_id.0, address.0)
+        }
+        Some(Address::Extended(pan_id, address)) => writeln!(
+            serial,
+            "{name}: PAN 0x{:04X} extended 0x{:016X}",
+            pan_id.0, address.0
+        ),
+        None => writeln!(serial, "{name}: none"),
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+fn write_sequence_number_mode(serial: &mut USBSerial, mode: SequenceNumberMode) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_sequence_number_mode";
+    match mode {
+        SequenceNumberMode::Fixed(sequence_number) => {
+            writeln!(serial, "sequence number: fixed {sequence_number}")
+        }
+        SequenceNumberMode::Increment { start } => {
+            writeln!(serial, "sequence number: increment from {start}")
+        }
+        SequenceNumberMode::Wrap { start, end } => {
+            writeln!(serial, "sequence number: {start} to {end} then wrap")
+        }
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+fn write_send_defaults(serial: &mut USBSerial, session: &SessionConfig) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_send_defaults";
+    let send = &session.send;
+    write!(
+        serial,
+        "send: interval_ms: {}, number_packets: {}, ",
+        send.interval_ms, send.number_packets
+    )
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    match send.payload {
+        PayloadMode::Sequential { length } => writeln!(serial, "sequential payload_size:{length}"),
+        PayloadMode::Random { length, seed } => {
+            writeln!(serial, "random payload_size:{length}, seed:{seed}")
+        }
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+fn write_schedule(serial: &mut USBSerial, mode: ScheduleMode) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_schedule";
+    match mode {
+        ScheduleMode::Fixed => writeln!(serial, "schedule: fixed"),
+        ScheduleMode::Poisson { seed } => writeln!(serial, "schedule: poisson seed:{seed}"),
+        ScheduleMode::Burst { length, gap_ms } => {
+            writeln!(serial, "schedule: bursts of {length}, gap_ms: {gap_ms}")
+        }
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+fn write_sideband(serial: &mut USBSerial, sideband: Option<Sideband>) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_sideband";
+    match sideband {
+        None => writeln!(serial, "sideband: both, antenna pin only"),
+        Some(Sideband::Upper) => writeln!(serial, "sideband: upper, I and Q pins"),
+        Some(Sideband::Lower) => writeln!(serial, "sideband: lower, I and Q pins"),
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+fn write_phy(serial: &mut USBSerial, session: &SessionConfig) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_phy";
+    match session.phy() {
+        Phy::Keyed(config) => {
+            match config.keying {
+                Keying::Ook => write!(serial, "phy: OOK"),
+                Keying::Fsk { deviation_hz } => write!(serial, "phy: 2-FSK +-{}kHz", deviation_hz / 1000),
+            }
+            .expect(SERIAL_PANIC_ERROR_MESSAGE);
+            writeln!(
+                serial,
+                ", {}b/s {}, {} preamble bytes, sync word 0x{:04X}, antenna pin only",
+                config.bit_rate,
+                match config.coding {
+                    LineCoding::Nrz => "NRZ",
+                    LineCoding::Manchester => "Manchester",
+                },
+                config.preamble_bytes,
+                config.sync_word
+            )
+        }
+        Phy::SubGhz(phy) => writeln!(
+            serial,
+            "phy: {} {}, {}kb/s, antenna pin only",
+            match phy {
+                SubGhzPhy::Bpsk868MHz | SubGhzPhy::OQpsk868MHz => "868MHz",
+                SubGhzPhy::Bpsk915MHz | SubGhzPhy::OQpsk915MHz => "915MHz",
+            },
+            if phy.is_bpsk() { "BPSK" } else { "O-QPSK" },
+            phy.bit_rate() / 1000
+        ),
+        _ => writeln!(serial, "phy: 2.4GHz O-QPSK, 250kb/s"),
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+/// Why the PIO can't play a keying on the subcarrier of the frequency offset
+fn write_keying_error(serial: &mut USBSerial, error: KeyingError) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_keying_error";
+    warn!("keying can't be sent: {}", error);
+    match error {
+        KeyingError::Zero => writeln!(serial, "{}", "the bit rate can't be 0".fg::<Red>()),
+        KeyingError::TooFast(highest) => writeln!(
+            serial,
+            "{} {}b/s",
+            "the bits are too short for the subcarrier, the highest bit rate is".fg::<Red>(),
+            highest
+        ),
+        KeyingError::Fsk(FskError::TooFast(highest)) => writeln!(
+            serial,
+            "{} {}Hz",
+            "the FSK tones are too high for the PIO clock, the highest is".fg::<Red>(),
+            highest
+        ),
+        KeyingError::Fsk(FskError::Zero) => {
+            writeln!(
+                serial,
+                "{}",
+                "the deviation is as big as the frequency offset".fg::<Red>()
+            )
+        }
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+/// The periods a run achieved, nothing before the second packet
+fn write_period_stats(serial: &mut USBSerial, stats: &PeriodStats) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_period_stats";
+    let Some(mean_us) = stats.mean_us() else {
+        return;
+    };
+    info!(
+        "period mean {}us min {}us max {}us, {} late",
+        mean_us, stats.min_us, stats.max_us, stats.late
+    );
+    writeln!(
+        serial,
+        "period: mean {}us, min {}us, max {}us, {}/{} late",
+        mean_us, stats.min_us, stats.max_us, stats.late, stats.count
+    )
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+/// Set the system clock and the PIO divider `option` sends with
+///
+/// returns: true when the system clock changed
+pub(crate) fn apply_transmit_option(
+    sys_clock: &mut SysClock,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    option: TransmitOption,
+) -> bool {
+    let switched = sys_clock.switch(option.processor_clock());
+    pio_ctrl.change_clock_divider(option.state_machine_clock());
+    switched
+}
+
+/// Run the PIO program for the session's sideband and PHY, without a Q pin the session goes back to both
+/// sidebands
+pub(crate) fn apply_sideband(pio_ctrl: &mut PioControl<PIO0, PullDown>, session: &mut SessionConfig) {
+    if !pio_ctrl.use_two_pins(session.phy().two_pins()) {
+        warn!("there is no Q pin, sending both sidebands");
+        session.sideband = None;
+    }
+}
+
+/// Switch to the standard option or the plan for `offset_hz`
+fn set_frequency_offset(
+    serial: &mut USBSerial,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    pio_dma: &mut PioDma,
+    session: &mut SessionConfig,
+    sys_clock: &mut SysClock,
+    offset_hz: u32,
+) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:executor:Command::SetFrequencyOffset";
+    let option = match TransmitOption::for_offset(offset_hz) {
+        Ok(option) => option,
+        Err(error) => {
+            warn!("no plan for {}Hz: {}", offset_hz, error);
+            let reason = match error {
+                PlanError::OutOfRange => "the offset has to be from 1.5MHz to 9MHz",
+                PlanError::ChipRate => "no clock setup keeps the chip rate within 40ppm",
+            };
+            writeln!(serial, "{}", reason.fg::<Red>()).expect(SERIAL_PANIC_ERROR_MESSAGE);
+            return;
+        }
+    };
+    session.transmit_option = option;
+    write_transmit_option(serial, option);
+    let error_hz = option.offset_millihertz() as i64 / 1000 - i64::from(offset_hz);
+    if error_hz != 0 {
+        writeln!(serial, "the offset is {}Hz from the one asked for", error_hz)
+            .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    }
+    if apply_transmit_option(sys_clock, pio_ctrl, option) {
+        writeln!(serial, "system clock changed to {}Hz", sys_clock.clock().hz())
+            .expect(SERIAL_PANIC_ERROR_MESSAGE);
+        replan_extra_outputs(serial, pio_dma.extras(), sys_clock.clock());
+    }
+    if let Some(config) = session.keying {
+        let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+        if let Err(error) = config.check(quarter_cycles, quarters_per_chip) {
+            write_keying_error(serial, error);
+        }
+    }
+}
+
+/// Switch to the whole session `config`, the one saved in the flash or sent by the host, after checking
+/// its keying against its transmit option like the `phy` and `offset` commands do
+///
+/// returns: the state machines of the extra outputs that can't send their offset at the new system clock,
+/// or the keying error and `session` is left as it was
+pub(crate) fn apply_session(
+    sys_clock: &mut SysClock,
+    pio_ctrl: &mut PioControl<PIO0, PullDown>,
+    extras: &mut ExtraOutputs,
+    session: &mut SessionConfig,
+    config: SessionConfig,
+) -> Result<Vec<usize, EXTRA_OUTPUTS>, KeyingError> {
+    if let Some(keying) = config.keying {
+        let (quarter_cycles, quarters_per_chip) = config.transmit_option.quarter_timing();
+        keying.check(quarter_cycles, quarters_per_chip)?;
+    }
+    *session = config;
+    apply_transmit_option(sys_clock, pio_ctrl, session.transmit_option);
+    apply_sideband(pio_ctrl, session);
+    Ok(extras.replan(sys_clock.clock()))
+}
+
+/// Plan the offsets of the extra outputs for the system clock `clock`, the ones that can't send theirs
+/// are reported
+fn replan_extra_outputs(serial: &mut USBSerial, extras: &mut ExtraOutputs, clock: ProcessorClock) {
+    write_replan_failures(serial, extras.replan(clock), clock);
+}
+
+/// Report the extra outputs of `failed` that can't send their offset at the system clock `clock`
+fn write_replan_failures(serial: &mut USBSerial, failed: Vec<usize, EXTRA_OUTPUTS>, clock: ProcessorClock) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:replan_extra_outputs";
+    for sm in failed {
+        warn!("extra output {} can't send its offset at {}Hz", sm, clock.hz());
+        writeln!(
+            serial,
+            "{} {} {} {}Hz",
+            "extra output".fg::<Red>(),
+            sm,
+            "can't send its offset at the system clock of".fg::<Red>(),
+            clock.hz()
+        )
+        .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    }
+}
+
+/// Turn the extra output of SM`sm` on or off
+fn set_extra_output(
+    serial: &mut USBSerial,
+    extras: &mut ExtraOutputs,
+    session: &SessionConfig,
+    sm: usize,
+    config: Option<ExtraOutputConfig>,
+) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:executor:Command::SetExtraOutput";
+    let clock = session.transmit_option.processor_clock();
+    if let Err(error) = extras.set(sm, config, clock) {
+        warn!("extra output {} not set: {}", sm, error);
+        match error {
+            ExtraOutputError::NoSuchOutput => {
+                writeln!(serial, "{}", "the output has no pin, see pins extra".fg::<Red>())
+            }
+            ExtraOutputError::Plan(PlanError::OutOfRange) => {
+                writeln!(
+                    serial,
+                    "{}",
+                    "the offset has to be from 1.5MHz to 9MHz".fg::<Red>()
+                )
+            }
+            ExtraOutputError::Plan(PlanError::ChipRate) => writeln!(
+                serial,
+                "{} {}Hz",
+                "no PIO divider keeps the chip rate within 40ppm at the system clock of".fg::<Red>(),
+                clock.hz()
+            ),
+            ExtraOutputError::TooLong => {
+                writeln!(
+                    serial,
+                    "{}",
+                    "the frame is too long for an extra output".fg::<Red>()
+                )
+            }
+        }
+        .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    }
+    write_extra_output(serial, extras, sm);
+}
+
+fn write_transmit_option(serial: &mut USBSerial, option: TransmitOption) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_transmit_option";
+    let offset_khz = option.offset_millihertz() / 1_000_000;
+    match option {
+        TransmitOption::Standard(option) => writeln!(
+            serial,
+            "transmit option: {:?}, {}.{:03}MHz offset",
+            option,
+            offset_khz / 1000,
+            offset_khz % 1000
+        ),
+        TransmitOption::Planned(plan) => {
+            let divider = plan.divider.to_256ths();
+            writeln!(
+                serial,
+                "transmit option: planned {}.{:03}MHz offset, {}Hz system clock, PIO divider {} {}/256, \
+                 {} cycles per quarter, {} quarters per chip, chip rate off by {}ppm",
+                offset_khz / 1000,
+                offset_khz % 1000,
+                plan.clock.hz(),
+                divider >> 8,
+                divider & 0xFF,
+                plan.quarter_cycles,
+                plan.quarters_per_chip,
+                plan.chip_rate_error_ppm()
+            )
+        }
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+fn write_extra_output(serial: &mut USBSerial, extras: &ExtraOutputs, sm: usize) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_extra_output";
+    let Some((config, option)) = extras.get(sm) else {
+        let state = if extras.exists(sm) { "off" } else { "no pin" };
+        writeln!(serial, "extra output {}: {}", sm, state).expect(SERIAL_PANIC_ERROR_MESSAGE);
+        return;
+    };
+    let offset_khz = match option {
+        Some(option) => option.offset_millihertz() / 1_000_000,
+        None => u64::from(config.offset_hz) / 1000,
+    };
+    write!(
+        serial,
+        "extra output {}: {}.{:03}MHz offset",
+        sm,
+        offset_khz / 1000,
+        offset_khz % 1000
+    )
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    if option.is_none() {
+        write!(serial, " {}", "(not at this system clock)".fg::<Red>()).expect(SERIAL_PANIC_ERROR_MESSAGE);
+    }
+    match &config.frame {
+        Some(frame) => writeln!(serial, ", its own {} byte frame", frame.len()),
+        None => writeln!(serial, ", the frame of every packet"),
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+/// Change the session's pins, only a layout [PinConfig::validate] accepts is kept
+fn set_pins(serial: &mut USBSerial, session: &mut SessionConfig, active: &PinConfig, change: PinChange) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:executor:Command::SetPins";
+    let mut pins = session.pins;
+    match change {
+        PinChange::Trigger { gpio, external } => {
+            pins.trigger = gpio;
+            pins.external_trigger = external;
+        }
+        PinChange::Antenna { gpio, quadrature } => {
+            pins.antenna = gpio;
+            pins.quadrature = quadrature;
+        }
+        PinChange::Extra { sm, gpio } => pins.extra[sm - 1] = gpio,
+        PinChange::Default => pins = PinConfig::default(),
+    }
+    if let Err(error) = pins.validate() {
+        warn!("pins not set: {}", error);
+        match error {
+            PinError::OutOfRange(gpio) => writeln!(
+                serial,
+                "{} GPIO{}",
+                "the Q pin is after the antenna pin, there is no".fg::<Red>(),
+                gpio
+            ),
+            PinError::UsedTwice(gpio) => {
+                writeln!(serial, "GPIO{} {}", gpio, "would have two jobs".fg::<Red>())
+            }
+        }
+        .expect(SERIAL_PANIC_ERROR_MESSAGE);
+        return;
+    }
+    session.pins = pins;
+    write_pins(serial, &session.pins, active);
+}
+
+/// `active` is the layout the PIO runs with, the session's is used after a restart
+fn write_pins(serial: &mut USBSerial, pins: &PinConfig, active: &PinConfig) {
+    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:write_pins";
+    let trigger = if pins.external_trigger {
+        "external"
+    } else {
+        "driven"
+    };
+    write!(
+        serial,
+        "pins: trigger GPIO{} ({}), antenna GPIO{}",
+        pins.trigger, trigger, pins.antenna
+    )
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    match pins.q_pin() {
+        Some(q_pin) => write!(serial, ", Q GPIO{}", q_pin),
+        None => write!(serial, ", no Q pin"),
+    }
+    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    for (sm, pin) in pins.extra.iter().enumerate() {
+        match pin {
+            Some(pin) => write!(serial, ", extra {} GPIO{}", sm + 1, pin),
+            None => write!(serial, ", extra {} none", sm + 1),
+        }
+        .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    }
+    if pins != active {
+        write!(serial, " {}", "(save and restart to use them)".fg::<Yellow>())
+            .expect(SERIAL_PANIC_ERROR_MESSAGE);
+    }
+    writeln!(serial).expect(SERIAL_PANIC_ERROR_MESSAGE);
+}
+
+fn show_config(
+    serial: &mut USBSerial,
+    session: &SessionConfig,
+    active_pins: &PinConfig,
+    extras: &ExtraOutputs,
+) {
+    write_transmit_option(serial, session.transmit_option);
+    write_sideband(serial, session.sideband);
+    write_phy(serial, session);
+    write_address(serial, "source", session.frame.source);
+    write_address(serial, "destination", session.frame.destination);
+    write_sequence_number_mode(serial, session.frame.sequence_number);
+    write_send_defaults(serial, session);
+    write_schedule(serial, session.send.schedule);
+    write_pins(serial, &session.pins, active_pins);
+    for sm in 1..=EXTRA_OUTPUTS {
+        write_extra_output(serial, extras, sm);
+    }
 }
 
 pub fn executor(
     serial: &mut USBSerial,
-    delay: &mut Delay,
-    tx: &mut Tx<(PIO0, SM0)>,
+    timer: &mut PacketTimer,
+    pio_dma: &mut PioDma,
     pio_ctrl: &mut PioControl<PIO0, PullDown>,
-    base_transmit_option: StandardTransmitOption,
+    mut session: SessionConfig,
+    sys_clock: &mut SysClock,
+    config_store: &mut FlashConfigStore,
 ) -> ! {
-    let mut transmit_option = base_transmit_option;
-
-    let mut command_buffer = Vec::<u8, 64>::new();
+    let mut command_buffer = Vec::<u8, MAX_COMMAND_LENGTH>::new();
     loop {
+        let first_byte = loop {
+            if let Some(byte) = serial.peek_byte() {
+                break byte;
+            }
+        };
+        // a console never sends 0x00, it is the delimiter in front of every binary message
+        if first_byte == 0x00 {
+            binary_executor::run(serial, timer, pio_dma, pio_ctrl, sys_clock, &mut session);
+            continue;
+        }
+
         command_buffer.clear();
         let response = serial.poll_until_enter(&mut command_buffer, true);
         if response.is_err() {
@@ -357,7 +1728,7 @@ pub fn executor(
             .as_slice()
             .iter()
             .map(|x| *x as char)
-            .collect::<String<64>>();
+            .collect::<String<MAX_COMMAND_LENGTH>>();
         match Command::from_str(&command_string) {
             Ok(response) => match response {
                 Command::Restart => {
@@ -373,38 +1744,242 @@ pub fn executor(
                 } => {
                     send_generic_packet(
                         serial,
-                        delay,
-                        tx,
+                        timer,
+                        pio_dma,
+                        pio_ctrl,
+                        &session,
+                        UserPacketOptions {
+                            payload_length,
+                            interval_ms,
+                            number_packets,
+                        },
+                    );
+                }
+                Command::SendRandomPacket {
+                    interval_ms,
+                    number_packets,
+                    payload_length,
+                    seed,
+                } => {
+                    send_random_packet(
+                        serial,
+                        timer,
+                        pio_dma,
                         pio_ctrl,
+                        &session,
                         UserPacketOptions {
-                            transmit_option,
                             payload_length,
                             interval_ms,
                             number_packets,
                         },
+                        seed,
+                    );
+                }
+                Command::SendRawFrame {
+                    interval_ms,
+                    number_packets,
+                    frame,
+                } => {
+                    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:executor:Command::SendRawFrame";
+                    writeln!(serial, "sending frame...").expect(SERIAL_PANIC_ERROR_MESSAGE);
+                    writeln!(
+                        serial,
+                        "interval_ms: {}, number_packets: {}, frame_size:{}",
+                        interval_ms,
+                        number_packets,
+                        frame.len()
+                    )
+                    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+                    send_frame(
+                        serial,
+                        timer,
+                        pio_dma,
+                        pio_ctrl,
+                        &session,
+                        interval_ms,
+                        number_packets,
+                        session.phy(),
+                        &frame,
+                    );
+                }
+                Command::SendPayload {
+                    interval_ms,
+                    number_packets,
+                    payload,
+                } => {
+                    const SERIAL_PANIC_ERROR_MESSAGE: &str = "write error:executor:Command::SendPayload";
+                    writeln!(serial, "sending payload...").expect(SERIAL_PANIC_ERROR_MESSAGE);
+                    writeln!(
+                        serial,
+                        "interval_ms: {}, number_packets: {}, payload_size:{}",
+                        interval_ms,
+                        number_packets,
+                        payload.len()
+                    )
+                    .expect(SERIAL_PANIC_ERROR_MESSAGE);
+                    send_payload_packets(
+                        serial,
+                        timer,
+                        pio_dma,
+                        pio_ctrl,
+                        &session,
+                        interval_ms,
+                        number_packets,
+                        &mut |_, packet_number, current_payload| {
+                            same_payload(&payload, packet_number, current_payload)
+                        },
                     );
                 }
 
-                Command::SetFrequencyOffset { frequency } => {
-                    const SERIAL_PANIC_ERROR_MESSAGE: &str =
-                        "write error:executor:Command::SetFrequencyOffset";
-                    match frequency {
-                        FrequencyOffsetCommandOption::F2MHz => {
-                            transmit_option = StandardTransmitOption::Clk128MHzOffset2MHz;
-                            writeln!(serial, "Changed Frequency offset to 2MHz")
-                                .expect(SERIAL_PANIC_ERROR_MESSAGE);
+                Command::SendAdvertisement {
+                    interval_ms,
+                    number_packets,
+                    channel,
+                    data,
+                } => {
+                    send_advertisement(
+                        serial,
+                        timer,
+                        pio_dma,
+                        pio_ctrl,
+                        &session,
+                        interval_ms,
+                        number_packets,
+                        channel,
+                        data,
+                    );
+                }
+                Command::SetFrequencyOffset { offset_hz } => {
+                    set_frequency_offset(serial, pio_ctrl, pio_dma, &mut session, sys_clock, offset_hz);
+                }
+                Command::SetSource(address) => {
+                    session.frame.source = address;
+                    write_address(serial, "source", address);
+                }
+                Command::SetDestination(address) => {
+                    session.frame.destination = address;
+                    write_address(serial, "destination", address);
+                }
+                Command::SetSequenceNumberMode(mode) => {
+                    session.frame.sequence_number = mode;
+                    write_sequence_number_mode(serial, mode);
+                }
+                Command::SetSendDefaults {
+                    interval_ms,
+                    number_packets,
+                } => {
+                    session.send.interval_ms = interval_ms;
+                    session.send.number_packets = number_packets;
+                    write_send_defaults(serial, &session);
+                }
+                Command::SetPayloadMode(payload) => {
+                    session.send.payload = payload;
+                    write_send_defaults(serial, &session);
+                }
+                Command::SetSchedule(mode) => {
+                    session.send.schedule = mode;
+                    write_schedule(serial, mode);
+                }
+                Command::SetSideband(sideband) => {
+                    session.sideband = sideband;
+                    apply_sideband(pio_ctrl, &mut session);
+                    write_sideband(serial, session.sideband);
+                }
+                Command::SetSubGhzPhy(sub_ghz) => {
+                    session.sub_ghz = sub_ghz;
+                    session.keying = None;
+                    apply_sideband(pio_ctrl, &mut session);
+                    write_phy(serial, &session);
+                }
+                Command::SetKeying {
+                    keying,
+                    coding,
+                    bit_rate,
+                } => {
+                    let config = KeyingConfig {
+                        keying,
+                        coding,
+                        bit_rate,
+                        ..session.keying.unwrap_or_default()
+                    };
+                    let (quarter_cycles, quarters_per_chip) = session.transmit_option.quarter_timing();
+                    match config.check(quarter_cycles, quarters_per_chip) {
+                        Ok(()) => {
+                            session.keying = Some(config);
+                            apply_sideband(pio_ctrl, &mut session);
+                            write_phy(serial, &session);
                         }
-                        FrequencyOffsetCommandOption::F4MHz => {
-                            transmit_option = StandardTransmitOption::Clk128MHzOffset4MHz;
-                            writeln!(serial, "Changed Frequency offset to 4MHz")
-                                .expect(SERIAL_PANIC_ERROR_MESSAGE);
+                        Err(error) => write_keying_error(serial, error),
+                    }
+                }
+                Command::SetSync {
+                    sync_word,
+                    preamble_bytes,
+                } => match session.keying.as_mut() {
+                    Some(config) => {
+                        config.sync_word = sync_word;
+                        config.preamble_bytes = preamble_bytes;
+                        write_phy(serial, &session);
+                    }
+                    None => writeln!(serial, "{}", "sync is for phy ook and fsk".fg::<Red>())
+                        .expect("write error:executor:Command::SetSync"),
+                },
+                Command::SetExtraOutput { sm, config } => {
+                    set_extra_output(serial, pio_dma.extras(), &session, sm, config);
+                }
+                Command::SetPins(change) => {
+                    set_pins(serial, &mut session, pio_ctrl.pins(), change);
+                }
+                Command::Send => {
+                    let (payload_length, seed) = match session.send.payload {
+                        PayloadMode::Sequential { length } => (length, None),
+                        PayloadMode::Random { length, seed } => (length, Some(seed)),
+                    };
+                    let user_options = UserPacketOptions {
+                        // a saved config is checked against the MAX_PAYLOAD_SIZE of the firmware that saved it
+                        payload_length: Some(u32::from(payload_length).min(MAX_PAYLOAD_SIZE as u32)),
+                        interval_ms: session.send.interval_ms,
+                        number_packets: session.send.number_packets,
+                    };
+                    match seed {
+                        None => send_generic_packet(serial, timer, pio_dma, pio_ctrl, &session, user_options),
+                        Some(seed) => {
+                            send_random_packet(serial, timer, pio_dma, pio_ctrl, &session, user_options, seed)
                         }
-                        FrequencyOffsetCommandOption::F8MHz => {
-                            transmit_option = StandardTransmitOption::Clk128MHzOffset8MHz;
-                            writeln!(serial, "Changed Frequency offset to 8MHz")
-                                .expect(SERIAL_PANIC_ERROR_MESSAGE);
+                    }
+                }
+                Command::ShowConfig => {
+                    show_config(serial, &session, pio_ctrl.pins(), pio_dma.extras());
+                }
+                Command::Save => match config_store.save(&session) {
+                    Ok(()) => writeln!(serial, "{}", "saved".fg::<Green>()).expect("write error:Save"),
+                    Err(error) => writeln!(serial, "{} {:?}", "save failed:".fg::<Red>(), error)
+                        .expect("write error:Save"),
+                },
+                Command::Load => match config_store.load() {
+                    Some(config) => {
+                        match apply_session(sys_clock, pio_ctrl, pio_dma.extras(), &mut session, config) {
+                            Ok(failed) => {
+                                write_replan_failures(serial, failed, sys_clock.clock());
+                                writeln!(serial, "{}", "loaded".fg::<Green>()).expect("write error:Load");
+                                show_config(serial, &session, pio_ctrl.pins(), pio_dma.extras());
+                            }
+                            Err(error) => write_keying_error(serial, error),
                         }
                     }
+                    None => {
+                        writeln!(serial, "{}", "no saved settings".fg::<Red>()).expect("write error:Load")
+                    }
+                },
+                Command::FactoryReset => {
+                    config_store.factory_reset();
+                    session = SessionConfig::default();
+                    apply_transmit_option(sys_clock, pio_ctrl, session.transmit_option);
+                    apply_sideband(pio_ctrl, &mut session);
+                    replan_extra_outputs(serial, pio_dma.extras(), sys_clock.clock());
+                    writeln!(serial, "{}", "saved settings erased".fg::<Green>())
+                        .expect("write error:FactoryReset");
+                    show_config(serial, &session, pio_ctrl.pins(), pio_dma.extras());
                 }
             },
             Err(err) => match err {
@@ -421,6 +1996,40 @@ pub fn executor(
                     )
                     .expect("write error:ArgsError");
                 }
+                CommandError::InvalidHex { arg_name, error } => {
+                    match error {
+                        HexStringError::OddLength => writeln!(
+                            serial,
+                            "{} {arg_name}{}",
+                            "error with arg:".fg::<Red>(),
+                            ", hex needs an even number of characters".fg::<Red>()
+                        ),
+                        HexStringError::NonHex { index, character } => writeln!(
+                            serial,
+                            "{} {arg_name}{} {character:?} {} {index}",
+                            "error with arg:".fg::<Red>(),
+                            ", not a hex character:".fg::<Red>(),
+                            "at".fg::<Red>()
+                        ),
+                        HexStringError::TooLong => writeln!(
+                            serial,
+                            "{} {arg_name}{}",
+                            "error with arg:".fg::<Red>(),
+                            ", too long".fg::<Red>()
+                        ),
+                    }
+                    .expect("write error:InvalidHex");
+                }
+                CommandError::TooLong { arg_name, max_len } => {
+                    writeln!(
+                        serial,
+                        "{} {arg_name}{} {max_len} {}",
+                        "error with arg:".fg::<Red>(),
+                        ", longer than".fg::<Red>(),
+                        "bytes".fg::<Red>()
+                    )
+                    .expect("write error:TooLong");
+                }
                 CommandError::UnknownCommand(command) => {
                     writeln!(
                         serial,
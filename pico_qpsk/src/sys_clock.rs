@@ -0,0 +1,87 @@
+//! Changing the system clock while the firmware runs
+//!
+//! The frequency offsets need different system clocks, see [pico_qpsk_core::transmit_option]. To change
+//! it clk_sys runs from clk_ref, the 12MHz crystal, while PLL_SYS is restarted with the new settings.
+//! USB runs from PLL_USB and the packet timer from the watchdog tick of clk_ref so neither notices, the
+//! PIO divider has to be set again after the change. Only change it between packets.
+use crate::board_setup::ProcessorClockConfig;
+use defmt::info;
+use pico_qpsk_core::transmit_option::ProcessorClock;
+use rp_pico::hal::clocks::{Clock, SystemClock};
+use rp_pico::hal::fugit::RateExtU32;
+use rp_pico::hal::pll::{start_pll_blocking, Locked, PhaseLockedLoop};
+use rp_pico::pac::{PLL_SYS, RESETS};
+use rp_pico::XOSC_CRYSTAL_FREQ;
+
+pub struct SysClock {
+    system_clock: SystemClock,
+    /// `None` only while [SysClock::switch] restarts it
+    pll: Option<PhaseLockedLoop<Locked, PLL_SYS>>,
+    resets: RESETS,
+    clock: ProcessorClock,
+}
+
+impl SysClock {
+    pub fn new(
+        system_clock: SystemClock,
+        pll: PhaseLockedLoop<Locked, PLL_SYS>,
+        resets: RESETS,
+        clock: ProcessorClock,
+    ) -> Self {
+        SysClock {
+            system_clock,
+            pll: Some(pll),
+            resets,
+            clock,
+        }
+    }
+
+    /// The clock the system runs at
+    pub fn clock(&self) -> ProcessorClock {
+        self.clock
+    }
+
+    /// For setting up the other peripherals, the PLL is reset through it when the clock changes
+    pub fn resets(&mut self) -> &mut RESETS {
+        &mut self.resets
+    }
+
+    /// Run the system at `clock`, nothing happens when it already does
+    ///
+    /// returns: true when the clock changed
+    pub fn switch(&mut self, clock: ProcessorClock) -> bool {
+        if clock == self.clock {
+            return false;
+        }
+        info!(
+            "switching the system clock from {}Hz to {}Hz",
+            self.clock.hz(),
+            clock.hz()
+        );
+        let pll = self
+            .pll
+            .take()
+            .expect("the PLL is only away during switch")
+            .free();
+        // the settings are checked before the system clock leaves the PLL
+        let pll = PhaseLockedLoop::new(
+            pll,
+            XOSC_CRYSTAL_FREQ.Hz(),
+            ProcessorClockConfig::from(clock).pll(),
+        )
+        .map_err(|_x| false)
+        .expect("ProcessorClock only holds valid PLL settings");
+
+        while self.system_clock.reset_source_await().is_err() {}
+        let pll = start_pll_blocking(pll, &mut self.resets)
+            .map_err(|_x| false)
+            .expect("starting a checked PLL is infallible");
+        self.system_clock
+            .configure_clock(&pll, pll.operating_frequency())
+            .map_err(|_x| false)
+            .expect("the system clock runs at the PLL frequency undivided");
+        self.pll = Some(pll);
+        self.clock = clock;
+        true
+    }
+}
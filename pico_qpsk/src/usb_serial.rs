@@ -1,5 +1,6 @@
 use core::fmt::Write;
 use defmt::{debug, error, info, Format};
+use heapless::Deque;
 // A shorter alias for the Peripheral Access Crate, which provides low-level
 // register access
 use rp_pico::hal::{clocks, pac};
@@ -17,14 +18,26 @@ use usbd_serial::SerialPort;
 pub struct USBSerial<'usb> {
     serial: SerialPort<'usb, hal::usb::UsbBus>,
     device: UsbDevice<'usb, hal::usb::UsbBus>,
+    /// bytes that were polled but not read yet, see [USBSerial::read_byte]
+    received: Deque<u8, 64>,
 }
 
 impl Write for USBSerial<'_> {
     fn write_str(&mut self, text: &str) -> core::fmt::Result {
         let add_return = text.ends_with("\n");
 
-        let bytes = text.as_bytes();
+        self.write_bytes(text.as_bytes())?;
 
+        if add_return {
+            return self.write_str("\r");
+        }
+        Ok(())
+    }
+}
+
+impl USBSerial<'_> {
+    /// Write bytes as they are, [Write::write_str] adds a `\r` after every `\n`
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> core::fmt::Result {
         // if bytes.len() < 64 {
         //     self.serial
         //         .write(bytes)
@@ -133,9 +146,6 @@ impl Write for USBSerial<'_> {
                 }
             }
         }
-        if add_return {
-            return self.write_str("\r");
-        }
         Ok(())
     }
 }
@@ -158,7 +168,35 @@ impl<'usb> USBSerial<'usb> {
                 .unwrap()
                 .device_class(2) // from: https://www.usb.org/defined-class-codes
                 .build();
-        Self { serial, device }
+        Self {
+            serial,
+            device,
+            received: Deque::new(),
+        }
+    }
+
+    /// The next received byte without taking it, `None` if nothing was received
+    pub fn peek_byte(&mut self) -> Option<u8> {
+        if self.received.is_empty() {
+            let (buffer, len) = self.poll()?;
+            for byte in buffer.into_iter().take(len) {
+                self.received
+                    .push_back(byte)
+                    .expect("a poll is never more than the 64 byte buffer");
+            }
+        }
+        self.received.front().copied()
+    }
+
+    /// The next received byte, `None` if nothing was received
+    pub fn read_byte(&mut self) -> Option<u8> {
+        self.peek_byte()?;
+        self.received.pop_front()
+    }
+
+    /// true while the host has the port open
+    pub fn dtr(&self) -> bool {
+        self.serial.dtr()
     }
 
     pub fn poll(&mut self) -> Option<([u8; 64], usize)> {
@@ -197,18 +235,25 @@ impl<'usb> USBSerial<'usb> {
         None
     }
 
+    /// Keep the USB device answering the host while busy, the bytes received meanwhile are kept for
+    /// [USBSerial::read_byte]
+    pub fn service(&mut self) {
+        if self.received.is_empty() {
+            self.peek_byte();
+        } else {
+            // the bytes stay in the USB buffer until there is room for a whole poll
+            self.device.poll(&mut [&mut self.serial]);
+        }
+    }
+
     // ctrl-c is 3 on serial which is etx end text
     pub fn poll_is_etx(&mut self) -> bool {
-        if let Some((buff, len)) = self.poll() {
-            if len > 0 {
-                for b in buff {
-                    if b == 3 {
-                        return true;
-                    }
-                }
-            }
+        // the bytes kept by [USBSerial::service] while a packet was sent are checked too
+        let mut is_etx = false;
+        while let Some(byte) = self.read_byte() {
+            is_etx |= byte == 3;
         }
-        false
+        is_etx
     }
 
     // this might not work on windows
@@ -218,42 +263,36 @@ impl<'usb> USBSerial<'usb> {
         echo: bool,
     ) -> Result<(), PollBufferError> {
         loop {
-            if let Some((buffer, num_of_chars)) = self.poll() {
-                for (i, item) in buffer.into_iter().take(num_of_chars).enumerate() {
-                    // enter key
-                    if item == 13 || item == 10 {
-                        debug!("finished -> {}", vec);
-                        if echo {
-                            write!(self, "\n\r").expect("failed to echo newline!");
-                        }
-                        return Ok(());
-                    }
-                    // delete key pressed
-                    if item == 127 {
-                        info!("delete key pressed ");
-                        if !vec.is_empty() {
-                            info!("-> popping last key off vec ");
-                            vec.pop();
-                            if echo {
-                                write!(self, "{}{}{}", 8u8 as char, 127u8 as char, 8u8 as char)
-                                    .expect("failed to echo delete key!");
-                            }
-                        }
-                        continue;
-                    }
-                    debug!(
-                        "received {:?} = {:?} ({}/{})",
-                        item as char,
-                        item,
-                        i + 1,
-                        num_of_chars
-                    );
+            // bytes are read one at a time so the first byte can be peeked to pick the protocol
+            let Some(item) = self.read_byte() else {
+                continue;
+            };
+            // enter key
+            if item == 13 || item == 10 {
+                debug!("finished -> {}", vec);
+                if echo {
+                    write!(self, "\n\r").expect("failed to echo newline!");
+                }
+                return Ok(());
+            }
+            // delete key pressed
+            if item == 127 {
+                info!("delete key pressed ");
+                if !vec.is_empty() {
+                    info!("-> popping last key off vec ");
+                    vec.pop();
                     if echo {
-                        write!(self, "{}", item as char).expect("failed to echo char!");
+                        write!(self, "{}{}{}", 8u8 as char, 127u8 as char, 8u8 as char)
+                            .expect("failed to echo delete key!");
                     }
-                    vec.push(item).map_err(|_err| PollBufferError {})?;
                 }
+                continue;
+            }
+            debug!("received {:?} = {:?}", item as char, item);
+            if echo {
+                write!(self, "{}", item as char).expect("failed to echo char!");
             }
+            vec.push(item).map_err(|_err| PollBufferError {})?;
         }
     }
 
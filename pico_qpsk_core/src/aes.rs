@@ -0,0 +1,136 @@
+//! Software AES-128, only the forward (encrypt) direction as that is all CCM* needs
+//!
+//! Follows FIPS-197, written for size rather than speed, the RP2040 has no crypto hardware
+
+/// Bytes in an AES block
+pub const BLOCK_SIZE: usize = 16;
+
+/// One AES block
+pub type Block = [u8; BLOCK_SIZE];
+
+/// Rounds for a 128 bit key
+const ROUNDS: usize = 10;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76, //
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0, //
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15, //
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75, //
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84, //
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf, //
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8, //
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2, //
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73, //
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb, //
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79, //
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08, //
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a, //
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e, //
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf, //
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16, //
+];
+
+/// Round constants for the key expansion
+const RCON: [u8; ROUNDS] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+/// multiply by x in GF(2^8)
+const fn xtime(b: u8) -> u8 {
+    (b << 1) ^ (((b >> 7) & 1) * 0x1b)
+}
+
+/// AES-128 with the expanded key
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::aes::Aes128;
+///
+/// // FIPS-197 appendix C.1
+/// let aes = Aes128::new(&[
+///     0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+/// ]);
+/// let mut block = [
+///     0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+/// ];
+/// aes.encrypt_block(&mut block);
+/// assert_eq!(
+///     block,
+///     [0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a]
+/// );
+/// ```
+#[derive(Clone)]
+pub struct Aes128 {
+    round_keys: [Block; ROUNDS + 1],
+}
+
+impl Aes128 {
+    pub fn new(key: &[u8; 16]) -> Self {
+        let mut round_keys = [[0u8; BLOCK_SIZE]; ROUNDS + 1];
+        round_keys[0] = *key;
+        for round in 1..=ROUNDS {
+            let previous = round_keys[round - 1];
+            // RotWord, SubWord and Rcon on the last word of the previous round key
+            let mut word = [
+                SBOX[usize::from(previous[13])] ^ RCON[round - 1],
+                SBOX[usize::from(previous[14])],
+                SBOX[usize::from(previous[15])],
+                SBOX[usize::from(previous[12])],
+            ];
+            let key = &mut round_keys[round];
+            for idx in 0..BLOCK_SIZE {
+                key[idx] = previous[idx] ^ word[idx % 4];
+                word[idx % 4] = key[idx];
+            }
+        }
+        Aes128 { round_keys }
+    }
+
+    /// Encrypt one block in place
+    pub fn encrypt_block(&self, block: &mut Block) {
+        add_round_key(block, &self.round_keys[0]);
+        for round in 1..=ROUNDS {
+            sub_bytes(block);
+            shift_rows(block);
+            if round != ROUNDS {
+                mix_columns(block);
+            }
+            add_round_key(block, &self.round_keys[round]);
+        }
+    }
+}
+
+/// The key isn't printed
+impl core::fmt::Debug for Aes128 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Aes128")
+    }
+}
+
+fn add_round_key(block: &mut Block, key: &Block) {
+    block.iter_mut().zip(key).for_each(|(b, k)| *b ^= k);
+}
+
+fn sub_bytes(block: &mut Block) {
+    block.iter_mut().for_each(|b| *b = SBOX[usize::from(*b)]);
+}
+
+/// the block is column major, byte `r + 4c` is row `r` of column `c`
+fn shift_rows(block: &mut Block) {
+    let state = *block;
+    for column in 0..4 {
+        for row in 1..4 {
+            block[row + 4 * column] = state[row + 4 * ((column + row) % 4)];
+        }
+    }
+}
+
+fn mix_columns(block: &mut Block) {
+    for column in block.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        column[0] ^= all ^ xtime(a0 ^ a1);
+        column[1] ^= all ^ xtime(a1 ^ a2);
+        column[2] ^= all ^ xtime(a2 ^ a3);
+        column[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
@@ -0,0 +1,355 @@
+//! Bluetooth Low Energy advertising packets for the 1M PHY
+//!
+//! A non connectable advertisement, `ADV_NONCONN_IND` with the advertiser's address and up to 31 bytes
+//! of AD structures, is all a phone needs to list a tag in a scanner app. [air_packet] adds the
+//! preamble, the advertising access address, the CRC-24 and the whitening of the channel, and
+//! [convert_ble] turns the packet into 2-FSK of the subcarrier, see [crate::fsk].
+//!
+//! The packet layout follows the Core Specification, Vol 6 Part B. The subcarrier doesn't shape the
+//! frequency change with a gaussian filter, receivers still pick the bits up as the deviation is the
+//! same ±250kHz.
+//!
+//! The receiver sees both sidebands of the single pin square wave, the upper one has 1 bits at the
+//! higher frequency like BLE so the carrier goes one subcarrier offset below the channel. The lower one
+//! is mirrored and no receiver finds the access address in it.
+use crate::fsk::{convert_fsk, FskConvertIter, FskTiming};
+use heapless::Vec;
+
+/// The longest AD data of a legacy advertisement
+pub const MAX_ADV_DATA: usize = 31;
+/// Header, AdvA and AD data
+pub const MAX_PDU_SIZE: usize = 2 + 6 + MAX_ADV_DATA;
+/// Preamble, access address, PDU and CRC
+pub const MAX_AIR_PACKET_SIZE: usize = 1 + 4 + MAX_PDU_SIZE + 3;
+
+/// The 1M PHY preamble for an access address with a 0 least significant bit
+pub const PREAMBLE: u8 = 0xAA;
+/// The access address of every advertising channel packet
+pub const ADVERTISING_ACCESS_ADDRESS: u32 = 0x8E89_BED6;
+/// The CRC preset of advertising channel packets
+pub const ADVERTISING_CRC_INIT: u32 = 0x55_5555;
+pub const BIT_RATE: u32 = 1_000_000;
+/// The frequency deviation of a 1 above and a 0 below the channel
+pub const DEVIATION_HZ: u32 = 250_000;
+
+/// `ADV_NONCONN_IND` in the PDU type bits of the header
+const ADV_NONCONN_IND: u8 = 0b0010;
+/// TxAdd, the advertiser's address is a random address
+const TX_ADD: u8 = 1 << 6;
+/// The CRC-24 polynomial `x^24 + x^10 + x^9 + x^6 + x^4 + x^3 + x + 1` for the bit reversed register
+const CRC_LFSR_MASK: u32 = 0x5A_6000;
+
+/// AD types the firmware builds, see the Assigned Numbers document
+pub mod ad_type {
+    pub const FLAGS: u8 = 0x01;
+    pub const SHORTENED_LOCAL_NAME: u8 = 0x08;
+    pub const COMPLETE_LOCAL_NAME: u8 = 0x09;
+    pub const MANUFACTURER_SPECIFIC_DATA: u8 = 0xFF;
+}
+
+/// The flags of a device that is only a broadcaster, LE General Discoverable Mode and BR/EDR Not Supported
+pub const BROADCASTER_FLAGS: u8 = 0x06;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BleError {
+    /// the AD structures don't fit in [MAX_ADV_DATA] bytes
+    TooLong,
+    /// an AD structure's length runs past the end of the data
+    AdStructure,
+    /// a received packet doesn't start with [PREAMBLE] and [ADVERTISING_ACCESS_ADDRESS]
+    AccessAddress,
+    /// a received packet's header isn't an `ADV_NONCONN_IND` or its length doesn't fit
+    Header,
+    /// a received packet's CRC doesn't match
+    Crc,
+}
+
+/// The three advertising channels
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdvertisingChannel {
+    Ch37,
+    Ch38,
+    Ch39,
+}
+
+impl AdvertisingChannel {
+    pub const ALL: [AdvertisingChannel; 3] = [
+        AdvertisingChannel::Ch37,
+        AdvertisingChannel::Ch38,
+        AdvertisingChannel::Ch39,
+    ];
+
+    /// 37, 38 or 39 to a channel
+    pub fn from_index(index: u8) -> Option<Self> {
+        AdvertisingChannel::ALL
+            .into_iter()
+            .find(|channel| channel.index() == index)
+    }
+
+    /// The channel index, it seeds the whitening
+    pub fn index(&self) -> u8 {
+        match self {
+            AdvertisingChannel::Ch37 => 37,
+            AdvertisingChannel::Ch38 => 38,
+            AdvertisingChannel::Ch39 => 39,
+        }
+    }
+
+    /// The centre frequency, the advertising channels sit in the gaps between the Wi-Fi channels
+    pub fn mhz(&self) -> u16 {
+        match self {
+            AdvertisingChannel::Ch37 => 2402,
+            AdvertisingChannel::Ch38 => 2426,
+            AdvertisingChannel::Ch39 => 2480,
+        }
+    }
+}
+
+/// AD structures, each is a length, the AD type and `length - 1` bytes of data
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertisingData {
+    bytes: Vec<u8, MAX_ADV_DATA>,
+}
+
+impl AdvertisingData {
+    pub fn new() -> Self {
+        AdvertisingData::default()
+    }
+
+    /// Check `bytes` is a run of AD structures, the way an advertisement's data is taken in raw
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BleError> {
+        let bytes = Vec::from_slice(bytes).map_err(|()| BleError::TooLong)?;
+        let data = AdvertisingData { bytes };
+        for structure in data.structures() {
+            structure?;
+        }
+        Ok(data)
+    }
+
+    /// Add an AD structure
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::ble::{ad_type, AdvertisingData, BleError, BROADCASTER_FLAGS};
+    ///
+    /// let mut data = AdvertisingData::new();
+    /// data.push(ad_type::FLAGS, &[BROADCASTER_FLAGS]).unwrap();
+    /// data.push(ad_type::COMPLETE_LOCAL_NAME, b"tag").unwrap();
+    /// assert_eq!(data.as_bytes(), [0x02, 0x01, 0x06, 0x04, 0x09, b't', b'a', b'g']);
+    /// assert_eq!(data.push(ad_type::COMPLETE_LOCAL_NAME, &[0; 22]), Err(BleError::TooLong));
+    /// ```
+    pub fn push(&mut self, ad_type: u8, data: &[u8]) -> Result<(), BleError> {
+        if self.bytes.len() + 2 + data.len() > MAX_ADV_DATA {
+            return Err(BleError::TooLong);
+        }
+        // the check above keeps every push in the capacity
+        self.bytes
+            .push(data.len() as u8 + 1)
+            .map_err(|_| BleError::TooLong)?;
+        self.bytes.push(ad_type).map_err(|_| BleError::TooLong)?;
+        self.bytes.extend_from_slice(data).map_err(|()| BleError::TooLong)
+    }
+
+    /// The flags and as much of `name` as fits, a name cut short is sent as the shortened local name
+    pub fn with_name(name: &[u8]) -> Self {
+        let mut data = AdvertisingData::new();
+        data.push(ad_type::FLAGS, &[BROADCASTER_FLAGS])
+            .expect("the flags fit in empty AD data");
+        let room = MAX_ADV_DATA - data.bytes.len() - 2;
+        let name_type = if name.len() > room {
+            ad_type::SHORTENED_LOCAL_NAME
+        } else {
+            ad_type::COMPLETE_LOCAL_NAME
+        };
+        data.push(name_type, &name[..name.len().min(room)])
+            .expect("the name was cut to the room left");
+        data
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// (AD type, data) of each AD structure, a 0 length ends the data early like the padding of a scan
+    /// response
+    pub fn structures(&self) -> impl Iterator<Item = Result<(u8, &[u8]), BleError>> + '_ {
+        let mut rest: &[u8] = &self.bytes;
+        core::iter::from_fn(move || {
+            let (&len, tail) = rest.split_first()?;
+            if len == 0 {
+                rest = &[];
+                return None;
+            }
+            let Some(structure) = tail.get(..usize::from(len)) else {
+                rest = &[];
+                return Some(Err(BleError::AdStructure));
+            };
+            rest = &tail[usize::from(len)..];
+            Some(Ok((structure[0], &structure[1..])))
+        })
+    }
+}
+
+/// A non connectable undirected advertisement, `ADV_NONCONN_IND`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Advertisement {
+    /// AdvA the way it is written, `C0:FF:EE:12:34:56` is `[0xC0, 0xFF, 0xEE, 0x12, 0x34, 0x56]`, it goes
+    /// on air the other way round
+    pub address: [u8; 6],
+    /// TxAdd, a random address needs the two top bits set for a static address
+    pub random_address: bool,
+    pub data: AdvertisingData,
+}
+
+impl Advertisement {
+    /// The PDU, a 2 byte header, AdvA and the AD data
+    pub fn pdu(&self) -> Vec<u8, MAX_PDU_SIZE> {
+        let mut pdu = Vec::new();
+        let tx_add = if self.random_address { TX_ADD } else { 0 };
+        let payload_len = 6 + self.data.as_bytes().len();
+        // MAX_PDU_SIZE is the header and the longest payload
+        pdu.push(ADV_NONCONN_IND | tx_add).unwrap();
+        pdu.push(payload_len as u8).unwrap();
+        pdu.extend(self.address.iter().rev().copied());
+        pdu.extend_from_slice(self.data.as_bytes()).unwrap();
+        pdu
+    }
+
+    /// Parse a PDU from [Advertisement::pdu]
+    pub fn from_pdu(pdu: &[u8]) -> Result<Self, BleError> {
+        let [header, len, payload @ ..] = pdu else {
+            return Err(BleError::Header);
+        };
+        let len = usize::from(*len);
+        if header & 0x0F != ADV_NONCONN_IND || !(6..=6 + MAX_ADV_DATA).contains(&len) || payload.len() != len
+        {
+            return Err(BleError::Header);
+        }
+        let mut address = [0; 6];
+        address.copy_from_slice(&payload[..6]);
+        address.reverse();
+        Ok(Advertisement {
+            address,
+            random_address: header & TX_ADD != 0,
+            data: AdvertisingData::from_bytes(&payload[6..])?,
+        })
+    }
+}
+
+/// The CRC-24 of `bytes` as it goes on air, first byte first
+///
+/// The register is kept bit reversed so the bytes go in least significant bit first like they are sent
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::ble::{crc24, ADVERTISING_CRC_INIT};
+///
+/// // a PDU followed by its own CRC leaves nothing in the register
+/// let pdu = [0x42, 0x06, 0x56, 0x34, 0x12, 0xEE, 0xFF, 0xC0];
+/// let crc = crc24(ADVERTISING_CRC_INIT, &pdu);
+/// let with_crc: Vec<u8> = pdu.iter().chain(&crc).copied().collect();
+/// assert_eq!(crc24(ADVERTISING_CRC_INIT, &with_crc), [0, 0, 0]);
+/// ```
+pub fn crc24(init: u32, bytes: &[u8]) -> [u8; 3] {
+    let mut state = init.reverse_bits() >> 8;
+    for byte in bytes {
+        let mut byte = *byte;
+        for _ in 0..8 {
+            let feedback = (state ^ u32::from(byte)) & 1 == 1;
+            byte >>= 1;
+            state >>= 1;
+            if feedback {
+                state |= 1 << 23;
+                state ^= CRC_LFSR_MASK;
+            }
+        }
+    }
+    [state as u8, (state >> 8) as u8, (state >> 16) as u8]
+}
+
+/// Whiten, or dewhiten, `bytes` for `channel` with the `x^7 + x^4 + 1` LFSR
+///
+/// The register starts with a 1 and the 6 bits of the channel index, the bits of a byte are whitened
+/// least significant bit first
+pub fn whiten(bytes: &mut [u8], channel: AdvertisingChannel) {
+    // position n of the register is bit n + 1, the channel index goes MSB first in positions 1 to 6
+    let mut lfsr = channel.index().reverse_bits() | 0b10;
+    for byte in bytes {
+        for bit in 0..8 {
+            if lfsr & 0x80 != 0 {
+                lfsr ^= 0x11;
+                *byte ^= 1 << bit;
+            }
+            lfsr <<= 1;
+        }
+    }
+}
+
+/// The bytes of `advertisement` on `channel`, preamble to CRC with the PDU and the CRC whitened
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::ble::{air_packet, decode_air_packet, AdvertisingChannel, AdvertisingData, Advertisement};
+///
+/// let advertisement = Advertisement {
+///     address: [0xC0, 0xFF, 0xEE, 0x12, 0x34, 0x56],
+///     random_address: true,
+///     data: AdvertisingData::with_name(b"pico"),
+/// };
+/// let packet = air_packet(&advertisement, AdvertisingChannel::Ch38);
+/// assert_eq!(packet[..5], [0xAA, 0xD6, 0xBE, 0x89, 0x8E]);
+/// assert_eq!(decode_air_packet(&packet, AdvertisingChannel::Ch38), Ok(advertisement));
+/// ```
+pub fn air_packet(
+    advertisement: &Advertisement,
+    channel: AdvertisingChannel,
+) -> Vec<u8, MAX_AIR_PACKET_SIZE> {
+    let pdu = advertisement.pdu();
+    let mut packet: Vec<u8, MAX_AIR_PACKET_SIZE> = Vec::new();
+    // MAX_AIR_PACKET_SIZE is the longest PDU with the rest of the packet
+    packet.push(PREAMBLE).unwrap();
+    packet
+        .extend_from_slice(&ADVERTISING_ACCESS_ADDRESS.to_le_bytes())
+        .unwrap();
+    packet.extend_from_slice(&pdu).unwrap();
+    packet
+        .extend_from_slice(&crc24(ADVERTISING_CRC_INIT, &pdu))
+        .unwrap();
+    whiten(&mut packet[5..], channel);
+    packet
+}
+
+/// Check and parse the bytes of an advertisement received on `channel`, from the preamble on
+pub fn decode_air_packet(packet: &[u8], channel: AdvertisingChannel) -> Result<Advertisement, BleError> {
+    let mut start = [PREAMBLE; 5];
+    start[1..].copy_from_slice(&ADVERTISING_ACCESS_ADDRESS.to_le_bytes());
+    if packet.get(..5) != Some(&start[..]) || packet.len() < 5 + 2 + 3 {
+        return Err(BleError::AccessAddress);
+    }
+    let mut pdu: Vec<u8, { MAX_PDU_SIZE + 3 }> =
+        Vec::from_slice(&packet[5..]).map_err(|()| BleError::Header)?;
+    whiten(&mut pdu, channel);
+    let pdu_len = 2 + usize::from(pdu[1]);
+    if pdu.len() != pdu_len + 3 {
+        return Err(BleError::Header);
+    }
+    if crc24(ADVERTISING_CRC_INIT, &pdu) != [0, 0, 0] {
+        return Err(BleError::Crc);
+    }
+    Advertisement::from_pdu(&pdu[..pdu_len])
+}
+
+/// The FSK of a BLE packet on a `subcarrier_hz` square wave from a state machine at `pio_hz`
+pub fn fsk_timing(pio_hz: u32, subcarrier_hz: u32) -> FskTiming {
+    FskTiming::around(pio_hz, BIT_RATE, subcarrier_hz, DEVIATION_HZ)
+}
+
+/// Translate the bytes of [air_packet] into pio bytecode for the single pin program
+pub fn convert_ble(packet: &[u8], pio_hz: u32, subcarrier_hz: u32) -> FskConvertIter<'_> {
+    convert_fsk(packet, fsk_timing(pio_hz, subcarrier_hz))
+}
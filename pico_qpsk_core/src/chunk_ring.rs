@@ -0,0 +1,152 @@
+//! A lock-free ring of fixed size word chunks with one producer and one consumer
+//!
+//! The firmware generates the PIO bytecode of a packet on core1 into the chunks while core0 has the DMA
+//! copy them into the PIO. Only atomic loads and stores are used, the RP2040's cores have no
+//! compare-and-swap.
+use core::cell::UnsafeCell;
+use core::iter::Peekable;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+struct Slot<const WORDS: usize> {
+    words: UnsafeCell<[u32; WORDS]>,
+    len: UnsafeCell<usize>,
+    last: UnsafeCell<bool>,
+}
+
+/// `SLOTS` chunks of up to `WORDS` words, see [ChunkRing::split]
+pub struct ChunkRing<const SLOTS: usize, const WORDS: usize> {
+    slots: [Slot<WORDS>; SLOTS],
+    /// chunks committed by the producer, counted modulo `2 * SLOTS` so a full ring isn't an empty one
+    written: AtomicUsize,
+    /// chunks released by the consumer, counted modulo `2 * SLOTS`
+    released: AtomicUsize,
+}
+
+// Safety: the producer only touches the slots the consumer released and the consumer only the slots the
+// producer committed, `written` and `released` hand them over
+unsafe impl<const SLOTS: usize, const WORDS: usize> Sync for ChunkRing<SLOTS, WORDS> {}
+
+impl<const SLOTS: usize, const WORDS: usize> Default for ChunkRing<SLOTS, WORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SLOTS: usize, const WORDS: usize> ChunkRing<SLOTS, WORDS> {
+    /// The words the chunks hold together
+    pub const CAPACITY: usize = SLOTS * WORDS;
+
+    /// An empty ring, all zeros so a `static` one lands in `.bss`
+    pub const fn new() -> Self {
+        ChunkRing {
+            slots: [const {
+                Slot {
+                    words: UnsafeCell::new([0; WORDS]),
+                    len: UnsafeCell::new(0),
+                    last: UnsafeCell::new(false),
+                }
+            }; SLOTS],
+            written: AtomicUsize::new(0),
+            released: AtomicUsize::new(0),
+        }
+    }
+
+    /// The two ends of the ring, they can be used from different cores or threads
+    pub fn split(&mut self) -> (Producer<'_, SLOTS, WORDS>, Consumer<'_, SLOTS, WORDS>) {
+        (Producer { ring: self }, Consumer { ring: self })
+    }
+
+    fn committed(&self) -> usize {
+        let written = self.written.load(Ordering::Acquire);
+        let released = self.released.load(Ordering::Acquire);
+        (written + 2 * SLOTS - released) % (2 * SLOTS)
+    }
+}
+
+/// Fills the chunks
+pub struct Producer<'a, const SLOTS: usize, const WORDS: usize> {
+    ring: &'a ChunkRing<SLOTS, WORDS>,
+}
+
+impl<const SLOTS: usize, const WORDS: usize> Producer<'_, SLOTS, WORDS> {
+    /// Fill the next free chunk with the next words, the chunk is the last of a packet when `words` runs
+    /// out
+    ///
+    /// returns: `None` when every chunk is waiting for the consumer, else whether the chunk was the last
+    pub fn push_from<I: Iterator<Item = u32>>(&mut self, words: &mut Peekable<I>) -> Option<bool> {
+        if self.ring.committed() == SLOTS {
+            return None;
+        }
+        let written = self.ring.written.load(Ordering::Relaxed);
+        let slot = &self.ring.slots[written % SLOTS];
+        // Safety: the consumer released the slot and doesn't read it until `written` moves past it
+        let (chunk, len, last) = unsafe {
+            (
+                &mut *slot.words.get(),
+                &mut *slot.len.get(),
+                &mut *slot.last.get(),
+            )
+        };
+        *len = 0;
+        // `chunk` comes first in the zip so no word is lost when the chunk is full
+        for (word, next) in chunk.iter_mut().zip(&mut *words) {
+            *word = next;
+            *len += 1;
+        }
+        *last = words.peek().is_none();
+        let is_last = *last;
+        self.ring
+            .written
+            .store((written + 1) % (2 * SLOTS), Ordering::Release);
+        Some(is_last)
+    }
+}
+
+/// A committed chunk
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Chunk<'a> {
+    pub words: &'a [u32],
+    /// the last chunk of a packet
+    pub last: bool,
+}
+
+/// Reads and releases the chunks in the order they were committed
+pub struct Consumer<'a, const SLOTS: usize, const WORDS: usize> {
+    ring: &'a ChunkRing<SLOTS, WORDS>,
+}
+
+impl<const SLOTS: usize, const WORDS: usize> Consumer<'_, SLOTS, WORDS> {
+    /// The chunks committed and not released yet
+    pub fn len(&self) -> usize {
+        self.ring.committed()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `n`th chunk not released yet, 0 is the oldest
+    pub fn chunk(&self, n: usize) -> Option<Chunk<'_>> {
+        if n >= self.len() {
+            return None;
+        }
+        let released = self.ring.released.load(Ordering::Relaxed);
+        let slot = &self.ring.slots[(released + n) % SLOTS];
+        // Safety: the producer committed the slot and doesn't write it until it is released, which
+        // needs `&mut self`
+        let (words, len, last) = unsafe { (&*slot.words.get(), *slot.len.get(), *slot.last.get()) };
+        Some(Chunk {
+            words: &words[..len],
+            last,
+        })
+    }
+
+    /// Give the oldest chunk back to the producer
+    pub fn release(&mut self) {
+        assert!(!self.is_empty(), "no chunk to release");
+        let released = self.ring.released.load(Ordering::Relaxed);
+        self.ring
+            .released
+            .store((released + 1) % (2 * SLOTS), Ordering::Release);
+    }
+}
@@ -0,0 +1,552 @@
+//! Keeping a [SessionConfig] in flash across reboots
+//!
+//! The store is [STORE_SECTORS] erase sectors split into [RECORD_SIZE] byte slots. Every save writes
+//! to the slot after the newest record, so the writes and erases are spread over the whole store.
+//! A sector is only erased right before its first slot is written, the newest record is then in
+//! another sector and survives a power cut during the erase.
+//!
+//! ```text
+//! [MAGIC][VERSION][RESERVED][COUNTER][CONFIG][PADDING][CRC32]
+//! ```
+//!
+//! The counter goes up by one every save, the record with the highest counter is the newest.
+use crate::keying::{Keying, KeyingConfig, LineCoding};
+use crate::offset_planner::{PllSettings, TransmitPlan};
+use crate::pio_bytecode_gen::Sideband;
+use crate::schedule::ScheduleMode;
+use crate::session_config::{
+    Address, ExtendedAddress, FrameConfig, PanId, PayloadMode, PinConfig, SendDefaults, SequenceNumberMode,
+    SessionConfig, ShortAddress, EXTRA_OUTPUTS,
+};
+use crate::sub_ghz::SubGhzPhy;
+use crate::transmit_option::{
+    ProcessorClock, StandardTransmitOption, StateMachineClockDividerSetting, TransmitOption,
+};
+use byte::{BytesExt, LE};
+use crc_all::CrcAlgo;
+
+/// Bytes in one record, the same as a slot
+pub const RECORD_SIZE: usize = 128;
+
+/// Version of the record layout, records from another version are ignored
+pub const RECORD_VERSION: u8 = 6;
+
+/// Erase sectors the store uses, at least 2 so an erase never removes the only record
+pub const STORE_SECTORS: usize = 2;
+
+const MAGIC: [u8; 2] = *b"PQ";
+
+/// The transmit option byte of a [TransmitPlan], the standard options are their index in
+/// [StandardTransmitOption::ALL]
+const PLANNED: u8 = 0x80;
+
+/// The flags byte of a [PinConfig]
+const EXTERNAL_TRIGGER: u8 = 1 << 0;
+const QUADRATURE: u8 = 1 << 1;
+/// An extra output pin byte for an unused state machine
+const NO_PIN: u8 = 0xFF;
+
+const CRC_OFFSET: usize = RECORD_SIZE - 4;
+
+const CRC32: CrcAlgo<u32> = CrcAlgo::<u32>::new(0x04C1_1DB7, 32, 0xFFFF_FFFF, 0xFFFF_FFFF, true);
+
+/// The flash the store lives in, offsets are from the start of the store
+///
+/// This is NOR flash, an erase sets every byte of a sector to 0xFF and programming can only clear bits
+pub trait Flash {
+    /// Bytes in an erase sector, a multiple of [RECORD_SIZE]
+    const SECTOR_SIZE: usize;
+
+    fn read(&self, offset: usize, bytes: &mut [u8]);
+
+    /// `offset` is the start of a sector
+    fn erase_sector(&mut self, offset: usize);
+
+    /// `bytes` never crosses a slot boundary
+    fn program(&mut self, offset: usize, bytes: &[u8]);
+}
+
+/// Why a slot doesn't hold a usable record
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecordError {
+    /// nothing was written to the slot since it was erased
+    Erased,
+    /// the slot doesn't start with the magic bytes, it isn't a record
+    Magic,
+    /// the record was written by a firmware with another record layout
+    Version(u8),
+    /// the record doesn't match its CRC, the write was cut off or the flash is worn out
+    Crc,
+    /// the CRC matches but a field has a value this firmware doesn't know
+    Field(&'static str),
+}
+
+/// A type to hold the possible errors that occur when the config is saved
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StoreError {
+    /// the record read back from flash isn't the one that was written
+    Verify,
+}
+
+/// Turn a config into a record
+///
+/// # Arguments
+///
+/// * `config`: the config to save
+/// * `counter`: one more than the counter of the newest record
+///
+/// returns: the record bytes, CRC included
+pub fn encode_record(config: &SessionConfig, counter: u32) -> [u8; RECORD_SIZE] {
+    let mut bytes = [0u8; RECORD_SIZE];
+    let offset = &mut 0;
+    bytes.write(offset, MAGIC[0]).expect("the fields fit in a record");
+    bytes.write(offset, MAGIC[1]).expect("the fields fit in a record");
+    bytes
+        .write(offset, RECORD_VERSION)
+        .expect("the fields fit in a record");
+    bytes.write(offset, 0u8).expect("the fields fit in a record");
+    bytes
+        .write_with(offset, counter, LE)
+        .expect("the fields fit in a record");
+    write_config(&mut bytes[..CRC_OFFSET], offset, config).expect("the fields fit in a record");
+
+    let crc = record_crc(&bytes);
+    bytes[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+    bytes
+}
+
+/// Check a record and read it back
+///
+/// returns: Result<(the counter, the config), [RecordError]>
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::config_store::{decode_record, encode_record};
+/// use pico_qpsk_core::session_config::SessionConfig;
+///
+/// let record = encode_record(&SessionConfig::default(), 7);
+/// assert_eq!(decode_record(&record), Ok((7, SessionConfig::default())));
+/// ```
+pub fn decode_record(bytes: &[u8; RECORD_SIZE]) -> Result<(u32, SessionConfig), RecordError> {
+    if bytes.iter().all(|byte| *byte == 0xFF) {
+        return Err(RecordError::Erased);
+    }
+    if bytes[..MAGIC.len()] != MAGIC {
+        return Err(RecordError::Magic);
+    }
+    if bytes[2] != RECORD_VERSION {
+        return Err(RecordError::Version(bytes[2]));
+    }
+    if bytes[CRC_OFFSET..] != record_crc(bytes).to_le_bytes() {
+        return Err(RecordError::Crc);
+    }
+
+    let offset = &mut 4;
+    let counter: u32 = bytes.read_with(offset, LE).map_err(field_error)?;
+    let config = read_config(&bytes[..CRC_OFFSET], offset).map_err(field_error)?;
+    Ok((counter, config))
+}
+
+fn record_crc(bytes: &[u8; RECORD_SIZE]) -> u32 {
+    let crc = &mut 0u32;
+    CRC32.init_crc(crc);
+    CRC32.update_crc(crc, &bytes[..CRC_OFFSET])
+}
+
+fn field_error(error: byte::Error) -> RecordError {
+    match error {
+        byte::Error::BadInput { err } => RecordError::Field(err),
+        // the fields are a fixed length so they are never cut off
+        byte::Error::Incomplete | byte::Error::BadOffset(_) => RecordError::Field("length"),
+    }
+}
+
+/// The config part of a record, the binary protocol sends configs the same way
+pub(crate) fn write_config(bytes: &mut [u8], offset: &mut usize, config: &SessionConfig) -> byte::Result<()> {
+    write_transmit_option(bytes, offset, config.transmit_option)?;
+
+    write_address(bytes, offset, config.frame.source)?;
+    write_address(bytes, offset, config.frame.destination)?;
+    let (mode, start, end) = match config.frame.sequence_number {
+        SequenceNumberMode::Fixed(sequence_number) => (0u8, sequence_number, 0),
+        SequenceNumberMode::Increment { start } => (1, start, 0),
+        SequenceNumberMode::Wrap { start, end } => (2, start, end),
+    };
+    bytes.write(offset, mode)?;
+    bytes.write(offset, start)?;
+    bytes.write(offset, end)?;
+
+    bytes.write_with(offset, config.send.interval_ms, LE)?;
+    bytes.write_with(offset, config.send.number_packets, LE)?;
+    let (mode, length, seed) = match config.send.payload {
+        PayloadMode::Sequential { length } => (0u8, length, 0),
+        PayloadMode::Random { length, seed } => (1, length, seed),
+    };
+    bytes.write(offset, mode)?;
+    bytes.write_with(offset, length, LE)?;
+    bytes.write_with(offset, seed, LE)?;
+
+    let (mode, length, value) = match config.send.schedule {
+        ScheduleMode::Fixed => (0u8, 0, 0),
+        ScheduleMode::Poisson { seed } => (1, 0, seed),
+        ScheduleMode::Burst { length, gap_ms } => (2, length, u64::from(gap_ms)),
+    };
+    bytes.write(offset, mode)?;
+    bytes.write_with(offset, length, LE)?;
+    bytes.write_with(offset, value, LE)?;
+
+    let sideband = match config.sideband {
+        None => 0u8,
+        Some(Sideband::Upper) => 1,
+        Some(Sideband::Lower) => 2,
+    };
+    bytes.write(offset, sideband)?;
+    // 0 for 2.4GHz, else one more than the index in SubGhzPhy::ALL
+    let sub_ghz = config.sub_ghz.map_or(0u8, |phy| {
+        1 + SubGhzPhy::ALL
+            .iter()
+            .position(|other| *other == phy)
+            .expect("ALL has every PHY") as u8
+    });
+    bytes.write(offset, sub_ghz)?;
+    write_keying(bytes, offset, config.keying)?;
+
+    write_pins(bytes, offset, &config.pins)
+}
+
+pub(crate) fn read_config(bytes: &[u8], offset: &mut usize) -> byte::Result<SessionConfig> {
+    let transmit_option = read_transmit_option(bytes, offset)?;
+
+    let source = read_address(bytes, offset)?;
+    let destination = read_address(bytes, offset)?;
+    let mode: u8 = bytes.read(offset)?;
+    let start: u8 = bytes.read(offset)?;
+    let end: u8 = bytes.read(offset)?;
+    let sequence_number = match mode {
+        0 => SequenceNumberMode::Fixed(start),
+        1 => SequenceNumberMode::Increment { start },
+        2 => SequenceNumberMode::Wrap { start, end },
+        _ => {
+            return Err(byte::Error::BadInput {
+                err: "sequence number mode",
+            })
+        }
+    };
+
+    let interval_ms = bytes.read_with(offset, LE)?;
+    let number_packets = bytes.read_with(offset, LE)?;
+    let mode: u8 = bytes.read(offset)?;
+    let length = bytes.read_with(offset, LE)?;
+    let seed = bytes.read_with(offset, LE)?;
+    let payload = match mode {
+        0 => PayloadMode::Sequential { length },
+        1 => PayloadMode::Random { length, seed },
+        _ => return Err(byte::Error::BadInput { err: "payload mode" }),
+    };
+
+    let mode: u8 = bytes.read(offset)?;
+    let length = bytes.read_with(offset, LE)?;
+    let value: u64 = bytes.read_with(offset, LE)?;
+    let schedule = match mode {
+        0 => ScheduleMode::Fixed,
+        1 => ScheduleMode::Poisson { seed: value },
+        2 => ScheduleMode::Burst {
+            length,
+            gap_ms: u32::try_from(value).map_err(|_| byte::Error::BadInput { err: "burst gap" })?,
+        },
+        _ => return Err(byte::Error::BadInput { err: "schedule mode" }),
+    };
+
+    let sideband = match bytes.read::<u8>(offset)? {
+        0 => None,
+        1 => Some(Sideband::Upper),
+        2 => Some(Sideband::Lower),
+        _ => return Err(byte::Error::BadInput { err: "sideband" }),
+    };
+    let sub_ghz = match bytes.read::<u8>(offset)? {
+        0 => None,
+        phy => Some(
+            *SubGhzPhy::ALL
+                .get(usize::from(phy) - 1)
+                .ok_or(byte::Error::BadInput { err: "sub-GHz PHY" })?,
+        ),
+    };
+
+    let keying = read_keying(bytes, offset)?;
+
+    let pins = read_pins(bytes, offset)?;
+
+    Ok(SessionConfig {
+        transmit_option,
+        sideband,
+        sub_ghz,
+        keying,
+        frame: FrameConfig {
+            source,
+            destination,
+            sequence_number,
+        },
+        send: SendDefaults {
+            interval_ms,
+            number_packets,
+            payload,
+            schedule,
+        },
+        pins,
+    })
+}
+
+/// `[ON][KEYING][DEVIATION][CODING][BIT RATE][PREAMBLE][SYNC WORD]`, the fields are there when it is off too
+fn write_keying(bytes: &mut [u8], offset: &mut usize, keying: Option<KeyingConfig>) -> byte::Result<()> {
+    bytes.write(offset, u8::from(keying.is_some()))?;
+    let config = keying.unwrap_or_default();
+    let (mode, deviation_hz) = match config.keying {
+        Keying::Ook => (0u8, 0),
+        Keying::Fsk { deviation_hz } => (1, deviation_hz),
+    };
+    bytes.write(offset, mode)?;
+    bytes.write_with(offset, deviation_hz, LE)?;
+    let coding = match config.coding {
+        LineCoding::Nrz => 0u8,
+        LineCoding::Manchester => 1,
+    };
+    bytes.write(offset, coding)?;
+    bytes.write_with(offset, config.bit_rate, LE)?;
+    bytes.write(offset, config.preamble_bytes)?;
+    bytes.write_with(offset, config.sync_word, LE)
+}
+
+fn read_keying(bytes: &[u8], offset: &mut usize) -> byte::Result<Option<KeyingConfig>> {
+    let on: u8 = bytes.read(offset)?;
+    let mode: u8 = bytes.read(offset)?;
+    let deviation_hz = bytes.read_with(offset, LE)?;
+    let keying = match mode {
+        0 => Keying::Ook,
+        1 => Keying::Fsk { deviation_hz },
+        _ => return Err(byte::Error::BadInput { err: "keying" }),
+    };
+    let coding = match bytes.read::<u8>(offset)? {
+        0 => LineCoding::Nrz,
+        1 => LineCoding::Manchester,
+        _ => return Err(byte::Error::BadInput { err: "line coding" }),
+    };
+    let config = KeyingConfig {
+        keying,
+        coding,
+        bit_rate: bytes.read_with(offset, LE)?,
+        preamble_bytes: bytes.read(offset)?,
+        sync_word: bytes.read_with(offset, LE)?,
+    };
+    match on {
+        0 => Ok(None),
+        1 if config.bit_rate > 0 => Ok(Some(config)),
+        _ => Err(byte::Error::BadInput { err: "keying" }),
+    }
+}
+
+/// `[TRIGGER][FLAGS][ANTENNA][EXTRA 1][EXTRA 2][EXTRA 3]`, an unused extra output is [NO_PIN]
+fn write_pins(bytes: &mut [u8], offset: &mut usize, pins: &PinConfig) -> byte::Result<()> {
+    let mut flags = 0u8;
+    if pins.external_trigger {
+        flags |= EXTERNAL_TRIGGER;
+    }
+    if pins.quadrature {
+        flags |= QUADRATURE;
+    }
+    bytes.write(offset, pins.trigger)?;
+    bytes.write(offset, flags)?;
+    bytes.write(offset, pins.antenna)?;
+    for pin in pins.extra {
+        bytes.write(offset, pin.unwrap_or(NO_PIN))?;
+    }
+    Ok(())
+}
+
+/// A layout [PinConfig::validate] rejects is a bad field, the firmware can't start with it
+fn read_pins(bytes: &[u8], offset: &mut usize) -> byte::Result<PinConfig> {
+    let trigger = bytes.read(offset)?;
+    let flags: u8 = bytes.read(offset)?;
+    if flags & !(EXTERNAL_TRIGGER | QUADRATURE) != 0 {
+        return Err(byte::Error::BadInput { err: "pin flags" });
+    }
+    let antenna = bytes.read(offset)?;
+    let mut extra = [None; EXTRA_OUTPUTS];
+    for pin in &mut extra {
+        let byte: u8 = bytes.read(offset)?;
+        *pin = (byte != NO_PIN).then_some(byte);
+    }
+    let pins = PinConfig {
+        trigger,
+        external_trigger: flags & EXTERNAL_TRIGGER != 0,
+        antenna,
+        quadrature: flags & QUADRATURE != 0,
+        extra,
+    };
+    pins.validate()
+        .map_err(|_| byte::Error::BadInput { err: "pins" })?;
+    Ok(pins)
+}
+
+/// The index of a standard option or [PLANNED] followed by
+/// `[REFDIV][FBDIV][POST DIV1][POST DIV2][DIVIDER INTEGER][DIVIDER FRACTION][QUARTER CYCLES][QUARTERS PER CHIP]`
+fn write_transmit_option(bytes: &mut [u8], offset: &mut usize, option: TransmitOption) -> byte::Result<()> {
+    let plan = match option {
+        TransmitOption::Standard(option) => {
+            let index = StandardTransmitOption::ALL
+                .iter()
+                .position(|standard| *standard == option)
+                .expect("ALL has every option");
+            return bytes.write(offset, index as u8);
+        }
+        TransmitOption::Planned(plan) => plan,
+    };
+    bytes.write(offset, PLANNED)?;
+    let pll = plan.clock.pll();
+    bytes.write(offset, pll.refdiv)?;
+    bytes.write_with(offset, pll.fbdiv, LE)?;
+    bytes.write(offset, pll.post_div1)?;
+    bytes.write(offset, pll.post_div2)?;
+    let divider = plan.divider.to_256ths();
+    bytes.write_with(offset, (divider >> 8) as u16, LE)?;
+    bytes.write(offset, divider as u8)?;
+    bytes.write(offset, plan.quarter_cycles)?;
+    bytes.write(offset, plan.quarters_per_chip)
+}
+
+fn read_transmit_option(bytes: &[u8], offset: &mut usize) -> byte::Result<TransmitOption> {
+    let index: u8 = bytes.read(offset)?;
+    if index != PLANNED {
+        return StandardTransmitOption::ALL
+            .get(usize::from(index))
+            .map(|option| TransmitOption::Standard(*option))
+            .ok_or(byte::Error::BadInput {
+                err: "transmit option",
+            });
+    }
+    let pll = PllSettings {
+        refdiv: bytes.read(offset)?,
+        fbdiv: bytes.read_with(offset, LE)?,
+        post_div1: bytes.read(offset)?,
+        post_div2: bytes.read(offset)?,
+    };
+    let integer_part: u16 = bytes.read_with(offset, LE)?;
+    let fractional_part: u8 = bytes.read(offset)?;
+    let divider = StateMachineClockDividerSetting::from_256ths(
+        u32::from(integer_part) << 8 | u32::from(fractional_part),
+    )
+    .ok_or(byte::Error::BadInput { err: "pio divider" })?;
+    let plan = TransmitPlan {
+        clock: ProcessorClock::from_pll(pll),
+        divider,
+        quarter_cycles: bytes.read(offset)?,
+        quarters_per_chip: bytes.read(offset)?,
+    };
+    if !plan.is_valid() {
+        return Err(byte::Error::BadInput { err: "transmit plan" });
+    }
+    Ok(TransmitOption::Planned(plan))
+}
+
+/// `[KIND][PAN ID][ADDRESS]`, kind 0 is no address, 1 short and 2 extended, always 11 bytes
+fn write_address(bytes: &mut [u8], offset: &mut usize, address: Option<Address>) -> byte::Result<()> {
+    let (kind, pan_id, address) = match address {
+        None => (0u8, 0, 0),
+        Some(Address::Short(pan_id, address)) => (1, pan_id.0, u64::from(address.0)),
+        Some(Address::Extended(pan_id, address)) => (2, pan_id.0, address.0),
+    };
+    bytes.write(offset, kind)?;
+    bytes.write_with(offset, pan_id, LE)?;
+    bytes.write_with(offset, address, LE)
+}
+
+fn read_address(bytes: &[u8], offset: &mut usize) -> byte::Result<Option<Address>> {
+    let kind: u8 = bytes.read(offset)?;
+    let pan_id = PanId(bytes.read_with(offset, LE)?);
+    let address: u64 = bytes.read_with(offset, LE)?;
+    match kind {
+        0 => Ok(None),
+        1 => Ok(Some(Address::Short(pan_id, ShortAddress(address as u16)))),
+        2 => Ok(Some(Address::Extended(pan_id, ExtendedAddress(address)))),
+        _ => Err(byte::Error::BadInput { err: "address" }),
+    }
+}
+
+/// The config store on top of a [Flash]
+#[derive(Debug)]
+pub struct ConfigStore<F: Flash> {
+    flash: F,
+}
+
+impl<F: Flash> ConfigStore<F> {
+    const SLOTS_PER_SECTOR: usize = F::SECTOR_SIZE / RECORD_SIZE;
+    /// Slots in the whole store
+    pub const SLOTS: usize = STORE_SECTORS * Self::SLOTS_PER_SECTOR;
+
+    pub fn new(flash: F) -> Self {
+        ConfigStore { flash }
+    }
+
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    /// The newest saved config, `None` if nothing was saved or every record is damaged
+    pub fn load(&self) -> Option<SessionConfig> {
+        self.newest().map(|(_, _, config)| config)
+    }
+
+    /// Save `config` as the newest record
+    pub fn save(&mut self, config: &SessionConfig) -> Result<(), StoreError> {
+        let (mut slot, counter) = match self.newest() {
+            Some((slot, counter, _)) => ((slot + 1) % Self::SLOTS, counter.wrapping_add(1)),
+            None => (0, 0),
+        };
+        // a damaged record (a cut off write) can't be written over without an erase, the sector it is in
+        // may still hold the newest record so the next sector is used instead
+        if slot % Self::SLOTS_PER_SECTOR != 0 && !self.is_erased(slot) {
+            slot = (slot / Self::SLOTS_PER_SECTOR + 1) % STORE_SECTORS * Self::SLOTS_PER_SECTOR;
+        }
+        if slot % Self::SLOTS_PER_SECTOR == 0 {
+            self.flash.erase_sector(slot * RECORD_SIZE);
+        }
+
+        let record = encode_record(config, counter);
+        self.flash.program(slot * RECORD_SIZE, &record);
+        if self.read_slot(slot) == record {
+            Ok(())
+        } else {
+            Err(StoreError::Verify)
+        }
+    }
+
+    /// Erase the whole store, [ConfigStore::load] returns `None` until the next save
+    pub fn factory_reset(&mut self) {
+        for sector in 0..STORE_SECTORS {
+            self.flash.erase_sector(sector * F::SECTOR_SIZE);
+        }
+    }
+
+    /// (slot, counter, config) of the record with the highest counter
+    fn newest(&self) -> Option<(usize, u32, SessionConfig)> {
+        (0..Self::SLOTS)
+            .filter_map(|slot| {
+                decode_record(&self.read_slot(slot))
+                    .ok()
+                    .map(|(counter, config)| (slot, counter, config))
+            })
+            .max_by_key(|(_, counter, _)| *counter)
+    }
+
+    fn read_slot(&self, slot: usize) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0u8; RECORD_SIZE];
+        self.flash.read(slot * RECORD_SIZE, &mut bytes);
+        bytes
+    }
+
+    fn is_erased(&self, slot: usize) -> bool {
+        self.read_slot(slot).iter().all(|byte| *byte == 0xFF)
+    }
+}
@@ -1,5 +1,5 @@
 /// this corresponds to 00000000A71741880B222234124444CDAB0102030405060708090A4B49 with 4 repeats
-#[allow(dead_code)]
+/// of `wave_array!(16)` (ie [crate::transmit_option::StandardTransmitOption::Clk128MHzOffset8MHz])
 pub const PACKET_IN_RAW_PIO_BYTECODE: &[u32] = &[
     0b11011011011011011011001101101,
     0b10110110110110110110110110110110,
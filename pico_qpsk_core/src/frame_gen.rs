@@ -0,0 +1,208 @@
+use crate::packet::FrameConstructionError;
+use crate::prng::SplitMix64;
+use crate::session_config::FrameConfig;
+use heapless::Vec;
+
+/// get a frams to test with a given payload
+///
+/// # Arguments
+///
+/// * `payload`:
+///
+/// returns: Vec<u8, { MAX_FRAME_SIZE }>
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::frame_gen::get_testing_generated_frame_bytes;
+/// use pico_qpsk_core::packet::fcs;
+/// use pico_qpsk_core::to_max_frame_size;
+///
+/// const PAYLOAD_SIZE: usize = 4;
+/// const MAX_FRAME_SIZE: usize = to_max_frame_size!(PAYLOAD_SIZE);
+/// let payload: [u8; PAYLOAD_SIZE] = [0x01, 0x02, 0xA, 0xB];
+/// let frame = get_testing_generated_frame_bytes::<PAYLOAD_SIZE, MAX_FRAME_SIZE>(&payload);
+/// // the PHY header, then the MAC frame ending in the payload and its FCS
+/// assert_eq!(frame[..5], [0x00, 0x00, 0x00, 0x00, 0xA7]);
+/// let (mac, footer) = frame[6..].split_at(frame.len() - 8);
+/// assert!(mac.ends_with(&payload));
+/// assert_eq!(footer, fcs(mac).to_le_bytes());
+/// ```
+pub fn get_testing_generated_frame_bytes<const MAX_PAYLOAD_SIZE: usize, const MAX_FRAME_SIZE: usize>(
+    payload: &[u8],
+) -> Vec<u8, MAX_FRAME_SIZE> {
+    assert!(payload.len() <= MAX_PAYLOAD_SIZE, "payload is too big!");
+
+    try_get_testing_generated_frame_bytes(payload).unwrap_or_else(|err| {
+        panic!(
+            "Failed to convert frame to bytes, this should never happen ERR:{:?}",
+            err
+        );
+    })
+}
+
+/// [get_testing_generated_frame_bytes] for payloads that come from the user, the frame uses
+/// [FrameConfig::default],
+/// a payload that doesn't fit in the frame is an error instead of a panic
+///
+/// # Arguments
+///
+/// * `payload`: the MAC payload
+///
+/// returns: Result<Vec<u8, { MAX_FRAME_SIZE }>, [FrameConstructionError]>
+pub fn try_get_testing_generated_frame_bytes<const MAX_FRAME_SIZE: usize>(
+    payload: &[u8],
+) -> Result<Vec<u8, MAX_FRAME_SIZE>, FrameConstructionError> {
+    let frame_bytes = FrameConfig::default().frame_bytes(0, payload)?;
+    info!("Created frame -> :{=[u8]:#x}", &frame_bytes);
+    Ok(frame_bytes)
+}
+
+/// The payloads `srp` sends, item `n` is the payload of packet `n + 1`
+///
+/// Rebuild it from the seed the firmware prints to score a run on the receiving side
+#[derive(Debug, Clone)]
+pub struct RandomPayloads<const MAX_PAYLOAD_SIZE: usize> {
+    rng: SplitMix64,
+    size: usize,
+}
+
+impl<const MAX_PAYLOAD_SIZE: usize> Iterator for RandomPayloads<MAX_PAYLOAD_SIZE> {
+    type Item = Vec<u8, MAX_PAYLOAD_SIZE>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut payload = Vec::new();
+        payload
+            .resize(self.size, 0)
+            .expect("size is checked in random_payloads");
+        self.rng.fill_bytes(&mut payload);
+        Some(payload)
+    }
+}
+
+/// The sequence of random payloads for a seed, see [SplitMix64::fill_bytes] for how the bytes are made
+///
+/// # Arguments
+///
+/// * `seed`: the seed given to `srp`
+/// * `size`: the payload length given to `srp`
+///
+/// returns: [RandomPayloads], it never ends
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::frame_gen::random_payloads;
+///
+/// let run: Vec<_> = random_payloads::<16>(42, 4).take(1000).collect();
+/// let again: Vec<_> = random_payloads::<16>(42, 4).take(1000).collect();
+/// assert_eq!(run, again);
+/// assert_ne!(run[0], run[1]);
+/// ```
+pub fn random_payloads<const MAX_PAYLOAD_SIZE: usize>(
+    seed: u64,
+    size: usize,
+) -> RandomPayloads<MAX_PAYLOAD_SIZE> {
+    assert!(size <= MAX_PAYLOAD_SIZE, "payload is too big!");
+    RandomPayloads {
+        rng: SplitMix64::new(seed),
+        size,
+    }
+}
+
+/// A test frame with the next payload of `payloads`
+pub fn get_random_payload_frame_bytes<const MAX_PAYLOAD_SIZE: usize, const MAX_FRAME_SIZE: usize>(
+    payloads: &mut RandomPayloads<MAX_PAYLOAD_SIZE>,
+) -> Vec<u8, MAX_FRAME_SIZE> {
+    let payload_vec = payloads.next().expect("random payloads never end");
+
+    get_testing_generated_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(&payload_vec)
+}
+
+pub fn get_seq_frame_bytes<const MAX_PAYLOAD_SIZE: usize, const MAX_FRAME_SIZE: usize>(
+    size: usize,
+) -> Vec<u8, MAX_FRAME_SIZE> {
+    let payload_vec = get_seq_payload::<MAX_PAYLOAD_SIZE>(size);
+
+    get_testing_generated_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(&payload_vec)
+}
+
+/// The payload of [get_seq_frame_bytes], counts up from 0x00 and rolls over after 0xFF
+pub fn get_seq_payload<const MAX_PAYLOAD_SIZE: usize>(size: usize) -> Vec<u8, MAX_PAYLOAD_SIZE> {
+    assert!(size <= MAX_PAYLOAD_SIZE, "payload is too big!");
+    const SEQ: [u8; 256] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10,
+        0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x1F, 0x20, 0x21,
+        0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2A, 0x2B, 0x2C, 0x2D, 0x2E, 0x2F, 0x30, 0x31, 0x32,
+        0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F, 0x40, 0x41, 0x42, 0x43,
+        0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F, 0x50, 0x51, 0x52, 0x53, 0x54,
+        0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x5B, 0x5C, 0x5D, 0x5E, 0x5F, 0x60, 0x61, 0x62, 0x63, 0x64, 0x65,
+        0x66, 0x67, 0x68, 0x69, 0x6A, 0x6B, 0x6C, 0x6D, 0x6E, 0x6F, 0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76,
+        0x77, 0x78, 0x79, 0x7A, 0x7B, 0x7C, 0x7D, 0x7E, 0x7F, 0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+        0x88, 0x89, 0x8A, 0x8B, 0x8C, 0x8D, 0x8E, 0x8F, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98,
+        0x99, 0x9A, 0x9B, 0x9C, 0x9D, 0x9E, 0x9F, 0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9,
+        0xAA, 0xAB, 0xAC, 0xAD, 0xAE, 0xAF, 0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA,
+        0xBB, 0xBC, 0xBD, 0xBE, 0xBF, 0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xCB,
+        0xCC, 0xCD, 0xCE, 0xCF, 0xD0, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xDB, 0xDC,
+        0xDD, 0xDE, 0xDF, 0xE0, 0xE1, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xEB, 0xEC, 0xED,
+        0xEE, 0xEF, 0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD, 0xFE,
+        0xFF,
+    ];
+
+    SEQ.into_iter().cycle().take(size).collect()
+}
+
+/// A type to hold the possible errors that occur when a hex string is converted to bytes
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HexStringError {
+    /// every byte needs two characters
+    OddLength,
+    /// a character that isn't 0-9, a-f or A-F, `index` counts characters from 0
+    NonHex { index: usize, character: char },
+    /// there are more bytes than the vec can hold
+    TooLong,
+}
+
+/// Convert a hex string, with no prefix or separators, into bytes
+///
+/// # Arguments
+///
+/// * `message_str`: the hex string, upper or lower case, `"A717"` is `[0xA7, 0x17]`
+///
+/// returns: Result<Vec<u8, { MAX_VEC_SIZE }>, [HexStringError]>
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::frame_gen::{get_hex_string_as_bytes, HexStringError};
+///
+/// assert_eq!(get_hex_string_as_bytes::<4>("A717").unwrap(), [0xA7, 0x17]);
+/// assert_eq!(get_hex_string_as_bytes::<4>("A71"), Err(HexStringError::OddLength));
+/// assert_eq!(
+///     get_hex_string_as_bytes::<4>("A7G7"),
+///     Err(HexStringError::NonHex { index: 2, character: 'G' })
+/// );
+/// assert_eq!(get_hex_string_as_bytes::<1>("A717"), Err(HexStringError::TooLong));
+/// ```
+pub fn get_hex_string_as_bytes<const MAX_VEC_SIZE: usize>(
+    message_str: &str,
+) -> Result<Vec<u8, MAX_VEC_SIZE>, HexStringError> {
+    if !message_str.chars().count().is_multiple_of(2) {
+        return Err(HexStringError::OddLength);
+    }
+    let mut bytes = Vec::new();
+    let mut high_nibble = None;
+    for (index, character) in message_str.chars().enumerate() {
+        let nibble = character
+            .to_digit(16)
+            .ok_or(HexStringError::NonHex { index, character })? as u8;
+        match high_nibble.take() {
+            None => high_nibble = Some(nibble),
+            Some(high) => bytes
+                .push(high << 4 | nibble)
+                .map_err(|_| HexStringError::TooLong)?,
+        }
+    }
+    Ok(bytes)
+}
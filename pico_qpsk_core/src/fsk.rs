@@ -0,0 +1,239 @@
+//! Continuous phase 2-FSK of the subcarrier square wave
+//!
+//! Every bit keeps the square wave at one of two frequencies, the phase carries on across the bits like
+//! the GFSK of a BLE radio without the gaussian filter. The edges are worked out in exact integer time
+//! and rounded to the even cycles the PIO program can play, so the bit boundaries don't drift over a
+//! packet whatever the ratio of the clock and the bit rate.
+
+/// The frequencies and bit rate of a 2-FSK square wave, all in Hz
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FskTiming {
+    /// state machine cycles per second
+    pub pio_hz: u32,
+    pub bit_rate: u32,
+    /// the square wave of a 0 bit
+    pub space_hz: u32,
+    /// the square wave of a 1 bit
+    pub mark_hz: u32,
+}
+
+/// A [FskTiming] the PIO can't play
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FskError {
+    /// a half period of the faster tone is shorter than the 4 cycle run of the PIO program, holds the
+    /// highest tone for the clock
+    TooFast(u32),
+    /// the slower tone is 0Hz or the bit rate is 0
+    Zero,
+}
+
+impl FskTiming {
+    /// Mark and space `deviation_hz` above and below `subcarrier_hz`
+    pub fn around(pio_hz: u32, bit_rate: u32, subcarrier_hz: u32, deviation_hz: u32) -> Self {
+        FskTiming {
+            pio_hz,
+            bit_rate,
+            space_hz: subcarrier_hz.saturating_sub(deviation_hz),
+            mark_hz: subcarrier_hz.saturating_add(deviation_hz),
+        }
+    }
+
+    /// Check the PIO can play both tones
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::fsk::{FskError, FskTiming};
+    ///
+    /// assert_eq!(FskTiming::around(128_000_000, 1_000_000, 8_000_000, 250_000).check(), Ok(()));
+    /// assert_eq!(
+    ///     FskTiming::around(128_000_000, 1_000_000, 16_000_000, 250_000).check(),
+    ///     Err(FskError::TooFast(16_000_000))
+    /// );
+    /// ```
+    pub fn check(&self) -> Result<(), FskError> {
+        if self.bit_rate == 0 || self.space_hz.min(self.mark_hz) == 0 {
+            return Err(FskError::Zero);
+        }
+        let highest = self.pio_hz / 8;
+        if self.space_hz.max(self.mark_hz) > highest {
+            return Err(FskError::TooFast(highest));
+        }
+        Ok(())
+    }
+
+    fn tone(&self, bit: bool) -> u64 {
+        u64::from(if bit { self.mark_hz } else { self.space_hz })
+    }
+}
+
+/// The bits of bytes, least significant bit first like BLE sends them
+#[derive(Clone)]
+pub struct LsbFirst<'a> {
+    bytes: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> LsbFirst<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        LsbFirst { bytes, bit: 0 }
+    }
+}
+
+impl Iterator for LsbFirst<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.bit / 8)?;
+        let bit = (byte >> (self.bit % 8)) & 1 == 1;
+        self.bit += 1;
+        Some(bit)
+    }
+}
+
+/// The run lengths, in state machine cycles, of the FSK square wave of a bit stream
+///
+/// The times are kept scaled by the bit rate, `t * bit_rate`, and the phase in half periods scaled by
+/// the bit rate too, so a bit is `pio_hz` long and the phase goes up by `2 * tone` over it.
+#[derive(Clone)]
+pub struct FskRuns<I> {
+    bits: I,
+    timing: FskTiming,
+    /// the tone of the current bit, `None` once the bits ran out
+    tone: Option<u64>,
+    /// the cycle the current bit starts at, `bit_start + bit_remainder / bit_rate`
+    bit_start: u64,
+    bit_remainder: u64,
+    /// phase from the start of the current bit to the next edge
+    to_edge: u64,
+    /// the cycle of the last edge, always even
+    last_edge: u64,
+}
+
+/// The runs of the continuous phase FSK square wave of `bits`
+///
+/// The square wave starts with an edge at the start of the first bit, the edge after the last one of
+/// the last bit ends the runs.
+pub fn fsk_runs<I: Iterator<Item = bool>>(mut bits: I, timing: FskTiming) -> FskRuns<I> {
+    let tone = bits.next().map(|bit| timing.tone(bit));
+    FskRuns {
+        bits,
+        timing,
+        tone,
+        bit_start: 0,
+        bit_remainder: 0,
+        to_edge: u64::from(timing.bit_rate),
+        last_edge: 0,
+    }
+}
+
+impl<I: Iterator<Item = bool>> Iterator for FskRuns<I> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let pio_hz = u64::from(self.timing.pio_hz);
+        let bit_rate = u64::from(self.timing.bit_rate);
+        loop {
+            let tone = self.tone?;
+            let bit_phase = 2 * tone;
+            if self.to_edge < bit_phase {
+                // t = bit start + to_edge / (2 tone) bits
+                let offset = self.bit_remainder * bit_phase + self.to_edge * pio_hz;
+                let edge = self.bit_start + offset / (bit_phase * bit_rate);
+                // the whole cycles of t rounded up to even are the nearest even cycle to t
+                let edge = edge.div_ceil(2) * 2;
+                let len = edge.saturating_sub(self.last_edge).max(4);
+                self.last_edge += len;
+                self.to_edge += bit_rate;
+                return Some(len as u32);
+            }
+            self.to_edge -= bit_phase;
+            self.bit_remainder += pio_hz;
+            self.bit_start += self.bit_remainder / bit_rate;
+            self.bit_remainder %= bit_rate;
+            self.tone = self.bits.next().map(|bit| self.timing.tone(bit));
+        }
+    }
+}
+
+/// Pack run lengths into pio bytecode for the single pin program, see
+/// [single_pin_program](crate::pio_program::single_pin_program)
+///
+/// The leading 0 bit is a 4 cycle run before the first one, every run is `4 + 2n` cycles for `n` set
+/// bits and a 0, packed most significant bit first like [convert](crate::pio_bytecode_gen::convert).
+#[derive(Clone)]
+pub struct RunWords<I> {
+    runs: I,
+    started: bool,
+    ones_left: u32,
+    zero_left: bool,
+}
+
+impl<I: Iterator<Item = u32>> RunWords<I> {
+    /// `runs` are even and at least 4
+    pub fn new(runs: I) -> Self {
+        RunWords {
+            runs,
+            started: false,
+            ones_left: 0,
+            zero_left: false,
+        }
+    }
+
+    fn next_bit(&mut self) -> Option<u8> {
+        if !self.started {
+            self.started = true;
+            return Some(0);
+        }
+        if self.ones_left == 0 && !self.zero_left {
+            self.ones_left = (self.runs.next()? - 4) / 2;
+            self.zero_left = true;
+        }
+        if self.ones_left > 0 {
+            self.ones_left -= 1;
+            Some(1)
+        } else {
+            self.zero_left = false;
+            Some(0)
+        }
+    }
+}
+
+impl<I: Iterator<Item = u32>> Iterator for RunWords<I> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for bit_idx in 0..32 {
+            match self.next_bit() {
+                Some(bit) => value |= u32::from(bit) << (31 - bit_idx),
+                None if bit_idx == 0 => return None,
+                None => break,
+            }
+        }
+        Some(value)
+    }
+}
+
+/// The pio bytecode of a BLE style FSK burst, the bits of `bytes` least significant bit first
+pub type FskConvertIter<'a> = RunWords<FskRuns<LsbFirst<'a>>>;
+
+/// Translate bytes to pio bytecode of a continuous phase 2-FSK square wave, least significant bit first
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::fsk::{convert_fsk, FskTiming};
+/// use pico_qpsk_core::pio_bytecode_decode::pio_run_lengths;
+///
+/// // 1Mbps at 128MHz, 1MHz for a 0 and 2MHz for a 1
+/// let timing = FskTiming { pio_hz: 128_000_000, bit_rate: 1_000_000, space_hz: 1_000_000, mark_hz: 2_000_000 };
+/// let words: Vec<u32> = convert_fsk(&[0b10], timing).collect();
+/// let runs: Vec<u32> = pio_run_lengths(&words).skip(1).take(6).collect();
+/// assert_eq!(runs, [64, 64, 32, 32, 32, 32]);
+/// ```
+pub fn convert_fsk(bytes: &[u8], timing: FskTiming) -> FskConvertIter<'_> {
+    RunWords::new(fsk_runs(LsbFirst::new(bytes), timing))
+}
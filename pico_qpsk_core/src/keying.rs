@@ -0,0 +1,436 @@
+//! On-off keying and 2-FSK of the subcarrier for simple receivers
+//!
+//! OOK turns the subcarrier on for a 1 and off for a 0, 2-FSK sends it a deviation above for a 1 and below
+//! for a 0. The bits are NRZ or Manchester coded and framed the way most sub-GHz packet radios expect:
+//!
+//! ```text
+//! [0xAA * preamble_bytes][SYNC WORD][PHR][PSDU]
+//! ```
+//!
+//! The SHR of the 802.15.4 frame is swapped for the 0xAA preamble and the sync word, the length byte and
+//! the PSDU with its FCS follow as they are, so the frame builders work unchanged. Every byte goes most
+//! significant bit first and the sync word big endian.
+//!
+//! OOK is generated as [Level]s of at most [MAX_LEVEL] cycles that are merged into the runs of the single
+//! pin program, the subcarrier keeps running under the off bits so the on ones stay in phase. 2-FSK is the
+//! continuous phase square wave of [crate::fsk]. Both run at the state machine clock of the
+//! [TransmitOption](crate::transmit_option::TransmitOption), the bit boundaries are worked out in exact
+//! integer time so they don't drift over a frame.
+use crate::fsk::{fsk_runs, FskError, FskRuns, FskTiming, RunWords};
+use crate::pio_bytecode_gen::Level;
+
+/// The 4 0x00 preamble octets and the 0xA7 SFD in front of the PHR
+const SHR_LEN: usize = 5;
+
+/// The preamble byte, alternating ones and zeros
+const PREAMBLE: u8 = 0xAA;
+
+/// The longest [Level] [OokLevels] yields, even so the edges stay on even cycles
+pub const MAX_LEVEL: u8 = 254;
+
+/// What the subcarrier does for a 1 and a 0
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Keying {
+    /// the subcarrier for a 1, nothing for a 0
+    Ook,
+    /// the subcarrier `deviation_hz` above it for a 1 and below it for a 0
+    Fsk { deviation_hz: u32 },
+}
+
+/// How a bit becomes symbols
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LineCoding {
+    /// one symbol per bit, the bit itself
+    Nrz,
+    /// two symbols per bit like IEEE 802.3, 0 then 1 for a 1 and 1 then 0 for a 0
+    Manchester,
+}
+
+/// The keying, line coding and framing of a frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyingConfig {
+    pub keying: Keying,
+    pub coding: LineCoding,
+    /// bits per second, a Manchester coded bit is two symbols
+    pub bit_rate: u32,
+    /// 0xAA bytes in front of the sync word
+    pub preamble_bytes: u8,
+    pub sync_word: u16,
+}
+
+impl Default for KeyingConfig {
+    /// 10kb/s Manchester coded OOK with 4 preamble bytes and the 0xD391 sync word of the CC1101
+    fn default() -> Self {
+        KeyingConfig {
+            keying: Keying::Ook,
+            coding: LineCoding::Manchester,
+            bit_rate: 10_000,
+            preamble_bytes: 4,
+            sync_word: 0xD391,
+        }
+    }
+}
+
+/// A [KeyingConfig] the PIO can't play on a subcarrier
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyingError {
+    /// the bit rate is 0
+    Zero,
+    /// a symbol is shorter than two subcarrier periods, holds the highest bit rate
+    TooFast(u32),
+    /// the FSK tones don't fit the state machine clock
+    Fsk(FskError),
+}
+
+impl KeyingConfig {
+    /// Symbols per second, twice the bit rate for Manchester
+    pub fn symbol_rate(&self) -> u32 {
+        match self.coding {
+            LineCoding::Nrz => self.bit_rate,
+            LineCoding::Manchester => self.bit_rate.saturating_mul(2),
+        }
+    }
+
+    /// The bytes on air of the 802.15.4 PHY frame `frame_len` bytes long
+    pub fn air_len(&self, frame_len: usize) -> usize {
+        usize::from(self.preamble_bytes) + 2 + frame_len.saturating_sub(SHR_LEN)
+    }
+
+    /// Microseconds a frame `frame_len` bytes long takes on air, rounded up
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::keying::KeyingConfig;
+    ///
+    /// // 4 preamble bytes, the sync word and 10 of the 15 bytes at 10kb/s
+    /// assert_eq!(KeyingConfig::default().airtime_us(15), 12_800);
+    /// ```
+    pub fn airtime_us(&self, frame_len: usize) -> u32 {
+        let bits = 8 * self.air_len(frame_len) as u64;
+        (bits * 1_000_000).div_ceil(u64::from(self.bit_rate.max(1))) as u32
+    }
+
+    /// Check the PIO can play the keying on the subcarrier of the quarter timing
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::keying::{Keying, KeyingConfig, KeyingError};
+    /// use pico_qpsk_core::transmit_option::StandardTransmitOption;
+    ///
+    /// let (quarter_cycles, quarters_per_chip) = StandardTransmitOption::Clk128MHzOffset2MHz.quarter_timing();
+    /// let config = KeyingConfig { bit_rate: 600_000, ..KeyingConfig::default() };
+    /// assert_eq!(config.check(quarter_cycles, quarters_per_chip), Err(KeyingError::TooFast(500_000)));
+    /// let config = KeyingConfig { keying: Keying::Fsk { deviation_hz: 100_000 }, ..KeyingConfig::default() };
+    /// assert_eq!(config.check(quarter_cycles, quarters_per_chip), Ok(()));
+    /// ```
+    pub fn check(&self, quarter_cycles: u8, quarters_per_chip: u8) -> Result<(), KeyingError> {
+        if self.bit_rate == 0 {
+            return Err(KeyingError::Zero);
+        }
+        let (pio_hz, subcarrier_hz) = subcarrier(quarter_cycles, quarters_per_chip);
+        let highest = match self.coding {
+            LineCoding::Nrz => subcarrier_hz / 2,
+            LineCoding::Manchester => subcarrier_hz / 4,
+        };
+        if self.bit_rate > highest {
+            return Err(KeyingError::TooFast(highest));
+        }
+        if let Keying::Fsk { deviation_hz } = self.keying {
+            FskTiming::around(pio_hz, self.symbol_rate(), subcarrier_hz, deviation_hz)
+                .check()
+                .map_err(KeyingError::Fsk)?;
+        }
+        Ok(())
+    }
+
+    /// The bits of `frame` on air, see the [module docs](self)
+    pub fn bits<'a>(&self, frame: &'a [u8]) -> KeyedBits<'a> {
+        KeyedBits {
+            preamble_bytes: usize::from(self.preamble_bytes),
+            sync_word: self.sync_word.to_be_bytes(),
+            frame: frame.get(SHR_LEN..).unwrap_or(&[]),
+            bit: 0,
+        }
+    }
+
+    /// The symbols of `frame` on air, true is the subcarrier on or the FSK mark
+    pub fn symbols<'a>(&self, frame: &'a [u8]) -> LineCoded<KeyedBits<'a>> {
+        LineCoded {
+            bits: self.bits(frame),
+            coding: self.coding,
+            second_half: None,
+        }
+    }
+}
+
+/// The state machine clock and the subcarrier of a quarter timing, see
+/// [TransmitOption::quarter_timing](crate::transmit_option::TransmitOption::quarter_timing)
+fn subcarrier(quarter_cycles: u8, quarters_per_chip: u8) -> (u32, u32) {
+    let quarters_per_chip = u32::from(quarters_per_chip);
+    (
+        u32::from(quarter_cycles) * quarters_per_chip * 2_000_000,
+        quarters_per_chip * 500_000,
+    )
+}
+
+/// The preamble, sync word and frame bits, most significant bit first
+#[derive(Clone)]
+pub struct KeyedBits<'a> {
+    preamble_bytes: usize,
+    sync_word: [u8; 2],
+    /// the frame after its SHR
+    frame: &'a [u8],
+    bit: usize,
+}
+
+impl KeyedBits<'_> {
+    fn byte(&self, idx: usize) -> Option<u8> {
+        match idx.checked_sub(self.preamble_bytes) {
+            None => Some(PREAMBLE),
+            Some(idx @ 0..=1) => Some(self.sync_word[idx]),
+            Some(idx) => self.frame.get(idx - 2).copied(),
+        }
+    }
+}
+
+impl Iterator for KeyedBits<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        let byte = self.byte(self.bit / 8)?;
+        let bit = (byte << (self.bit % 8)) & 0x80 != 0;
+        self.bit += 1;
+        Some(bit)
+    }
+}
+
+/// The symbols of a bit stream in a [LineCoding]
+#[derive(Clone)]
+pub struct LineCoded<I> {
+    bits: I,
+    coding: LineCoding,
+    /// the second symbol of a Manchester coded bit
+    second_half: Option<bool>,
+}
+
+impl<I: Iterator<Item = bool>> Iterator for LineCoded<I> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if let Some(symbol) = self.second_half.take() {
+            return Some(symbol);
+        }
+        let bit = self.bits.next()?;
+        match self.coding {
+            LineCoding::Nrz => Some(bit),
+            LineCoding::Manchester => {
+                self.second_half = Some(bit);
+                Some(!bit)
+            }
+        }
+    }
+}
+
+/// The OOK square wave of a symbol stream as [Level]s
+///
+/// The subcarrier is high for the first half of its period, a symbol starts and ends on the nearest even
+/// cycle to its exact time.
+#[derive(Clone)]
+pub struct OokLevels<I> {
+    symbols: I,
+    /// the current symbol, `None` once the symbols ran out
+    on: Option<bool>,
+    pio_hz: u64,
+    symbol_rate: u64,
+    /// cycles in half a subcarrier period, even
+    half_period: u64,
+    /// index of the current symbol
+    symbol: u64,
+    /// the cycle the next level starts at
+    now: u64,
+}
+
+/// The OOK square wave of `symbols` with a subcarrier of `half_period` cycles high and low
+pub fn ook_levels<I: Iterator<Item = bool>>(
+    mut symbols: I,
+    pio_hz: u32,
+    symbol_rate: u32,
+    half_period: u32,
+) -> OokLevels<I> {
+    assert!(
+        half_period >= 4 && half_period.is_multiple_of(2),
+        "the PIO plays even runs of at least 4 cycles"
+    );
+    OokLevels {
+        on: symbols.next(),
+        symbols,
+        pio_hz: u64::from(pio_hz),
+        symbol_rate: u64::from(symbol_rate),
+        half_period: u64::from(half_period),
+        symbol: 0,
+        now: 0,
+    }
+}
+
+impl<I> OokLevels<I> {
+    /// The cycle symbol `idx` starts at
+    fn symbol_start(&self, idx: u64) -> u64 {
+        (idx * self.pio_hz / self.symbol_rate).div_ceil(2) * 2
+    }
+}
+
+impl<I: Iterator<Item = bool>> Iterator for OokLevels<I> {
+    type Item = Level;
+
+    fn next(&mut self) -> Option<Level> {
+        loop {
+            let on = self.on?;
+            let symbol_end = self.symbol_start(self.symbol + 1);
+            if self.now >= symbol_end {
+                self.symbol += 1;
+                self.on = self.symbols.next();
+                continue;
+            }
+            // the subcarrier runs on under the off symbols
+            let period_half = self.now / self.half_period;
+            let end = if on {
+                ((period_half + 1) * self.half_period).min(symbol_end)
+            } else {
+                symbol_end
+            }
+            .min(self.now + u64::from(MAX_LEVEL));
+            let len = (end - self.now) as u8;
+            self.now = end;
+            return Some(if on && period_half.is_multiple_of(2) {
+                Level::High(len)
+            } else {
+                Level::Low(len)
+            });
+        }
+    }
+}
+
+/// Merge [Level]s into the run lengths of the single pin program
+///
+/// Neighbouring levels that are the same are one run, [Level::Nop] is skipped. A run shorter than the 4
+/// cycles of the program is stretched to 4 and the next runs are shortened to catch up.
+#[derive(Clone)]
+pub struct LevelRuns<I> {
+    levels: I,
+    /// the first level of the next run
+    next_level: Option<Level>,
+    /// cycles the runs so far are longer than the levels
+    late: u32,
+}
+
+impl<I: Iterator<Item = Level>> LevelRuns<I> {
+    pub fn new(levels: I) -> Self {
+        LevelRuns {
+            levels,
+            next_level: None,
+            late: 0,
+        }
+    }
+
+    fn next_level(&mut self) -> Option<(bool, u32)> {
+        loop {
+            match self.next_level.take().or_else(|| self.levels.next())? {
+                Level::High(len) => return Some((true, u32::from(len))),
+                Level::Low(len) => return Some((false, u32::from(len))),
+                Level::Nop => continue,
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = Level>> Iterator for LevelRuns<I> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let (high, mut len) = self.next_level()?;
+        for level in self.levels.by_ref() {
+            match level {
+                Level::High(more) if high => len += u32::from(more),
+                Level::Low(more) if !high => len += u32::from(more),
+                Level::Nop => {}
+                other => {
+                    self.next_level = Some(other);
+                    break;
+                }
+            }
+        }
+        let run = len.saturating_sub(self.late).max(4);
+        self.late = self.late + run - len;
+        Some(run)
+    }
+}
+
+/// The runs of an OOK or 2-FSK frame
+#[derive(Clone)]
+pub enum KeyedRuns<'a> {
+    Ook(LevelRuns<OokLevels<LineCoded<KeyedBits<'a>>>>),
+    Fsk(FskRuns<LineCoded<KeyedBits<'a>>>),
+}
+
+impl Iterator for KeyedRuns<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            KeyedRuns::Ook(runs) => runs.next(),
+            KeyedRuns::Fsk(runs) => runs.next(),
+        }
+    }
+}
+
+/// The pio bytecode of an OOK or 2-FSK frame
+pub type KeyedConvertIter<'a> = RunWords<KeyedRuns<'a>>;
+
+/// Translate an 802.15.4 PHY frame to pio bytecode of its OOK or 2-FSK frame, see the [module docs](self)
+///
+/// `quarter_cycles` and `quarters_per_chip` are the quarter timing of the subcarrier, see
+/// [TransmitOption::quarter_timing](crate::transmit_option::TransmitOption::quarter_timing), the bytecode
+/// is for the state machine clock of that option.
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::keying::{convert_keyed, KeyingConfig, LineCoding};
+/// use pico_qpsk_core::pio_bytecode_decode::pio_run_lengths;
+///
+/// // 2MHz on 4 cycle quarters at 32MHz, 125kb/s NRZ is 256 cycles a bit
+/// let config = KeyingConfig { coding: LineCoding::Nrz, bit_rate: 125_000, ..KeyingConfig::default() };
+/// let words: Vec<u32> = convert_keyed(&[0, 0, 0, 0, 0xA7, 0], config, 4, 4).collect();
+/// // the preamble starts with a 1, 32 half periods of the subcarrier, the last low one goes on for the 0
+/// let runs: Vec<u32> = pio_run_lengths(&words).skip(1).take(32).collect();
+/// assert_eq!(runs[..31], [8; 31]);
+/// assert_eq!(runs[31], 8 + 256);
+/// ```
+pub fn convert_keyed(
+    frame: &[u8],
+    config: KeyingConfig,
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+) -> KeyedConvertIter<'_> {
+    let (pio_hz, subcarrier_hz) = subcarrier(quarter_cycles, quarters_per_chip);
+    let symbols = config.symbols(frame);
+    let runs = match config.keying {
+        Keying::Ook => KeyedRuns::Ook(LevelRuns::new(ook_levels(
+            symbols,
+            pio_hz,
+            config.symbol_rate().max(1),
+            2 * u32::from(quarter_cycles),
+        ))),
+        Keying::Fsk { deviation_hz } => KeyedRuns::Fsk(fsk_runs(
+            symbols,
+            FskTiming::around(pio_hz, config.symbol_rate().max(1), subcarrier_hz, deviation_hz),
+        )),
+    };
+    RunWords::new(runs)
+}
@@ -0,0 +1,34 @@
+//! Host buildable core of the O-QPSK backscatter transmitter
+//!
+//! Frame building, chip spreading, wave/PIO bytecode generation, the transmit option tables and the
+//! offset planner live here so the RP2040 firmware (`pico_qpsk`) and the host tools in `data_pipeline`
+//! share one copy of them. [ble] and [fsk] build BLE advertisements for the same subcarrier,
+//! [sub_ghz] sends the frames with the 868MHz and 915MHz PHYs and [keying] with OOK or 2-FSK for
+//! simple receivers.
+//!
+//! Turn on the `defmt` feature when building for the pico to get logging and [defmt::Format] impls.
+#![no_std]
+
+#[macro_use]
+mod log;
+
+pub mod aes;
+pub mod ble;
+pub mod chunk_ring;
+pub mod config_store;
+pub mod data_array;
+pub mod frame_gen;
+pub mod fsk;
+pub mod keying;
+pub mod offset_planner;
+pub mod packet;
+pub mod pio_bytecode_decode;
+pub mod pio_bytecode_gen;
+pub mod pio_program;
+pub mod prng;
+pub mod protocol;
+pub mod schedule;
+pub mod security;
+pub mod session_config;
+pub mod sub_ghz;
+pub mod transmit_option;
@@ -0,0 +1,9 @@
+//! Logging shims, these forward to [defmt] when the `defmt` feature is enabled
+//! and compile to nothing otherwise (defmt needs a global logger to link, which hosts don't have)
+
+macro_rules! info {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "defmt")]
+        defmt::info!($($arg)*);
+    }};
+}
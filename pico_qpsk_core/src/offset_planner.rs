@@ -0,0 +1,302 @@
+//! Planning the clocks for a subcarrier offset the standard options don't have
+//!
+//! The chips always go out at 2Mchip/s (a chip every 0.5us once the O-QPSK middle chips are in), the
+//! subcarrier is `quarters_per_chip` quarters of its period per chip, see
+//! [crate::pio_bytecode_gen::convert_quarters]. That puts the offsets on a 0.5MHz grid and the state
+//! machine has to run at `2MHz x quarters_per_chip x quarter_cycles`. [plan] searches the system PLL,
+//! the PIO clock divider and the cycles per quarter for the setup that gets closest, the same search
+//! `vcocalc.py` does for the PLL alone.
+use crate::pio_bytecode_decode::{decode_quarter_bytecode, DecodeError, DecodedFrame};
+use crate::pio_bytecode_gen::{convert_quarters, QuarterConvertIter};
+use crate::transmit_option::{ProcessorClock, StateMachineClockDividerSetting};
+use core::cmp::Reverse;
+
+/// The crystal on the pico
+pub const XOSC_HZ: u32 = 12_000_000;
+
+/// The fastest system clock a plan uses, the fastest one the firmware already runs at
+pub const MAX_SYS_HZ: u32 = 144_000_000;
+
+/// The slowest system clock a plan uses, the USB controller needs at least its own 48MHz
+pub const MIN_SYS_HZ: u32 = 48_000_000;
+
+/// 802.15.4 allows the chip rate to be off by this much
+pub const MAX_CHIP_RATE_ERROR_PPM: i32 = 40;
+
+/// The smallest offset [plan] takes, 3 quarters per chip
+///
+/// with 2 quarters every pair of quarters looks like one of the 4 chips, a receiver can't tell where the
+/// chips start
+pub const MIN_OFFSET_HZ: u32 = 1_500_000;
+
+/// The largest offset [plan] takes, 18 quarters of 4 cycles per chip at [MAX_SYS_HZ]
+pub const MAX_OFFSET_HZ: u32 = 9_000_000;
+
+/// Longer quarters only make the bytecode longer, they give the divider more values to hit
+const MAX_QUARTER_CYCLES: u8 = 16;
+
+/// The chips, middle chips included, per second
+const CHIP_RATE_HZ: u64 = 2_000_000;
+
+/// The offset a quarter per chip adds
+const OFFSET_STEP_HZ: u32 = 500_000;
+
+const VCO_MIN_HZ: u32 = 750_000_000;
+const VCO_MAX_HZ: u32 = 1_600_000_000;
+
+/// The settings of the system PLL, the output is `XOSC_HZ / refdiv * fbdiv / (post_div1 * post_div2)`
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PllSettings {
+    pub refdiv: u8,
+    pub fbdiv: u16,
+    pub post_div1: u8,
+    pub post_div2: u8,
+}
+
+impl PllSettings {
+    /// 1536MHz / 6 / 2
+    pub const SYS_128MHZ: PllSettings = PllSettings {
+        refdiv: 1,
+        fbdiv: 128,
+        post_div1: 6,
+        post_div2: 2,
+    };
+    /// 1440MHz / 5 / 2
+    pub const SYS_144MHZ: PllSettings = PllSettings {
+        refdiv: 1,
+        fbdiv: 120,
+        post_div1: 5,
+        post_div2: 2,
+    };
+
+    pub fn vco_hz(&self) -> u32 {
+        XOSC_HZ / u32::from(self.refdiv.max(1)) * u32::from(self.fbdiv)
+    }
+
+    pub fn sys_hz(&self) -> u32 {
+        self.vco_hz() / (u32::from(self.post_div1.max(1)) * u32::from(self.post_div2.max(1)))
+    }
+
+    /// Whether the RP2040's PLL can run with these settings, the reference at least 5MHz and the VCO
+    /// from 750MHz to 1600MHz
+    pub fn is_valid(&self) -> bool {
+        (1..=XOSC_HZ / 5_000_000).contains(&u32::from(self.refdiv))
+            && (16..=320).contains(&self.fbdiv)
+            && (1..=7).contains(&self.post_div1)
+            && (1..=7).contains(&self.post_div2)
+            && (VCO_MIN_HZ..=VCO_MAX_HZ).contains(&self.vco_hz())
+    }
+}
+
+/// A clock setup for a subcarrier offset, see [plan]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransmitPlan {
+    pub clock: ProcessorClock,
+    pub divider: StateMachineClockDividerSetting,
+    /// state machine cycles per quarter of the subcarrier period
+    pub quarter_cycles: u8,
+    /// quarters of the subcarrier period per chip
+    pub quarters_per_chip: u8,
+}
+
+impl TransmitPlan {
+    /// Whether the firmware can run the plan, a plan from [plan] always is
+    pub fn is_valid(&self) -> bool {
+        let pll_valid = match self.clock {
+            ProcessorClock::Custom(pll) => {
+                pll.is_valid() && (MIN_SYS_HZ..=MAX_SYS_HZ).contains(&pll.sys_hz())
+            }
+            ProcessorClock::F128MHz | ProcessorClock::F144MHz => true,
+        };
+        pll_valid
+            && self.divider.to_256ths() >= 256
+            && self.quarter_cycles >= 4
+            && self.quarter_cycles.is_multiple_of(2)
+            && self.quarters_per_chip >= 3
+    }
+
+    /// The subcarrier offset the plan sends at
+    pub fn offset_millihertz(&self) -> u64 {
+        u64::from(self.clock.hz()) * 256 * 1000
+            / (u64::from(self.divider.to_256ths()) * 4 * u64::from(self.quarter_cycles))
+    }
+
+    /// How far the plan's offset is from `requested_hz`
+    pub fn offset_error_millihertz(&self, requested_hz: u32) -> i64 {
+        self.offset_millihertz() as i64 - i64::from(requested_hz) * 1000
+    }
+
+    /// How much faster than 2Mchip/s the chips go out, in parts per million
+    pub fn chip_rate_error_ppm(&self) -> i32 {
+        // the chip rate is clock * 256 / (divider * quarter_cycles * quarters_per_chip)
+        let actual = i64::from(self.clock.hz()) * 256;
+        let nominal = CHIP_RATE_HZ as i64
+            * i64::from(self.divider.to_256ths())
+            * i64::from(self.quarter_cycles)
+            * i64::from(self.quarters_per_chip);
+        ((actual - nominal) * 1_000_000 / nominal) as i32
+    }
+
+    pub fn convert<'a>(&self, message_bytes: &'a [u8]) -> QuarterConvertIter<'a> {
+        convert_quarters(message_bytes, self.quarter_cycles, self.quarters_per_chip)
+    }
+
+    /// Decode the output of [TransmitPlan::convert] back into the message bytes
+    pub fn decode<const MAX_FRAME_SIZE: usize>(
+        &self,
+        words: &[u32],
+    ) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+        decode_quarter_bytecode(words, self.quarter_cycles, self.quarters_per_chip)
+    }
+}
+
+/// A type to hold the possible errors that occur when an offset is planned
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PlanError {
+    /// the offset is below [MIN_OFFSET_HZ] or above [MAX_OFFSET_HZ]
+    OutOfRange,
+    /// no clock setup sends the chips within [MAX_CHIP_RATE_ERROR_PPM] of 2Mchip/s
+    ChipRate,
+}
+
+/// Find the clock setup whose subcarrier is closest to `offset_hz`
+///
+/// Offsets that aren't a multiple of 0.5MHz go to the closest one, the chip rate can't move far enough
+/// to make up the difference. Among the setups that keep the chip rate within
+/// [MAX_CHIP_RATE_ERROR_PPM] the one picked
+/// 1. has an integer PIO divider, a fractional one adds a system clock cycle of jitter
+/// 2. is closest to `offset_hz`
+/// 3. runs the system clock at 128MHz or 144MHz like the standard options, switching between those is
+///    known to work
+/// 4. has the fewest cycles per quarter so there is less bytecode
+/// 5. has the fastest system clock so core1 has the most time to generate the bytecode
+/// 6. has the highest VCO frequency, which has the least jitter
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::offset_planner::plan;
+/// use pico_qpsk_core::transmit_option::{ProcessorClock, StateMachineClockDividerSetting};
+///
+/// let plan = plan(3_000_000).unwrap();
+/// assert_eq!(plan.clock, ProcessorClock::F144MHz);
+/// assert_eq!(plan.divider, StateMachineClockDividerSetting::Integer(3));
+/// assert_eq!((plan.quarter_cycles, plan.quarters_per_chip), (4, 6));
+/// assert_eq!(plan.offset_error_millihertz(3_000_000), 0);
+/// ```
+pub fn plan(offset_hz: u32) -> Result<TransmitPlan, PlanError> {
+    best_plan(offset_hz, pll_settings())
+}
+
+/// [plan] with the system clock fixed at `clock`, for outputs that share the system PLL with another one
+///
+/// With one clock only the PIO divider is left to pick, most offsets can't keep the chip rate on a clock
+/// planned for another one and get [PlanError::ChipRate].
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::offset_planner::{plan_on_clock, PlanError};
+/// use pico_qpsk_core::transmit_option::ProcessorClock;
+///
+/// // the standard 4MHz option runs at 128MHz
+/// let plan = plan_on_clock(4_000_000, ProcessorClock::F144MHz).unwrap();
+/// assert_eq!(plan.clock, ProcessorClock::F144MHz);
+/// assert_eq!(plan.divider.to_256ths(), 576);
+/// assert_eq!(plan.offset_error_millihertz(4_000_000), 0);
+/// // no divider gets 6MHz out of 128MHz
+/// assert_eq!(plan_on_clock(6_000_000, ProcessorClock::F128MHz), Err(PlanError::ChipRate));
+/// ```
+pub fn plan_on_clock(offset_hz: u32, clock: ProcessorClock) -> Result<TransmitPlan, PlanError> {
+    best_plan(offset_hz, core::iter::once(clock.pll()))
+}
+
+/// The best plan for `offset_hz` with one of `plls`, see [plan] for what best is
+fn best_plan(offset_hz: u32, plls: impl Iterator<Item = PllSettings>) -> Result<TransmitPlan, PlanError> {
+    if !(MIN_OFFSET_HZ - OFFSET_STEP_HZ / 2..=MAX_OFFSET_HZ).contains(&offset_hz) {
+        return Err(PlanError::OutOfRange);
+    }
+    let quarters_per_chip = ((offset_hz + OFFSET_STEP_HZ / 2) / OFFSET_STEP_HZ) as u8;
+
+    let mut best: Option<(PlanRank, TransmitPlan)> = None;
+    for pll in plls {
+        for quarter_cycles in (4..=MAX_QUARTER_CYCLES).step_by(2) {
+            let Some(divider) = closest_divider(pll.sys_hz(), quarter_cycles, quarters_per_chip) else {
+                continue;
+            };
+            let plan = TransmitPlan {
+                clock: ProcessorClock::from_pll(pll),
+                divider,
+                quarter_cycles,
+                quarters_per_chip,
+            };
+            if plan.chip_rate_error_ppm().abs() > MAX_CHIP_RATE_ERROR_PPM {
+                continue;
+            }
+            let rank = PlanRank {
+                fractional: !divider.to_256ths().is_multiple_of(256),
+                offset_error: plan.offset_error_millihertz(offset_hz).unsigned_abs(),
+                custom_clock: matches!(plan.clock, ProcessorClock::Custom(_)),
+                quarter_cycles,
+                sys_hz: Reverse(pll.sys_hz()),
+                vco_hz: Reverse(pll.vco_hz()),
+                refdiv: pll.refdiv,
+            };
+            if best.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank) {
+                best = Some((rank, plan));
+            }
+        }
+    }
+    best.map(|(_, plan)| plan).ok_or(PlanError::ChipRate)
+}
+
+/// What [plan] compares the setups by, smaller is better, in the order of the fields
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct PlanRank {
+    fractional: bool,
+    offset_error: u64,
+    custom_clock: bool,
+    quarter_cycles: u8,
+    sys_hz: Reverse<u32>,
+    vco_hz: Reverse<u32>,
+    refdiv: u8,
+}
+
+/// Every valid PLL setup with a system clock of a whole number of Hz from [MIN_SYS_HZ] to [MAX_SYS_HZ]
+///
+/// like `vcocalc.py` only `post_div1` >= `post_div2` is used, the other order gives the same clocks
+fn pll_settings() -> impl Iterator<Item = PllSettings> {
+    (1..=(XOSC_HZ / 5_000_000) as u8).flat_map(|refdiv| {
+        (16..=320u16).flat_map(move |fbdiv| {
+            (1..=7u8).flat_map(move |post_div1| {
+                (1..=post_div1).filter_map(move |post_div2| {
+                    let pll = PllSettings {
+                        refdiv,
+                        fbdiv,
+                        post_div1,
+                        post_div2,
+                    };
+                    let exact = pll
+                        .vco_hz()
+                        .is_multiple_of(u32::from(post_div1) * u32::from(post_div2));
+                    let sys_hz = pll.sys_hz();
+                    (pll.is_valid() && exact && (MIN_SYS_HZ..=MAX_SYS_HZ).contains(&sys_hz)).then_some(pll)
+                })
+            })
+        })
+    })
+}
+
+/// The divider that gets the state machine closest to `2MHz x quarters_per_chip x quarter_cycles`
+fn closest_divider(
+    sys_hz: u32,
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+) -> Option<StateMachineClockDividerSetting> {
+    let state_machine_hz = CHIP_RATE_HZ * u64::from(quarters_per_chip) * u64::from(quarter_cycles);
+    let divider = (u64::from(sys_hz) * 256 + state_machine_hz / 2) / state_machine_hz;
+    StateMachineClockDividerSetting::from_256ths(u32::try_from(divider).ok()?)
+}
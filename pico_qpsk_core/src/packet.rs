@@ -0,0 +1,551 @@
+use crate::security::{ccm_star_encrypt, is_encrypted, FrameSecurity};
+use byte::{BytesExt, LE};
+use crc_all::CrcAlgo;
+use heapless::Vec;
+use ieee802154::mac::{
+    Address, AddressMode, FooterMode, Frame, FrameContent, FrameType, FrameVersion, Header, PanId,
+    ShortAddress,
+};
+
+/// Macro add padding to go from the size of the payload to the size of the entire Physical packet
+///
+/// Get the max frame size from the maximum packet size in bytes
+///
+/// Needed as [heapless::Vec] requires a constant capacity and so the [PhysicalFrame] also requires the same
+#[macro_export]
+macro_rules! to_max_frame_size {
+    // SEE RHODE & SCHWARTZ APP NOTE in technical documents
+
+    ($x:expr) => {
+        // MAC Frame FCF[2] + SN[0|1] + Address[0..20] +  Aux Sec. Header[0|5|6|10|14] + payload[MAX_PAYLOAD_SIZE] + MIC[0|4|8|16] + FCS/CRC[2]
+        2 + 1 + 20 + 14 + $x + 16 + 2
+        // Phyisical Preamble[4] + SFD[1] + Frame Length[1]
+        + 4+1+1
+        as usize
+
+    };
+}
+
+/// The FCS of a MAC frame without its footer, a CRC-16/KERMIT sent least significant byte first
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::packet::fcs;
+///
+/// assert_eq!(fcs(b"123456789"), 0x2189);
+/// ```
+pub fn fcs(mac_bytes: &[u8]) -> u16 {
+    const CRC16_KERMIT: CrcAlgo<u16> = CrcAlgo::<u16>::new(0x1021, 16, 0, 0, true);
+    let crc = &mut 0u16;
+    CRC16_KERMIT.init_crc(crc);
+    CRC16_KERMIT.update_crc(crc, mac_bytes);
+    *crc
+}
+
+/// A type to hold the possible errors that occur whe a Physical frame is being constructed or converted to bytes
+#[derive(Debug)]
+pub enum FrameConstructionError {
+    FrameWrite(byte::Error),
+    VecLen,
+    MacFrameLength,
+    /// PAN ID compression was requested for a set of addresses the frame version can't compress
+    PanIdCompress,
+    /// sequence number suppression is only available in 802.15.4-2015 frames
+    SeqNoSuppressVersion,
+    /// 802.15.4-2003 frames have a different security header, only 2006 and later frames can be secured
+    SecurityVersion,
+}
+#[cfg(feature = "defmt")]
+impl defmt::Format for FrameConstructionError {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            FrameConstructionError::FrameWrite(byte_err) => {
+                defmt::write!(fmt, "FrameWrite(");
+                match byte_err {
+                    byte::Error::Incomplete => {
+                        defmt::write!(fmt, "byte::Error::Incomplete");
+                    }
+                    byte::Error::BadOffset(size) => {
+                        defmt::write!(fmt, "byte::Error::BadOffset({})", size);
+                    }
+                    byte::Error::BadInput { err } => {
+                        defmt::write!(fmt, "byte::Error::BadInput({})", err);
+                    }
+                }
+                defmt::write!(fmt, ")");
+            }
+            FrameConstructionError::VecLen => defmt::write!(fmt, "FrameConstructionError::VecLen"),
+            FrameConstructionError::MacFrameLength => {
+                defmt::write!(fmt, "FrameConstructionError::MacFrameLength")
+            }
+            FrameConstructionError::PanIdCompress => {
+                defmt::write!(fmt, "FrameConstructionError::PanIdCompress")
+            }
+            FrameConstructionError::SeqNoSuppressVersion => {
+                defmt::write!(fmt, "FrameConstructionError::SeqNoSuppressVersion")
+            }
+            FrameConstructionError::SecurityVersion => {
+                defmt::write!(fmt, "FrameConstructionError::SecurityVersion")
+            }
+        }
+    }
+}
+
+/// Physical packet preamble
+const PHY_PREAMBLE: [u8; 4] = [0x00, 0x00, 0x00, 0x00];
+
+/// Start of frame delimiter
+const PHY_SFD: u8 = 0xA7;
+
+/// A Physical frame to send over O-QPSK 802.15.4
+///
+/// A group only contains the mac frame and how to secure it, everything else is generated on conversion to bytes.
+#[derive(Debug)]
+pub struct PhysicalFrame<'p, const MAX_FRAME_SIZE: usize> {
+    mac_frame: Frame<'p>,
+    security: Option<FrameSecurity>,
+    /// the destination PAN ID of a 2015 frame with PAN ID compression and no addresses
+    destination_pan_id: PanId,
+}
+
+impl<'p, const MAX_FRAME_SIZE: usize> PhysicalFrame<'p, MAX_FRAME_SIZE> {
+    /// A 2006 data frame with short addresses, see [PhysicalFrameBuilder] for any other frame
+    ///
+    /// ### Arguments
+    ///
+    /// * `sequence_num`: the packet number in the sequence
+    /// * `source_id`: Source PAN ID
+    /// * `source`: Source address
+    /// * `destination_id`:  Destination PAN ID
+    /// * `destination`: Destination address
+    /// * `payload`: the data to send
+    ///
+    /// #### returns: Result<[PhysicalFrame], [FrameConstructionError]>
+    ///
+    /// ### Examples
+    ///
+    /// ```
+    /// use ieee802154::mac::{PanId, ShortAddress};
+    /// use pico_qpsk_core::packet::PhysicalFrame;
+    /// use pico_qpsk_core::to_max_frame_size;
+    ///
+    /// const MAX_PAYLOAD_SIZE: usize = 8;
+    /// let frame: PhysicalFrame<{ to_max_frame_size!(MAX_PAYLOAD_SIZE) }> = PhysicalFrame::new(
+    ///     1,
+    ///     PanId(0x2222),        // source
+    ///     ShortAddress(0x1234), // source
+    ///     PanId(0x4444),        // destination
+    ///     ShortAddress(0xABCD), // destination
+    ///     &[0x01],
+    /// )
+    /// .unwrap();
+    /// let bytes = frame.to_bytes().unwrap();
+    /// // preamble, SFD, 11 header bytes + 1 payload byte + 2 FCS bytes, FCF, sequence number
+    /// assert_eq!(bytes[..9], [0x00, 0x00, 0x00, 0x00, 0xA7, 14, 0x01, 0x98, 1]);
+    /// // destination then source, PAN ID first, little endian
+    /// assert_eq!(bytes[9..17], [0x44, 0x44, 0xCD, 0xAB, 0x22, 0x22, 0x34, 0x12]);
+    /// ```
+    pub fn new(
+        sequence_num: u8,
+        source_id: PanId,
+        source: ShortAddress,
+        destination_id: PanId,
+        destination: ShortAddress,
+        payload: &'p [u8],
+    ) -> Result<Self, FrameConstructionError> {
+        // in order of items from Rhode & Schwartz App note excel packet generator
+        PhysicalFrameBuilder::new()
+            .source(Some(Address::Short(source_id, source)))
+            .version(FrameVersion::Ieee802154_2006)
+            .destination(Some(Address::Short(destination_id, destination)))
+            .sequence_number(sequence_num)
+            .payload(payload)
+            .build()
+    }
+
+    /// ```text
+    /// [PREAMBLE][SFD][LEN][----------------MAC PACKET--------------]
+    ///                     [FCF][SN][ADDRESS][AUX SEC.][PAYLOAD][MIC][FCS]
+    /// ```
+    pub fn to_bytes(&self) -> Result<Vec<u8, MAX_FRAME_SIZE>, FrameConstructionError> {
+        let mut v: Vec<u8, { MAX_FRAME_SIZE }> = Vec::new();
+        v.extend_from_slice(&PHY_PREAMBLE)
+            .map_err(|_| FrameConstructionError::VecLen)?;
+
+        let mac_vec: Vec<u8, { MAX_FRAME_SIZE }> = write_mac_frame(
+            self.mac_frame,
+            self.security.as_ref(),
+            self.destination_pan_id,
+            FooterMode::Explicit,
+        )?;
+
+        v.push(PHY_SFD).map_err(|_| FrameConstructionError::VecLen)?;
+
+        let len = u8::try_from(mac_vec.len()).map_err(|_| FrameConstructionError::MacFrameLength)?;
+
+        v.push(len).map_err(|_| FrameConstructionError::VecLen)?;
+        v.extend_from_slice(&mac_vec)
+            .map_err(|_| FrameConstructionError::VecLen)?;
+        Ok(v)
+    }
+}
+
+/// Builds a [PhysicalFrame] with any MAC header
+///
+/// Starts out as a 2006 data frame, with no addresses, no payload, no security and sequence number 0
+///
+/// ### Examples
+///
+/// ```
+/// use ieee802154::mac::{Address, ExtendedAddress, FrameVersion, PanId};
+/// use pico_qpsk_core::packet::{PhysicalFrame, PhysicalFrameBuilder};
+///
+/// let frame: PhysicalFrame<64> = PhysicalFrameBuilder::new()
+///     .version(FrameVersion::Ieee802154)
+///     .destination(Some(Address::Extended(PanId(0x4444), ExtendedAddress(0x0123_4567_89AB_CDEF))))
+///     .ack_request(true)
+///     .suppress_sequence_number(true)
+///     .payload(&[0x01, 0x02])
+///     .build()
+///     .unwrap();
+/// let bytes = frame.to_bytes().unwrap();
+/// // [PREAMBLE][SFD][LEN][FCF][PAN ID][ADDRESS][PAYLOAD][FCS]
+/// assert_eq!(bytes.len(), 4 + 1 + 1 + 2 + 2 + 8 + 2 + 2);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicalFrameBuilder<'p> {
+    header: Header,
+    content: FrameContent,
+    payload: &'p [u8],
+    security: Option<FrameSecurity>,
+    destination_pan_id: PanId,
+}
+
+impl Default for PhysicalFrameBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'p> PhysicalFrameBuilder<'p> {
+    pub fn new() -> Self {
+        PhysicalFrameBuilder {
+            header: Header {
+                ie_present: false, // information elements are not supported
+                seq_no_suppress: false,
+                source: None,
+                version: FrameVersion::Ieee802154_2006,
+                destination: None,
+                pan_id_compress: false,
+                ack_request: false,
+                frame_type: FrameType::Data,
+                frame_pending: false,
+                auxiliary_security_header: None,
+                seq: 0,
+            },
+            content: FrameContent::Data,
+            payload: &[],
+            security: None,
+            destination_pan_id: BROADCAST_PAN_ID,
+        }
+    }
+
+    /// Set what kind of frame this is, the frame type is taken from the content
+    ///
+    /// [FrameContent::Beacon] and [FrameContent::Command] add their fields in front of the payload
+    pub fn content(mut self, content: FrameContent) -> Self {
+        self.header.frame_type = match content {
+            FrameContent::Beacon(_) => FrameType::Beacon,
+            FrameContent::Data => FrameType::Data,
+            FrameContent::Acknowledgement => FrameType::Acknowledgement,
+            FrameContent::Command(_) => FrameType::MacCommand,
+            FrameContent::Multipurpose => FrameType::Multipurpose,
+            FrameContent::FragOrFragAck => FrameType::FragOrFragAck,
+            FrameContent::Extended => FrameType::Extended,
+        };
+        self.content = content;
+        self
+    }
+
+    /// `None` leaves the address (and its PAN ID) out of the frame
+    pub fn source(mut self, source: Option<Address>) -> Self {
+        self.header.source = source;
+        self
+    }
+
+    /// `None` leaves the address (and its PAN ID) out of the frame
+    pub fn destination(mut self, destination: Option<Address>) -> Self {
+        self.header.destination = destination;
+        self
+    }
+
+    /// Leave out a PAN ID, which one depends on the addresses and the frame version
+    ///
+    /// 2003/2006 frames need both addresses and drop the source PAN ID,
+    /// 2015 frames follow table 7-2 of 802.15.4-2015, without addresses it adds the destination PAN ID of
+    /// [destination_pan_id](Self::destination_pan_id) instead
+    pub fn pan_id_compress(mut self, pan_id_compress: bool) -> Self {
+        self.header.pan_id_compress = pan_id_compress;
+        self
+    }
+
+    /// The destination PAN ID of a 2015 frame with PAN ID compression and no addresses, the broadcast
+    /// PAN ID 0xFFFF unless set, the PAN ID of an address is kept in the [Address]
+    pub fn destination_pan_id(mut self, pan_id: PanId) -> Self {
+        self.destination_pan_id = pan_id;
+        self
+    }
+
+    pub fn ack_request(mut self, ack_request: bool) -> Self {
+        self.header.ack_request = ack_request;
+        self
+    }
+
+    pub fn frame_pending(mut self, frame_pending: bool) -> Self {
+        self.header.frame_pending = frame_pending;
+        self
+    }
+
+    /// [FrameVersion::Ieee802154] is 802.15.4-2015
+    pub fn version(mut self, version: FrameVersion) -> Self {
+        self.header.version = version;
+        self
+    }
+
+    pub fn sequence_number(mut self, sequence_num: u8) -> Self {
+        self.header.seq = sequence_num;
+        self
+    }
+
+    /// Leave the sequence number out of the frame, only 2015 frames can do this
+    pub fn suppress_sequence_number(mut self, suppress: bool) -> Self {
+        self.header.seq_no_suppress = suppress;
+        self
+    }
+
+    pub fn payload(mut self, payload: &'p [u8]) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Add the auxiliary security header and protect the frame with CCM*
+    ///
+    /// The payload is encrypted for the ENC levels and the MIC goes between the payload and the FCS,
+    /// `None` sends the frame unsecured
+    pub fn security(mut self, security: Option<FrameSecurity>) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Check the header, secure the frame and calculate the FCS
+    ///
+    /// #### returns: Result<[PhysicalFrame], [FrameConstructionError]>
+    pub fn build<const MAX_FRAME_SIZE: usize>(
+        self,
+    ) -> Result<PhysicalFrame<'p, MAX_FRAME_SIZE>, FrameConstructionError> {
+        let no_crc_frame = Frame {
+            header: self.header,
+            content: self.content,
+            payload: self.payload,
+            footer: [0x00, 0x00],
+        };
+
+        let frame = {
+            let v: Vec<_, MAX_FRAME_SIZE> = write_mac_frame(
+                no_crc_frame,
+                self.security.as_ref(),
+                self.destination_pan_id,
+                FooterMode::None,
+            )?;
+            let mut frame = no_crc_frame;
+            frame.footer = fcs(&v).to_le_bytes();
+            frame
+        };
+
+        Ok(PhysicalFrame {
+            mac_frame: frame,
+            security: self.security,
+            destination_pan_id: self.destination_pan_id,
+        })
+    }
+}
+
+/// The destination PAN ID of a 2015 frame with PAN ID compression and no addresses, unless the builder
+/// sets another
+const BROADCAST_PAN_ID: PanId = PanId(0xFFFF);
+
+/// Which PAN IDs are written to the frame
+///
+/// #### returns: Result<(destination PAN ID, source PAN ID), [FrameConstructionError]>
+fn pan_ids_present(header: &Header) -> Result<(bool, bool), FrameConstructionError> {
+    let compress = header.pan_id_compress;
+    match header.version {
+        // 802.15.4-2015 table 7-2
+        FrameVersion::Ieee802154 => match (header.destination, header.source) {
+            // a destination PAN ID without an address, the header has nowhere to keep one so it comes
+            // from the builder
+            (None, None) => Ok((compress, false)),
+            (Some(_), None) => Ok((!compress, false)),
+            (None, Some(_)) => Ok((false, !compress)),
+            (Some(Address::Extended(..)), Some(Address::Extended(..))) => Ok((!compress, false)),
+            (Some(_), Some(_)) => Ok((true, !compress)),
+        },
+        _ => match (header.destination, header.source) {
+            (Some(_), Some(_)) => Ok((true, !compress)),
+            _ if compress => Err(FrameConstructionError::PanIdCompress),
+            (destination, source) => Ok((destination.is_some(), source.is_some())),
+        },
+    }
+}
+
+/// Write the MAC header
+///
+/// this is done here instead of in [ieee802154] as it never writes the sequence number suppression bit
+/// and only knows the 2003/2006 PAN ID compression rules
+fn write_mac_header(
+    header: &Header,
+    security: Option<&FrameSecurity>,
+    pan_id_without_address: PanId,
+    bytes: &mut [u8],
+    offset: &mut usize,
+) -> Result<(), FrameConstructionError> {
+    if header.seq_no_suppress && header.version != FrameVersion::Ieee802154 {
+        return Err(FrameConstructionError::SeqNoSuppressVersion);
+    }
+    if security.is_some() && header.version == FrameVersion::Ieee802154_2003 {
+        return Err(FrameConstructionError::SecurityVersion);
+    }
+    let (destination_pan_id, source_pan_id) = pan_ids_present(header)?;
+
+    let frame_control = (header.frame_type as u16)
+        | (u16::from(security.is_some()) << 3)
+        | (u16::from(header.frame_pending) << 4)
+        | (u16::from(header.ack_request) << 5)
+        | (u16::from(header.pan_id_compress) << 6)
+        | (u16::from(header.seq_no_suppress) << 8)
+        | (u16::from(header.ie_present) << 9)
+        | ((AddressMode::from(header.destination) as u16) << 10)
+        | ((header.version as u16) << 12)
+        | ((AddressMode::from(header.source) as u16) << 14);
+
+    let write = |bytes: &mut [u8], offset: &mut usize| -> byte::Result<()> {
+        bytes.write_with(offset, frame_control, LE)?;
+        if !header.seq_no_suppress {
+            bytes.write(offset, header.seq)?;
+        }
+        if header.destination.is_none() && destination_pan_id {
+            bytes.write_with(offset, pan_id_without_address.0, LE)?;
+        }
+        for (address, pan_id_present) in [
+            (header.destination, destination_pan_id),
+            (header.source, source_pan_id),
+        ] {
+            match address {
+                Some(Address::Short(pan_id, address)) => {
+                    if pan_id_present {
+                        bytes.write_with(offset, pan_id.0, LE)?;
+                    }
+                    bytes.write_with(offset, address.0, LE)?;
+                }
+                Some(Address::Extended(pan_id, address)) => {
+                    if pan_id_present {
+                        bytes.write_with(offset, pan_id.0, LE)?;
+                    }
+                    bytes.write_with(offset, address.0, LE)?;
+                }
+                None => (),
+            }
+        }
+        if let Some(security) = security {
+            security.write_auxiliary_header(bytes, offset)?;
+        }
+        Ok(())
+    };
+    write(bytes, offset).map_err(FrameConstructionError::FrameWrite)
+}
+
+/// Convert mac frame to
+///
+/// ### Arguments
+///
+/// * `frame`: the mac frame to convert to bytes
+/// * `footer_mode`: if a footer should be included (AKA the CRC/FCS)
+///
+/// #### returns: Result<Vec<u8, { MAX_FRAME_SIZE }>, FrameConstructionError>
+///
+pub fn mac_frame_to_vec<const MAX_FRAME_SIZE: usize>(
+    frame: Frame,
+    footer_mode: FooterMode,
+) -> Result<Vec<u8, MAX_FRAME_SIZE>, FrameConstructionError> {
+    secured_mac_frame_to_vec(frame, None, footer_mode)
+}
+
+/// Convert mac frame to bytes, securing it when `security` is set
+///
+/// Which fields are encrypted follows table 53 of 802.15.4-2011:
+/// beacon fields and the command identifier stay open, the payload (and command payload) is private.
+/// The MIC only levels leave everything open and only authenticate it.
+///
+/// ### Arguments
+///
+/// * `frame`: the mac frame to convert to bytes
+/// * `security`: the security level, key and nonce fields, `None` for an unsecured frame
+/// * `footer_mode`: if a footer should be included (AKA the CRC/FCS)
+///
+/// #### returns: Result<Vec<u8, { MAX_FRAME_SIZE }>, FrameConstructionError>
+pub fn secured_mac_frame_to_vec<const MAX_FRAME_SIZE: usize>(
+    frame: Frame,
+    security: Option<&FrameSecurity>,
+    footer_mode: FooterMode,
+) -> Result<Vec<u8, MAX_FRAME_SIZE>, FrameConstructionError> {
+    write_mac_frame(frame, security, BROADCAST_PAN_ID, footer_mode)
+}
+
+/// [secured_mac_frame_to_vec] with the destination PAN ID of a 2015 frame with PAN ID compression and
+/// no addresses
+fn write_mac_frame<const MAX_FRAME_SIZE: usize>(
+    frame: Frame,
+    security: Option<&FrameSecurity>,
+    pan_id_without_address: PanId,
+    footer_mode: FooterMode,
+) -> Result<Vec<u8, MAX_FRAME_SIZE>, FrameConstructionError> {
+    let mut bytes = [0u8; MAX_FRAME_SIZE];
+    let mut len = 0usize; // written len
+    write_mac_header(
+        &frame.header,
+        security,
+        pan_id_without_address,
+        &mut bytes,
+        &mut len,
+    )?;
+    let header_len = len;
+    bytes
+        .write(&mut len, frame.content)
+        .map_err(FrameConstructionError::FrameWrite)?;
+    let content_len = len;
+    bytes
+        .write(&mut len, frame.payload)
+        .map_err(FrameConstructionError::FrameWrite)?;
+
+    if let Some(security) = security {
+        let private_start = match frame.content {
+            _ if !is_encrypted(security.level) => len,
+            // the command frame identifier is open, the rest of the command is private
+            FrameContent::Command(_) => header_len + 1,
+            _ => content_len,
+        };
+        let (open, private) = bytes[..len].split_at_mut(private_start);
+        let mic = ccm_star_encrypt(&security.key, &security.nonce(), security.level, open, private);
+        bytes
+            .write(&mut len, &mic[..])
+            .map_err(FrameConstructionError::FrameWrite)?;
+    }
+    if let FooterMode::Explicit = footer_mode {
+        bytes
+            .write(&mut len, &frame.footer[..])
+            .map_err(FrameConstructionError::FrameWrite)?;
+    }
+
+    Vec::<u8, MAX_FRAME_SIZE>::from_slice(&bytes[0..len]).map_err(|_| FrameConstructionError::VecLen)
+}
@@ -0,0 +1,505 @@
+use crate::pio_bytecode_gen::{Level, Sideband, CHIP_ARRAY, QUARTER_LEVELS};
+use heapless::Vec;
+
+/// The longest wave (in state machine cycles) the decoder can handle, waves are held in a [u64]
+const MAX_WAVE_CYCLES: usize = 64;
+
+/// A type to hold the possible errors that occur when a square wave or pio bytecode is decoded
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// a wave in the wave table is longer than [MAX_WAVE_CYCLES] or the waves are not all the same length,
+    /// or a quarter or chip of 0 cycles
+    WaveTable,
+    /// there wasn't enough data to decode a single byte
+    NoData,
+    VecLen,
+}
+
+/// The bytes recovered from a square wave
+#[derive(Debug)]
+pub struct DecodedFrame<const MAX_FRAME_SIZE: usize> {
+    /// the PHY bytes, `[PREAMBLE][SFD][LEN][MAC PACKET]`
+    pub bytes: Vec<u8, MAX_FRAME_SIZE>,
+    /// number of 2 bit chips that did not match the closest chip sequence, 0 for a clean decode
+    ///
+    /// `convert_advanced` never writes the last level of a frame so the last chip can count as an error
+    pub chip_errors: u32,
+}
+
+/// Iterator over the run lengths the PIO program outputs, the reverse of `lengths_to_pio_byte_code_ints`
+///
+/// every run is `n` set bits followed by a 0 and lasts `4 + 2n` cycles
+#[derive(Clone)]
+pub struct PioRunLengths<'a> {
+    words: &'a [u32],
+    bit_idx: usize,
+}
+
+impl Iterator for PioRunLengths<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let mut ones = 0;
+        loop {
+            // a run that is cut off by the end of the data is dropped
+            let word = self.words.get(self.bit_idx / 32)?;
+            let bit = (word >> (31 - self.bit_idx % 32)) & 1;
+            self.bit_idx += 1;
+            if bit == 0 {
+                return Some(4 + 2 * ones);
+            }
+            ones += 1;
+        }
+    }
+}
+
+/// unpack the pio bytecode into run lengths
+///
+/// # Arguments
+///
+/// * `words`: the words written to the PIO TX FIFO
+///
+/// returns: [PioRunLengths]
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_decode::pio_run_lengths;
+///
+/// // 4 cycles, 12 cycles, 4 cycles and the padding
+/// let lengths = pio_run_lengths(&[0b0111_1000_0000_0000_0000_0000_0000_0000]);
+/// assert_eq!(lengths.take(3).collect::<Vec<_>>(), [4, 12, 4]);
+/// ```
+pub fn pio_run_lengths(words: &[u32]) -> PioRunLengths<'_> {
+    PioRunLengths { words, bit_idx: 0 }
+}
+
+/// Rebuild the square wave, one item per state machine cycle, from pio bytecode
+///
+/// The levels follow the naming of [Level] (true is [Level::High]), the first run is a [Level::Low].
+/// The leading 0 bit `convert_advanced` puts before the data is dropped.
+///
+/// Note: the PIO program drives the pin low during a [Level::High],
+/// so a capture of the pin needs to be inverted before it is passed to [decode_square_wave]
+///
+/// # Arguments
+///
+/// * `words`: the words written to the PIO TX FIFO
+///
+/// returns: ~ impl Iterator<Item=bool>
+pub fn pio_words_to_square_wave(words: &[u32]) -> impl Iterator<Item = bool> + Clone + '_ {
+    pio_run_lengths(words)
+        .skip(1)
+        .zip([false, true].into_iter().cycle())
+        .flat_map(|(len, level)| core::iter::repeat_n(level, len as usize))
+}
+
+/// the wave for each 2 bit chip as a bit mask, bit n is set when the wave is high on cycle n
+///
+/// returns: ([u64; 4], cycles per wave)
+fn wave_masks(waves: &[[Level; 3]; 4]) -> Result<([u64; 4], usize), DecodeError> {
+    let mut masks = [0u64; 4];
+    let mut wave_cycles = None;
+    for (mask, wave) in masks.iter_mut().zip(waves) {
+        let mut cycle = 0usize;
+        for level in wave {
+            let (len, high) = match *level {
+                Level::High(len) => (len, true),
+                Level::Low(len) => (len, false),
+                Level::Nop => (0, false),
+            };
+            for _ in 0..len {
+                if cycle >= MAX_WAVE_CYCLES {
+                    return Err(DecodeError::WaveTable);
+                }
+                if high {
+                    *mask |= 1 << cycle;
+                }
+                cycle += 1;
+            }
+        }
+        if *wave_cycles.get_or_insert(cycle) != cycle {
+            return Err(DecodeError::WaveTable);
+        }
+    }
+    match wave_cycles {
+        Some(cycles) if cycles > 0 => Ok((masks, cycles)),
+        _ => Err(DecodeError::WaveTable),
+    }
+}
+
+/// Reads a square wave one 2 bit chip (all repeats of its wave) at a time
+#[derive(Clone)]
+struct SymbolReader<I> {
+    samples: I,
+    masks: [u64; 4],
+    wave_cycles: usize,
+    repeats: usize,
+}
+
+impl<I: Iterator<Item = bool>> Iterator for SymbolReader<I> {
+    /// (mismatched cycles against each wave, cycles read)
+    type Item = ([u32; 4], usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut costs = [0u32; 4];
+        let mut read = 0;
+        for _ in 0..self.repeats {
+            let mut sampled = 0u64;
+            let mut valid = 0u64;
+            for cycle in 0..self.wave_cycles {
+                let Some(high) = self.samples.next() else {
+                    break;
+                };
+                valid |= 1 << cycle;
+                if high {
+                    sampled |= 1 << cycle;
+                }
+                read += 1;
+            }
+            for (cost, mask) in costs.iter_mut().zip(self.masks) {
+                *cost += ((sampled ^ mask) & valid).count_ones();
+            }
+        }
+        // the last level of a frame is never written by the generator so accept a partial last chip
+        if read * 2 < self.wave_cycles * self.repeats {
+            return None;
+        }
+        Some((costs, read))
+    }
+}
+
+/// Reads a square wave from [crate::pio_bytecode_gen::convert_quarters] one 2 bit chip at a time
+#[derive(Clone)]
+struct QuarterReader<I> {
+    samples: I,
+    quarter_cycles: usize,
+    quarters_per_chip: usize,
+    /// the quarter of the subcarrier period, counted from the start of the frame
+    phase: usize,
+}
+
+impl<I: Iterator<Item = bool>> Iterator for QuarterReader<I> {
+    /// (mismatched cycles against each chip, cycles read)
+    type Item = ([u32; 4], usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut costs = [0u32; 4];
+        let mut read = 0;
+        'quarters: for _ in 0..self.quarters_per_chip {
+            let quarter = self.phase % 4;
+            self.phase += 1;
+            for _ in 0..self.quarter_cycles {
+                let Some(high) = self.samples.next() else {
+                    break 'quarters;
+                };
+                for (cost, levels) in costs.iter_mut().zip(QUARTER_LEVELS) {
+                    *cost += u32::from(levels[quarter] != high);
+                }
+                read += 1;
+            }
+        }
+        // the last level of a frame is never written by the generator so accept a partial last chip
+        if read * 2 < self.quarter_cycles * self.quarters_per_chip {
+            return None;
+        }
+        Some((costs, read))
+    }
+}
+
+/// the chip whose wave matches best and how many cycles didn't match it
+fn best_symbol(costs: [u32; 4]) -> (u8, u32) {
+    let (idx, cost) = costs
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, cost)| **cost)
+        .expect("there are always 4 costs");
+    (idx as u8, *cost)
+}
+
+/// find the closest chip sequence in [CHIP_ARRAY]
+///
+/// returns: (nibble, chips that don't match)
+fn correlate_chips(chips: &[u8; 16]) -> (u8, u32) {
+    let (nibble, errors) = CHIP_ARRAY
+        .iter()
+        .map(|sequence| {
+            sequence
+                .iter()
+                .zip(chips)
+                .filter(|(expected, actual)| expected != actual)
+                .count() as u32
+        })
+        .enumerate()
+        .min_by_key(|(_, errors)| *errors)
+        .expect("CHIP_ARRAY is not empty");
+    (nibble as u8, errors)
+}
+
+/// Turn the chips of a frame, the O-QPSK middle chips included, back into its bytes
+fn chips_to_frame<const MAX_FRAME_SIZE: usize>(
+    chips_with_middle: impl Iterator<Item = u8>,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+    let mut bytes = Vec::new();
+    let mut chip_errors = 0;
+    let mut chips = [0u8; 16];
+    let mut low_nibble = None;
+    // -> remove the O-QPSK middle chips, the first chip is kept
+    for (idx, chip) in chips_with_middle.step_by(2).enumerate() {
+        chips[idx % 16] = chip;
+        if idx % 16 != 15 {
+            continue;
+        }
+        // -> correlate chips back into half bytes
+        let (nibble, errors) = correlate_chips(&chips);
+        chip_errors += errors;
+        // -> swap back for endianness
+        match low_nibble.take() {
+            None => low_nibble = Some(nibble),
+            Some(low) => bytes.push(low | nibble << 4).map_err(|_| DecodeError::VecLen)?,
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(DecodeError::NoData);
+    }
+    Ok(DecodedFrame { bytes, chip_errors })
+}
+
+/// Decode a square wave back into the bytes given to `convert_advanced`
+///
+/// The wave has to start at the start of the frame, it can be off by less than one wave,
+/// every alignment inside of the first wave is tried and the one that matches best is used
+///
+/// # Arguments
+///
+/// * `samples`: one item per state machine cycle, true for [Level::High]
+/// * `waves`: the wave table used to generate the wave, see [crate::wave_array]
+/// * `repeats`: the number of times each wave was repeated, `NUMBER_OF_REPEATED_WAVES` in `convert_advanced`
+///
+/// returns: Result<[DecodedFrame], [DecodeError]>
+pub fn decode_square_wave<I, const MAX_FRAME_SIZE: usize>(
+    samples: I,
+    waves: &[[Level; 3]; 4],
+    repeats: u8,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError>
+where
+    I: Iterator<Item = bool> + Clone,
+{
+    let (masks, wave_cycles) = wave_masks(waves)?;
+    let reader = |offset: usize| SymbolReader {
+        samples: samples.clone().skip(offset),
+        masks,
+        wave_cycles,
+        repeats: usize::from(repeats.max(1)),
+    };
+
+    // -> find where the waves start
+    let offset = (0..wave_cycles)
+        .min_by_key(|offset| {
+            reader(*offset)
+                .map(|(costs, _)| best_symbol(costs).1)
+                .sum::<u32>()
+        })
+        .expect("wave_cycles is never 0");
+
+    chips_to_frame(reader(offset).map(|(costs, _)| best_symbol(costs).0))
+}
+
+/// Decode pio bytecode back into the bytes given to `convert_advanced`
+///
+/// # Arguments
+///
+/// * `words`: the pio bytecode
+/// * `waves`: the wave table used to generate the bytecode, see [crate::wave_array]
+/// * `repeats`: the number of times each wave was repeated, `NUMBER_OF_REPEATED_WAVES` in `convert_advanced`
+///
+/// returns: Result<[DecodedFrame], [DecodeError]>
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_decode::decode_pio_bytecode;
+/// use pico_qpsk_core::pio_bytecode_gen::convert_advanced;
+/// use pico_qpsk_core::wave_array;
+///
+/// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+/// let words: Vec<u32> = convert_advanced::<4>(&frame, &wave_array!(16)).collect();
+/// let decoded = decode_pio_bytecode::<32>(&words, &wave_array!(16), 4).unwrap();
+/// assert_eq!(decoded.bytes, frame);
+/// ```
+pub fn decode_pio_bytecode<const MAX_FRAME_SIZE: usize>(
+    words: &[u32],
+    waves: &[[Level; 3]; 4],
+    repeats: u8,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+    decode_square_wave(pio_words_to_square_wave(words), waves, repeats)
+}
+
+/// Decode a square wave back into the bytes given to [crate::pio_bytecode_gen::convert_quarters]
+///
+/// The wave has to start at the start of the frame, it can be off by less than a quarter of the subcarrier
+/// period plus the 4 cycles of the first run when the frame starts high. Every alignment in there is
+/// tried, a whole chip further on looks like other chips as the subcarrier doesn't start over with
+/// every chip.
+///
+/// # Arguments
+///
+/// * `samples`: one item per state machine cycle, true for [Level::High]
+/// * `quarter_cycles`: the state machine cycles per quarter of the subcarrier period
+/// * `quarters_per_chip`: the quarters each chip lasts
+///
+/// returns: Result<[DecodedFrame], [DecodeError]>
+pub fn decode_quarter_square_wave<I, const MAX_FRAME_SIZE: usize>(
+    samples: I,
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError>
+where
+    I: Iterator<Item = bool> + Clone,
+{
+    decode_quarters(samples, quarter_cycles, quarters_per_chip, 0)
+}
+
+/// [decode_quarter_square_wave] for a wave whose first quarter is `first_quarter` of [QUARTER_LEVELS]
+fn decode_quarters<I, const MAX_FRAME_SIZE: usize>(
+    samples: I,
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+    first_quarter: usize,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError>
+where
+    I: Iterator<Item = bool> + Clone,
+{
+    if quarter_cycles == 0 || quarters_per_chip == 0 {
+        return Err(DecodeError::WaveTable);
+    }
+    let reader = |offset: usize| QuarterReader {
+        samples: samples.clone().skip(offset),
+        quarter_cycles: usize::from(quarter_cycles),
+        quarters_per_chip: usize::from(quarters_per_chip),
+        phase: first_quarter,
+    };
+
+    // -> find where the subcarrier starts
+    let offset = (0..usize::from(quarter_cycles) + 4)
+        .min_by_key(|offset| {
+            reader(*offset)
+                .map(|(costs, _)| best_symbol(costs).1)
+                .sum::<u32>()
+        })
+        .expect("quarter_cycles is never 0");
+
+    chips_to_frame(reader(offset).map(|(costs, _)| best_symbol(costs).0))
+}
+
+/// Decode pio bytecode from [crate::pio_bytecode_gen::convert_quarters] back into its bytes
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_decode::decode_quarter_bytecode;
+/// use pico_qpsk_core::pio_bytecode_gen::convert_quarters;
+///
+/// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+/// // a 3MHz subcarrier
+/// let words: Vec<u32> = convert_quarters(&frame, 4, 6).collect();
+/// let decoded = decode_quarter_bytecode::<32>(&words, 4, 6).unwrap();
+/// assert_eq!(decoded.bytes, frame);
+/// ```
+pub fn decode_quarter_bytecode<const MAX_FRAME_SIZE: usize>(
+    words: &[u32],
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+    decode_quarter_square_wave(pio_words_to_square_wave(words), quarter_cycles, quarters_per_chip)
+}
+
+/// Iterator over the runs the two pin PIO program outputs, see [crate::pio_bytecode_gen::convert_quadrature]
+///
+/// every run is the 2 pin bits, Q first, then `n` set bits and a 0, it lasts `4 + 2n` cycles
+#[derive(Clone)]
+pub struct QuadratureRuns<'a> {
+    words: &'a [u32],
+    bit_idx: usize,
+}
+
+impl QuadratureRuns<'_> {
+    fn next_bit(&mut self) -> Option<u32> {
+        let word = self.words.get(self.bit_idx / 32)?;
+        let bit = (word >> (31 - self.bit_idx % 32)) & 1;
+        self.bit_idx += 1;
+        Some(bit)
+    }
+}
+
+impl Iterator for QuadratureRuns<'_> {
+    /// (pins, bit 0 is the I pin and bit 1 the Q pin, cycles)
+    type Item = (u8, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a run that is cut off by the end of the data is dropped
+        let pins = (self.next_bit()? << 1 | self.next_bit()?) as u8;
+        let mut ones = 0;
+        while self.next_bit()? == 1 {
+            ones += 1;
+        }
+        Some((pins, 4 + 2 * ones))
+    }
+}
+
+/// unpack two pin pio bytecode into runs
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_decode::quadrature_runs;
+///
+/// // Q high for 4 cycles, both high for 6 cycles, I high for 4 cycles and the padding
+/// let runs = quadrature_runs(&[0b1001_1100_1000_0000_0000_0000_0000_0000]);
+/// assert_eq!(runs.take(3).collect::<Vec<_>>(), [(0b10, 4), (0b11, 6), (0b01, 4)]);
+/// ```
+pub fn quadrature_runs(words: &[u32]) -> QuadratureRuns<'_> {
+    QuadratureRuns { words, bit_idx: 0 }
+}
+
+/// Rebuild the (I, Q) pin levels, one item per state machine cycle, from two pin pio bytecode
+///
+/// The levels are the pins, a pin is low during a [Level::High]
+pub fn quadrature_words_to_pins(words: &[u32]) -> impl Iterator<Item = (bool, bool)> + Clone + '_ {
+    quadrature_runs(words)
+        .flat_map(|(pins, len)| core::iter::repeat_n((pins & 1 == 1, pins & 2 == 2), len as usize))
+}
+
+/// Decode the I and the Q pin of [crate::pio_bytecode_gen::convert_quadrature] bytecode on their own
+///
+/// # Arguments
+///
+/// * `words`: the pio bytecode
+/// * `quarter_cycles`: the state machine cycles per quarter of the subcarrier period
+/// * `quarters_per_chip`: the quarters each chip lasts
+/// * `sideband`: the sideband the bytecode was generated for, it decides where the Q pin's wave starts
+///
+/// returns: Result<[the I pin, the Q pin], [DecodeError]>
+pub fn decode_quadrature_bytecode<const MAX_FRAME_SIZE: usize>(
+    words: &[u32],
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+    sideband: Sideband,
+) -> Result<[DecodedFrame<MAX_FRAME_SIZE>; 2], DecodeError> {
+    let pins = quadrature_words_to_pins(words);
+    let i = decode_quarters(
+        pins.clone().map(|(i, _)| !i),
+        quarter_cycles,
+        quarters_per_chip,
+        0,
+    )?;
+    let q = decode_quarters(
+        pins.map(|(_, q)| !q),
+        quarter_cycles,
+        quarters_per_chip,
+        sideband.q_quarters_ahead(),
+    )?;
+    Ok([i, q])
+}
@@ -0,0 +1,779 @@
+use core::iter;
+use core::iter::{once, Chain, FilterMap, FlatMap, Flatten, Once, Repeat, RepeatN, Scan, Skip, Zip};
+use core::slice::Iter;
+
+use crate::fsk::RunWords;
+use itertools::{Batching, Itertools};
+
+/// The chips of each 4 bit symbol of the 2.4GHz O-QPSK PHY as 2 bit (I, Q) chips, `c0` is the I bit of the
+/// first one
+pub const CHIP_ARRAY: &[[u8; 16]] = &[
+    [
+        0b11, 0b01, 0b10, 0b01, 0b11, 0b00, 0b00, 0b11, 0b01, 0b01, 0b00, 0b10, 0b00, 0b10, 0b11, 0b10,
+    ],
+    [
+        0b11, 0b10, 0b11, 0b01, 0b10, 0b01, 0b11, 0b00, 0b00, 0b11, 0b01, 0b01, 0b00, 0b10, 0b00, 0b10,
+    ],
+    [
+        0b00, 0b10, 0b11, 0b10, 0b11, 0b01, 0b10, 0b01, 0b11, 0b00, 0b00, 0b11, 0b01, 0b01, 0b00, 0b10,
+    ],
+    [
+        0b00, 0b10, 0b00, 0b10, 0b11, 0b10, 0b11, 0b01, 0b10, 0b01, 0b11, 0b00, 0b00, 0b11, 0b01, 0b01,
+    ],
+    [
+        0b01, 0b01, 0b00, 0b10, 0b00, 0b10, 0b11, 0b10, 0b11, 0b01, 0b10, 0b01, 0b11, 0b00, 0b00, 0b11,
+    ],
+    [
+        0b00, 0b11, 0b01, 0b01, 0b00, 0b10, 0b00, 0b10, 0b11, 0b10, 0b11, 0b01, 0b10, 0b01, 0b11, 0b00,
+    ],
+    [
+        0b11, 0b00, 0b00, 0b11, 0b01, 0b01, 0b00, 0b10, 0b00, 0b10, 0b11, 0b10, 0b11, 0b01, 0b10, 0b01,
+    ],
+    [
+        0b10, 0b01, 0b11, 0b00, 0b00, 0b11, 0b01, 0b01, 0b00, 0b10, 0b00, 0b10, 0b11, 0b10, 0b11, 0b01,
+    ],
+    [
+        0b10, 0b00, 0b11, 0b00, 0b10, 0b01, 0b01, 0b10, 0b00, 0b00, 0b01, 0b11, 0b01, 0b11, 0b10, 0b11,
+    ],
+    [
+        0b10, 0b11, 0b10, 0b00, 0b11, 0b00, 0b10, 0b01, 0b01, 0b10, 0b00, 0b00, 0b01, 0b11, 0b01, 0b11,
+    ],
+    [
+        0b01, 0b11, 0b10, 0b11, 0b10, 0b00, 0b11, 0b00, 0b10, 0b01, 0b01, 0b10, 0b00, 0b00, 0b01, 0b11,
+    ],
+    [
+        0b01, 0b11, 0b01, 0b11, 0b10, 0b11, 0b10, 0b00, 0b11, 0b00, 0b10, 0b01, 0b01, 0b10, 0b00, 0b00,
+    ],
+    [
+        0b00, 0b00, 0b01, 0b11, 0b01, 0b11, 0b10, 0b11, 0b10, 0b00, 0b11, 0b00, 0b10, 0b01, 0b01, 0b10,
+    ],
+    [
+        0b01, 0b10, 0b00, 0b00, 0b01, 0b11, 0b01, 0b11, 0b10, 0b11, 0b10, 0b00, 0b11, 0b00, 0b10, 0b01,
+    ],
+    [
+        0b10, 0b01, 0b01, 0b10, 0b00, 0b00, 0b01, 0b11, 0b01, 0b11, 0b10, 0b11, 0b10, 0b00, 0b11, 0b00,
+    ],
+    [
+        0b11, 0b00, 0b10, 0b01, 0b01, 0b10, 0b00, 0b00, 0b01, 0b11, 0b01, 0b11, 0b10, 0b11, 0b10, 0b00,
+    ],
+];
+
+type SwapType<'a> = FlatMap<Iter<'a, u8>, [u8; 2], fn(&u8) -> [u8; 2]>;
+type ChipSequenceType<'a> = FlatMap<SwapType<'a>, [u8; 16], fn(u8) -> [u8; 16]>;
+type MiddleBitsType<'a> = Skip<Flatten<Scan<ChipSequenceType<'a>, u8, fn(&mut u8, u8) -> Option<[u8; 2]>>>>;
+type RepeatType<'a> =
+    Zip<FlatMap<MiddleBitsType<'a>, RepeatN<u8>, fn(u8) -> RepeatN<u8>>, Repeat<&'a [[Level; 3]; 4]>>;
+
+type LengthsType<'a> = Scan<
+    FlatMap<RepeatType<'a>, [Level; 3], fn((u8, &[[Level; 3]; 4])) -> [Level; 3]>,
+    Level,
+    fn(&mut Level, Level) -> Option<Level>,
+>;
+
+type IntsListType<'a> = Chain<
+    Once<u8>,
+    FlatMap<
+        FilterMap<LengthsType<'a>, fn(Level) -> Option<u8>>,
+        Chain<RepeatN<u8>, Once<u8>>,
+        fn(u8) -> Chain<RepeatN<u8>, Once<u8>>,
+    >,
+>;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Level {
+    High(u8),
+    Low(u8),
+    Nop,
+}
+
+/// get a swapped version of provided tuple
+///
+/// ### Arguments
+///
+/// * `(a, b)`:
+///
+/// #### returns: [char; 2]
+/// [b, a]
+///
+fn swap_and_split_fn(byte: &u8) -> [u8; 2] {
+    let ret: [u8; 2] = [byte & 0b00001111u8, byte >> 4];
+    ret
+}
+
+///
+///
+/// ### Arguments
+///
+/// * `c`: character that is valid hex
+///
+/// #### returns: [u8; 16]
+/// The corresponding chip sequence for the hex character
+fn hex_to_chips(c: u8) -> [u8; 16] {
+    let idx = usize::from(c);
+    CHIP_ARRAY[idx]
+}
+
+///
+/// This is used to generate the offset part of the offset psk,
+/// you cannot go from 11 to 00 you need an intermediary value 01
+///
+/// [ (1,1) = (i_1,q_1)   (0,0) = (i_2,q_2) ] -> (i_2,q_1) = (0,1) = 0b01
+///
+///
+/// ### Arguments
+///
+/// * `prev`: the previous 2 bit chip
+/// * `current`: the current 2 bit chip
+///
+/// #### returns: Option<[u8; 2]>
+///  return the intermediate chip and the next chip
+#[allow(clippy::unnecessary_wraps)]
+fn add_middle(prev: &mut u8, current: u8) -> Option<[u8; 2]> {
+    // middle will have q from previous, i from next
+    let middle: u8 = (*prev & 0b01) | (current & 0b10);
+    *prev = current;
+    Some([middle, current])
+}
+
+/// Turn chips into digital wave forms
+///
+/// ### Arguments
+///
+/// * `bit_chip2`: the 2 bit chip
+///
+/// #### returns: [Level; 3]
+///
+///
+/// basically modeling this 0000111111110000
+///
+///
+///  ie [Level::Low(4), Level::High(8), Level::Low(4)];
+///
+///
+///  ie 4 cycles low, 8 cycles high, 4 cycles lo
+fn chips_to_waves(input: (u8, &[[Level; 3]; 4])) -> [Level; 3] {
+    let (bit_chip2, waves) = input;
+    match bit_chip2 {
+        0b00 => {
+            //0000111111110000
+            // [Level::Low(4), Level::High(8), Level::Low(4)]
+            waves[0]
+        }
+        0b01 => {
+            //0000000011111111
+            // [Level::Low(8), Level::High(8), Level::Nop]
+            waves[1]
+        }
+        0b10 => {
+            // 1111111100000000
+            // [Level::High(8), Level::Low(8), Level::Nop]
+            waves[2]
+        }
+        0b11 => {
+            //1111000000001111
+            // [Level::High(4), Level::Low(8), Level::High(4)]
+            waves[3]
+        }
+
+        _ => {
+            panic!("Illegal bitChip: {}", bit_chip2)
+        }
+    }
+}
+
+/// Merge waves that are in sequence that are the same level (High or Low)
+///
+/// ### Arguments
+///
+/// * `state`: the current High or Low timing
+/// * `next`: the next one tp process
+///
+/// #### returns: [Option<Level>]
+///  only returns Some(Level)
+///
+///  Nop when still adding waves
+///
+///  High/Low when it changes
+///
+/// Nop is used so that there is a return value for every time this is called
+fn combine_waves(state: &mut Level, next: Level) -> Option<Level> {
+    match next {
+        Level::High(next_len) => {
+            match state {
+                Level::High(state_len) => {
+                    // if the state is high, and the next one is high,
+                    // add them and return nop
+                    *state = Level::High(next_len + *state_len);
+                    Some(Level::Nop)
+                }
+                Level::Low(_) => {
+                    // if the state is low, and the next one is high,
+                    // return the state, and save next as the state
+                    let temp = Some(*state);
+                    *state = next;
+                    temp
+                }
+                Level::Nop => Some(Level::Nop),
+            }
+        }
+        Level::Low(next_len) => {
+            match state {
+                Level::Low(state_len) => {
+                    // if the state is low, and the next one is low,
+                    // add them and return nop
+                    *state = Level::Low(next_len + *state_len);
+                    Some(Level::Nop)
+                }
+                Level::High(_) => {
+                    // if the state is high, and the next one is low,
+                    // return the state, and save next as the state
+                    let temp = Some(*state);
+                    *state = next;
+                    temp
+                }
+                Level::Nop => Some(Level::Nop),
+            }
+        }
+        Level::Nop => Some(Level::Nop),
+    }
+}
+
+/// remove state kind wrapper ie High(8) -> 8
+///
+/// ### Arguments
+///
+/// * `l`: the level
+///
+/// #### returns: [Option<u8>]
+/// returns None for Nop,
+/// Return Some(l) for High/Low
+/// Option is used to filter out Nops
+fn levels_to_ints(l: Level) -> Option<u8> {
+    match l {
+        Level::Low(v) | Level::High(v) => {
+            // the first value could be zero due to how combine_waves_works
+            if v > 4 {
+                Some(v)
+            } else {
+                Some(4)
+            }
+        }
+        Level::Nop => None,
+    }
+}
+
+/// turn length into a series of bits / pio bytecode
+///
+/// ### Arguments
+///
+/// * `len`: the length to translate
+///
+/// #### returns: [Chain<RepeatN<u8>, Once<u8>>]
+///
+///
+/// ### Examples
+///
+/// ```text
+/// lengths_to_pio_byte_code_ints(12) -> 0,1,1,1,1 (this side is first)
+///
+/// ```
+fn lengths_to_pio_byte_code_ints(len: u8) -> Chain<RepeatN<u8>, Once<u8>> {
+    let repeats = usize::from((len - 4) / 2);
+    iter::repeat_n(1u8, repeats).chain(once(0u8))
+}
+
+/// A helper function to repeat a value `n`, `repeat` times
+///
+/// ### Arguments
+///
+/// * `repeats`: the number of time to repeat n
+/// * `n`: the value to repeat
+///
+/// #### returns: [RepeatN<u8>]
+///
+///
+/// ### Examples
+///
+/// ```text
+/// repeater(8,2) -> 8,8
+/// ```
+fn repeater(repeats: u8, n: u8) -> RepeatN<u8> {
+    iter::repeat_n(n, repeats as usize)
+}
+
+/// repeat n [TIMES] times,
+///
+/// # Arguments
+///
+/// * `n`: the number to repeat (usually a chip value)
+///
+/// returns: RepeatN<u8>
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_gen::repeat_n;
+///
+/// assert!(repeat_n::<3>(0b10).eq([0b10, 0b10, 0b10]));
+/// ```
+pub fn repeat_n<const TIMES: u8>(n: u8) -> RepeatN<u8> {
+    repeater(TIMES, n)
+}
+
+pub type ConvertIterType<'a> = Batching<IntsListType<'a>, fn(&mut IntsListType) -> Option<u32>>;
+
+/// from an iterator of 0 and 1, pack them into a u32
+///
+/// ### Arguments
+///
+/// * `it`: the iterator
+///
+/// #### returns: [Option<u32>]
+///  Some(u32) while there is data in iter
+///  None when the iterator is empty
+///
+/// # Examples
+///
+/// ```text
+///  ...0,1,0,1 -> 0b1010..
+/// ```
+fn pack_bits_into_u32(it: &mut IntsListType) -> Option<u32> {
+    let mut value = 0u32;
+    let mut bit_idx = 0;
+    for set_bit in it.by_ref() {
+        value |= u32::from(set_bit) << (31 - bit_idx);
+        if bit_idx == 31 {
+            return Some(value);
+        } else {
+            bit_idx += 1;
+        }
+    }
+    // if there isn't a number of bits divisible by 32, make sure that last bit gets saved
+    if bit_idx == 0 {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// swap every 2 characters in a string
+///
+///  length ~ * 2
+/// # Arguments
+///
+/// * `s`: the string
+///
+/// returns: ~ impl Iterator<Item=[u8; 2]>
+///
+/// # Examples
+///
+/// ```text
+/// "ABCD" -> "BADC"
+/// ```
+fn swap(s: &[u8]) -> SwapType<'_> {
+    s.iter()
+        // -> swap every other char for endianness
+        .flat_map(swap_and_split_fn)
+}
+
+/// convert half bytes to chips
+///
+/// input * 16
+/// # Arguments
+///
+/// * `s`: swap iterator ~ impl Iterator<Item=[u8; 2]>
+///
+/// returns: ~ impl Iterator<Item=[u8; 16]>
+fn get_chip_sequences(s: SwapType) -> ChipSequenceType {
+    s.flat_map(hex_to_chips)
+}
+
+/// add middle bits to not cross 0,0 in phase diagram
+///
+/// input * 2
+/// # Arguments
+///
+/// * `cs`: iterator of chip sequences ~ impl Iterator<Item=[u8; 16]>
+///
+/// returns:  ~ impl Iterator<Item=[u8; 2]>
+///
+fn add_middle_bits_for_o_qpsk(cs: ChipSequenceType) -> MiddleBitsType {
+    cs.scan(0u8, add_middle as fn(&mut u8, u8) -> Option<[u8; 2]>)
+        .flatten()
+        // make it align better with existing implementation for debugging, would work fine without this
+        .skip(1)
+}
+
+/// Translate frame bytes to O-QPSK pio bytecode
+///
+/// # Arguments
+///
+/// * `s`: the frame bytes to translate to pio bytecode
+/// * `waves`: the wave of each 2 bit chip, see [wave_array](crate::wave_array)
+/// * `NUMBER_OF_REPEATED_WAVES`: how many times every wave is repeated
+///
+/// returns:  [ConvertIterType]
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_decode::decode_pio_bytecode;
+/// use pico_qpsk_core::pio_bytecode_gen::convert_advanced;
+/// use pico_qpsk_core::wave_array;
+///
+/// // every chip is 4 waves of 16 cycles, for an 8MHz offset at 128MHz
+/// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+/// let words: Vec<u32> = convert_advanced::<4>(&frame, &wave_array!(16)).collect();
+/// assert_eq!(decode_pio_bytecode::<32>(&words, &wave_array!(16), 4).unwrap().bytes, frame);
+/// ```
+pub fn convert_advanced<'a, const NUMBER_OF_REPEATED_WAVES: u8>(
+    s: &'a [u8],
+    waves: &'a [[Level; 3]; 4],
+) -> ConvertIterType<'a> {
+    // TODO: make sure there is an even number of characters in s
+    // swap for endianness
+    let a: SwapType = swap(s); //  length*2
+
+    // ->  get chip sequences
+    let b: ChipSequenceType = get_chip_sequences(a); //  length*16
+
+    // -> add middle bits for O-QPSK
+    let b2: MiddleBitsType = add_middle_bits_for_o_qpsk(b); //  length*2
+
+    let repeat_fn: fn(u8) -> RepeatN<u8> = repeat_n::<NUMBER_OF_REPEATED_WAVES>;
+
+    let b3: RepeatType = b2 // length * number of repeats
+        // repeat the chips the number of times needed,
+        // and add reference to waves array for each element for next step
+        .flat_map(repeat_fn)
+        .zip(iter::repeat(waves)); // length * repeat
+
+    let c1: LengthsType = b3
+        // translate chips into waves
+        .flat_map(chips_to_waves as fn((u8, &[[Level; 3]; 4])) -> [Level; 3]) // max: length * 3
+        .scan(
+            Level::Low(0),
+            combine_waves as fn(&mut Level, Level) -> Option<Level>,
+        );
+
+    let c1_1: IntsListType = once(0).chain(
+        c1.filter_map(levels_to_ints as fn(Level) -> Option<u8>)
+            .flat_map(lengths_to_pio_byte_code_ints as fn(u8) -> Chain<RepeatN<u8>, Once<u8>>),
+    );
+    let c2: ConvertIterType = c1_1.batching(pack_bits_into_u32 as fn(&mut IntsListType) -> Option<u32>);
+
+    c2
+}
+
+/// The level of each quarter of a subcarrier period for each 2 bit chip, true is [Level::High]
+///
+/// the same shapes as [crate::wave_array], `0b00` is low, high, high, low
+pub(crate) const QUARTER_LEVELS: [[bool; 4]; 4] = [
+    [false, true, true, false],
+    [false, false, true, true],
+    [true, true, false, false],
+    [true, false, false, true],
+];
+
+/// How many quarters of the subcarrier period each chip of a [QuarterRuns] lasts
+pub(crate) trait ChipQuarterCount {
+    /// the quarters of the next chip
+    fn next_chip(&mut self) -> u32;
+}
+
+/// every chip lasts the same number of quarters
+impl ChipQuarterCount for u8 {
+    fn next_chip(&mut self) -> u32 {
+        u32::from(*self)
+    }
+}
+
+/// Walks the quarters of the subcarrier period chip by chip and merges the quarters that set the pins the
+/// same way into runs
+///
+/// The level of a quarter comes from its chip and its place in the subcarrier period, the period keeps
+/// its phase across the chips. A run can be longer than a [Level] holds when a chip is shorter than a
+/// subcarrier period.
+#[derive(Clone)]
+pub(crate) struct QuarterRuns<C, Q> {
+    chips: C,
+    quarters: Q,
+    quarter_cycles: u32,
+    /// how many quarters ahead of the I pin the Q pin is, `None` for the single pin program
+    q_quarters_ahead: Option<usize>,
+    /// the chip being sent and how many of its quarters are left
+    chip: u8,
+    quarters_left: u32,
+    /// the quarter of the subcarrier period
+    phase: usize,
+    /// the pins of the run being merged and its length
+    run_pins: u8,
+    run_cycles: u32,
+}
+
+impl<C: Iterator<Item = u8>, Q: ChipQuarterCount> QuarterRuns<C, Q> {
+    pub(crate) fn new(chips: C, quarters: Q, quarter_cycles: u8, q_quarters_ahead: Option<usize>) -> Self {
+        QuarterRuns {
+            chips,
+            quarters,
+            quarter_cycles: u32::from(quarter_cycles),
+            q_quarters_ahead,
+            chip: 0,
+            quarters_left: 0,
+            phase: 0,
+            run_pins: 0,
+            run_cycles: 0,
+        }
+    }
+
+    /// the pins of the next quarter, `None` after the last chip
+    ///
+    /// the single pin is bit 0 and set for [Level::High], with a Q pin bit 0 is the I pin and bit 1 the
+    /// Q pin and a pin is low during a [Level::High] like in the single pin program
+    fn next_quarter(&mut self) -> Option<u8> {
+        while self.quarters_left == 0 {
+            self.chip = self.chips.next()?;
+            self.quarters_left = self.quarters.next_chip();
+        }
+        self.quarters_left -= 1;
+        let levels = QUARTER_LEVELS[usize::from(self.chip)];
+        let i = levels[self.phase];
+        let pins = match self.q_quarters_ahead {
+            None => u8::from(i),
+            Some(ahead) => u8::from(!levels[(self.phase + ahead) % 4]) << 1 | u8::from(!i),
+        };
+        self.phase = (self.phase + 1) % 4;
+        Some(pins)
+    }
+
+    /// the pins and length of the next run that ends in a change of the pins, `None` after the last chip
+    /// and the run being merged is left for [take_last](Self::take_last)
+    ///
+    /// the first run is 0 cycles long when the first quarter doesn't leave the pins at 0
+    pub(crate) fn next_run(&mut self) -> Option<(u8, u32)> {
+        loop {
+            let pins = self.next_quarter()?;
+            if pins == self.run_pins {
+                self.run_cycles += self.quarter_cycles;
+            } else {
+                let run = (self.run_pins, self.run_cycles);
+                self.run_pins = pins;
+                self.run_cycles = self.quarter_cycles;
+                return Some(run);
+            }
+        }
+    }
+
+    /// the last run of the frame once [next_run](Self::next_run) returned `None`, after it `None`
+    pub(crate) fn take_last(&mut self) -> Option<(u8, u32)> {
+        let cycles = core::mem::take(&mut self.run_cycles);
+        (cycles > 0).then_some((self.run_pins, cycles))
+    }
+}
+
+/// The run lengths of [convert_quarters], merged like `combine_waves` and at least 4 like
+/// `levels_to_ints`, the last run of a frame is never written like in [convert_advanced]
+#[derive(Clone)]
+pub struct QuarterLengths<'a>(QuarterRuns<MiddleBitsType<'a>, u8>);
+
+impl Iterator for QuarterLengths<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.0.next_run().map(|(_, len)| len.max(4))
+    }
+}
+
+/// Iterator over the pio bytecode of [convert_quarters]
+pub type QuarterConvertIter<'a> = RunWords<QuarterLengths<'a>>;
+
+/// Translate bytes to O-QPSK pio bytecode with a subcarrier that keeps its phase across the chips
+///
+/// Every chip (with the O-QPSK middle chips that is every 0.5us) lasts `quarters_per_chip` quarters of
+/// the subcarrier period and each quarter `quarter_cycles` state machine cycles. The level of a quarter
+/// comes from the chip and the quarter's place in the subcarrier period, so the subcarrier is
+/// `quarters_per_chip` x 0.5MHz and doesn't have to be a multiple of the chip rate.
+///
+/// With `quarters_per_chip` a multiple of 4 this is the same bytecode as [convert_advanced] with
+/// `wave_array!(4 * quarter_cycles)` and `quarters_per_chip / 4` repeats.
+///
+/// # Arguments
+///
+/// * `s`: the bytes to translate
+/// * `quarter_cycles`: even and at least 4, the PIO program's shortest run
+/// * `quarters_per_chip`: at least 1, see [crate::offset_planner::MIN_OFFSET_HZ] for what a receiver
+///   can tell apart
+///
+/// returns: [QuarterConvertIter]
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_gen::{convert_advanced, convert_quarters};
+/// use pico_qpsk_core::wave_array;
+///
+/// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+/// let words: Vec<u32> = convert_quarters(&frame, 4, 16).collect();
+/// assert!(words.iter().eq(convert_advanced::<4>(&frame, &wave_array!(16)).collect::<Vec<_>>().iter()));
+/// ```
+pub fn convert_quarters(s: &[u8], quarter_cycles: u8, quarters_per_chip: u8) -> QuarterConvertIter<'_> {
+    assert!(
+        quarter_cycles >= 4 && quarter_cycles.is_multiple_of(2),
+        "a quarter must be an even number of cycles, at least 4"
+    );
+    assert!(quarters_per_chip > 0, "a chip is at least one quarter");
+    RunWords::new(QuarterLengths(QuarterRuns::new(
+        add_middle_bits_for_o_qpsk(get_chip_sequences(swap(s))),
+        quarters_per_chip,
+        quarter_cycles,
+        None,
+    )))
+}
+
+/// The sideband [convert_quadrature] keeps, the other one is cancelled
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Sideband {
+    /// the carrier plus the subcarrier offset, the Q pin lags the I pin by a quarter period
+    Upper,
+    /// the carrier minus the subcarrier offset, the Q pin leads the I pin by a quarter period
+    Lower,
+}
+
+impl Sideband {
+    /// how many quarters ahead in [QUARTER_LEVELS] the Q pin's level is, a lag of one quarter is 3 ahead
+    pub(crate) fn q_quarters_ahead(&self) -> usize {
+        match self {
+            Sideband::Upper => 3,
+            Sideband::Lower => 1,
+        }
+    }
+}
+
+/// Iterator returned by [convert_quadrature]
+#[derive(Clone)]
+pub struct QuadratureConvertIter<'a> {
+    runs: QuarterRuns<MiddleBitsType<'a>, u8>,
+    /// the run that sets both pins low after the frame was written
+    finished: bool,
+    /// bits of the run being written, the 2 pin bits, the set bits and then the 0
+    pin_bits_left: u8,
+    pins: u8,
+    ones_left: u32,
+    zero_left: bool,
+}
+
+impl QuadratureConvertIter<'_> {
+    /// the pins and length of the next run, bit 0 is the I pin and bit 1 the Q pin
+    fn next_run(&mut self) -> Option<(u8, u32)> {
+        loop {
+            match self.runs.next_run() {
+                // the pins start out as the first quarter sets them
+                Some((_, 0)) => continue,
+                Some(run) => return Some(run),
+                None => break,
+            }
+        }
+        if let Some(run) = self.runs.take_last() {
+            return Some(run);
+        }
+        if !self.finished {
+            self.finished = true;
+            return Some((0, 4));
+        }
+        None
+    }
+
+    fn next_bit(&mut self) -> Option<u8> {
+        if self.pin_bits_left == 0 && self.ones_left == 0 && !self.zero_left {
+            let (pins, len) = self.next_run()?;
+            self.pins = pins;
+            self.pin_bits_left = 2;
+            self.ones_left = (len - 4) / 2;
+            self.zero_left = true;
+        }
+        if self.pin_bits_left > 0 {
+            self.pin_bits_left -= 1;
+            Some((self.pins >> self.pin_bits_left) & 1)
+        } else if self.ones_left > 0 {
+            self.ones_left -= 1;
+            Some(1)
+        } else {
+            self.zero_left = false;
+            Some(0)
+        }
+    }
+}
+
+impl Iterator for QuadratureConvertIter<'_> {
+    type Item = u32;
+
+    /// packs the bits like `pack_bits_into_u32`
+    fn next(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for bit_idx in 0..32 {
+            match self.next_bit() {
+                Some(bit) => value |= u32::from(bit) << (31 - bit_idx),
+                None if bit_idx == 0 => return None,
+                None => break,
+            }
+        }
+        Some(value)
+    }
+}
+
+/// Translate bytes to pio bytecode for two antenna pins driven a quarter of the subcarrier period apart
+///
+/// The I pin carries the wave of [convert_quarters], the Q pin the same chips a quarter period later
+/// (or earlier for [Sideband::Lower]). Switching the antenna between the four reflections the two pins
+/// select, 90° apart, makes `I + jQ` a subcarrier that only turns in one direction, so there is no mirror
+/// image on the other side of the carrier.
+///
+/// Every run is the state of both pins, Q first, then `n` set bits and a 0, it lasts `4 + 2n` cycles
+/// like the runs of the single pin program. The frame ends with both pins low.
+///
+/// # Arguments
+///
+/// * `s`: the bytes to translate
+/// * `quarter_cycles`: even and at least 4, the PIO program's shortest run
+/// * `quarters_per_chip`: at least 1, the subcarrier is `quarters_per_chip` x 0.5MHz
+/// * `sideband`: the sideband that is kept
+///
+/// returns: [QuadratureConvertIter]
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::pio_bytecode_decode::decode_quadrature_bytecode;
+/// use pico_qpsk_core::pio_bytecode_gen::{convert_quadrature, Sideband};
+///
+/// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+/// let words: Vec<u32> = convert_quadrature(&frame, 4, 16, Sideband::Upper).collect();
+/// let [i, q] = decode_quadrature_bytecode::<32>(&words, 4, 16, Sideband::Upper).unwrap();
+/// assert_eq!((i.bytes, q.bytes), (frame.into_iter().collect(), frame.into_iter().collect()));
+/// ```
+pub fn convert_quadrature(
+    s: &[u8],
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+    sideband: Sideband,
+) -> QuadratureConvertIter<'_> {
+    assert!(
+        quarter_cycles >= 4 && quarter_cycles.is_multiple_of(2),
+        "a quarter must be an even number of cycles, at least 4"
+    );
+    assert!(quarters_per_chip > 0, "a chip is at least one quarter");
+    QuadratureConvertIter {
+        runs: QuarterRuns::new(
+            add_middle_bits_for_o_qpsk(get_chip_sequences(swap(s))),
+            quarters_per_chip,
+            quarter_cycles,
+            Some(sideband.q_quarters_ahead()),
+        ),
+        finished: false,
+        pin_bits_left: 0,
+        pins: 0,
+        ones_left: 0,
+        zero_left: false,
+    }
+}
@@ -0,0 +1,82 @@
+//! The PIO programs that play the bytecode of [crate::pio_bytecode_gen]
+//!
+//! They start with a `wait` for the trigger pin, it is a GPIO number from
+//! [PinConfig](crate::session_config::PinConfig) so the programs are assembled at start up instead of
+//! with `pio_asm!`.
+use pio::{Assembler, JmpCondition, OutDestination, Program, SetDestination, WaitSource};
+
+/// The longest program a PIO block holds
+pub const MAX_PROGRAM_SIZE: usize = 32;
+
+/// `wait 0 gpio <trigger>`, without it the first high or low of a packet has no fixed length
+fn wait_for_trigger(assembler: &mut Assembler<MAX_PROGRAM_SIZE>, trigger: u8) {
+    assembler.wait(0, WaitSource::GPIO, trigger, false);
+}
+
+/// The single pin program, `set pins` toggles the antenna pin
+///
+/// A run is 4 + 2n cycles for the n set bits and a 0 the bytecode has for it, see
+/// [convert](crate::pio_bytecode_gen::convert). It wraps to the instruction after the `wait`, address 1
+/// when it is installed first.
+///
+/// ```text
+///     wait 0 gpio <trigger>
+/// .wrap_target
+///     set pins 0 [1]
+/// loop1:
+///     out x 1
+///     jmp x-- loop1
+///     set pins, 1 [1]
+/// loop2:
+///     out y 1
+///     jmp y-- loop2
+/// .wrap
+/// ```
+pub fn single_pin_program(trigger: u8) -> Program<MAX_PROGRAM_SIZE> {
+    let mut a = Assembler::new();
+    let mut wrap_target = a.label();
+    let mut wrap_source = a.label();
+    let mut loop1 = a.label();
+    let mut loop2 = a.label();
+    wait_for_trigger(&mut a, trigger);
+    a.bind(&mut wrap_target);
+    a.set_with_delay(SetDestination::PINS, 0, 1);
+    a.bind(&mut loop1);
+    a.out(OutDestination::X, 1);
+    a.jmp(JmpCondition::XDecNonZero, &mut loop1);
+    a.set_with_delay(SetDestination::PINS, 1, 1);
+    a.bind(&mut loop2);
+    a.out(OutDestination::Y, 1);
+    a.jmp(JmpCondition::YDecNonZero, &mut loop2);
+    a.bind(&mut wrap_source);
+    a.assemble_with_wrap(wrap_source, wrap_target)
+}
+
+/// The two pin program, `out pins 2` sets the I and Q pins of
+/// [convert_quadrature](crate::pio_bytecode_gen::convert_quadrature)
+///
+/// The pins for the run, then n set bits and a 0, 4 + 2n cycles like the single pin runs. It doesn't
+/// wrap, the single pin program's wrap is set on the state machine, so the jump back is part of the run.
+///
+/// ```text
+///     wait 0 gpio <trigger>
+/// run:
+///     out pins 2
+/// count:
+///     out x 1
+///     jmp x-- count
+///     jmp run
+/// ```
+pub fn two_pin_program(trigger: u8) -> Program<MAX_PROGRAM_SIZE> {
+    let mut a = Assembler::new();
+    let mut run = a.label();
+    let mut count = a.label();
+    wait_for_trigger(&mut a, trigger);
+    a.bind(&mut run);
+    a.out(OutDestination::PINS, 2);
+    a.bind(&mut count);
+    a.out(OutDestination::X, 1);
+    a.jmp(JmpCondition::XDecNonZero, &mut count);
+    a.jmp(JmpCondition::Always, &mut run);
+    a.assemble_program()
+}
@@ -0,0 +1,43 @@
+//! Seeded pseudo random numbers for test payloads
+//!
+//! SplitMix64 is used as it is tiny, takes any seed (0 included) and is easy to rewrite in the
+//! receiver's language, it is NOT cryptographically secure.
+
+/// The SplitMix64 generator
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::prng::SplitMix64;
+///
+/// let mut rng = SplitMix64::new(0);
+/// assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Fill `bytes` from consecutive outputs, each output is used little endian first
+    ///
+    /// the unused bytes of the last output are dropped so every call starts on a new output
+    pub fn fill_bytes(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+}
@@ -0,0 +1,523 @@
+//! The binary control protocol, for scripts that drive the firmware instead of a person at the console
+//!
+//! Every message is framed with COBS so 0x00 only shows up between messages:
+//!
+//! ```text
+//! 0x00 COBS([VERSION][TYPE][REQUEST ID][BODY][CRC]) 0x00
+//! ```
+//!
+//! The request ID and CRC are little endian, the CRC is the same CRC-16 as the 802.15.4 FCS over
+//! everything before it. The firmware answers with the ID of the request, a transmit request gets a
+//! [Response::PacketSent] for every packet and then a [Response::Done]. A console session never sends
+//! 0x00 so the firmware can tell the two apart from the first byte.
+use crate::config_store::{read_config, write_config};
+use crate::schedule::PeriodStats;
+use crate::session_config::SessionConfig;
+use byte::ctx::Bytes;
+use byte::{BytesExt, TryRead, TryWrite, LE};
+use crc_all::CrcAlgo;
+
+/// Version of the message layout, messages from another version are answered with
+/// [ErrorCode::Version]
+pub const PROTOCOL_VERSION: u8 = 6;
+
+/// `[VERSION][TYPE][REQUEST ID]`
+const HEADER_SIZE: usize = 4;
+const CRC_SIZE: usize = 2;
+
+/// Bytes a message with a `body_size` byte body takes before COBS
+pub const fn message_size(body_size: usize) -> usize {
+    HEADER_SIZE + body_size + CRC_SIZE
+}
+
+/// Bytes a `message_size` byte message takes on the wire, COBS and both delimiters included
+pub const fn max_encoded_size(message_size: usize) -> usize {
+    message_size + message_size / 254 + 1 + 2
+}
+
+const CRC16_KERMIT: CrcAlgo<u16> = CrcAlgo::<u16>::new(0x1021, 16, 0, 0, true);
+
+/// What a [Request::Transmit] sends
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransmitSource<'a> {
+    /// the payload mode of the session's [SendDefaults](crate::session_config::SendDefaults)
+    Defaults,
+    /// a MAC payload, the frame around it comes from the session config
+    Payload(&'a [u8]),
+    /// a PHY frame sent as is
+    RawFrame(&'a [u8]),
+}
+
+/// Messages from the host
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Request<'a> {
+    /// replace the session config, answered with [Response::Ok], or with [ErrorCode::BadBody] and the
+    /// session kept when its keying can't be sent at its transmit option
+    Configure(SessionConfig),
+    /// send `number_packets` packets, `interval_ms` apart in the schedule mode of the session's
+    /// [SendDefaults](crate::session_config::SendDefaults)
+    Transmit {
+        interval_ms: u32,
+        number_packets: u32,
+        source: TransmitSource<'a>,
+    },
+    /// answered with [Response::Status], also while transmitting
+    Status,
+    /// stop transmitting, the transmit request gets a [Response::Done] with `aborted` set
+    Abort,
+}
+
+/// Why a request failed
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorCode {
+    /// the COBS, CRC or length of the message is wrong, the request ID can't be trusted so the error is
+    /// sent with request ID 0
+    BadMessage = 1,
+    /// the message has another [PROTOCOL_VERSION]
+    Version = 2,
+    /// the message type is not a request this version knows
+    UnknownMessage = 3,
+    /// the body of the request can't be read, or the config it holds can't be sent
+    BadBody = 4,
+    /// the request can't be handled while transmitting
+    Busy = 5,
+    /// there is nothing to abort
+    NotTransmitting = 6,
+    /// the payload doesn't fit in a frame
+    FrameConstruction = 7,
+    /// the frame is longer than the largest frame the firmware builds
+    PacketTooLong = 8,
+    /// the PIO bytecode of a packet longer than the firmware's buffer couldn't be generated as fast as it
+    /// was sent, the packet was cut short
+    Underrun = 9,
+    /// the frame is too long for the bytecode buffer of an extra output that sends it too, the packet
+    /// wasn't sent
+    ExtraOutputTooLong = 10,
+}
+
+impl ErrorCode {
+    const ALL: [ErrorCode; 10] = [
+        ErrorCode::BadMessage,
+        ErrorCode::Version,
+        ErrorCode::UnknownMessage,
+        ErrorCode::BadBody,
+        ErrorCode::Busy,
+        ErrorCode::NotTransmitting,
+        ErrorCode::FrameConstruction,
+        ErrorCode::PacketTooLong,
+        ErrorCode::Underrun,
+        ErrorCode::ExtraOutputTooLong,
+    ];
+}
+
+/// The state the firmware reports in a [Response::Status]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Status {
+    pub transmitting: bool,
+    /// packets sent by the current (or last) transmit request
+    pub packets_sent: u32,
+    pub number_packets: u32,
+    pub config: SessionConfig,
+}
+
+/// Messages from the firmware
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Response<'a> {
+    /// the request is done
+    Ok,
+    Error(ErrorCode),
+    Status(Status),
+    /// a packet is being sent, `frame` is the PHY frame, the first packet is 1
+    PacketSent {
+        packet_number: u32,
+        frame: &'a [u8],
+    },
+    /// a transmit request finished, `period` is what the packets' start times achieved
+    Done {
+        packets_sent: u32,
+        aborted: bool,
+        period: PeriodStats,
+    },
+}
+
+/// A type to hold the possible errors that occur when a message is encoded or decoded
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolError {
+    /// the output buffer is too small
+    TooLong,
+    /// the COBS encoding is broken
+    Cobs,
+    /// shorter than a header and CRC
+    TooShort,
+    Crc,
+    Version(u8),
+    UnknownMessage(u8),
+    /// the body doesn't match the message type
+    Body(&'static str),
+}
+
+impl ProtocolError {
+    /// The [ErrorCode] to answer a request that failed to decode with
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            ProtocolError::TooLong | ProtocolError::Cobs | ProtocolError::TooShort | ProtocolError::Crc => {
+                ErrorCode::BadMessage
+            }
+            ProtocolError::Version(_) => ErrorCode::Version,
+            ProtocolError::UnknownMessage(_) => ErrorCode::UnknownMessage,
+            ProtocolError::Body(_) => ErrorCode::BadBody,
+        }
+    }
+}
+
+/// A [Request] or [Response], the message type picks which body is read
+pub trait MessageBody<'a>: TryRead<'a, u8> + TryWrite {
+    fn message_type(&self) -> u8;
+}
+
+impl<'a> MessageBody<'a> for Request<'a> {
+    fn message_type(&self) -> u8 {
+        match self {
+            Request::Configure(_) => 0x01,
+            Request::Transmit { .. } => 0x02,
+            Request::Status => 0x03,
+            Request::Abort => 0x04,
+        }
+    }
+}
+
+impl<'a> MessageBody<'a> for Response<'a> {
+    fn message_type(&self) -> u8 {
+        match self {
+            Response::Ok => 0x81,
+            Response::Error(_) => 0x82,
+            Response::Status(_) => 0x83,
+            Response::PacketSent { .. } => 0x84,
+            Response::Done { .. } => 0x85,
+        }
+    }
+}
+
+impl TryWrite for Request<'_> {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let offset = &mut 0;
+        match self {
+            Request::Configure(config) => write_config(bytes, offset, &config)?,
+            Request::Transmit {
+                interval_ms,
+                number_packets,
+                source,
+            } => {
+                bytes.write_with(offset, interval_ms, LE)?;
+                bytes.write_with(offset, number_packets, LE)?;
+                match source {
+                    TransmitSource::Defaults => bytes.write(offset, 0u8)?,
+                    TransmitSource::Payload(payload) => {
+                        bytes.write(offset, 1u8)?;
+                        bytes.write(offset, payload)?;
+                    }
+                    TransmitSource::RawFrame(frame) => {
+                        bytes.write(offset, 2u8)?;
+                        bytes.write(offset, frame)?;
+                    }
+                }
+            }
+            Request::Status | Request::Abort => (),
+        }
+        Ok(*offset)
+    }
+}
+
+impl<'a> TryRead<'a, u8> for Request<'a> {
+    fn try_read(bytes: &'a [u8], message_type: u8) -> byte::Result<(Self, usize)> {
+        let offset = &mut 0;
+        let request = match message_type {
+            0x01 => Request::Configure(read_config(bytes, offset)?),
+            0x02 => {
+                let interval_ms = bytes.read_with(offset, LE)?;
+                let number_packets = bytes.read_with(offset, LE)?;
+                let kind: u8 = bytes.read(offset)?;
+                let rest = bytes.read_with(offset, Bytes::Len(bytes.len() - *offset))?;
+                let source = match kind {
+                    0 => TransmitSource::Defaults,
+                    1 => TransmitSource::Payload(rest),
+                    2 => TransmitSource::RawFrame(rest),
+                    _ => {
+                        return Err(byte::Error::BadInput {
+                            err: "transmit source",
+                        })
+                    }
+                };
+                Request::Transmit {
+                    interval_ms,
+                    number_packets,
+                    source,
+                }
+            }
+            0x03 => Request::Status,
+            0x04 => Request::Abort,
+            _ => return Err(byte::Error::BadInput { err: "message type" }),
+        };
+        Ok((request, *offset))
+    }
+}
+
+impl TryWrite for Response<'_> {
+    fn try_write(self, bytes: &mut [u8], _ctx: ()) -> byte::Result<usize> {
+        let offset = &mut 0;
+        match self {
+            Response::Ok => (),
+            Response::Error(code) => bytes.write(offset, code as u8)?,
+            Response::Status(status) => {
+                bytes.write(offset, u8::from(status.transmitting))?;
+                bytes.write_with(offset, status.packets_sent, LE)?;
+                bytes.write_with(offset, status.number_packets, LE)?;
+                write_config(bytes, offset, &status.config)?;
+            }
+            Response::PacketSent { packet_number, frame } => {
+                bytes.write_with(offset, packet_number, LE)?;
+                bytes.write(offset, frame)?;
+            }
+            Response::Done {
+                packets_sent,
+                aborted,
+                period,
+            } => {
+                bytes.write_with(offset, packets_sent, LE)?;
+                bytes.write(offset, u8::from(aborted))?;
+                bytes.write_with(offset, period.count, LE)?;
+                bytes.write_with(offset, period.min_us, LE)?;
+                bytes.write_with(offset, period.max_us, LE)?;
+                bytes.write_with(offset, period.total_us, LE)?;
+                bytes.write_with(offset, period.late, LE)?;
+            }
+        }
+        Ok(*offset)
+    }
+}
+
+impl<'a> TryRead<'a, u8> for Response<'a> {
+    fn try_read(bytes: &'a [u8], message_type: u8) -> byte::Result<(Self, usize)> {
+        let offset = &mut 0;
+        let response = match message_type {
+            0x81 => Response::Ok,
+            0x82 => {
+                let code: u8 = bytes.read(offset)?;
+                Response::Error(
+                    *ErrorCode::ALL
+                        .iter()
+                        .find(|error| **error as u8 == code)
+                        .ok_or(byte::Error::BadInput { err: "error code" })?,
+                )
+            }
+            0x83 => {
+                let transmitting = bytes.read::<u8>(offset)? != 0;
+                let packets_sent = bytes.read_with(offset, LE)?;
+                let number_packets = bytes.read_with(offset, LE)?;
+                let config = read_config(bytes, offset)?;
+                Response::Status(Status {
+                    transmitting,
+                    packets_sent,
+                    number_packets,
+                    config,
+                })
+            }
+            0x84 => {
+                let packet_number = bytes.read_with(offset, LE)?;
+                let frame = bytes.read_with(offset, Bytes::Len(bytes.len() - *offset))?;
+                Response::PacketSent { packet_number, frame }
+            }
+            0x85 => {
+                let packets_sent = bytes.read_with(offset, LE)?;
+                let aborted = bytes.read::<u8>(offset)? != 0;
+                let period = PeriodStats {
+                    count: bytes.read_with(offset, LE)?,
+                    min_us: bytes.read_with(offset, LE)?,
+                    max_us: bytes.read_with(offset, LE)?,
+                    total_us: bytes.read_with(offset, LE)?,
+                    late: bytes.read_with(offset, LE)?,
+                };
+                Response::Done {
+                    packets_sent,
+                    aborted,
+                    period,
+                }
+            }
+            _ => return Err(byte::Error::BadInput { err: "message type" }),
+        };
+        Ok((response, *offset))
+    }
+}
+
+/// Encode a message ready to be written to the serial port
+///
+/// # Arguments
+///
+/// * `request_id`: the ID the host picked for the request, responses use the ID of their request
+/// * `body`: a [Request] or [Response]
+/// * `out`: at least [max_encoded_size] bytes
+///
+/// returns: Result<the number of bytes of `out` used, [ProtocolError]>
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::protocol::{decode_message, encode_message, Request};
+///
+/// let mut out = [0u8; 32];
+/// let len = encode_message::<_, 16>(7, Request::Status, &mut out).unwrap();
+/// assert_eq!(out[0], 0x00);
+/// assert_eq!(out[len - 1], 0x00);
+/// // the firmware reads the bytes between the delimiters
+/// let (request_id, request) = decode_message::<Request>(&mut out[1..len - 1]).unwrap();
+/// assert_eq!((request_id, request), (7, Request::Status));
+/// ```
+pub fn encode_message<'a, B: MessageBody<'a>, const MAX_MESSAGE_SIZE: usize>(
+    request_id: u16,
+    body: B,
+    out: &mut [u8],
+) -> Result<usize, ProtocolError> {
+    let mut message = [0u8; MAX_MESSAGE_SIZE];
+    let offset = &mut 0;
+    message
+        .write(offset, PROTOCOL_VERSION)
+        .map_err(|_| ProtocolError::TooLong)?;
+    message
+        .write(offset, body.message_type())
+        .map_err(|_| ProtocolError::TooLong)?;
+    message
+        .write_with(offset, request_id, LE)
+        .map_err(|_| ProtocolError::TooLong)?;
+    message.write(offset, body).map_err(|_| ProtocolError::TooLong)?;
+    let crc = message_crc(&message[..*offset]);
+    message
+        .write_with(offset, crc, LE)
+        .map_err(|_| ProtocolError::TooLong)?;
+
+    let encoded = out.get_mut(1..).ok_or(ProtocolError::TooLong)?;
+    let len = cobs_encode(&message[..*offset], encoded).ok_or(ProtocolError::TooLong)?;
+    let end = out.get_mut(len + 1).ok_or(ProtocolError::TooLong)?;
+    *end = 0x00;
+    out[0] = 0x00;
+    Ok(len + 2)
+}
+
+/// A message with a good CRC, the version and body are not checked yet
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RawMessage<'a> {
+    pub version: u8,
+    pub message_type: u8,
+    pub request_id: u16,
+    pub body: &'a [u8],
+}
+
+impl<'a> RawMessage<'a> {
+    /// Check the version and read the body
+    pub fn read_body<B: MessageBody<'a>>(&self) -> Result<B, ProtocolError> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(ProtocolError::Version(self.version));
+        }
+        let (body, _) = B::try_read(self.body, self.message_type).map_err(|error| match error {
+            byte::Error::BadInput { err: "message type" } => ProtocolError::UnknownMessage(self.message_type),
+            byte::Error::BadInput { err } => ProtocolError::Body(err),
+            byte::Error::Incomplete | byte::Error::BadOffset(_) => ProtocolError::Body("length"),
+        })?;
+        Ok(body)
+    }
+}
+
+/// Decode the COBS bytes between two 0x00 delimiters and check the CRC, `frame` is decoded in place
+///
+/// Use this instead of [decode_message] to get the request ID of a request that has the wrong version
+/// or body, so the error can be sent back with it
+pub fn decode_frame(frame: &mut [u8]) -> Result<RawMessage<'_>, ProtocolError> {
+    let len = cobs_decode_in_place(frame).ok_or(ProtocolError::Cobs)?;
+    let message = &frame[..len];
+    if message.len() < HEADER_SIZE + CRC_SIZE {
+        return Err(ProtocolError::TooShort);
+    }
+    let (message, crc) = message.split_at(message.len() - CRC_SIZE);
+    if crc != message_crc(message).to_le_bytes() {
+        return Err(ProtocolError::Crc);
+    }
+    Ok(RawMessage {
+        version: message[0],
+        message_type: message[1],
+        request_id: u16::from_le_bytes([message[2], message[3]]),
+        body: &message[HEADER_SIZE..],
+    })
+}
+
+/// Decode the COBS bytes between two 0x00 delimiters, `frame` is decoded in place
+///
+/// returns: Result<(request ID, body), [ProtocolError]>
+pub fn decode_message<'a, B: MessageBody<'a>>(frame: &'a mut [u8]) -> Result<(u16, B), ProtocolError> {
+    let message = decode_frame(frame)?;
+    Ok((message.request_id, message.read_body()?))
+}
+
+fn message_crc(message: &[u8]) -> u16 {
+    let crc = &mut 0u16;
+    CRC16_KERMIT.init_crc(crc);
+    CRC16_KERMIT.update_crc(crc, message)
+}
+
+/// COBS encode `data` into `out`, without delimiters
+///
+/// returns: the number of bytes of `out` used, `None` if `out` is too small
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::protocol::cobs_encode;
+///
+/// let mut out = [0u8; 8];
+/// let len = cobs_encode(&[0x11, 0x22, 0x00, 0x33], &mut out).unwrap();
+/// assert_eq!(out[..len], [0x03, 0x11, 0x22, 0x02, 0x33]);
+/// ```
+pub fn cobs_encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut code_idx = 0;
+    let mut out_idx = 1;
+    let mut code = 1u8;
+    for byte in data {
+        if *byte != 0 {
+            *out.get_mut(out_idx)? = *byte;
+            out_idx += 1;
+            code += 1;
+        }
+        if *byte == 0 || code == 0xFF {
+            *out.get_mut(code_idx)? = code;
+            code_idx = out_idx;
+            out_idx += 1;
+            code = 1;
+        }
+    }
+    *out.get_mut(code_idx)? = code;
+    Some(out_idx)
+}
+
+/// Undo [cobs_encode] in place
+///
+/// returns: the length of the decoded data at the start of `bytes`, `None` if it isn't valid COBS
+pub fn cobs_decode_in_place(bytes: &mut [u8]) -> Option<usize> {
+    let mut read = 0;
+    let mut write = 0;
+    while read < bytes.len() {
+        let code = usize::from(bytes[read]);
+        let end = read + code;
+        if code == 0 || end > bytes.len() || bytes[read + 1..end].contains(&0) {
+            return None;
+        }
+        bytes.copy_within(read + 1..end, write);
+        write += code - 1;
+        read = end;
+        if code != 0xFF && read < bytes.len() {
+            bytes[write] = 0;
+            write += 1;
+        }
+    }
+    Some(write)
+}
@@ -0,0 +1,149 @@
+//! When the packets of a run start
+//!
+//! The firmware starts every packet at an absolute time of the RP2040 timer. [Schedule] gives those times
+//! as offsets from the start of the first packet, so the airtime of a packet and the time spent building
+//! the next one don't add up to a longer period. [PeriodStats] sums up the periods that were achieved.
+use crate::prng::SplitMix64;
+use core::f32::consts::LN_2;
+
+/// How the packets of a run are spread out around the interval of the run
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScheduleMode {
+    /// a packet every interval
+    #[default]
+    Fixed,
+    /// random gaps with the interval as their mean, the starts are a Poisson process like the packets of
+    /// many independent transmitters, the gaps only depend on the seed
+    Poisson { seed: u64 },
+    /// trains of `length` packets an interval apart, the next train starts `gap_ms` after the last packet
+    /// of the one before
+    Burst { length: u16, gap_ms: u32 },
+}
+
+impl ScheduleMode {
+    /// The longest gap between two packets a [Schedule] with this mode can have, for a host that waits
+    /// for the next packet
+    pub fn longest_gap_ms(&self, interval_ms: u32) -> u64 {
+        match *self {
+            ScheduleMode::Fixed => u64::from(interval_ms),
+            // the smallest uniform number is 2^-24 and -ln(2^-24) is 16.6
+            ScheduleMode::Poisson { .. } => 17 * u64::from(interval_ms),
+            ScheduleMode::Burst { gap_ms, .. } => u64::from(interval_ms.max(gap_ms)),
+        }
+    }
+}
+
+/// The start times of the packets of a run, in microseconds after the start of the first packet
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::schedule::{Schedule, ScheduleMode};
+///
+/// let burst = ScheduleMode::Burst { length: 3, gap_ms: 100 };
+/// let starts: Vec<u64> = Schedule::new(10, burst).take(5).collect();
+/// assert_eq!(starts, [0, 10_000, 20_000, 120_000, 130_000]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    interval_us: u64,
+    mode: ScheduleMode,
+    rng: SplitMix64,
+    /// packets whose start was returned
+    packets: u32,
+    next_start_us: u64,
+}
+
+impl Schedule {
+    pub fn new(interval_ms: u32, mode: ScheduleMode) -> Self {
+        let seed = match mode {
+            ScheduleMode::Poisson { seed } => seed,
+            _ => 0,
+        };
+        Schedule {
+            interval_us: u64::from(interval_ms) * 1000,
+            mode,
+            rng: SplitMix64::new(seed),
+            packets: 0,
+            next_start_us: 0,
+        }
+    }
+
+    /// The gap from the packet just returned to the next one
+    fn next_gap_us(&mut self) -> u64 {
+        match self.mode {
+            ScheduleMode::Fixed => self.interval_us,
+            ScheduleMode::Poisson { .. } => {
+                // 24 random bits, the most an f32 holds exactly, as a uniform number in (0, 1]
+                let uniform = ((self.rng.next_u64() >> 40) + 1) as f32 / (1u32 << 24) as f32;
+                (-ln(uniform) * self.interval_us as f32) as u64
+            }
+            ScheduleMode::Burst { length, gap_ms } => {
+                if self.packets.is_multiple_of(u32::from(length.max(1))) {
+                    u64::from(gap_ms) * 1000
+                } else {
+                    self.interval_us
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Schedule {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start_us = self.next_start_us;
+        self.packets = self.packets.wrapping_add(1);
+        self.next_start_us = start_us.saturating_add(self.next_gap_us());
+        Some(start_us)
+    }
+}
+
+/// Natural log of `x` > 0
+///
+/// libm isn't available, the exponent is split off and the log of the mantissa in [1, 2) comes from
+/// the series of 2·atanh((m - 1) / (m + 1)), which is good to about 1e-5 there
+fn ln(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    let mantissa = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    let s = (mantissa - 1.0) / (mantissa + 1.0);
+    let s2 = s * s;
+    exponent as f32 * LN_2 + 2.0 * s * (1.0 + s2 * (1.0 / 3.0 + s2 * (1.0 / 5.0 + s2 / 7.0)))
+}
+
+/// The periods between the starts of consecutive packets of a run
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PeriodStats {
+    /// periods measured, one less than the packets sent
+    pub count: u32,
+    pub min_us: u64,
+    pub max_us: u64,
+    pub total_us: u64,
+    /// packets that started after their scheduled time because the packet before, and building this
+    /// one, took longer than the gap between them
+    pub late: u32,
+}
+
+impl PeriodStats {
+    /// Add the period from the start of the last packet to the start of this one
+    pub fn record(&mut self, period_us: u64, late: bool) {
+        if self.count == 0 {
+            self.min_us = period_us;
+            self.max_us = period_us;
+        }
+        self.count += 1;
+        self.min_us = self.min_us.min(period_us);
+        self.max_us = self.max_us.max(period_us);
+        self.total_us += period_us;
+        self.late += u32::from(late);
+    }
+
+    /// The mean period, `None` before the second packet
+    pub fn mean_us(&self) -> Option<u64> {
+        self.total_us.checked_div(u64::from(self.count))
+    }
+}
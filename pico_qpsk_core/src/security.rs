@@ -0,0 +1,298 @@
+//! 802.15.4 frame security, the auxiliary security header and CCM* (802.15.4-2011 7.4 and annex B)
+//!
+//! Only the 2006/2011 style header is written, the 2015 frame counter suppression and ASN in nonce bits
+//! are always 0
+use crate::aes::{Aes128, Block, BLOCK_SIZE};
+use byte::{BytesExt, LE};
+use heapless::Vec;
+pub use ieee802154::mac::frame::security::{KeyIdentifier, KeySource, SecurityLevel};
+use ieee802154::mac::ExtendedAddress;
+
+/// Length of the CCM* nonce, `[SOURCE ADDRESS][FRAME COUNTER][SECURITY LEVEL]`
+pub const NONCE_SIZE: usize = 13;
+
+/// The CCM* nonce
+pub type Nonce = [u8; NONCE_SIZE];
+
+/// The longest MIC, [SecurityLevel::MIC128] and [SecurityLevel::ENCMIC128]
+pub const MAX_MIC_SIZE: usize = 16;
+
+/// Message integrity code, empty for [SecurityLevel::None] and [SecurityLevel::ENC]
+pub type Mic = Vec<u8, MAX_MIC_SIZE>;
+
+/// A type to hold the possible errors that occur when a secured payload is checked
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SecurityError {
+    /// the MIC is not as long as the security level needs
+    MicLength,
+    /// the MIC does not match the data, the wrong key or nonce was used or the frame was changed
+    MicMismatch,
+}
+
+/// Everything needed to secure a frame
+///
+/// The key identifier decides the key identifier mode:
+/// no identifier is mode 0 (implicit), no key source is mode 1,
+/// a [KeySource::Short] is mode 2 and a [KeySource::Long] is mode 3
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSecurity {
+    pub level: SecurityLevel,
+    pub key_identifier: Option<KeyIdentifier>,
+    pub frame_counter: u32,
+    pub key: [u8; 16],
+    /// extended address of the sender, it is used in the nonce even if the frame has a short source address
+    pub source: ExtendedAddress,
+}
+
+impl FrameSecurity {
+    /// Length of the auxiliary security header, 5, 6, 10 or 14 bytes
+    pub fn auxiliary_header_len(&self) -> usize {
+        // security control + frame counter
+        1 + 4
+            + match self.key_identifier {
+                None => 0,
+                Some(KeyIdentifier { key_source: None, .. }) => 1,
+                Some(KeyIdentifier {
+                    key_source: Some(KeySource::Short(_)),
+                    ..
+                }) => 5,
+                Some(KeyIdentifier {
+                    key_source: Some(KeySource::Long(_)),
+                    ..
+                }) => 9,
+            }
+    }
+
+    pub fn mic_len(&self) -> usize {
+        self.level.get_mic_octet_size()
+    }
+
+    pub fn nonce(&self) -> Nonce {
+        ccm_star_nonce(self.source, self.frame_counter, self.level)
+    }
+
+    /// Write the auxiliary security header
+    ///
+    /// ```text
+    /// [SECURITY CONTROL][FRAME COUNTER][KEY SOURCE][KEY INDEX]
+    /// ```
+    pub fn write_auxiliary_header(&self, bytes: &mut [u8], offset: &mut usize) -> byte::Result<()> {
+        let key_identifier_mode: u8 = match self.key_identifier {
+            None => 0b00,
+            Some(KeyIdentifier { key_source: None, .. }) => 0b01,
+            Some(KeyIdentifier {
+                key_source: Some(KeySource::Short(_)),
+                ..
+            }) => 0b10,
+            Some(KeyIdentifier {
+                key_source: Some(KeySource::Long(_)),
+                ..
+            }) => 0b11,
+        };
+        bytes.write(offset, self.level as u8 | key_identifier_mode << 3)?;
+        bytes.write_with(offset, self.frame_counter, LE)?;
+        if let Some(key_identifier) = self.key_identifier {
+            match key_identifier.key_source {
+                Some(KeySource::Short(source)) => bytes.write_with(offset, source, LE)?,
+                Some(KeySource::Long(source)) => bytes.write_with(offset, source, LE)?,
+                None => (),
+            }
+            bytes.write(offset, key_identifier.key_index)?;
+        }
+        Ok(())
+    }
+}
+
+/// Build the CCM* nonce, all fields are big endian
+///
+/// # Arguments
+///
+/// * `source`: extended address of the sender
+/// * `frame_counter`: the frame counter from the auxiliary security header
+/// * `level`: the security level from the auxiliary security header
+///
+/// returns: [Nonce]
+pub fn ccm_star_nonce(source: ExtendedAddress, frame_counter: u32, level: SecurityLevel) -> Nonce {
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce[0..8].copy_from_slice(&source.0.to_be_bytes());
+    nonce[8..12].copy_from_slice(&frame_counter.to_be_bytes());
+    nonce[12] = level as u8;
+    nonce
+}
+
+/// CBC-MAC that takes its input a few bytes at a time
+struct CbcMac<'a> {
+    aes: &'a Aes128,
+    state: Block,
+    filled: usize,
+}
+
+impl<'a> CbcMac<'a> {
+    fn new(aes: &'a Aes128, b0: &Block) -> Self {
+        let mut state = *b0;
+        aes.encrypt_block(&mut state);
+        CbcMac {
+            aes,
+            state,
+            filled: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.state[self.filled] ^= byte;
+            self.filled += 1;
+            if self.filled == BLOCK_SIZE {
+                self.aes.encrypt_block(&mut self.state);
+                self.filled = 0;
+            }
+        }
+    }
+
+    /// zero pad to the end of the block
+    fn pad(&mut self) {
+        if self.filled != 0 {
+            self.aes.encrypt_block(&mut self.state);
+            self.filled = 0;
+        }
+    }
+}
+
+/// Block `i` of the CTR key stream, `A_i` encrypted
+fn key_stream_block(aes: &Aes128, nonce: &Nonce, counter: u16) -> Block {
+    let mut block = [0u8; BLOCK_SIZE];
+    // L - 1, the length field is 2 bytes
+    block[0] = 0x01;
+    block[1..=NONCE_SIZE].copy_from_slice(nonce);
+    block[14..16].copy_from_slice(&counter.to_be_bytes());
+    aes.encrypt_block(&mut block);
+    block
+}
+
+/// xor the data with the key stream, starting at `A_1`
+fn apply_key_stream(aes: &Aes128, nonce: &Nonce, data: &mut [u8]) {
+    for (counter, chunk) in (1..=u16::MAX).zip(data.chunks_mut(BLOCK_SIZE)) {
+        let stream = key_stream_block(aes, nonce, counter);
+        chunk.iter_mut().zip(stream).for_each(|(b, s)| *b ^= s);
+    }
+}
+
+/// The unencrypted MIC `T`, the CBC-MAC of `B_0`, the length of `a`, `a` and the plaintext `m`
+fn authentication_tag(aes: &Aes128, nonce: &Nonce, mic_len: usize, a: &[u8], m: &[u8]) -> Mic {
+    let mut b0 = [0u8; BLOCK_SIZE];
+    // Adata, (M - 2) / 2 and L - 1
+    b0[0] = (u8::from(!a.is_empty()) << 6) | ((((mic_len as u8).saturating_sub(2)) / 2) << 3) | 0x01;
+    b0[1..=NONCE_SIZE].copy_from_slice(nonce);
+    b0[14..16].copy_from_slice(&(m.len() as u16).to_be_bytes());
+
+    let mut mac = CbcMac::new(aes, &b0);
+    if !a.is_empty() {
+        if a.len() < 0xFF00 {
+            mac.update(&(a.len() as u16).to_be_bytes());
+        } else {
+            mac.update(&[0xFF, 0xFE]);
+            mac.update(&(a.len() as u32).to_be_bytes());
+        }
+        mac.update(a);
+        mac.pad();
+    }
+    mac.update(m);
+    mac.pad();
+
+    Vec::from_slice(&mac.state[..mic_len]).expect("mic_len is never more than MAX_MIC_SIZE")
+}
+
+/// CCM* forward transformation, encrypts `m` in place (for the ENC levels) and returns the encrypted MIC
+///
+/// # Arguments
+///
+/// * `key`: the 128 bit key
+/// * `nonce`: see [ccm_star_nonce]
+/// * `level`: decides the MIC length and if `m` is encrypted
+/// * `a`: the data that is only authenticated, the MAC header, auxiliary security header and any open fields
+/// * `m`: the data that is authenticated and encrypted, for MIC only levels this should be empty and
+///   everything should be in `a`
+///
+/// returns: [Mic], append it after the (encrypted) payload
+///
+/// # Examples
+///
+/// ```
+/// use ieee802154::mac::ExtendedAddress;
+/// use pico_qpsk_core::security::{ccm_star_encrypt, ccm_star_nonce, SecurityLevel};
+///
+/// let key = [0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xCB, 0xCC, 0xCD, 0xCE, 0xCF];
+/// let nonce = ccm_star_nonce(ExtendedAddress(0xACDE_4800_0000_0001), 5, SecurityLevel::ENC);
+/// let mut m = [0x61, 0x62, 0x63, 0x64];
+/// let a = [
+///     0x69, 0xDC, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xDE, 0xAC, 0x01, 0x00, 0x00,
+///     0x00, 0x00, 0x48, 0xDE, 0xAC, 0x04, 0x05, 0x00, 0x00, 0x00,
+/// ];
+/// let mic = ccm_star_encrypt(&key, &nonce, SecurityLevel::ENC, &a, &mut m);
+/// // 802.15.4-2011 C.2.2
+/// assert_eq!(m, [0xD4, 0x3E, 0x02, 0x2B]);
+/// assert!(mic.is_empty());
+/// ```
+pub fn ccm_star_encrypt(key: &[u8; 16], nonce: &Nonce, level: SecurityLevel, a: &[u8], m: &mut [u8]) -> Mic {
+    let aes = Aes128::new(key);
+    let mic_len = level.get_mic_octet_size();
+    let mut mic = authentication_tag(&aes, nonce, mic_len, a, m);
+    if is_encrypted(level) {
+        apply_key_stream(&aes, nonce, m);
+    }
+    let s0 = key_stream_block(&aes, nonce, 0);
+    mic.iter_mut().zip(s0).for_each(|(t, s)| *t ^= s);
+    mic
+}
+
+/// CCM* inverse transformation, decrypts `c` in place (for the ENC levels) and checks the MIC
+///
+/// # Arguments
+///
+/// * `key`: the 128 bit key
+/// * `nonce`: see [ccm_star_nonce]
+/// * `level`: decides the MIC length and if `c` is decrypted
+/// * `a`: the data that is only authenticated
+/// * `c`: the encrypted data, it is the plaintext after the call even when the MIC doesn't match
+/// * `mic`: the encrypted MIC from the end of the frame
+///
+/// returns: Result<(), [SecurityError]>
+pub fn ccm_star_decrypt(
+    key: &[u8; 16],
+    nonce: &Nonce,
+    level: SecurityLevel,
+    a: &[u8],
+    c: &mut [u8],
+    mic: &[u8],
+) -> Result<(), SecurityError> {
+    let mic_len = level.get_mic_octet_size();
+    if mic.len() != mic_len {
+        return Err(SecurityError::MicLength);
+    }
+    let aes = Aes128::new(key);
+    if is_encrypted(level) {
+        apply_key_stream(&aes, nonce, c);
+    }
+    let s0 = key_stream_block(&aes, nonce, 0);
+    let expected = authentication_tag(&aes, nonce, mic_len, a, c);
+    // compare every byte so the time taken doesn't depend on where the first difference is
+    let difference = expected
+        .iter()
+        .zip(s0)
+        .zip(mic)
+        .fold(0u8, |difference, ((t, s), u)| difference | (t ^ s ^ u));
+    if difference == 0 {
+        Ok(())
+    } else {
+        Err(SecurityError::MicMismatch)
+    }
+}
+
+/// The ENC levels encrypt the private payload fields
+pub fn is_encrypted(level: SecurityLevel) -> bool {
+    matches!(
+        level,
+        SecurityLevel::ENC | SecurityLevel::ENCMIC32 | SecurityLevel::ENCMIC64 | SecurityLevel::ENCMIC128
+    )
+}
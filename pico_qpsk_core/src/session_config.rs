@@ -0,0 +1,266 @@
+//! The settings the firmware's serial commands change at runtime
+use crate::keying::KeyingConfig;
+use crate::packet::{FrameConstructionError, PhysicalFrame, PhysicalFrameBuilder};
+use crate::pio_bytecode_gen::Sideband;
+use crate::schedule::ScheduleMode;
+use crate::sub_ghz::SubGhzPhy;
+use crate::transmit_option::{Phy, StandardTransmitOption, TransmitOption};
+use heapless::Vec;
+use ieee802154::mac::FrameVersion;
+pub use ieee802154::mac::{Address, ExtendedAddress, PanId, ShortAddress};
+
+/// How the sequence number changes from packet to packet
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SequenceNumberMode {
+    /// every packet has the same sequence number
+    Fixed(u8),
+    /// start at `start` and add 1 every packet, 255 rolls over to 0
+    Increment { start: u8 },
+    /// count from `start` to `end`, both included, then go back to `start`
+    ///
+    /// `end` can be below `start`, the count then rolls over from 255 to 0 on the way
+    Wrap { start: u8, end: u8 },
+}
+
+impl SequenceNumberMode {
+    /// The sequence number of packet `packet_index`, the first packet is 0
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::session_config::SequenceNumberMode;
+    ///
+    /// let wrap = SequenceNumberMode::Wrap { start: 3, end: 5 };
+    /// let sequence: Vec<u8> = (0..5).map(|idx| wrap.sequence_number(idx)).collect();
+    /// assert_eq!(sequence, [3, 4, 5, 3, 4]);
+    /// ```
+    pub fn sequence_number(&self, packet_index: u32) -> u8 {
+        match *self {
+            SequenceNumberMode::Fixed(sequence_num) => sequence_num,
+            SequenceNumberMode::Increment { start } => start.wrapping_add(packet_index as u8),
+            SequenceNumberMode::Wrap { start, end } => {
+                let span = u32::from(end.wrapping_sub(start)) + 1;
+                start.wrapping_add((packet_index % span) as u8)
+            }
+        }
+    }
+
+    /// true when every packet gets the same sequence number, so a frame can be reused
+    pub fn is_fixed(&self) -> bool {
+        match *self {
+            SequenceNumberMode::Fixed(_) => true,
+            SequenceNumberMode::Increment { .. } => false,
+            SequenceNumberMode::Wrap { start, end } => start == end,
+        }
+    }
+}
+
+/// The MAC header fields of the frames the firmware builds around a payload
+///
+/// The default is the frame the firmware always sent before these could be changed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameConfig {
+    /// `None` leaves the address (and its PAN ID) out of the frame
+    pub source: Option<Address>,
+    /// `None` leaves the address (and its PAN ID) out of the frame
+    pub destination: Option<Address>,
+    pub sequence_number: SequenceNumberMode,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        FrameConfig {
+            source: Some(Address::Short(PanId(0x4444), ShortAddress(0xABCD))),
+            destination: Some(Address::Short(PanId(0x2222), ShortAddress(0x1234))),
+            sequence_number: SequenceNumberMode::Fixed(1),
+        }
+    }
+}
+
+impl FrameConfig {
+    /// Build the PHY bytes of packet `packet_index` (the first packet is 0), a 2006 data frame
+    ///
+    /// # Arguments
+    ///
+    /// * `packet_index`: picks the sequence number, see [SequenceNumberMode::sequence_number]
+    /// * `payload`: the MAC payload
+    ///
+    /// returns: Result<Vec<u8, { MAX_FRAME_SIZE }>, [FrameConstructionError]>
+    pub fn frame_bytes<const MAX_FRAME_SIZE: usize>(
+        &self,
+        packet_index: u32,
+        payload: &[u8],
+    ) -> Result<Vec<u8, MAX_FRAME_SIZE>, FrameConstructionError> {
+        let frame: PhysicalFrame<MAX_FRAME_SIZE> = PhysicalFrameBuilder::new()
+            .version(FrameVersion::Ieee802154_2006)
+            .source(self.source)
+            .destination(self.destination)
+            .sequence_number(self.sequence_number.sequence_number(packet_index))
+            .payload(payload)
+            .build()?;
+        frame.to_bytes()
+    }
+}
+
+/// What goes in the payload of the packets the firmware's `send` command sends
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PayloadMode {
+    /// the same payload every packet, see [get_seq_payload](crate::frame_gen::get_seq_payload)
+    Sequential { length: u16 },
+    /// a new payload every packet, see [random_payloads](crate::frame_gen::random_payloads)
+    Random { length: u16, seed: u64 },
+}
+
+/// The settings `send` uses so a run doesn't need its arguments typed in every time
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SendDefaults {
+    pub interval_ms: u32,
+    pub number_packets: u32,
+    pub payload: PayloadMode,
+    /// how the packets are spread around `interval_ms`
+    pub schedule: ScheduleMode,
+}
+
+impl Default for SendDefaults {
+    fn default() -> Self {
+        SendDefaults {
+            interval_ms: 1000,
+            number_packets: 10,
+            payload: PayloadMode::Sequential { length: 4 },
+            schedule: ScheduleMode::Fixed,
+        }
+    }
+}
+
+/// GPIOs of the RP2040, GPIO0 to GPIO29
+pub const GPIO_COUNT: u8 = 30;
+/// State machines besides SM0 that can drive an antenna pin of their own
+pub const EXTRA_OUTPUTS: usize = 3;
+
+/// A type to hold the possible errors of a [PinConfig]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PinError {
+    /// there is no such GPIO, see [GPIO_COUNT]
+    OutOfRange(u8),
+    /// the GPIO has two jobs
+    UsedTwice(u8),
+}
+
+/// Which GPIOs the PIO uses, the board layout
+///
+/// The default is the layout the firmware always had: the trigger on GPIO3, the antenna (and I) pin on
+/// GPIO6, the Q pin on GPIO7 and the extra outputs on GPIO8 to GPIO10. They only change at start up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PinConfig {
+    /// every state machine waits for this pin to go low before it starts a packet
+    pub trigger: u8,
+    /// another device pulls `trigger` low, the firmware only pulls it up. Otherwise the firmware drives it.
+    pub external_trigger: bool,
+    /// the antenna pin of the single pin program and the I pin of the two pin one
+    pub antenna: u8,
+    /// the pin after `antenna` is the Q pin, the sidebands need it
+    pub quadrature: bool,
+    /// the antenna pins of the extra outputs on SM1 to SM3, `None` leaves the state machine unused
+    pub extra: [Option<u8>; EXTRA_OUTPUTS],
+}
+
+impl Default for PinConfig {
+    fn default() -> Self {
+        PinConfig {
+            trigger: 3,
+            external_trigger: false,
+            antenna: 6,
+            quadrature: true,
+            extra: [Some(8), Some(9), Some(10)],
+        }
+    }
+}
+
+impl PinConfig {
+    /// The Q pin, `None` without [PinConfig::quadrature]
+    pub fn q_pin(&self) -> Option<u8> {
+        self.quadrature.then_some(self.antenna.saturating_add(1))
+    }
+
+    /// Every pin that is used, the trigger first
+    pub fn used(&self) -> impl Iterator<Item = u8> {
+        [Some(self.trigger), Some(self.antenna), self.q_pin()]
+            .into_iter()
+            .chain(self.extra)
+            .flatten()
+    }
+
+    /// Check that every pin exists and has one job
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::session_config::{PinConfig, PinError};
+    ///
+    /// assert_eq!(PinConfig::default().validate(), Ok(()));
+    /// // GPIO7 is the Q pin of the default layout
+    /// let pins = PinConfig { trigger: 7, ..PinConfig::default() };
+    /// assert_eq!(pins.validate(), Err(PinError::UsedTwice(7)));
+    /// ```
+    pub fn validate(&self) -> Result<(), PinError> {
+        let mut used = 0u32;
+        for pin in self.used() {
+            if pin >= GPIO_COUNT {
+                return Err(PinError::OutOfRange(pin));
+            }
+            if used & (1 << pin) != 0 {
+                return Err(PinError::UsedTwice(pin));
+            }
+            used |= 1 << pin;
+        }
+        Ok(())
+    }
+}
+
+/// Everything a serial session can change
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SessionConfig {
+    pub transmit_option: TransmitOption,
+    /// `None` toggles the one antenna pin and makes both sidebands, a sideband drives the I and Q pins,
+    /// see [TransmitOption::convert_sideband]
+    pub sideband: Option<Sideband>,
+    /// `None` sends the 2.4GHz O-QPSK PHY, a sub-GHz PHY is always on the antenna pin alone whatever the
+    /// sideband
+    pub sub_ghz: Option<SubGhzPhy>,
+    /// OOK or 2-FSK instead of an 802.15.4 PHY, it goes before [SessionConfig::sub_ghz]
+    pub keying: Option<KeyingConfig>,
+    pub frame: FrameConfig,
+    pub send: SendDefaults,
+    /// the board layout, it is used from the next start up on
+    pub pins: PinConfig,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            transmit_option: TransmitOption::Standard(StandardTransmitOption::Clk128MHzOffset8MHz),
+            sideband: None,
+            sub_ghz: None,
+            keying: None,
+            frame: FrameConfig::default(),
+            send: SendDefaults::default(),
+            pins: PinConfig::default(),
+        }
+    }
+}
+
+impl SessionConfig {
+    /// What the frames of the session are sent as
+    pub fn phy(&self) -> Phy {
+        match (self.keying, self.sub_ghz) {
+            (Some(keying), _) => Phy::Keyed(keying),
+            (None, Some(sub_ghz)) => Phy::SubGhz(sub_ghz),
+            (None, None) => Phy::OQpsk(self.sideband),
+        }
+    }
+}
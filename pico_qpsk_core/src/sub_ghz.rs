@@ -0,0 +1,492 @@
+//! The 868MHz and 915MHz PHYs of 802.15.4-2011, BPSK and the sub-GHz O-QPSK
+//!
+//! The frames are the same bytes as the 2.4GHz ones, four 0x00 octets of preamble, the 0xA7 SFD and the
+//! length, so [to_max_frame_size](crate::to_max_frame_size) and the frame builders work for every PHY.
+//! What changes is how the bits become chips and how long a chip lasts:
+//!
+//! - BPSK differentially encodes the bits, least significant bit first, and spreads each one to the 15
+//!   chips of [BPSK_CHIPS], the chips flip the subcarrier by 180°
+//! - the sub-GHz O-QPSK spreads every 4 bits to 16 chips of [SUB_GHZ_CHIP_ARRAY] instead of 32 and
+//!   offsets the Q chips by one chip like the 2.4GHz PHY
+//!
+//! The square wave has no pulse shaping, like the 2.4GHz O-QPSK it relies on the receiver's filter.
+//! The subcarrier comes from the [TransmitOption](crate::transmit_option::TransmitOption)'s quarter timing,
+//! a chip lasts as many quarters of the subcarrier as fit in it at the PHY's chip rate, the fraction
+//! left over is carried to the next chip so the chips don't drift over a frame.
+use crate::fsk::{LsbFirst, RunWords};
+use crate::pio_bytecode_decode::{pio_words_to_square_wave, DecodeError, DecodedFrame};
+use crate::pio_bytecode_gen::{ChipQuarterCount, QuarterRuns, QUARTER_LEVELS};
+use heapless::Vec;
+
+/// The chips of a 0 bit, `c0` first, a 1 bit is the inverse
+pub const BPSK_CHIPS: [u8; 15] = [1, 1, 1, 1, 0, 1, 0, 1, 1, 0, 0, 1, 0, 0, 0];
+
+/// The chips of each 4 bit symbol of the sub-GHz O-QPSK PHY as 2 bit (I, Q) chips like
+/// [CHIP_ARRAY](crate::pio_bytecode_gen), `c0` is the I bit of the first one
+pub const SUB_GHZ_CHIP_ARRAY: [[u8; 8]; 16] = [
+    [0b00, 0b11, 0b11, 0b10, 0b00, 0b10, 0b01, 0b01],
+    [0b01, 0b00, 0b11, 0b11, 0b10, 0b00, 0b10, 0b01],
+    [0b01, 0b01, 0b00, 0b11, 0b11, 0b10, 0b00, 0b10],
+    [0b10, 0b01, 0b01, 0b00, 0b11, 0b11, 0b10, 0b00],
+    [0b00, 0b10, 0b01, 0b01, 0b00, 0b11, 0b11, 0b10],
+    [0b10, 0b00, 0b10, 0b01, 0b01, 0b00, 0b11, 0b11],
+    [0b11, 0b10, 0b00, 0b10, 0b01, 0b01, 0b00, 0b11],
+    [0b11, 0b11, 0b10, 0b00, 0b10, 0b01, 0b01, 0b00],
+    [0b01, 0b10, 0b10, 0b11, 0b01, 0b11, 0b00, 0b00],
+    [0b00, 0b01, 0b10, 0b10, 0b11, 0b01, 0b11, 0b00],
+    [0b00, 0b00, 0b01, 0b10, 0b10, 0b11, 0b01, 0b11],
+    [0b11, 0b00, 0b00, 0b01, 0b10, 0b10, 0b11, 0b01],
+    [0b01, 0b11, 0b00, 0b00, 0b01, 0b10, 0b10, 0b11],
+    [0b11, 0b01, 0b11, 0b00, 0b00, 0b01, 0b10, 0b10],
+    [0b10, 0b11, 0b01, 0b11, 0b00, 0b00, 0b01, 0b10],
+    [0b10, 0b10, 0b11, 0b01, 0b11, 0b00, 0b00, 0b01],
+];
+
+/// The chips per second of the 2.4GHz PHY, [crate::pio_bytecode_gen] splits each of its chips, the O-QPSK
+/// middle chips included, into `quarters_per_chip` quarters of the subcarrier
+const CHIP_RATE_2450MHZ: u32 = 2_000_000;
+
+/// A sub-GHz PHY of 802.15.4-2011
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SubGhzPhy {
+    /// 20kb/s BPSK at 300kchip/s
+    Bpsk868MHz,
+    /// 40kb/s BPSK at 600kchip/s
+    Bpsk915MHz,
+    /// 100kb/s O-QPSK at 400kchip/s
+    OQpsk868MHz,
+    /// 250kb/s O-QPSK at 1000kchip/s
+    OQpsk915MHz,
+}
+
+impl SubGhzPhy {
+    /// every PHY, in the order they are declared
+    pub const ALL: [SubGhzPhy; 4] = [
+        SubGhzPhy::Bpsk868MHz,
+        SubGhzPhy::Bpsk915MHz,
+        SubGhzPhy::OQpsk868MHz,
+        SubGhzPhy::OQpsk915MHz,
+    ];
+
+    pub fn chip_rate(&self) -> u32 {
+        match self {
+            SubGhzPhy::Bpsk868MHz => 300_000,
+            SubGhzPhy::Bpsk915MHz => 600_000,
+            SubGhzPhy::OQpsk868MHz => 400_000,
+            SubGhzPhy::OQpsk915MHz => 1_000_000,
+        }
+    }
+
+    pub fn bit_rate(&self) -> u32 {
+        match self {
+            SubGhzPhy::Bpsk868MHz => 20_000,
+            SubGhzPhy::Bpsk915MHz => 40_000,
+            SubGhzPhy::OQpsk868MHz => 100_000,
+            SubGhzPhy::OQpsk915MHz => 250_000,
+        }
+    }
+
+    pub fn is_bpsk(&self) -> bool {
+        matches!(self, SubGhzPhy::Bpsk868MHz | SubGhzPhy::Bpsk915MHz)
+    }
+
+    /// How long `frame_len` PHY bytes, preamble to FCS, take on air
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::sub_ghz::SubGhzPhy;
+    ///
+    /// // the 6 bytes of the preamble, SFD and length and a 2 byte MAC frame
+    /// assert_eq!(SubGhzPhy::Bpsk868MHz.airtime_us(8), 3200);
+    /// assert_eq!(SubGhzPhy::OQpsk915MHz.airtime_us(8), 256);
+    /// ```
+    pub fn airtime_us(&self, frame_len: usize) -> u32 {
+        (frame_len as u64 * 8 * 1_000_000 / u64::from(self.bit_rate())) as u32
+    }
+}
+
+/// Whole quarters of the subcarrier for each chip, the remainder is carried to the next chip
+#[derive(Clone)]
+struct ChipQuarters {
+    /// quarters per chip times the chip rate
+    numerator: u32,
+    chip_rate: u32,
+    remainder: u32,
+}
+
+impl ChipQuarters {
+    fn new(phy: SubGhzPhy, quarters_per_chip: u8) -> Self {
+        ChipQuarters {
+            numerator: CHIP_RATE_2450MHZ * u32::from(quarters_per_chip),
+            chip_rate: phy.chip_rate(),
+            remainder: 0,
+        }
+    }
+}
+
+impl ChipQuarterCount for ChipQuarters {
+    fn next_chip(&mut self) -> u32 {
+        let total = self.remainder + self.numerator;
+        self.remainder = total % self.chip_rate;
+        total / self.chip_rate
+    }
+}
+
+/// The BPSK chips of bytes as 2 bit chips, `0b00` for a 0 chip and `0b11` for a 1 so the subcarrier is
+/// the I and Q of [QUARTER_LEVELS] at once
+#[derive(Clone)]
+pub struct BpskChips<'a> {
+    bits: LsbFirst<'a>,
+    /// the last differentially encoded bit, the one before the first bit is 0
+    encoded: bool,
+    /// the next chip of `encoded`, 15 when the next bit is due
+    chip: usize,
+}
+
+impl Iterator for BpskChips<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.chip == BPSK_CHIPS.len() {
+            self.encoded ^= self.bits.next()?;
+            self.chip = 0;
+        }
+        let chip = BPSK_CHIPS[self.chip] ^ u8::from(self.encoded);
+        self.chip += 1;
+        Some(chip * 0b11)
+    }
+}
+
+/// The sub-GHz O-QPSK chips of bytes with the middle chips, the same as `add_middle_bits_for_o_qpsk`
+/// does for the 2.4GHz chips
+#[derive(Clone)]
+pub struct OQpskChips<'a> {
+    bytes: &'a [u8],
+    /// the symbol being spread, the low half of a byte goes first
+    nibble: usize,
+    /// the next chip of the symbol
+    chip: usize,
+    prev: u8,
+    /// the chip after a middle chip
+    pending: Option<u8>,
+    started: bool,
+}
+
+impl Iterator for OQpskChips<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if let Some(chip) = self.pending.take() {
+            return Some(chip);
+        }
+        let byte = self.bytes.get(self.nibble / 2)?;
+        let symbol = if self.nibble.is_multiple_of(2) {
+            byte & 0x0F
+        } else {
+            byte >> 4
+        };
+        let current = SUB_GHZ_CHIP_ARRAY[usize::from(symbol)][self.chip];
+        self.chip += 1;
+        if self.chip == SUB_GHZ_CHIP_ARRAY[0].len() {
+            self.chip = 0;
+            self.nibble += 1;
+        }
+        // middle will have q from previous, i from next
+        let middle = (self.prev & 0b01) | (current & 0b10);
+        self.prev = current;
+        // the first middle chip is left out like for the 2.4GHz chips
+        if !self.started {
+            self.started = true;
+            return Some(current);
+        }
+        self.pending = Some(current);
+        Some(middle)
+    }
+}
+
+/// The chips of a [SubGhzPhy], every one lasts one chip period
+#[derive(Clone)]
+pub enum SubGhzChips<'a> {
+    Bpsk(BpskChips<'a>),
+    OQpsk(OQpskChips<'a>),
+}
+
+impl<'a> SubGhzChips<'a> {
+    pub fn new(bytes: &'a [u8], phy: SubGhzPhy) -> Self {
+        if phy.is_bpsk() {
+            SubGhzChips::Bpsk(BpskChips {
+                bits: LsbFirst::new(bytes),
+                encoded: false,
+                chip: BPSK_CHIPS.len(),
+            })
+        } else {
+            SubGhzChips::OQpsk(OQpskChips {
+                bytes,
+                nibble: 0,
+                chip: 0,
+                prev: 0,
+                pending: None,
+                started: false,
+            })
+        }
+    }
+}
+
+impl Iterator for SubGhzChips<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        match self {
+            SubGhzChips::Bpsk(chips) => chips.next(),
+            SubGhzChips::OQpsk(chips) => chips.next(),
+        }
+    }
+}
+
+/// The run lengths, in state machine cycles, of 2 bit chips that each last a chip period
+///
+/// The quarters are walked like in [convert_quarters](crate::pio_bytecode_gen::convert_quarters), a chip
+/// lasts the whole quarters the chip rate gives it instead. The last run of the frame is written too.
+#[derive(Clone)]
+pub struct ChipRuns<I>(QuarterRuns<I, ChipQuarters>);
+
+impl<I: Iterator<Item = u8>> Iterator for ChipRuns<I> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let (_, len) = self.0.next_run().or_else(|| self.0.take_last())?;
+        Some(len.max(4))
+    }
+}
+
+/// The pio bytecode of a sub-GHz frame for the single pin program
+pub type SubGhzConvertIter<'a> = RunWords<ChipRuns<SubGhzChips<'a>>>;
+
+/// Translate PHY bytes to pio bytecode for `phy`
+///
+/// # Arguments
+///
+/// * `s`: the bytes to translate, preamble to FCS
+/// * `phy`: picks the chips and the chip rate
+/// * `quarter_cycles`: even and at least 4, the PIO program's shortest run
+/// * `quarters_per_chip`: the quarters in 0.5us, the subcarrier is `quarters_per_chip` x 0.5MHz, see
+///   [TransmitOption::quarter_timing](crate::transmit_option::TransmitOption::quarter_timing)
+///
+/// returns: [SubGhzConvertIter]
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::sub_ghz::{convert_sub_ghz, decode_sub_ghz_bytecode, SubGhzPhy};
+///
+/// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+/// let words: Vec<u32> = convert_sub_ghz(&frame, SubGhzPhy::Bpsk915MHz, 4, 16).collect();
+/// let decoded = decode_sub_ghz_bytecode::<32>(&words, SubGhzPhy::Bpsk915MHz, 4, 16).unwrap();
+/// assert_eq!(decoded.bytes, frame);
+/// ```
+pub fn convert_sub_ghz(
+    s: &[u8],
+    phy: SubGhzPhy,
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+) -> SubGhzConvertIter<'_> {
+    assert!(
+        quarter_cycles >= 4 && quarter_cycles.is_multiple_of(2),
+        "a quarter must be an even number of cycles, at least 4"
+    );
+    assert!(quarters_per_chip > 0, "a chip is at least one quarter");
+    RunWords::new(ChipRuns(QuarterRuns::new(
+        SubGhzChips::new(s, phy),
+        ChipQuarters::new(phy, quarters_per_chip),
+        quarter_cycles,
+        None,
+    )))
+}
+
+/// Reads a square wave from [convert_sub_ghz] one chip at a time
+#[derive(Clone)]
+struct ChipReader<I> {
+    samples: I,
+    quarters: ChipQuarters,
+    quarter_cycles: usize,
+    /// the quarter of the subcarrier period
+    phase: usize,
+}
+
+impl<I: Iterator<Item = bool>> Iterator for ChipReader<I> {
+    /// mismatched cycles against each 2 bit chip
+    type Item = [u32; 4];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut costs = [0u32; 4];
+        let mut read = 0;
+        let quarters = self.quarters.next_chip() as usize;
+        'quarters: for _ in 0..quarters {
+            let quarter = self.phase;
+            self.phase = (self.phase + 1) % 4;
+            for _ in 0..self.quarter_cycles {
+                let Some(high) = self.samples.next() else {
+                    break 'quarters;
+                };
+                for (cost, levels) in costs.iter_mut().zip(QUARTER_LEVELS) {
+                    *cost += u32::from(levels[quarter] != high);
+                }
+                read += 1;
+            }
+        }
+        if read * 2 < self.quarter_cycles * quarters {
+            return None;
+        }
+        Some(costs)
+    }
+}
+
+/// the chip that matches best and how many cycles didn't match it, BPSK only has `0b00` and `0b11`
+fn best_chip(costs: [u32; 4], bpsk: bool) -> (u8, u32) {
+    if bpsk {
+        return if costs[0b11] < costs[0b00] {
+            (0b11, costs[0b11])
+        } else {
+            (0b00, costs[0b00])
+        };
+    }
+    let (chip, cost) = costs
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, cost)| **cost)
+        .expect("there are always 4 costs");
+    (chip as u8, *cost)
+}
+
+/// Turn BPSK chips back into bytes, undoing the spreading and the differential encoding
+fn bpsk_chips_to_frame<const MAX_FRAME_SIZE: usize>(
+    chips: impl Iterator<Item = u8>,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+    let mut bytes = Vec::new();
+    let mut chip_errors = 0;
+    let (mut mismatched, mut prev_encoded, mut byte, mut bits) = (0u32, false, 0u8, 0);
+    for (idx, chip) in chips.enumerate() {
+        let idx = idx % BPSK_CHIPS.len();
+        mismatched += u32::from(chip & 1 != BPSK_CHIPS[idx]);
+        if idx != BPSK_CHIPS.len() - 1 {
+            continue;
+        }
+        // -> correlate the chips against a 0 bit, more than half off is a 1
+        let encoded = mismatched * 2 > BPSK_CHIPS.len() as u32;
+        chip_errors += mismatched.min(BPSK_CHIPS.len() as u32 - mismatched);
+        mismatched = 0;
+        // -> undo the differential encoding
+        byte |= u8::from(encoded ^ prev_encoded) << bits;
+        prev_encoded = encoded;
+        bits += 1;
+        if bits == 8 {
+            bytes.push(byte).map_err(|_| DecodeError::VecLen)?;
+            (byte, bits) = (0, 0);
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(DecodeError::NoData);
+    }
+    Ok(DecodedFrame { bytes, chip_errors })
+}
+
+/// Turn sub-GHz O-QPSK chips, the middle chips included, back into bytes
+fn o_qpsk_chips_to_frame<const MAX_FRAME_SIZE: usize>(
+    chips_with_middle: impl Iterator<Item = u8>,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+    const CHIPS: usize = SUB_GHZ_CHIP_ARRAY[0].len();
+    let mut bytes = Vec::new();
+    let mut chip_errors = 0;
+    let mut chips = [0u8; CHIPS];
+    let mut low_nibble = None;
+    for (idx, chip) in chips_with_middle.step_by(2).enumerate() {
+        chips[idx % CHIPS] = chip;
+        if idx % CHIPS != CHIPS - 1 {
+            continue;
+        }
+        let (nibble, errors) = SUB_GHZ_CHIP_ARRAY
+            .iter()
+            .map(|sequence| sequence.iter().zip(&chips).filter(|(a, b)| a != b).count() as u32)
+            .enumerate()
+            .min_by_key(|(_, errors)| *errors)
+            .expect("SUB_GHZ_CHIP_ARRAY is not empty");
+        chip_errors += errors;
+        match low_nibble.take() {
+            None => low_nibble = Some(nibble as u8),
+            Some(low) => bytes
+                .push(low | (nibble as u8) << 4)
+                .map_err(|_| DecodeError::VecLen)?,
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(DecodeError::NoData);
+    }
+    Ok(DecodedFrame { bytes, chip_errors })
+}
+
+/// Decode a square wave back into the bytes given to [convert_sub_ghz]
+///
+/// The start of the wave is found like in
+/// [decode_quarter_square_wave](crate::pio_bytecode_decode::decode_quarter_square_wave), the chips are
+/// read with the same whole quarters [convert_sub_ghz] gave them
+///
+/// # Arguments
+///
+/// * `samples`: one item per state machine cycle, true for [Level::High](crate::pio_bytecode_gen::Level)
+/// * `phy`, `quarter_cycles`, `quarters_per_chip`: what the wave was generated with
+///
+/// returns: Result<[DecodedFrame], [DecodeError]>
+pub fn decode_sub_ghz_square_wave<I, const MAX_FRAME_SIZE: usize>(
+    samples: I,
+    phy: SubGhzPhy,
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError>
+where
+    I: Iterator<Item = bool> + Clone,
+{
+    if quarter_cycles == 0 || quarters_per_chip == 0 {
+        return Err(DecodeError::WaveTable);
+    }
+    let bpsk = phy.is_bpsk();
+    let reader = |offset: usize| {
+        ChipReader {
+            samples: samples.clone().skip(offset),
+            quarters: ChipQuarters::new(phy, quarters_per_chip),
+            quarter_cycles: usize::from(quarter_cycles),
+            phase: 0,
+        }
+        .map(move |costs| best_chip(costs, bpsk))
+    };
+
+    // -> find where the subcarrier starts
+    let offset = (0..usize::from(quarter_cycles) + 4)
+        .min_by_key(|offset| reader(*offset).map(|(_, cost)| cost).sum::<u32>())
+        .expect("quarter_cycles is never 0");
+
+    let chips = reader(offset).map(|(chip, _)| chip);
+    if bpsk {
+        bpsk_chips_to_frame(chips)
+    } else {
+        o_qpsk_chips_to_frame(chips)
+    }
+}
+
+/// Decode pio bytecode from [convert_sub_ghz] back into its bytes
+pub fn decode_sub_ghz_bytecode<const MAX_FRAME_SIZE: usize>(
+    words: &[u32],
+    phy: SubGhzPhy,
+    quarter_cycles: u8,
+    quarters_per_chip: u8,
+) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+    decode_sub_ghz_square_wave(
+        pio_words_to_square_wave(words),
+        phy,
+        quarter_cycles,
+        quarters_per_chip,
+    )
+}
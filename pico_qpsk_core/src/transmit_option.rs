@@ -0,0 +1,485 @@
+use crate::ble;
+use crate::fsk::FskConvertIter;
+use crate::keying::{self, KeyedConvertIter, KeyingConfig};
+use crate::offset_planner::{self, PlanError, PllSettings, TransmitPlan};
+use crate::pio_bytecode_decode::{
+    decode_pio_bytecode, decode_quadrature_bytecode, DecodeError, DecodedFrame,
+};
+use crate::pio_bytecode_gen::{
+    convert_advanced, convert_quadrature, ConvertIterType, QuadratureConvertIter, QuarterConvertIter,
+    Sideband,
+};
+use crate::sub_ghz::{self, SubGhzConvertIter, SubGhzPhy};
+
+/// Build the table of waves for each 2 bit chip, see `chips_to_waves` in [crate::pio_bytecode_gen]
+///
+/// `$chip_count` is the number of state machine cycles per chip, it must be divisible by 4
+///
+/// # Examples
+///
+/// ```
+/// use pico_qpsk_core::wave_array;
+/// use pico_qpsk_core::pio_bytecode_gen::Level;
+///
+/// let waves = wave_array!(16);
+/// assert_eq!(waves[0], [Level::Low(4), Level::High(8), Level::Low(4)]);
+/// ```
+#[macro_export]
+macro_rules! wave_array {
+    ($chip_count:literal) => {{
+        use $crate::pio_bytecode_gen::Level;
+        const CHIP_COUNT: u8 = $chip_count;
+        const QUARTER_CNT: u8 = CHIP_COUNT / 4;
+        const HALF_CNT: u8 = CHIP_COUNT / 2;
+        const {
+            core::assert!(CHIP_COUNT % 4 == 0, "Chip Count must be evenly dividable by 4");
+            core::assert!(CHIP_COUNT % 2 == 0, "Chip Count must be evenly dividable by 2");
+        };
+        [
+            [
+                Level::Low(QUARTER_CNT),
+                Level::High(HALF_CNT),
+                Level::Low(QUARTER_CNT),
+            ],
+            [Level::Low(HALF_CNT), Level::High(HALF_CNT), Level::Nop],
+            [Level::High(HALF_CNT), Level::Low(HALF_CNT), Level::Nop],
+            [
+                Level::High(QUARTER_CNT),
+                Level::Low(HALF_CNT),
+                Level::High(QUARTER_CNT),
+            ],
+        ]
+    }};
+}
+
+/// The system clock the processor needs to run at for a [TransmitOption]
+///
+/// The firmware maps this onto the actual PLL configuration
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProcessorClock {
+    F128MHz,
+    F144MHz,
+    /// a PLL setup from [offset_planner::plan]
+    Custom(PllSettings),
+}
+
+impl ProcessorClock {
+    /// The clock the PLL runs at, the standard clocks are kept as [ProcessorClock::F128MHz] and
+    /// [ProcessorClock::F144MHz]
+    pub fn from_pll(pll: PllSettings) -> Self {
+        match pll {
+            PllSettings::SYS_128MHZ => ProcessorClock::F128MHz,
+            PllSettings::SYS_144MHZ => ProcessorClock::F144MHz,
+            pll => ProcessorClock::Custom(pll),
+        }
+    }
+
+    pub fn pll(&self) -> PllSettings {
+        match self {
+            ProcessorClock::F128MHz => PllSettings::SYS_128MHZ,
+            ProcessorClock::F144MHz => PllSettings::SYS_144MHZ,
+            ProcessorClock::Custom(pll) => *pll,
+        }
+    }
+
+    pub fn hz(&self) -> u32 {
+        match self {
+            ProcessorClock::F128MHz => 128_000_000,
+            ProcessorClock::F144MHz => 144_000_000,
+            ProcessorClock::Custom(pll) => pll.sys_hz(),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StandardTransmitOption {
+    Clk128MHzOffset8MHz,
+    Clk144MHzOffset6MHz,
+    Clk128MHzOffset4MHz,
+    Clk128MHzOffset2MHz,
+}
+
+impl StandardTransmitOption {
+    /// every option, in the order they are declared
+    pub const ALL: [StandardTransmitOption; 4] = [
+        StandardTransmitOption::Clk128MHzOffset8MHz,
+        StandardTransmitOption::Clk144MHzOffset6MHz,
+        StandardTransmitOption::Clk128MHzOffset4MHz,
+        StandardTransmitOption::Clk128MHzOffset2MHz,
+    ];
+
+    /// The subcarrier offset
+    pub fn offset_hz(&self) -> u32 {
+        match self {
+            StandardTransmitOption::Clk128MHzOffset8MHz => 8_000_000,
+            StandardTransmitOption::Clk144MHzOffset6MHz => 6_000_000,
+            StandardTransmitOption::Clk128MHzOffset4MHz => 4_000_000,
+            StandardTransmitOption::Clk128MHzOffset2MHz => 2_000_000,
+        }
+    }
+
+    pub fn state_machine_clock(&self) -> StateMachineClockDividerSetting {
+        match self {
+            StandardTransmitOption::Clk128MHzOffset8MHz => StateMachineClockDividerSetting::None,
+            StandardTransmitOption::Clk128MHzOffset2MHz => StateMachineClockDividerSetting::Integer(4),
+            StandardTransmitOption::Clk144MHzOffset6MHz => StateMachineClockDividerSetting::None,
+            StandardTransmitOption::Clk128MHzOffset4MHz => StateMachineClockDividerSetting::Integer(2),
+        }
+    }
+    pub fn processor_clock(&self) -> ProcessorClock {
+        match self {
+            StandardTransmitOption::Clk128MHzOffset8MHz => ProcessorClock::F128MHz,
+            StandardTransmitOption::Clk128MHzOffset2MHz => ProcessorClock::F128MHz,
+            StandardTransmitOption::Clk144MHzOffset6MHz => ProcessorClock::F144MHz,
+            StandardTransmitOption::Clk128MHzOffset4MHz => ProcessorClock::F128MHz,
+        }
+    }
+
+    pub fn convert<'a>(&self, message_bytes: &'a [u8]) -> ConvertIterType<'a> {
+        match self {
+            StandardTransmitOption::Clk128MHzOffset2MHz => {
+                convert_advanced::<1>(message_bytes, &wave_array!(16))
+            }
+            StandardTransmitOption::Clk128MHzOffset8MHz => {
+                convert_advanced::<4>(message_bytes, &wave_array!(16))
+            }
+            StandardTransmitOption::Clk144MHzOffset6MHz => {
+                convert_advanced::<3>(message_bytes, &wave_array!(24))
+            }
+            StandardTransmitOption::Clk128MHzOffset4MHz => {
+                convert_advanced::<2>(message_bytes, &wave_array!(16))
+            }
+        }
+    }
+
+    /// (state machine cycles per quarter of the subcarrier period, quarters per chip), the wave table of
+    /// [StandardTransmitOption::convert] is 4 quarters and each wave is one chip
+    pub fn quarter_timing(&self) -> (u8, u8) {
+        match self {
+            StandardTransmitOption::Clk128MHzOffset2MHz => (4, 4),
+            StandardTransmitOption::Clk128MHzOffset8MHz => (4, 16),
+            StandardTransmitOption::Clk144MHzOffset6MHz => (6, 12),
+            StandardTransmitOption::Clk128MHzOffset4MHz => (4, 8),
+        }
+    }
+
+    /// Decode the output of [StandardTransmitOption::convert] back into the message bytes
+    pub fn decode<const MAX_FRAME_SIZE: usize>(
+        &self,
+        words: &[u32],
+    ) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+        match self {
+            StandardTransmitOption::Clk128MHzOffset2MHz => decode_pio_bytecode(words, &wave_array!(16), 1),
+            StandardTransmitOption::Clk128MHzOffset8MHz => decode_pio_bytecode(words, &wave_array!(16), 4),
+            StandardTransmitOption::Clk144MHzOffset6MHz => decode_pio_bytecode(words, &wave_array!(24), 3),
+            StandardTransmitOption::Clk128MHzOffset4MHz => decode_pio_bytecode(words, &wave_array!(16), 2),
+        }
+    }
+}
+
+/// What the firmware sends with, one of the standard options or a plan for another offset
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransmitOption {
+    Standard(StandardTransmitOption),
+    Planned(TransmitPlan),
+}
+
+impl From<StandardTransmitOption> for TransmitOption {
+    fn from(option: StandardTransmitOption) -> Self {
+        TransmitOption::Standard(option)
+    }
+}
+
+impl TransmitOption {
+    /// The standard option for `offset_hz` if there is one, else the [offset_planner::plan] for it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::transmit_option::{StandardTransmitOption, TransmitOption};
+    ///
+    /// let six = TransmitOption::for_offset(6_000_000).unwrap();
+    /// assert_eq!(six, TransmitOption::Standard(StandardTransmitOption::Clk144MHzOffset6MHz));
+    /// assert!(matches!(TransmitOption::for_offset(5_000_000), Ok(TransmitOption::Planned(_))));
+    /// ```
+    pub fn for_offset(offset_hz: u32) -> Result<Self, PlanError> {
+        match StandardTransmitOption::ALL
+            .into_iter()
+            .find(|option| option.offset_hz() == offset_hz)
+        {
+            Some(option) => Ok(TransmitOption::Standard(option)),
+            None => offset_planner::plan(offset_hz).map(TransmitOption::Planned),
+        }
+    }
+
+    /// [TransmitOption::for_offset] for an output that has to run at the system clock `clock`
+    ///
+    /// The standard option is only used when it runs at `clock`, else the [offset_planner::plan_on_clock]
+    /// for `offset_hz`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::transmit_option::{ProcessorClock, StandardTransmitOption, TransmitOption};
+    ///
+    /// let two = TransmitOption::for_offset_on_clock(2_000_000, ProcessorClock::F128MHz).unwrap();
+    /// assert_eq!(two, TransmitOption::Standard(StandardTransmitOption::Clk128MHzOffset2MHz));
+    /// let four = TransmitOption::for_offset_on_clock(4_000_000, ProcessorClock::F144MHz).unwrap();
+    /// assert!(matches!(four, TransmitOption::Planned(_)));
+    /// assert_eq!(four.processor_clock(), ProcessorClock::F144MHz);
+    /// ```
+    pub fn for_offset_on_clock(offset_hz: u32, clock: ProcessorClock) -> Result<Self, PlanError> {
+        match StandardTransmitOption::ALL
+            .into_iter()
+            .find(|option| option.offset_hz() == offset_hz && option.processor_clock() == clock)
+        {
+            Some(option) => Ok(TransmitOption::Standard(option)),
+            None => offset_planner::plan_on_clock(offset_hz, clock).map(TransmitOption::Planned),
+        }
+    }
+
+    /// The subcarrier offset, in millihertz as a plan can be off by a fraction of a Hz
+    pub fn offset_millihertz(&self) -> u64 {
+        match self {
+            TransmitOption::Standard(option) => u64::from(option.offset_hz()) * 1000,
+            TransmitOption::Planned(plan) => plan.offset_millihertz(),
+        }
+    }
+
+    pub fn state_machine_clock(&self) -> StateMachineClockDividerSetting {
+        match self {
+            TransmitOption::Standard(option) => option.state_machine_clock(),
+            TransmitOption::Planned(plan) => plan.divider,
+        }
+    }
+
+    pub fn processor_clock(&self) -> ProcessorClock {
+        match self {
+            TransmitOption::Standard(option) => option.processor_clock(),
+            TransmitOption::Planned(plan) => plan.clock,
+        }
+    }
+
+    pub fn convert<'a>(&self, message_bytes: &'a [u8]) -> TransmitConvertIter<'a> {
+        match self {
+            TransmitOption::Standard(option) => TransmitConvertIter::Standard(option.convert(message_bytes)),
+            TransmitOption::Planned(plan) => TransmitConvertIter::Planned(plan.convert(message_bytes)),
+        }
+    }
+
+    /// Decode the output of [TransmitOption::convert] back into the message bytes
+    pub fn decode<const MAX_FRAME_SIZE: usize>(
+        &self,
+        words: &[u32],
+    ) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+        match self {
+            TransmitOption::Standard(option) => option.decode(words),
+            TransmitOption::Planned(plan) => plan.decode(words),
+        }
+    }
+
+    /// (state machine cycles per quarter of the subcarrier period, quarters per chip)
+    pub fn quarter_timing(&self) -> (u8, u8) {
+        match self {
+            TransmitOption::Standard(option) => option.quarter_timing(),
+            TransmitOption::Planned(plan) => (plan.quarter_cycles, plan.quarters_per_chip),
+        }
+    }
+
+    /// The pio bytecode for one antenna pin with both sidebands, [TransmitOption::convert], or for the
+    /// I and Q pins with only `sideband`, see [convert_quadrature]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::pio_bytecode_gen::Sideband;
+    /// use pico_qpsk_core::transmit_option::{StandardTransmitOption, TransmitOption};
+    ///
+    /// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+    /// let option = TransmitOption::Standard(StandardTransmitOption::Clk144MHzOffset6MHz);
+    /// let words: Vec<u32> = option.convert_sideband(&frame, Some(Sideband::Lower)).collect();
+    /// let decoded = option.decode_sideband::<32>(&words, Some(Sideband::Lower)).unwrap();
+    /// assert_eq!(decoded.bytes, frame);
+    /// ```
+    pub fn convert_sideband<'a>(
+        &self,
+        message_bytes: &'a [u8],
+        sideband: Option<Sideband>,
+    ) -> TransmitConvertIter<'a> {
+        match sideband {
+            None => self.convert(message_bytes),
+            Some(sideband) => {
+                let (quarter_cycles, quarters_per_chip) = self.quarter_timing();
+                TransmitConvertIter::Quadrature(convert_quadrature(
+                    message_bytes,
+                    quarter_cycles,
+                    quarters_per_chip,
+                    sideband,
+                ))
+            }
+        }
+    }
+
+    /// The subcarrier offset to the nearest Hz
+    pub fn offset_hz(&self) -> u32 {
+        ((self.offset_millihertz() + 500) / 1000) as u32
+    }
+
+    /// The state machine clock divider for `phy`, [Phy::Ble1M] runs at the system clock as the FSK
+    /// edges need the finest steps there are
+    pub fn state_machine_clock_for(&self, phy: Phy) -> StateMachineClockDividerSetting {
+        match phy {
+            Phy::OQpsk(_) | Phy::SubGhz(_) | Phy::Keyed(_) => self.state_machine_clock(),
+            Phy::Ble1M => StateMachineClockDividerSetting::None,
+        }
+    }
+
+    /// The pio bytecode of `message_bytes` for `phy`, the state machine runs with
+    /// [TransmitOption::state_machine_clock_for] and the program [Phy::two_pins] picks
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pico_qpsk_core::pio_bytecode_gen::Sideband;
+    /// use pico_qpsk_core::transmit_option::{Phy, StandardTransmitOption, TransmitOption};
+    ///
+    /// let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x01, 0x42];
+    /// let option = TransmitOption::Standard(StandardTransmitOption::Clk128MHzOffset4MHz);
+    /// let words = option.convert_phy(&frame, Phy::OQpsk(Some(Sideband::Upper)));
+    /// assert!(words.eq(option.convert_sideband(&frame, Some(Sideband::Upper))));
+    /// ```
+    pub fn convert_phy<'a>(&self, message_bytes: &'a [u8], phy: Phy) -> TransmitConvertIter<'a> {
+        match phy {
+            Phy::OQpsk(sideband) => self.convert_sideband(message_bytes, sideband),
+            Phy::Ble1M => TransmitConvertIter::Fsk(ble::convert_ble(
+                message_bytes,
+                self.processor_clock().hz(),
+                self.offset_hz(),
+            )),
+            Phy::SubGhz(sub_ghz_phy) => {
+                let (quarter_cycles, quarters_per_chip) = self.quarter_timing();
+                TransmitConvertIter::SubGhz(sub_ghz::convert_sub_ghz(
+                    message_bytes,
+                    sub_ghz_phy,
+                    quarter_cycles,
+                    quarters_per_chip,
+                ))
+            }
+            Phy::Keyed(config) => {
+                let (quarter_cycles, quarters_per_chip) = self.quarter_timing();
+                TransmitConvertIter::Keyed(keying::convert_keyed(
+                    message_bytes,
+                    config,
+                    quarter_cycles,
+                    quarters_per_chip,
+                ))
+            }
+        }
+    }
+
+    /// Decode the output of [TransmitOption::convert_sideband] back into the message bytes, for two pins
+    /// this is the I pin and the chip errors of both pins
+    pub fn decode_sideband<const MAX_FRAME_SIZE: usize>(
+        &self,
+        words: &[u32],
+        sideband: Option<Sideband>,
+    ) -> Result<DecodedFrame<MAX_FRAME_SIZE>, DecodeError> {
+        let Some(sideband) = sideband else {
+            return self.decode(words);
+        };
+        let (quarter_cycles, quarters_per_chip) = self.quarter_timing();
+        let [mut i, q] = decode_quadrature_bytecode(words, quarter_cycles, quarters_per_chip, sideband)?;
+        i.chip_errors += q.chip_errors;
+        Ok(i)
+    }
+}
+
+/// What the bytes a [TransmitOption] sends are modulated as
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phy {
+    /// an 802.15.4 O-QPSK frame, on one pin or with one sideband on the I and Q pins
+    OQpsk(Option<Sideband>),
+    /// a BLE 1M air packet from [ble::air_packet], 2-FSK on one pin with the state machine at the
+    /// system clock
+    Ble1M,
+    /// an 802.15.4 frame on a sub-GHz PHY, on one pin with the subcarrier of the [TransmitOption]
+    SubGhz(SubGhzPhy),
+    /// the frame with its SHR swapped for a preamble and sync word, OOK or 2-FSK on one pin with the
+    /// subcarrier of the [TransmitOption]
+    Keyed(KeyingConfig),
+}
+
+impl Phy {
+    /// true for the two pin program, else the single pin one
+    pub fn two_pins(&self) -> bool {
+        matches!(self, Phy::OQpsk(Some(_)))
+    }
+}
+
+/// The pio bytecode of a [TransmitOption]
+// there is no allocator to box the iterators, core1 only has one at a time
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+pub enum TransmitConvertIter<'a> {
+    Standard(ConvertIterType<'a>),
+    Planned(QuarterConvertIter<'a>),
+    Quadrature(QuadratureConvertIter<'a>),
+    Fsk(FskConvertIter<'a>),
+    SubGhz(SubGhzConvertIter<'a>),
+    Keyed(KeyedConvertIter<'a>),
+}
+
+impl Iterator for TransmitConvertIter<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            TransmitConvertIter::Standard(words) => words.next(),
+            TransmitConvertIter::Planned(words) => words.next(),
+            TransmitConvertIter::Quadrature(words) => words.next(),
+            TransmitConvertIter::Fsk(words) => words.next(),
+            TransmitConvertIter::SubGhz(words) => words.next(),
+            TransmitConvertIter::Keyed(words) => words.next(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StateMachineClockDividerSetting {
+    Fixed { integer_part: u16, fractional_part: u8 },
+    Integer(u16),
+    None,
+}
+
+impl StateMachineClockDividerSetting {
+    /// The divider in 1/256 steps like the PIO's CLKDIV register, `None` below 1 or above 65535 + 255/256
+    pub fn from_256ths(divider: u32) -> Option<Self> {
+        let integer_part = u16::try_from(divider >> 8).ok().filter(|integer| *integer > 0)?;
+        Some(match (integer_part, divider as u8) {
+            (1, 0) => StateMachineClockDividerSetting::None,
+            (integer_part, 0) => StateMachineClockDividerSetting::Integer(integer_part),
+            (integer_part, fractional_part) => StateMachineClockDividerSetting::Fixed {
+                integer_part,
+                fractional_part,
+            },
+        })
+    }
+
+    /// The divider in 1/256 steps, [StateMachineClockDividerSetting::None] is 1
+    pub fn to_256ths(&self) -> u32 {
+        match *self {
+            StateMachineClockDividerSetting::Fixed {
+                integer_part,
+                fractional_part,
+            } => u32::from(integer_part) << 8 | u32::from(fractional_part),
+            StateMachineClockDividerSetting::Integer(integer_part) => u32::from(integer_part) << 8,
+            StateMachineClockDividerSetting::None => 256,
+        }
+    }
+}
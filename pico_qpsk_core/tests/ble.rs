@@ -0,0 +1,230 @@
+//! BLE advertisements: the CRC and whitening against bit by bit models of the LFSRs in the Core
+//! Specification (Vol 6 Part B, 3.1.1 and 3.2), the CRC against its published check value, packet round trips and an FSK demodulation of the bytecode
+
+use pico_qpsk_core::ble::{
+    ad_type, air_packet, convert_ble, crc24, decode_air_packet, whiten, Advertisement, AdvertisingChannel,
+    AdvertisingData, BleError, ADVERTISING_CRC_INIT, MAX_ADV_DATA,
+};
+use pico_qpsk_core::fsk::FskTiming;
+use pico_qpsk_core::pio_bytecode_decode::{pio_run_lengths, pio_words_to_square_wave};
+use pico_qpsk_core::prng::SplitMix64;
+use pico_qpsk_core::transmit_option::{Phy, TransmitOption};
+use std::f64::consts::PI;
+
+/// Bits of `bytes` least significant bit first, the order they go on air
+fn air_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).map(move |bit| (byte >> bit) & 1 == 1))
+        .collect()
+}
+
+fn air_bytes(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|byte| {
+            byte.iter()
+                .enumerate()
+                .fold(0, |value, (bit, set)| value | (u8::from(*set) << bit))
+        })
+        .collect()
+}
+
+/// The 24 position LFSR of the spec: position 0 holds the LSB of the preset, the data bit and position 23
+/// feed position 0 and the taps, the CRC goes out from position 23 to position 0
+fn spec_crc(pdu: &[u8]) -> Vec<u8> {
+    let mut lfsr: [bool; 24] = core::array::from_fn(|position| (ADVERTISING_CRC_INIT >> position) & 1 == 1);
+    for bit in air_bits(pdu) {
+        let feedback = bit ^ lfsr[23];
+        for position in (1..24).rev() {
+            lfsr[position] = lfsr[position - 1];
+        }
+        lfsr[0] = feedback;
+        for tap in [1, 3, 4, 6, 9, 10] {
+            lfsr[tap] ^= feedback;
+        }
+    }
+    let out: Vec<bool> = (0..24).rev().map(|position| lfsr[position]).collect();
+    air_bytes(&out)
+}
+
+/// The 7 position whitening LFSR of the spec: position 0 starts at 1 and positions 1 to 6 hold the
+/// channel index MSB first, position 6 is XORed into the data and fed back to positions 0 and 4
+fn spec_whiten(bytes: &[u8], channel: u8) -> Vec<u8> {
+    let mut lfsr = [true; 7];
+    for (position, bit) in (1..7).zip((0..6).rev()) {
+        lfsr[position] = (channel >> bit) & 1 == 1;
+    }
+    let bits: Vec<bool> = air_bits(bytes)
+        .into_iter()
+        .map(|bit| {
+            let out = lfsr[6];
+            for position in (1..7).rev() {
+                lfsr[position] = lfsr[position - 1];
+            }
+            lfsr[0] = out;
+            lfsr[4] ^= out;
+            bit ^ out
+        })
+        .collect();
+    air_bytes(&bits)
+}
+
+fn random_bytes(rng: &mut SplitMix64, max_len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; rng.next_u64() as usize % (max_len + 1)];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn advertisement(name: &[u8]) -> Advertisement {
+    Advertisement {
+        address: [0xC0, 0xFF, 0xEE, 0x12, 0x34, 0x56],
+        random_address: true,
+        data: AdvertisingData::with_name(name),
+    }
+}
+
+#[test]
+fn crc_matches_the_spec_lfsr() {
+    let mut rng = SplitMix64::new(24);
+    for _ in 0..200 {
+        let pdu = random_bytes(&mut rng, 39);
+        assert_eq!(
+            crc24(ADVERTISING_CRC_INIT, &pdu).to_vec(),
+            spec_crc(&pdu),
+            "{pdu:02X?}"
+        );
+    }
+}
+
+/// The check value of CRC-24/BLE in the CRC RevEng catalogue, a source apart from the spec LFSR above: the
+/// CRC of "123456789" is 0xC25A56 with the init 0x555555 reflected in, it goes on air least significant
+/// byte first
+#[test]
+fn crc_matches_the_published_check_value() {
+    assert_eq!(crc24(ADVERTISING_CRC_INIT, b"123456789"), [0x56, 0x5A, 0xC2]);
+    assert_eq!(spec_crc(b"123456789"), [0x56, 0x5A, 0xC2]);
+}
+
+#[test]
+fn whitening_matches_the_spec_lfsr() {
+    let mut rng = SplitMix64::new(7);
+    for channel in AdvertisingChannel::ALL {
+        for _ in 0..50 {
+            let bytes = random_bytes(&mut rng, 42);
+            let mut whitened = bytes.clone();
+            whiten(&mut whitened, channel);
+            assert_eq!(whitened, spec_whiten(&bytes, channel.index()));
+            // it is its own inverse
+            whiten(&mut whitened, channel);
+            assert_eq!(whitened, bytes);
+        }
+    }
+}
+
+#[test]
+fn air_packet_layout() {
+    let advertisement = advertisement(b"pico");
+    let packet = air_packet(&advertisement, AdvertisingChannel::Ch37);
+    let mut pdu = packet[5..].to_vec();
+    whiten(&mut pdu, AdvertisingChannel::Ch37);
+    assert_eq!(
+        pdu[..pdu.len() - 3],
+        [
+            0x42, 0x0F, 0x56, 0x34, 0x12, 0xEE, 0xFF, 0xC0, 0x02, 0x01, 0x06, 0x05, 0x09, b'p', b'i', b'c',
+            b'o'
+        ]
+    );
+    assert_eq!(pdu[pdu.len() - 3..], spec_crc(&pdu[..pdu.len() - 3]));
+}
+
+#[test]
+fn packets_decode_on_their_own_channel_only() {
+    let advertisement = advertisement(b"a name that is too long for one advertisement");
+    let names: Vec<_> = advertisement.data.structures().map(Result::unwrap).collect();
+    assert_eq!(names[1].0, ad_type::SHORTENED_LOCAL_NAME);
+    assert_eq!(advertisement.data.as_bytes().len(), MAX_ADV_DATA);
+    for channel in AdvertisingChannel::ALL {
+        let packet = air_packet(&advertisement, channel);
+        assert_eq!(decode_air_packet(&packet, channel).as_ref(), Ok(&advertisement));
+        for other in AdvertisingChannel::ALL
+            .into_iter()
+            .filter(|other| *other != channel)
+        {
+            assert!(decode_air_packet(&packet, other).is_err());
+        }
+        let mut flipped = packet.to_vec();
+        flipped[20] ^= 0x10;
+        assert_eq!(decode_air_packet(&flipped, channel), Err(BleError::Crc));
+    }
+}
+
+#[test]
+fn raw_ad_data_is_checked() {
+    assert!(AdvertisingData::from_bytes(&[0x03, 0xFF, 0x59, 0x00]).is_ok());
+    assert_eq!(
+        AdvertisingData::from_bytes(&[0x05, 0xFF, 0x59, 0x00]),
+        Err(BleError::AdStructure)
+    );
+    assert_eq!(AdvertisingData::from_bytes(&[0; 32]), Err(BleError::TooLong));
+}
+
+/// One bit per `pio_hz / bit_rate` cycles, each bit is the tone the square wave correlates best with
+fn demodulate(words: &[u32], timing: FskTiming, bits: usize) -> Vec<bool> {
+    let wave: Vec<f64> = pio_words_to_square_wave(words)
+        .map(|high| if high { 1.0 } else { -1.0 })
+        .collect();
+    let cycles_per_bit = f64::from(timing.pio_hz) / f64::from(timing.bit_rate);
+    let power = |samples: &[f64], start: usize, hz: u32| {
+        let cycles_per_period = f64::from(timing.pio_hz) / f64::from(hz);
+        let (re, im) = samples
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, sample)| {
+                let (sin, cos) = (-2.0 * PI * (start + n) as f64 / cycles_per_period).sin_cos();
+                (re + sample * cos, im + sample * sin)
+            });
+        re * re + im * im
+    };
+    (0..bits)
+        .map(|bit| {
+            let start = (bit as f64 * cycles_per_bit).round() as usize;
+            let end = ((bit + 1) as f64 * cycles_per_bit).round() as usize;
+            let samples = &wave[start..end.min(wave.len())];
+            power(samples, start, timing.mark_hz) > power(samples, start, timing.space_hz)
+        })
+        .collect()
+}
+
+#[test]
+fn fsk_bytecode_demodulates() {
+    let advertisement = advertisement(b"pico");
+    let packet = air_packet(&advertisement, AdvertisingChannel::Ch39);
+    for offset_hz in [8_000_000, 6_000_000, 5_000_000, 3_000_000] {
+        let option = TransmitOption::for_offset(offset_hz).unwrap();
+        let pio_hz = option.processor_clock().hz();
+        let timing = FskTiming::around(pio_hz, 1_000_000, offset_hz, 250_000);
+        timing.check().unwrap();
+        let words: Vec<u32> = option.convert_phy(&packet, Phy::Ble1M).collect();
+        assert!(words
+            .iter()
+            .eq(convert_ble(&packet, pio_hz, offset_hz).collect::<Vec<_>>().iter()));
+
+        let runs: Vec<u32> = pio_run_lengths(&words).skip(1).collect();
+        assert!(runs.iter().all(|run| *run >= 4 && run % 2 == 0));
+        // the padding at the end are 4 cycle runs, the wave lasts the packet to within a half period
+        let half_period = u64::from(pio_hz / (2 * timing.mark_hz) + 2);
+        let cycles: u64 = runs.iter().map(|run| u64::from(*run)).sum();
+        let packet_cycles = packet.len() as u64 * 8 * u64::from(pio_hz) / 1_000_000;
+        assert!(
+            cycles + half_period >= packet_cycles,
+            "{offset_hz}: {cycles} of {packet_cycles}"
+        );
+        assert!(
+            cycles <= packet_cycles + half_period + 4 * 31,
+            "{offset_hz}: {cycles} of {packet_cycles}"
+        );
+
+        let bits = demodulate(&words, timing, packet.len() * 8);
+        assert_eq!(bits, air_bits(&packet), "{offset_hz}");
+    }
+}
@@ -0,0 +1,64 @@
+//! Check the chunks come out of the ring in order with the packet ends marked
+
+use pico_qpsk_core::chunk_ring::ChunkRing;
+use pico_qpsk_core::transmit_option::StandardTransmitOption;
+
+#[test]
+fn full_ring_waits_for_the_consumer() {
+    let mut ring = ChunkRing::<3, 4>::new();
+    let (mut producer, mut consumer) = ring.split();
+    let mut words = (0..10).peekable();
+    assert_eq!(producer.push_from(&mut words), Some(false));
+    assert_eq!(producer.push_from(&mut words), Some(false));
+    assert_eq!(producer.push_from(&mut words), Some(true));
+    assert_eq!(consumer.len(), 3);
+    assert_eq!(consumer.chunk(2).unwrap().words, [8, 9]);
+
+    // a second packet has to wait for a free chunk
+    let mut words = (0..4).peekable();
+    assert_eq!(producer.push_from(&mut words), None);
+    consumer.release();
+    assert_eq!(consumer.chunk(0).unwrap().words, [4, 5, 6, 7]);
+    // a packet that fills its chunk exactly still ends there
+    assert_eq!(producer.push_from(&mut words), Some(true));
+    assert_eq!(consumer.chunk(2).unwrap().words, [0, 1, 2, 3]);
+    assert!(consumer.chunk(2).unwrap().last);
+    assert_eq!(consumer.chunk(3), None);
+}
+
+#[test]
+fn bytecode_streamed_across_threads() {
+    let frame = [0xA5u8; 40];
+    let option = StandardTransmitOption::Clk144MHzOffset6MHz;
+    let expected: Vec<u32> = option.convert(&frame).collect();
+
+    // much smaller than the bytecode, so the ring wraps many times
+    let mut ring = ChunkRing::<4, 16>::new();
+    let (mut producer, mut consumer) = ring.split();
+    let received = std::thread::scope(|scope| {
+        scope.spawn(move || {
+            for _ in 0..3 {
+                let mut words = option.convert(&frame).peekable();
+                while producer.push_from(&mut words) != Some(true) {}
+            }
+        });
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let mut packet = Vec::new();
+            loop {
+                let Some(chunk) = consumer.chunk(0) else {
+                    continue;
+                };
+                let last = chunk.last;
+                packet.extend_from_slice(chunk.words);
+                consumer.release();
+                if last {
+                    break;
+                }
+            }
+            received.push(packet);
+        }
+        received
+    });
+    assert_eq!(received, vec![expected; 3]);
+}
@@ -0,0 +1,209 @@
+//! Check the config records and the wear levelled store against an in-memory flash
+
+use ieee802154::mac::{Address, ExtendedAddress, PanId};
+use pico_qpsk_core::config_store::{
+    decode_record, encode_record, ConfigStore, Flash, RecordError, RECORD_SIZE, RECORD_VERSION, STORE_SECTORS,
+};
+use pico_qpsk_core::keying::{Keying, KeyingConfig, LineCoding};
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::schedule::ScheduleMode;
+use pico_qpsk_core::session_config::{
+    FrameConfig, PayloadMode, PinConfig, SendDefaults, SequenceNumberMode, SessionConfig,
+};
+use pico_qpsk_core::sub_ghz::SubGhzPhy;
+use pico_qpsk_core::transmit_option::TransmitOption;
+
+const SECTOR_SIZE: usize = 256;
+
+/// NOR flash in memory, programming can only clear bits
+#[derive(Debug)]
+struct MockFlash {
+    bytes: Vec<u8>,
+    erases: [u32; STORE_SECTORS],
+    /// cut the next program off after this many bytes, like a power cut
+    cut_off_after: Option<usize>,
+}
+
+impl MockFlash {
+    fn new() -> Self {
+        MockFlash {
+            bytes: vec![0xFF; STORE_SECTORS * SECTOR_SIZE],
+            erases: [0; STORE_SECTORS],
+            cut_off_after: None,
+        }
+    }
+}
+
+impl Flash for MockFlash {
+    const SECTOR_SIZE: usize = SECTOR_SIZE;
+
+    fn read(&self, offset: usize, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+    }
+
+    fn erase_sector(&mut self, offset: usize) {
+        assert_eq!(offset % SECTOR_SIZE, 0);
+        self.erases[offset / SECTOR_SIZE] += 1;
+        self.bytes[offset..offset + SECTOR_SIZE].fill(0xFF);
+    }
+
+    fn program(&mut self, offset: usize, bytes: &[u8]) {
+        let len = self.cut_off_after.take().unwrap_or(bytes.len());
+        for (flash, byte) in self.bytes[offset..].iter_mut().zip(&bytes[..len]) {
+            *flash &= byte;
+        }
+    }
+}
+
+fn changed_config() -> SessionConfig {
+    SessionConfig {
+        // a planned option, the default is a standard one
+        transmit_option: TransmitOption::for_offset(5_000_000).unwrap(),
+        sideband: Some(Sideband::Lower),
+        sub_ghz: Some(SubGhzPhy::OQpsk868MHz),
+        keying: Some(KeyingConfig {
+            keying: Keying::Fsk { deviation_hz: 50_000 },
+            coding: LineCoding::Nrz,
+            bit_rate: 38_400,
+            preamble_bytes: 6,
+            sync_word: 0x2DD4,
+        }),
+        frame: FrameConfig {
+            source: None,
+            destination: Some(Address::Extended(
+                PanId(0x1AAA),
+                ExtendedAddress(0x0011_2233_4455_6677),
+            )),
+            sequence_number: SequenceNumberMode::Wrap { start: 250, end: 3 },
+        },
+        send: SendDefaults {
+            interval_ms: 20,
+            number_packets: 5000,
+            payload: PayloadMode::Random {
+                length: 100,
+                seed: 0xDEAD_BEEF_0BAD_F00D,
+            },
+            schedule: ScheduleMode::Burst {
+                length: 8,
+                gap_ms: 250,
+            },
+        },
+        pins: PinConfig {
+            trigger: 15,
+            external_trigger: true,
+            antenna: 2,
+            quadrature: false,
+            extra: [Some(3), None, Some(29)],
+        },
+    }
+}
+
+#[test]
+fn record_round_trip() {
+    for config in [SessionConfig::default(), changed_config()] {
+        let record = encode_record(&config, 0x0102_0304);
+        assert_eq!(decode_record(&record), Ok((0x0102_0304, config)));
+    }
+}
+
+#[test]
+fn damaged_records_are_rejected() {
+    let record = encode_record(&changed_config(), 1);
+
+    assert_eq!(decode_record(&[0xFF; RECORD_SIZE]), Err(RecordError::Erased));
+
+    let mut not_a_record = record;
+    not_a_record[0] = 0;
+    assert_eq!(decode_record(&not_a_record), Err(RecordError::Magic));
+
+    let mut other_version = record;
+    other_version[2] = RECORD_VERSION + 1;
+    assert_eq!(
+        decode_record(&other_version),
+        Err(RecordError::Version(RECORD_VERSION + 1))
+    );
+
+    for idx in 4..RECORD_SIZE {
+        let mut flipped = record;
+        flipped[idx] ^= 0x10;
+        assert_eq!(decode_record(&flipped), Err(RecordError::Crc), "byte {idx}");
+    }
+}
+
+#[test]
+fn plan_the_firmware_cannot_run_is_rejected() {
+    let mut config = changed_config();
+    let TransmitOption::Planned(plan) = &mut config.transmit_option else {
+        panic!("5MHz is planned");
+    };
+    plan.quarter_cycles = 3;
+    let record = encode_record(&config, 1);
+    assert_eq!(decode_record(&record), Err(RecordError::Field("transmit plan")));
+}
+
+#[test]
+fn pins_the_firmware_cannot_use_are_rejected() {
+    let mut config = changed_config();
+    // the pin after the antenna pin is the Q pin
+    config.pins.quadrature = true;
+    config.pins.extra[0] = Some(config.pins.antenna + 1);
+    let record = encode_record(&config, 1);
+    assert_eq!(decode_record(&record), Err(RecordError::Field("pins")));
+}
+
+#[test]
+fn empty_store_has_no_config() {
+    let store = ConfigStore::new(MockFlash::new());
+    assert_eq!(store.load(), None);
+}
+
+#[test]
+fn newest_save_is_loaded() {
+    let mut store = ConfigStore::new(MockFlash::new());
+    store.save(&SessionConfig::default()).unwrap();
+    assert_eq!(store.load(), Some(SessionConfig::default()));
+    store.save(&changed_config()).unwrap();
+    assert_eq!(store.load(), Some(changed_config()));
+}
+
+#[test]
+fn saves_are_spread_over_every_sector() {
+    let mut store = ConfigStore::new(MockFlash::new());
+    let slots = ConfigStore::<MockFlash>::SLOTS as u32;
+    let mut config = SessionConfig::default();
+    for number_packets in 0..10 * slots {
+        config.send.number_packets = number_packets;
+        store.save(&config).unwrap();
+        assert_eq!(store.load(), Some(config));
+    }
+    // every sector is erased once per pass over the store
+    assert_eq!(store.flash().erases, [10; STORE_SECTORS]);
+}
+
+#[test]
+fn cut_off_save_keeps_the_last_config() {
+    let mut store = ConfigStore::new(MockFlash::new());
+    store.save(&SessionConfig::default()).unwrap();
+
+    let mut flash = MockFlash::new();
+    flash.bytes = store.flash().bytes.clone();
+    flash.cut_off_after = Some(RECORD_SIZE / 2);
+    let mut store = ConfigStore::new(flash);
+    // the half written record fails the read back and the CRC check
+    assert!(store.save(&changed_config()).is_err());
+    assert_eq!(store.load(), Some(SessionConfig::default()));
+
+    // the damaged slot is skipped, not written over
+    store.save(&changed_config()).unwrap();
+    assert_eq!(store.load(), Some(changed_config()));
+}
+
+#[test]
+fn factory_reset_removes_the_config() {
+    let mut store = ConfigStore::new(MockFlash::new());
+    store.save(&changed_config()).unwrap();
+    store.factory_reset();
+    assert_eq!(store.load(), None);
+    store.save(&SessionConfig::default()).unwrap();
+    assert_eq!(store.load(), Some(SessionConfig::default()));
+}
@@ -0,0 +1,118 @@
+//! Golden vectors for the pio bytecode generator
+//!
+//! Every [StandardTransmitOption] has a file in `tests/golden` with the pio bytecode for a set of frames,
+//! if the generator changes on purpose regenerate them with
+//!
+//! ```bash
+//! UPDATE_GOLDEN_VECTORS=1 cargo test --test golden_vectors
+//! ```
+//!
+//! and check the diff
+
+use pico_qpsk_core::data_array::PACKET_IN_RAW_PIO_BYTECODE;
+use pico_qpsk_core::frame_gen::{
+    get_hex_string_as_bytes, get_random_payload_frame_bytes, get_seq_frame_bytes, random_payloads,
+};
+use pico_qpsk_core::pio_bytecode_gen::convert_advanced;
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::StandardTransmitOption;
+use pico_qpsk_core::wave_array;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+const KNOWN_FRAME: &str = "00000000A71741880B222234124444CDAB0102030405060708090A4B49";
+
+const MAX_PAYLOAD_SIZE: usize = 127;
+const MAX_FRAME_SIZE: usize = to_max_frame_size!(MAX_PAYLOAD_SIZE);
+
+/// the frames every option is checked with
+fn frames() -> Vec<Vec<u8>> {
+    let mut frames = vec![get_hex_string_as_bytes::<{ KNOWN_FRAME.len() }>(KNOWN_FRAME)
+        .unwrap()
+        .to_vec()];
+    for size in [0, 1, 4, 16] {
+        frames.push(get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(size).to_vec());
+    }
+    for (seed, size) in [(1, 4), (7, 32)] {
+        let mut payloads = random_payloads(seed, size);
+        frames
+            .push(get_random_payload_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(&mut payloads).to_vec());
+    }
+    frames
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02X}").unwrap();
+        s
+    })
+}
+
+/// ```text
+/// > FRAME HEX
+/// PIO WORD HEX
+/// PIO WORD HEX
+/// ...
+/// ```
+fn render(option: StandardTransmitOption) -> String {
+    let mut out = format!(
+        "# {option:?} golden vectors, regenerate with `UPDATE_GOLDEN_VECTORS=1 cargo test --test golden_vectors`\n"
+    );
+    for frame in frames() {
+        writeln!(out, "> {}", hex(&frame)).unwrap();
+        for word in option.convert(&frame) {
+            writeln!(out, "{word:08X}").unwrap();
+        }
+    }
+    out
+}
+
+fn golden_path(option: StandardTransmitOption) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{option:?}.txt"))
+}
+
+#[test]
+fn known_frame_matches_data_array() {
+    let frame = get_hex_string_as_bytes::<{ KNOWN_FRAME.len() }>(KNOWN_FRAME).unwrap();
+    let words: Vec<u32> = convert_advanced::<4>(&frame, &wave_array!(16)).collect();
+    assert_eq!(words, PACKET_IN_RAW_PIO_BYTECODE);
+
+    let words: Vec<u32> = StandardTransmitOption::Clk128MHzOffset8MHz
+        .convert(&frame)
+        .collect();
+    assert_eq!(words, PACKET_IN_RAW_PIO_BYTECODE);
+}
+
+#[test]
+fn standard_transmit_options_match_golden_vectors() {
+    let update = std::env::var_os("UPDATE_GOLDEN_VECTORS").is_some();
+    let mut mismatched = Vec::new();
+    for option in StandardTransmitOption::ALL {
+        let path = golden_path(option);
+        let rendered = render(option);
+        if update {
+            std::fs::write(&path, &rendered).unwrap_or_else(|err| panic!("failed to write {path:?}: {err}"));
+            continue;
+        }
+        let golden = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+            panic!("failed to read {path:?}: {err}, run with UPDATE_GOLDEN_VECTORS=1 to create it")
+        });
+        if let Some((line, _)) = golden
+            .lines()
+            .zip(rendered.lines())
+            .enumerate()
+            .find(|(_, (golden, rendered))| golden != rendered)
+        {
+            mismatched.push(format!("{option:?} from line {}", line + 1));
+        } else if golden.lines().count() != rendered.lines().count() {
+            mismatched.push(format!("{option:?} length"));
+        }
+    }
+    assert!(
+        mismatched.is_empty(),
+        "pio bytecode changed for {mismatched:?}, if this is on purpose rerun with UPDATE_GOLDEN_VECTORS=1"
+    );
+}
@@ -0,0 +1,224 @@
+//! OOK and 2-FSK: line coding, framing, timing and round trips through a symbol by symbol edge counter
+
+use pico_qpsk_core::frame_gen::get_seq_frame_bytes;
+use pico_qpsk_core::keying::{convert_keyed, Keying, KeyingConfig, LevelRuns, LineCoding};
+use pico_qpsk_core::pio_bytecode_decode::pio_run_lengths;
+use pico_qpsk_core::pio_bytecode_gen::Level;
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::{StandardTransmitOption, TransmitOption};
+
+const MAX_PAYLOAD_SIZE: usize = 20;
+const MAX_FRAME_SIZE: usize = to_max_frame_size!(MAX_PAYLOAD_SIZE);
+
+fn options() -> Vec<TransmitOption> {
+    let mut options: Vec<TransmitOption> = StandardTransmitOption::ALL.into_iter().map(Into::into).collect();
+    options.push(TransmitOption::for_offset(3_000_000).unwrap());
+    options
+}
+
+fn configs() -> [KeyingConfig; 4] {
+    [
+        KeyingConfig::default(),
+        KeyingConfig {
+            coding: LineCoding::Nrz,
+            bit_rate: 50_000,
+            preamble_bytes: 2,
+            sync_word: 0x2DD4,
+            ..KeyingConfig::default()
+        },
+        KeyingConfig {
+            keying: Keying::Fsk {
+                deviation_hz: 100_000,
+            },
+            ..KeyingConfig::default()
+        },
+        KeyingConfig {
+            keying: Keying::Fsk {
+                deviation_hz: 250_000,
+            },
+            coding: LineCoding::Nrz,
+            bit_rate: 100_000,
+            preamble_bytes: 8,
+            sync_word: 0x7A0E,
+        },
+    ]
+}
+
+/// (state machine clock, subcarrier) of a quarter timing
+fn clocks(option: &TransmitOption) -> (u64, u64) {
+    let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+    let quarters_per_chip = u64::from(quarters_per_chip);
+    (
+        u64::from(quarter_cycles) * quarters_per_chip * 2_000_000,
+        quarters_per_chip * 500_000,
+    )
+}
+
+/// The cycles the pin changes at, the leading run and the padding of the last word left out
+fn edges(words: &[u32]) -> Vec<u64> {
+    let runs: Vec<u32> = pio_run_lengths(words).skip(1).collect();
+    let padding = runs.iter().rev().take_while(|len| **len == 4).count();
+    runs[..runs.len() - padding]
+        .iter()
+        .scan(0, |now, len| {
+            *now += u64::from(*len);
+            Some(*now)
+        })
+        .collect()
+}
+
+/// The symbols of a keyed frame, by the number of edges in each of them
+fn symbols(edges: &[u64], config: &KeyingConfig, pio_hz: u64, subcarrier_hz: u64) -> Vec<bool> {
+    let symbol_rate = u64::from(config.symbol_rate());
+    let symbol_start = |idx: u64| (idx * pio_hz / symbol_rate).div_ceil(2) * 2;
+    let last = *edges.last().unwrap();
+    let mut symbols = Vec::new();
+    let mut idx = 0;
+    while symbol_start(idx) < last {
+        let (start, end) = (symbol_start(idx), symbol_start(idx + 1));
+        let count = edges.iter().filter(|edge| (start..end).contains(*edge)).count() as u64;
+        symbols.push(match config.keying {
+            // an off symbol can only hold the edge that ends the last on one
+            Keying::Ook => count >= 2,
+            // the edges of the subcarrier are between the ones of the tones
+            Keying::Fsk { .. } => count * symbol_rate > 2 * subcarrier_hz,
+        });
+        idx += 1;
+    }
+    symbols
+}
+
+fn bytes(symbols: &[bool], coding: LineCoding) -> Vec<u8> {
+    let bits: Vec<bool> = match coding {
+        LineCoding::Nrz => symbols.to_vec(),
+        LineCoding::Manchester => symbols
+            .chunks(2)
+            .map(|pair| {
+                assert_ne!(pair[0], pair[1], "no edge in the middle");
+                pair[1]
+            })
+            .collect(),
+    };
+    bits.chunks(8)
+        .map(|byte| byte.iter().fold(0, |byte, bit| byte << 1 | u8::from(*bit)))
+        .collect()
+}
+
+fn air_bytes(frame: &[u8], config: &KeyingConfig) -> Vec<u8> {
+    let mut bytes = vec![0xAA; usize::from(config.preamble_bytes)];
+    bytes.extend(config.sync_word.to_be_bytes());
+    bytes.extend(&frame[5..]);
+    bytes
+}
+
+#[test]
+fn every_keying_round_trips_on_every_option() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(9);
+    for option in options() {
+        let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+        let (pio_hz, subcarrier_hz) = clocks(&option);
+        for config in configs() {
+            assert_eq!(
+                config.check(quarter_cycles, quarters_per_chip),
+                Ok(()),
+                "{option:?} {config:?}"
+            );
+            let words: Vec<u32> = convert_keyed(&frame, config, quarter_cycles, quarters_per_chip).collect();
+            let mut symbols = symbols(&edges(&words), &config, pio_hz, subcarrier_hz);
+            // the frame ends with the last edge, trailing off symbols don't have one
+            symbols.resize(
+                8 * config.air_len(frame.len()) * config.symbol_rate() as usize / config.bit_rate as usize,
+                false,
+            );
+            assert_eq!(
+                bytes(&symbols, config.coding),
+                air_bytes(&frame, &config),
+                "{option:?} {config:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn a_keyed_frame_lasts_its_airtime() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(20);
+    for option in options() {
+        let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+        let (pio_hz, _) = clocks(&option);
+        for config in configs() {
+            let words: Vec<u32> = convert_keyed(&frame, config, quarter_cycles, quarters_per_chip).collect();
+            let cycles = *edges(&words).last().unwrap();
+            let expected = u64::from(config.airtime_us(frame.len())) * pio_hz / 1_000_000;
+            // the last symbol is a 1 or a Manchester coded bit, it ends with an edge
+            let slack = pio_hz / 1_000_000 + 4;
+            assert!(
+                cycles.abs_diff(expected) <= slack,
+                "{option:?} {config:?}: {cycles} cycles, expected {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn ook_edges_stay_on_the_subcarrier() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(3);
+    for option in options() {
+        let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+        let (pio_hz, _) = clocks(&option);
+        let half_period = 2 * u64::from(quarter_cycles);
+        for config in configs()
+            .into_iter()
+            .filter(|config| config.keying == Keying::Ook)
+        {
+            let symbol_rate = u64::from(config.symbol_rate());
+            let words: Vec<u32> = convert_keyed(&frame, config, quarter_cycles, quarters_per_chip).collect();
+            // the subcarrier runs on under the off symbols, an edge is on its grid or where a symbol starts
+            for edge in edges(&words) {
+                let symbol = edge * symbol_rate / pio_hz;
+                let symbol_starts =
+                    [symbol, symbol + 1].map(|idx| (idx * pio_hz / symbol_rate).div_ceil(2) * 2);
+                assert!(
+                    edge.is_multiple_of(half_period) || symbol_starts.contains(&edge),
+                    "{option:?} {config:?}: edge at {edge}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn manchester_has_an_edge_in_the_middle_of_every_bit() {
+    let config = KeyingConfig {
+        preamble_bytes: 1,
+        sync_word: 0x0FF0,
+        ..KeyingConfig::default()
+    };
+    let symbols: Vec<bool> = config.symbols(&[0, 0, 0, 0, 0xA7, 0x81]).collect();
+    let bits: Vec<bool> = config.bits(&[0, 0, 0, 0, 0xA7, 0x81]).collect();
+    assert_eq!(bits.len(), 32);
+    assert_eq!(symbols.len(), 2 * bits.len());
+    for (bit, pair) in bits.iter().zip(symbols.chunks(2)) {
+        assert_eq!(pair, [!bit, *bit]);
+    }
+    let nrz = KeyingConfig {
+        coding: LineCoding::Nrz,
+        ..config
+    };
+    assert!(nrz.symbols(&[0, 0, 0, 0, 0xA7, 0x81]).eq(bits));
+}
+
+#[test]
+fn short_levels_are_stretched_and_caught_up() {
+    let levels = [
+        Level::High(2),
+        Level::Nop,
+        Level::Low(6),
+        Level::Low(4),
+        Level::High(6),
+        Level::Low(2),
+        Level::High(8),
+    ];
+    let runs: Vec<u32> = LevelRuns::new(levels.into_iter()).collect();
+    // 2 cycles late after the first run, the next one makes up for it
+    assert_eq!(runs, [4, 8, 6, 4, 6]);
+}
@@ -0,0 +1,194 @@
+//! Check the MAC headers [PhysicalFrameBuilder] writes
+
+use byte::BytesExt;
+use ieee802154::mac::{
+    Address, ExtendedAddress, FooterMode, Frame, FrameContent, FrameType, FrameVersion, PanId, ShortAddress,
+};
+use pico_qpsk_core::frame_gen::get_hex_string_as_bytes;
+use pico_qpsk_core::packet::{FrameConstructionError, PhysicalFrame, PhysicalFrameBuilder};
+
+const MAX_FRAME_SIZE: usize = 64;
+
+/// the MAC part of the phy bytes, checks the length byte on the way
+fn mac_bytes(frame: PhysicalFrame<MAX_FRAME_SIZE>) -> Vec<u8> {
+    let bytes = frame.to_bytes().unwrap();
+    assert_eq!(bytes[..5], [0x00, 0x00, 0x00, 0x00, 0xA7]);
+    assert_eq!(usize::from(bytes[5]), bytes.len() - 6);
+    bytes[6..].to_vec()
+}
+
+#[test]
+fn matches_rohde_schwarz_app_note_frame() {
+    // 2003 data frame with PAN ID compression, this is the frame in data_array.rs
+    const KNOWN_FRAME: &str = "00000000A71741880B222234124444CDAB0102030405060708090A4B49";
+    let payload = [
+        0xCD, 0xAB, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A,
+    ];
+    let frame: PhysicalFrame<MAX_FRAME_SIZE> = PhysicalFrameBuilder::new()
+        .version(FrameVersion::Ieee802154_2003)
+        .destination(Some(Address::Short(PanId(0x2222), ShortAddress(0x1234))))
+        .source(Some(Address::Short(PanId(0x2222), ShortAddress(0x4444))))
+        .pan_id_compress(true)
+        .sequence_number(0x0B)
+        .payload(&payload)
+        .build()
+        .unwrap();
+    assert_eq!(
+        frame.to_bytes().unwrap(),
+        get_hex_string_as_bytes::<{ KNOWN_FRAME.len() }>(KNOWN_FRAME).unwrap()
+    );
+}
+
+#[test]
+fn new_is_a_2006_short_address_data_frame() {
+    let frame: PhysicalFrame<MAX_FRAME_SIZE> = PhysicalFrame::new(
+        1,
+        PanId(0x4444),
+        ShortAddress(0xABCD),
+        PanId(0x2222),
+        ShortAddress(0x1234),
+        &[0x01],
+    )
+    .unwrap();
+    let mac = mac_bytes(frame);
+    assert_eq!(
+        mac[..12],
+        [0x01, 0x98, 0x01, 0x22, 0x22, 0x34, 0x12, 0x44, 0x44, 0xCD, 0xAB, 0x01]
+    );
+
+    let parsed: Frame = mac.read_with(&mut 0, FooterMode::Explicit).unwrap();
+    assert_eq!(parsed.header.frame_type, FrameType::Data);
+    assert_eq!(parsed.header.version, FrameVersion::Ieee802154_2006);
+    assert_eq!(
+        parsed.header.source,
+        Some(Address::Short(PanId(0x4444), ShortAddress(0xABCD)))
+    );
+}
+
+#[test]
+fn acknowledgement() {
+    let frame = PhysicalFrameBuilder::new()
+        .content(FrameContent::Acknowledgement)
+        .version(FrameVersion::Ieee802154_2003)
+        .frame_pending(true)
+        .sequence_number(0x56)
+        .build()
+        .unwrap();
+    let mac = mac_bytes(frame);
+    assert_eq!(mac[..3], [0x12, 0x00, 0x56]);
+    assert_eq!(mac.len(), 5);
+}
+
+#[test]
+fn extended_addresses_with_ack_request() {
+    let destination = Address::Extended(PanId(0x1234), ExtendedAddress(0x0102_0304_0506_0708));
+    let source = Address::Extended(PanId(0x1234), ExtendedAddress(0x1112_1314_1516_1718));
+    let frame = PhysicalFrameBuilder::new()
+        .destination(Some(destination))
+        .source(Some(source))
+        .pan_id_compress(true)
+        .ack_request(true)
+        .sequence_number(9)
+        .build()
+        .unwrap();
+    let mac = mac_bytes(frame);
+    #[rustfmt::skip]
+    assert_eq!(mac[..21], [
+        0x61, 0xDC, 0x09,
+        0x34, 0x12, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+        0x18, 0x17, 0x16, 0x15, 0x14, 0x13, 0x12, 0x11,
+    ]);
+
+    let parsed: Frame = mac.read_with(&mut 0, FooterMode::Explicit).unwrap();
+    assert_eq!(parsed.header.destination, Some(destination));
+    assert_eq!(parsed.header.source, Some(source));
+    assert!(parsed.header.ack_request);
+}
+
+#[test]
+fn ieee802154_2015_pan_id_compression() {
+    let extended = |address| Some(Address::Extended(PanId(0x1234), ExtendedAddress(address)));
+    let short = |address| Some(Address::Short(PanId(0x1234), ShortAddress(address)));
+    let header_len = |destination, source, compress| {
+        let frame = PhysicalFrameBuilder::new()
+            .version(FrameVersion::Ieee802154)
+            .destination(destination)
+            .source(source)
+            .pan_id_compress(compress)
+            .build()
+            .unwrap();
+        // FCS
+        mac_bytes(frame).len() - 2
+    };
+    // FCF + SN + PAN IDs + addresses
+    assert_eq!(header_len(extended(1), extended(2), false), 3 + 2 + 16);
+    assert_eq!(header_len(extended(1), extended(2), true), 3 + 16);
+    assert_eq!(header_len(short(1), extended(2), true), 3 + 2 + 10);
+    assert_eq!(header_len(short(1), short(2), false), 3 + 4 + 4);
+    assert_eq!(header_len(short(1), None, true), 3 + 2);
+    assert_eq!(header_len(None, short(2), false), 3 + 2 + 2);
+    assert_eq!(header_len(None, None, false), 3);
+    assert_eq!(header_len(None, None, true), 3 + 2);
+}
+
+#[test]
+fn ieee802154_2015_pan_id_without_addresses() {
+    let frame = PhysicalFrameBuilder::new()
+        .version(FrameVersion::Ieee802154)
+        .pan_id_compress(true)
+        .sequence_number(7)
+        .payload(&[0xAA])
+        .build()
+        .unwrap();
+    // data frame, PAN ID compression, no addresses, 2015 version, then the broadcast destination PAN ID
+    assert_eq!(mac_bytes(frame)[..6], [0x41, 0x20, 0x07, 0xFF, 0xFF, 0xAA]);
+
+    let frame = PhysicalFrameBuilder::new()
+        .version(FrameVersion::Ieee802154)
+        .pan_id_compress(true)
+        .destination_pan_id(PanId(0x1234))
+        .sequence_number(7)
+        .payload(&[0xAA])
+        .build()
+        .unwrap();
+    assert_eq!(mac_bytes(frame)[..6], [0x41, 0x20, 0x07, 0x34, 0x12, 0xAA]);
+
+    // without PAN ID compression there is no PAN ID to set
+    let frame = PhysicalFrameBuilder::new()
+        .version(FrameVersion::Ieee802154)
+        .destination_pan_id(PanId(0x1234))
+        .sequence_number(7)
+        .payload(&[0xAA])
+        .build()
+        .unwrap();
+    assert_eq!(mac_bytes(frame)[..4], [0x01, 0x20, 0x07, 0xAA]);
+}
+
+#[test]
+fn suppressed_sequence_number() {
+    let frame = PhysicalFrameBuilder::new()
+        .version(FrameVersion::Ieee802154)
+        .suppress_sequence_number(true)
+        .sequence_number(0xFF)
+        .payload(&[0xAA])
+        .build()
+        .unwrap();
+    let mac = mac_bytes(frame);
+    assert_eq!(mac[..3], [0x01, 0x21, 0xAA]);
+
+    let error = PhysicalFrameBuilder::new()
+        .suppress_sequence_number(true)
+        .build::<MAX_FRAME_SIZE>()
+        .unwrap_err();
+    assert!(matches!(error, FrameConstructionError::SeqNoSuppressVersion));
+}
+
+#[test]
+fn pan_id_compression_needs_both_addresses_before_2015() {
+    let error = PhysicalFrameBuilder::new()
+        .destination(Some(Address::Short(PanId(1), ShortAddress(2))))
+        .pan_id_compress(true)
+        .build::<MAX_FRAME_SIZE>()
+        .unwrap_err();
+    assert!(matches!(error, FrameConstructionError::PanIdCompress));
+}
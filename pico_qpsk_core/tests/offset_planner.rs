@@ -0,0 +1,116 @@
+//! Plan subcarrier offsets and check the bytecode of the plans
+
+use pico_qpsk_core::frame_gen::get_seq_frame_bytes;
+use pico_qpsk_core::offset_planner::{
+    plan, plan_on_clock, PlanError, MAX_CHIP_RATE_ERROR_PPM, MAX_OFFSET_HZ, MIN_OFFSET_HZ,
+};
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::{
+    ProcessorClock, StandardTransmitOption, StateMachineClockDividerSetting, TransmitOption,
+};
+
+const MAX_PAYLOAD_SIZE: usize = 100;
+const MAX_FRAME_SIZE: usize = to_max_frame_size!(MAX_PAYLOAD_SIZE);
+
+#[test]
+fn standard_offsets_plan_like_the_standard_options() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(20);
+    for option in StandardTransmitOption::ALL {
+        let plan = plan(option.offset_hz()).unwrap();
+        assert_eq!(plan.clock, option.processor_clock(), "{option:?}");
+        assert_eq!(plan.divider, option.state_machine_clock(), "{option:?}");
+        assert_eq!(plan.chip_rate_error_ppm(), 0, "{option:?}");
+        assert!(
+            plan.convert(&frame).eq(option.convert(&frame)),
+            "{option:?} has other bytecode"
+        );
+    }
+}
+
+#[test]
+fn offsets_between_the_standard_ones() {
+    let three = plan(3_000_000).unwrap();
+    assert_eq!(three.clock, ProcessorClock::F144MHz);
+    assert_eq!(three.divider, StateMachineClockDividerSetting::Integer(3));
+
+    let five = plan(5_000_000).unwrap();
+    assert_eq!(five.clock.hz(), 80_000_000);
+    assert!(five.clock.pll().is_valid());
+    assert_eq!(five.divider, StateMachineClockDividerSetting::None);
+    assert_eq!(five.offset_error_millihertz(5_000_000), 0);
+    assert_eq!(five.chip_rate_error_ppm(), 0);
+}
+
+#[test]
+fn every_half_mhz_round_trips() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(30);
+    for offset_hz in (MIN_OFFSET_HZ..=MAX_OFFSET_HZ).step_by(500_000) {
+        let plan = plan(offset_hz).unwrap_or_else(|err| panic!("{offset_hz}Hz: {err:?}"));
+        assert!(plan.is_valid(), "{offset_hz}Hz: {plan:?}");
+        assert!(plan.chip_rate_error_ppm().abs() <= MAX_CHIP_RATE_ERROR_PPM);
+        // the offset is off by as much as the chip rate
+        let max_error = i64::from(offset_hz) * i64::from(MAX_CHIP_RATE_ERROR_PPM) / 1000;
+        assert!(
+            plan.offset_error_millihertz(offset_hz).abs() <= max_error,
+            "{offset_hz}Hz: {plan:?}"
+        );
+
+        let option = TransmitOption::Planned(plan);
+        let words: Vec<u32> = option.convert(&frame).collect();
+        let decoded = option.decode::<MAX_FRAME_SIZE>(&words).unwrap();
+        assert_eq!(decoded.bytes, frame, "{offset_hz}Hz");
+        assert!(decoded.chip_errors <= 1, "{offset_hz}Hz");
+    }
+}
+
+#[test]
+fn offsets_off_the_grid_report_the_error() {
+    let plan = plan(5_300_000).unwrap();
+    assert_eq!(plan.offset_millihertz(), 5_500_000_000);
+    assert_eq!(plan.offset_error_millihertz(5_300_000), 200_000_000);
+}
+
+#[test]
+fn offsets_out_of_range() {
+    assert_eq!(plan(0), Err(PlanError::OutOfRange));
+    assert_eq!(plan(1_000_000), Err(PlanError::OutOfRange));
+    assert_eq!(plan(MAX_OFFSET_HZ + 1), Err(PlanError::OutOfRange));
+}
+
+#[test]
+fn offsets_on_a_shared_system_clock() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(10);
+    for (clock, reachable_mhz) in [
+        (ProcessorClock::F128MHz, &[2.0, 4.0, 8.0][..]),
+        (
+            ProcessorClock::F144MHz,
+            &[1.5, 2.0, 3.0, 4.0, 4.5, 6.0, 8.0, 9.0][..],
+        ),
+    ] {
+        let mut reached = Vec::new();
+        for offset_hz in (MIN_OFFSET_HZ..=MAX_OFFSET_HZ).step_by(500_000) {
+            let option = match TransmitOption::for_offset_on_clock(offset_hz, clock) {
+                Ok(option) => option,
+                Err(error) => {
+                    assert_eq!(error, PlanError::ChipRate, "{offset_hz}Hz on {clock:?}");
+                    continue;
+                }
+            };
+            reached.push(f64::from(offset_hz) / 1e6);
+            assert_eq!(option.processor_clock(), clock, "{offset_hz}Hz");
+            let words: Vec<u32> = option.convert(&frame).collect();
+            let decoded = option.decode::<MAX_FRAME_SIZE>(&words).unwrap();
+            assert_eq!(decoded.bytes, frame, "{offset_hz}Hz on {clock:?}");
+        }
+        assert_eq!(reached, reachable_mhz, "{clock:?}");
+    }
+    // the standard options at 128MHz are used as they are
+    for offset_hz in [2_000_000, 4_000_000, 8_000_000] {
+        let option = TransmitOption::for_offset_on_clock(offset_hz, ProcessorClock::F128MHz).unwrap();
+        assert!(matches!(option, TransmitOption::Standard(_)), "{offset_hz}Hz");
+    }
+    assert_eq!(
+        plan_on_clock(1_000_000, ProcessorClock::F128MHz),
+        Err(PlanError::OutOfRange)
+    );
+}
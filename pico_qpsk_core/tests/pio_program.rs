@@ -0,0 +1,68 @@
+//! The assembled programs against the `pio_asm!` ones the firmware had for the trigger on GPIO3
+
+use pico_qpsk_core::pio_program::{single_pin_program, two_pin_program, MAX_PROGRAM_SIZE};
+use pio::{Instruction, InstructionOperands, Program, SideSet, WaitSource};
+
+fn assert_same(assembled: &Program<MAX_PROGRAM_SIZE>, expected: &Program<MAX_PROGRAM_SIZE>) {
+    assert_eq!(assembled.code, expected.code);
+    assert_eq!(assembled.wrap.source, expected.wrap.source);
+    assert_eq!(assembled.wrap.target, expected.wrap.target);
+    assert_eq!(assembled.origin, expected.origin);
+}
+
+#[test]
+fn programs_match_the_hand_written_ones() {
+    let single_pin = pio_proc::pio_asm!(
+        "wait 0 gpio 3",
+        ".wrap_target",
+        "set pins 0 [1]",
+        "loop1:",
+        "out x 1",
+        "jmp x-- loop1",
+        "set pins, 1 [1]",
+        "loop2:",
+        "out y 1",
+        "jmp y-- loop2",
+        ".wrap",
+        options(max_program_size = 32)
+    );
+    let two_pins = pio_proc::pio_asm!(
+        "wait 0 gpio 3",
+        "run:",
+        "out pins 2",
+        "count:",
+        "out x 1",
+        "jmp x-- count",
+        "jmp run",
+        options(max_program_size = 32)
+    );
+    assert_same(&single_pin_program(3), &single_pin.program);
+    assert_same(&two_pin_program(3), &two_pins.program);
+}
+
+#[test]
+fn the_wait_is_for_the_trigger_pin() {
+    let programs: [fn(u8) -> Program<MAX_PROGRAM_SIZE>; 2] = [single_pin_program, two_pin_program];
+    for assemble in programs {
+        let gpio3 = assemble(3);
+        for trigger in [0, 15, 29] {
+            let program = assemble(trigger);
+            let wait = Instruction::decode(program.code[0], SideSet::default()).unwrap();
+            let InstructionOperands::WAIT {
+                polarity,
+                source,
+                index,
+                relative,
+            } = wait.operands
+            else {
+                panic!("the program starts with {wait:?}");
+            };
+            assert_eq!(
+                (polarity, source, index, relative),
+                (0, WaitSource::GPIO, trigger, false)
+            );
+            // only the wait changes
+            assert_eq!(program.code[1..], gpio3.code[1..]);
+        }
+    }
+}
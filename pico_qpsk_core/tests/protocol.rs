@@ -0,0 +1,195 @@
+//! Check the COBS framing and the binary protocol messages
+
+use pico_qpsk_core::protocol::{
+    cobs_decode_in_place, cobs_encode, decode_frame, decode_message, encode_message, max_encoded_size,
+    message_size, ErrorCode, MessageBody, ProtocolError, Request, Response, Status, TransmitSource,
+    PROTOCOL_VERSION,
+};
+use pico_qpsk_core::schedule::{PeriodStats, ScheduleMode};
+use pico_qpsk_core::session_config::{PayloadMode, SessionConfig};
+
+const MAX_MESSAGE_SIZE: usize = message_size(300);
+const MAX_ENCODED_SIZE: usize = max_encoded_size(MAX_MESSAGE_SIZE);
+
+fn encode<'a>(request_id: u16, body: impl MessageBody<'a>) -> Vec<u8> {
+    let mut out = [0u8; MAX_ENCODED_SIZE];
+    let len = encode_message::<_, MAX_MESSAGE_SIZE>(request_id, body, &mut out).unwrap();
+    out[..len].to_vec()
+}
+
+/// the bytes between the delimiters, as the firmware or host would split them off
+fn between_delimiters(encoded: &[u8]) -> Vec<u8> {
+    assert_eq!(encoded[0], 0x00);
+    assert_eq!(encoded[encoded.len() - 1], 0x00);
+    let frame = encoded[1..encoded.len() - 1].to_vec();
+    assert!(!frame.contains(&0x00));
+    frame
+}
+
+#[test]
+fn cobs_wikipedia_examples() {
+    let long: Vec<u8> = (1..=0xFF).collect();
+    let cases: [(Vec<u8>, Vec<u8>); 6] = [
+        (vec![0x00], vec![0x01, 0x01]),
+        (vec![0x00, 0x00], vec![0x01, 0x01, 0x01]),
+        (vec![0x00, 0x11, 0x00], vec![0x01, 0x02, 0x11, 0x01]),
+        (vec![0x11, 0x22, 0x00, 0x33], vec![0x03, 0x11, 0x22, 0x02, 0x33]),
+        (vec![0x11, 0x22, 0x33, 0x44], vec![0x05, 0x11, 0x22, 0x33, 0x44]),
+        (long.clone(), [&[0xFF][..], &long[..0xFE], &[0x02, 0xFF]].concat()),
+    ];
+    for (data, expected) in cases {
+        let mut out = [0u8; 300];
+        let len = cobs_encode(&data, &mut out).unwrap();
+        assert_eq!(out[..len], expected, "{data:02X?}");
+
+        let mut decoded = expected.clone();
+        let len = cobs_decode_in_place(&mut decoded).unwrap();
+        assert_eq!(decoded[..len], data);
+    }
+}
+
+#[test]
+fn cobs_rejects_bad_input() {
+    // a code that runs past the end
+    assert_eq!(cobs_decode_in_place(&mut [0x05, 0x11, 0x22]), None);
+    // a zero inside the frame
+    assert_eq!(cobs_decode_in_place(&mut [0x03, 0x11, 0x00]), None);
+    assert_eq!(cobs_decode_in_place(&mut [0x00]), None);
+    assert_eq!(cobs_encode(&[0x11, 0x22], &mut [0u8; 2]), None);
+}
+
+#[test]
+fn requests_round_trip() {
+    let mut config = SessionConfig::default();
+    config.send.payload = PayloadMode::Random { length: 12, seed: 99 };
+    config.send.schedule = ScheduleMode::Poisson { seed: 7 };
+    let payload: Vec<u8> = (0..=255).collect();
+    let requests = [
+        Request::Configure(config),
+        Request::Transmit {
+            interval_ms: 10,
+            number_packets: 1000,
+            source: TransmitSource::Defaults,
+        },
+        Request::Transmit {
+            interval_ms: 0,
+            number_packets: 1,
+            source: TransmitSource::Payload(&payload),
+        },
+        Request::Transmit {
+            interval_ms: 1000,
+            number_packets: u32::MAX,
+            source: TransmitSource::RawFrame(&[0x00, 0x00, 0x00, 0x00, 0xA7, 0x00]),
+        },
+        Request::Status,
+        Request::Abort,
+    ];
+    for (request_id, request) in (0xFFF0..).zip(requests) {
+        let mut frame = between_delimiters(&encode(request_id, request));
+        assert_eq!(decode_message::<Request>(&mut frame), Ok((request_id, request)));
+    }
+}
+
+#[test]
+fn responses_round_trip() {
+    let frame = [0x00, 0x00, 0x00, 0x00, 0xA7, 0x05, 0x41, 0x88, 0x01, 0x76, 0x52];
+    let responses = [
+        Response::Ok,
+        Response::Error(ErrorCode::Busy),
+        Response::Status(Status {
+            transmitting: true,
+            packets_sent: 5,
+            number_packets: 10,
+            config: SessionConfig::default(),
+        }),
+        Response::PacketSent {
+            packet_number: 5,
+            frame: &frame,
+        },
+        Response::Done {
+            packets_sent: 3,
+            aborted: true,
+            period: PeriodStats {
+                count: 2,
+                min_us: 9_990,
+                max_us: 10_020,
+                total_us: 20_010,
+                late: 1,
+            },
+        },
+    ];
+    for response in responses {
+        let mut encoded = between_delimiters(&encode(42, response));
+        assert_eq!(decode_message::<Response>(&mut encoded), Ok((42, response)));
+    }
+}
+
+#[test]
+fn damaged_messages_are_rejected() {
+    let encoded = between_delimiters(&encode(1, Request::Abort));
+
+    for idx in 0..encoded.len() {
+        let mut flipped = encoded.clone();
+        flipped[idx] ^= 0x40;
+        // a flipped code byte can also break the COBS, either way it must not decode
+        assert!(decode_message::<Request>(&mut flipped).is_err(), "byte {idx}");
+    }
+
+    let mut short = encoded[..3].to_vec();
+    assert!(decode_message::<Request>(&mut short).is_err());
+}
+
+/// encode a message by hand so the header can be anything
+fn raw_message(version: u8, message_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = [&[version, message_type, 0x34, 0x12][..], body].concat();
+    let crc = crc16_kermit(&message);
+    message.extend(crc.to_le_bytes());
+    let mut out = [0u8; 64];
+    let len = cobs_encode(&message, &mut out).unwrap();
+    out[..len].to_vec()
+}
+
+fn crc16_kermit(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in bytes {
+        crc ^= u16::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[test]
+fn other_versions_and_types_are_rejected() {
+    let mut status = raw_message(PROTOCOL_VERSION, 0x03, &[]);
+    assert_eq!(
+        decode_message::<Request>(&mut status),
+        Ok((0x1234, Request::Status))
+    );
+
+    let mut newer = raw_message(PROTOCOL_VERSION + 1, 0x03, &[]);
+    let message = decode_frame(&mut newer).unwrap();
+    // the request ID is still there to send the error back with
+    assert_eq!(message.request_id, 0x1234);
+    let error = message.read_body::<Request>().unwrap_err();
+    assert_eq!(error, ProtocolError::Version(PROTOCOL_VERSION + 1));
+    assert_eq!(error.error_code(), ErrorCode::Version);
+
+    // a response type is not a request
+    let mut response = raw_message(PROTOCOL_VERSION, 0x81, &[]);
+    assert_eq!(
+        decode_message::<Request>(&mut response),
+        Err(ProtocolError::UnknownMessage(0x81))
+    );
+
+    let mut bad_source = raw_message(PROTOCOL_VERSION, 0x02, &[0, 0, 0, 0, 1, 0, 0, 0, 9]);
+    assert_eq!(
+        decode_message::<Request>(&mut bad_source),
+        Err(ProtocolError::Body("transmit source"))
+    );
+}
@@ -0,0 +1,113 @@
+//! Two pin bytecode: round trips and how much of the mirror image is left
+
+use pico_qpsk_core::frame_gen::get_seq_frame_bytes;
+use pico_qpsk_core::pio_bytecode_decode::{pio_words_to_square_wave, quadrature_words_to_pins};
+use pico_qpsk_core::pio_bytecode_gen::Sideband;
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::{StandardTransmitOption, TransmitOption};
+use std::f64::consts::PI;
+
+const MAX_PAYLOAD_SIZE: usize = 100;
+const MAX_FRAME_SIZE: usize = to_max_frame_size!(MAX_PAYLOAD_SIZE);
+
+fn options() -> Vec<TransmitOption> {
+    let mut options: Vec<TransmitOption> = StandardTransmitOption::ALL.into_iter().map(Into::into).collect();
+    options.push(TransmitOption::for_offset(3_000_000).unwrap());
+    options.push(TransmitOption::for_offset(5_000_000).unwrap());
+    options
+}
+
+/// Power in the 2MHz around `+offset` and `-offset` of one sample per state machine cycle
+fn sideband_power(samples: &[(f64, f64)], cycles_per_quarter: u8, quarters_per_chip: u8) -> (f64, f64) {
+    // the subcarrier is a quarter period every quarter_cycles and a chip, 0.5MHz, is quarters_per_chip quarters
+    let offset = 1.0 / (4.0 * f64::from(cycles_per_quarter));
+    let one_mhz = 2.0 / (4.0 * f64::from(cycles_per_quarter) * f64::from(quarters_per_chip));
+    let power_at = |frequency: f64| {
+        let (re, im) = samples
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, (i, q))| {
+                let (sin, cos) = (-2.0 * PI * frequency * n as f64).sin_cos();
+                (re + i * cos - q * sin, im + i * sin + q * cos)
+            });
+        re * re + im * im
+    };
+    let band = |center: f64| {
+        (-20..=20)
+            .map(|step| power_at(center + one_mhz * f64::from(step) / 20.0))
+            .sum()
+    };
+    (band(offset), band(-offset))
+}
+
+fn level(pin: bool) -> f64 {
+    if pin {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+#[test]
+fn both_sidebands_round_trip_on_both_pins() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(20);
+    for option in options() {
+        for sideband in [Sideband::Upper, Sideband::Lower] {
+            let words: Vec<u32> = option.convert_sideband(&frame, Some(sideband)).collect();
+            let decoded = option
+                .decode_sideband::<MAX_FRAME_SIZE>(&words, Some(sideband))
+                .unwrap();
+            assert_eq!(decoded.bytes, frame, "{option:?} {sideband:?}");
+            assert_eq!(decoded.chip_errors, 0, "{option:?} {sideband:?}");
+        }
+    }
+}
+
+#[test]
+fn every_run_lasts_at_least_a_quarter_and_both_pins_end_low() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(4);
+    for option in options() {
+        let (quarter_cycles, _) = option.quarter_timing();
+        let words: Vec<u32> = option.convert_sideband(&frame, Some(Sideband::Upper)).collect();
+        let pins: Vec<(bool, bool)> = quadrature_words_to_pins(&words).collect();
+        let mut runs = pins.chunk_by(|a, b| a == b).map(<[_]>::len).collect::<Vec<_>>();
+        // the last run is the low pins after the frame and the padding
+        runs.pop();
+        assert!(
+            runs.iter().all(|len| *len >= usize::from(quarter_cycles)),
+            "{option:?} has a run shorter than a quarter"
+        );
+        assert_eq!(pins.last(), Some(&(false, false)), "{option:?}");
+    }
+}
+
+#[test]
+fn the_mirror_image_is_suppressed() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(4);
+    let option = TransmitOption::Standard(StandardTransmitOption::Clk128MHzOffset8MHz);
+    let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+
+    let single: Vec<(f64, f64)> = pio_words_to_square_wave(&option.convert(&frame).collect::<Vec<_>>())
+        .map(|high| (level(high), 0.0))
+        .collect();
+    let (upper, lower) = sideband_power(&single, quarter_cycles, quarters_per_chip);
+    assert!((upper / lower - 1.0).abs() < 1e-6, "one pin makes both sidebands");
+
+    for (sideband, wanted_is_upper) in [(Sideband::Upper, true), (Sideband::Lower, false)] {
+        let words: Vec<u32> = option.convert_sideband(&frame, Some(sideband)).collect();
+        let samples: Vec<(f64, f64)> = quadrature_words_to_pins(&words)
+            .map(|(i, q)| (level(i), level(q)))
+            .collect();
+        let (upper, lower) = sideband_power(&samples, quarter_cycles, quarters_per_chip);
+        let (wanted, image) = if wanted_is_upper {
+            (upper, lower)
+        } else {
+            (lower, upper)
+        };
+        let rejection_db = 10.0 * (wanted / image).log10();
+        assert!(
+            rejection_db > 20.0,
+            "{sideband:?} image only {rejection_db:.1}dB down"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Check the `srp` payloads can be rebuilt from the seed
+
+use pico_qpsk_core::frame_gen::random_payloads;
+use pico_qpsk_core::prng::SplitMix64;
+
+#[test]
+fn split_mix_64_reference_outputs() {
+    let mut rng = SplitMix64::new(0);
+    assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+    assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+    assert_eq!(rng.next_u64(), 0x06C4_5D18_8009_454F);
+}
+
+#[test]
+fn payload_bytes_are_little_endian_outputs() {
+    let mut payloads = random_payloads::<16>(0, 10);
+    // the last 6 bytes of the second output are dropped, the next payload starts on the third
+    assert_eq!(
+        payloads.next().unwrap(),
+        [0xAF, 0xCD, 0x1D, 0x7B, 0x39, 0xA8, 0x20, 0xE2, 0xF4, 0x65]
+    );
+    assert_eq!(
+        payloads.next().unwrap()[..8],
+        0x06C4_5D18_8009_454Fu64.to_le_bytes()
+    );
+}
+
+#[test]
+fn same_seed_same_run() {
+    let run: Vec<_> = random_payloads::<64>(0xDEAD_BEEF, 64).take(500).collect();
+    assert_eq!(
+        run,
+        random_payloads::<64>(0xDEAD_BEEF, 64)
+            .take(500)
+            .collect::<Vec<_>>()
+    );
+    assert_ne!(
+        run,
+        random_payloads::<64>(0xDEAD_BEF0, 64)
+            .take(500)
+            .collect::<Vec<_>>()
+    );
+    assert!(run.windows(2).all(|pair| pair[0] != pair[1]));
+}
+
+#[test]
+fn empty_payloads() {
+    assert!(random_payloads::<4>(1, 0)
+        .take(3)
+        .all(|payload| payload.is_empty()));
+}
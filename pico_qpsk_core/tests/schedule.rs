@@ -0,0 +1,57 @@
+//! Check the packet start times of the schedule modes and the achieved period statistics
+
+use pico_qpsk_core::schedule::{PeriodStats, Schedule, ScheduleMode};
+
+#[test]
+fn fixed_starts_an_interval_apart() {
+    let starts: Vec<u64> = Schedule::new(250, ScheduleMode::Fixed).take(4).collect();
+    assert_eq!(starts, [0, 250_000, 500_000, 750_000]);
+    // a zero interval sends back to back
+    assert!(Schedule::new(0, ScheduleMode::Fixed)
+        .take(10)
+        .all(|start| start == 0));
+}
+
+#[test]
+fn bursts_of_one_are_a_fixed_gap() {
+    let burst = ScheduleMode::Burst {
+        length: 1,
+        gap_ms: 40,
+    };
+    let starts: Vec<u64> = Schedule::new(5, burst).take(3).collect();
+    assert_eq!(starts, [0, 40_000, 80_000]);
+}
+
+#[test]
+fn poisson_gaps_average_the_interval() {
+    const PACKETS: usize = 20_000;
+    let mode = ScheduleMode::Poisson { seed: 42 };
+    let starts: Vec<u64> = Schedule::new(10, mode).take(PACKETS + 1).collect();
+    // the same seed gives the same run
+    assert!(Schedule::new(10, mode)
+        .take(PACKETS + 1)
+        .eq(starts.iter().copied()));
+
+    let gaps: Vec<u64> = starts.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let mean = gaps.iter().sum::<u64>() as f64 / PACKETS as f64;
+    assert!((mean - 10_000.0).abs() < 300.0, "mean gap {mean}");
+    // about 1/e of the gaps of a Poisson process are longer than the mean
+    let longer = gaps.iter().filter(|gap| **gap > 10_000).count() as f64 / PACKETS as f64;
+    assert!(
+        (longer - (-1f64).exp()).abs() < 0.02,
+        "{longer} longer than the mean"
+    );
+}
+
+#[test]
+fn period_stats() {
+    let mut stats = PeriodStats::default();
+    assert_eq!(stats.mean_us(), None);
+    stats.record(10_050, false);
+    stats.record(9_950, false);
+    stats.record(12_000, true);
+    assert_eq!(stats.count, 3);
+    assert_eq!((stats.min_us, stats.max_us), (9_950, 12_000));
+    assert_eq!(stats.mean_us(), Some(10_666));
+    assert_eq!(stats.late, 1);
+}
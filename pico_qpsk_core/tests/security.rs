@@ -0,0 +1,232 @@
+//! Check AES-128, CCM* and secured frames against FIPS-197 and annex C of 802.15.4-2011
+
+use byte::BytesExt;
+use ieee802154::mac::beacon::Beacon;
+use ieee802154::mac::command::{CapabilityInformation, Command};
+use ieee802154::mac::{Address, ExtendedAddress, FrameContent, FrameVersion, PanId, ShortAddress};
+use pico_qpsk_core::aes::Aes128;
+use pico_qpsk_core::packet::{FrameConstructionError, PhysicalFrame, PhysicalFrameBuilder};
+use pico_qpsk_core::security::{
+    ccm_star_decrypt, ccm_star_encrypt, ccm_star_nonce, FrameSecurity, KeyIdentifier, KeySource,
+    SecurityError, SecurityLevel,
+};
+
+const MAX_FRAME_SIZE: usize = 80;
+
+/// the key used by all of annex C
+const KEY: [u8; 16] = [
+    0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xCB, 0xCC, 0xCD, 0xCE, 0xCF,
+];
+const SOURCE: ExtendedAddress = ExtendedAddress(0xACDE_4800_0000_0001);
+const DESTINATION: ExtendedAddress = ExtendedAddress(0xACDE_4800_0000_0002);
+
+fn annex_c_security(level: SecurityLevel) -> FrameSecurity {
+    FrameSecurity {
+        level,
+        key_identifier: None,
+        frame_counter: 5,
+        key: KEY,
+        source: SOURCE,
+    }
+}
+
+/// the MAC part of the phy bytes without the FCS, annex C leaves the FCS out
+fn mac_bytes_without_fcs(frame: PhysicalFrame<MAX_FRAME_SIZE>) -> Vec<u8> {
+    let bytes = frame.to_bytes().unwrap();
+    assert_eq!(usize::from(bytes[5]), bytes.len() - 6);
+    bytes[6..bytes.len() - 2].to_vec()
+}
+
+#[test]
+fn aes_fips_197_appendix_b() {
+    let aes = Aes128::new(&[
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+    ]);
+    let mut block = [
+        0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d, 0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34,
+    ];
+    aes.encrypt_block(&mut block);
+    assert_eq!(
+        block,
+        [0x39, 0x25, 0x84, 0x1d, 0x02, 0xdc, 0x09, 0xfb, 0xdc, 0x11, 0x85, 0x97, 0x19, 0x6a, 0x0b, 0x32]
+    );
+}
+
+#[test]
+fn nonce_c_2_1() {
+    assert_eq!(
+        ccm_star_nonce(SOURCE, 5, SecurityLevel::MIC64),
+        [0xAC, 0xDE, 0x48, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x02]
+    );
+}
+
+#[test]
+fn mic_c_2_1_beacon() {
+    let a = [
+        0x08, 0xD0, 0x84, 0x21, 0x43, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xDE, 0xAC, 0x02, 0x05, 0x00, 0x00,
+        0x00, 0x55, 0xCF, 0x00, 0x00, 0x51, 0x52, 0x53, 0x54,
+    ];
+    let nonce = ccm_star_nonce(SOURCE, 5, SecurityLevel::MIC64);
+    let mic = ccm_star_encrypt(&KEY, &nonce, SecurityLevel::MIC64, &a, &mut []);
+    assert_eq!(mic, [0x22, 0x3B, 0xC1, 0xEC, 0x84, 0x1A, 0xB5, 0x53]);
+    assert_eq!(
+        ccm_star_decrypt(&KEY, &nonce, SecurityLevel::MIC64, &a, &mut [], &mic),
+        Ok(())
+    );
+}
+
+#[test]
+fn secured_beacon_c_2_1() {
+    let beacon: Beacon = [0x55, 0xCF, 0x00, 0x00].read(&mut 0).unwrap();
+    let frame = PhysicalFrameBuilder::new()
+        .content(FrameContent::Beacon(beacon))
+        .source(Some(Address::Extended(PanId(0x4321), SOURCE)))
+        .sequence_number(0x84)
+        .payload(&[0x51, 0x52, 0x53, 0x54])
+        .security(Some(annex_c_security(SecurityLevel::MIC64)))
+        .build()
+        .unwrap();
+    assert_eq!(
+        mac_bytes_without_fcs(frame),
+        [
+            0x08, 0xD0, 0x84, 0x21, 0x43, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xDE, 0xAC, 0x02, 0x05, 0x00,
+            0x00, 0x00, 0x55, 0xCF, 0x00, 0x00, 0x51, 0x52, 0x53, 0x54, 0x22, 0x3B, 0xC1, 0xEC, 0x84, 0x1A,
+            0xB5, 0x53,
+        ]
+    );
+}
+
+#[test]
+fn secured_data_frame_c_2_2() {
+    let frame = PhysicalFrameBuilder::new()
+        .destination(Some(Address::Extended(PanId(0x4321), DESTINATION)))
+        .source(Some(Address::Extended(PanId(0x4321), SOURCE)))
+        .pan_id_compress(true)
+        .ack_request(true)
+        .sequence_number(0x84)
+        .payload(&[0x61, 0x62, 0x63, 0x64])
+        .security(Some(annex_c_security(SecurityLevel::ENC)))
+        .build()
+        .unwrap();
+    assert_eq!(
+        mac_bytes_without_fcs(frame),
+        [
+            0x69, 0xDC, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xDE, 0xAC, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x48, 0xDE, 0xAC, 0x04, 0x05, 0x00, 0x00, 0x00, 0xD4, 0x3E, 0x02, 0x2B,
+        ]
+    );
+}
+
+#[test]
+fn secured_command_frame_c_2_3() {
+    let association_request = Command::AssociationRequest(CapabilityInformation::from(0xCE));
+    let frame = PhysicalFrameBuilder::new()
+        .content(FrameContent::Command(association_request))
+        .destination(Some(Address::Extended(PanId(0x4321), DESTINATION)))
+        .source(Some(Address::Extended(PanId(0xFFFF), SOURCE)))
+        .ack_request(true)
+        .sequence_number(0x84)
+        .security(Some(annex_c_security(SecurityLevel::ENCMIC64)))
+        .build()
+        .unwrap();
+    assert_eq!(
+        mac_bytes_without_fcs(frame),
+        [
+            0x2B, 0xDC, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xDE, 0xAC, 0xFF, 0xFF, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x48, 0xDE, 0xAC, 0x06, 0x05, 0x00, 0x00, 0x00, 0x01, 0xD8, 0x4F, 0xDE,
+            0x52, 0x90, 0x61, 0xF9, 0xC6, 0xF1,
+        ]
+    );
+}
+
+#[test]
+fn decrypt_round_trip_every_level() {
+    let a = [0x41, 0x98, 0x01, 0x22, 0x22, 0xCD, 0xAB, 0x34, 0x12];
+    // longer than one block so the CTR counter moves on
+    let plaintext: Vec<u8> = (0..40).collect();
+    for level in [
+        SecurityLevel::MIC32,
+        SecurityLevel::MIC64,
+        SecurityLevel::MIC128,
+        SecurityLevel::ENC,
+        SecurityLevel::ENCMIC32,
+        SecurityLevel::ENCMIC64,
+        SecurityLevel::ENCMIC128,
+    ] {
+        let nonce = ccm_star_nonce(SOURCE, 0x0102_0304, level);
+        let mut data = plaintext.clone();
+        let mic = ccm_star_encrypt(&KEY, &nonce, level, &a, &mut data);
+        assert_eq!(mic.len(), level.get_mic_octet_size());
+        assert_eq!(ccm_star_decrypt(&KEY, &nonce, level, &a, &mut data, &mic), Ok(()));
+        assert_eq!(data, plaintext, "{level:?}");
+
+        if !mic.is_empty() {
+            let mut tampered = a;
+            tampered[2] ^= 0x01;
+            let mut data = plaintext.clone();
+            let mic = ccm_star_encrypt(&KEY, &nonce, level, &a, &mut data);
+            assert_eq!(
+                ccm_star_decrypt(&KEY, &nonce, level, &tampered, &mut data, &mic),
+                Err(SecurityError::MicMismatch)
+            );
+            assert_eq!(
+                ccm_star_decrypt(&KEY, &nonce, level, &a, &mut data, &mic[1..]),
+                Err(SecurityError::MicLength)
+            );
+        }
+    }
+}
+
+#[test]
+fn key_identifier_modes() {
+    let cases = [
+        (None, vec![0x05]),
+        (
+            Some(KeyIdentifier {
+                key_source: None,
+                key_index: 0x07,
+            }),
+            vec![0x0D, 0x07],
+        ),
+        (
+            Some(KeyIdentifier {
+                key_source: Some(KeySource::Short(0x0403_0201)),
+                key_index: 0x07,
+            }),
+            vec![0x15, 0x01, 0x02, 0x03, 0x04, 0x07],
+        ),
+        (
+            Some(KeyIdentifier {
+                key_source: Some(KeySource::Long(0x0807_0605_0403_0201)),
+                key_index: 0x07,
+            }),
+            vec![0x1D, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x07],
+        ),
+    ];
+    for (key_identifier, expected) in cases {
+        let security = FrameSecurity {
+            key_identifier,
+            frame_counter: 0xDDCC_BBAA,
+            ..annex_c_security(SecurityLevel::ENCMIC32)
+        };
+        let mut bytes = [0u8; 14];
+        let mut len = 0;
+        security.write_auxiliary_header(&mut bytes, &mut len).unwrap();
+        assert_eq!(len, security.auxiliary_header_len());
+        // [SECURITY CONTROL][FRAME COUNTER][KEY IDENTIFIER]
+        assert_eq!(bytes[0], expected[0]);
+        assert_eq!(bytes[1..5], [0xAA, 0xBB, 0xCC, 0xDD]);
+        assert_eq!(bytes[5..len], expected[1..]);
+    }
+}
+
+#[test]
+fn security_needs_2006_or_later() {
+    let error = PhysicalFrameBuilder::new()
+        .version(FrameVersion::Ieee802154_2003)
+        .destination(Some(Address::Short(PanId(0x2222), ShortAddress(0x1234))))
+        .security(Some(annex_c_security(SecurityLevel::ENCMIC32)))
+        .build::<MAX_FRAME_SIZE>()
+        .unwrap_err();
+    assert!(matches!(error, FrameConstructionError::SecurityVersion));
+}
@@ -0,0 +1,130 @@
+//! Check the frames built from a [FrameConfig] and the pin layouts [PinConfig] accepts
+
+use ieee802154::mac::{Address, ExtendedAddress, PanId, ShortAddress};
+use pico_qpsk_core::frame_gen::get_testing_generated_frame_bytes;
+use pico_qpsk_core::session_config::{FrameConfig, PinConfig, PinError, SequenceNumberMode};
+
+const MAX_FRAME_SIZE: usize = 64;
+
+/// index of the sequence number in the PHY bytes, `[PREAMBLE][SFD][LEN][FCF]`
+const SEQ_IDX: usize = 4 + 1 + 1 + 2;
+
+fn sequence(mode: SequenceNumberMode, packets: u32) -> Vec<u8> {
+    (0..packets).map(|idx| mode.sequence_number(idx)).collect()
+}
+
+#[test]
+fn default_is_the_testing_frame() {
+    let payload = [0x01, 0x02, 0x03];
+    let frame = FrameConfig::default()
+        .frame_bytes::<MAX_FRAME_SIZE>(0, &payload)
+        .unwrap();
+    assert_eq!(
+        frame,
+        get_testing_generated_frame_bytes::<4, MAX_FRAME_SIZE>(&payload)
+    );
+}
+
+#[test]
+fn sequence_number_modes() {
+    assert_eq!(sequence(SequenceNumberMode::Fixed(7), 3), [7, 7, 7]);
+    assert_eq!(
+        sequence(SequenceNumberMode::Increment { start: 254 }, 4),
+        [254, 255, 0, 1]
+    );
+    assert_eq!(
+        sequence(SequenceNumberMode::Wrap { start: 10, end: 12 }, 7),
+        [10, 11, 12, 10, 11, 12, 10]
+    );
+    assert_eq!(
+        sequence(SequenceNumberMode::Wrap { start: 254, end: 1 }, 5),
+        [254, 255, 0, 1, 254]
+    );
+    assert!(SequenceNumberMode::Wrap { start: 3, end: 3 }.is_fixed());
+    assert!(!SequenceNumberMode::Increment { start: 0 }.is_fixed());
+}
+
+#[test]
+fn sequence_number_is_written_per_packet() {
+    let config = FrameConfig {
+        sequence_number: SequenceNumberMode::Increment { start: 0x10 },
+        ..FrameConfig::default()
+    };
+    for idx in 0..3 {
+        let frame = config.frame_bytes::<MAX_FRAME_SIZE>(idx, &[0xAA]).unwrap();
+        assert_eq!(frame[SEQ_IDX], 0x10 + idx as u8);
+    }
+}
+
+#[test]
+fn extended_and_missing_addresses() {
+    let config = FrameConfig {
+        source: Some(Address::Extended(
+            PanId(0x1111),
+            ExtendedAddress(0x0102_0304_0506_0708),
+        )),
+        destination: Some(Address::Short(PanId(0xFFFF), ShortAddress(0xFFFF))),
+        sequence_number: SequenceNumberMode::Fixed(0),
+    };
+    let frame = config.frame_bytes::<MAX_FRAME_SIZE>(0, &[0xAA]).unwrap();
+    // FCF, SN, destination PAN + short address, source PAN + extended address, payload, FCS
+    assert_eq!(usize::from(frame[5]), 2 + 1 + 4 + 10 + 1 + 2);
+    assert_eq!(
+        frame[SEQ_IDX + 1..SEQ_IDX + 1 + 14],
+        [0xFF, 0xFF, 0xFF, 0xFF, 0x11, 0x11, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+    );
+
+    let no_source = FrameConfig {
+        source: None,
+        ..config
+    };
+    let frame = no_source.frame_bytes::<MAX_FRAME_SIZE>(0, &[0xAA]).unwrap();
+    assert_eq!(usize::from(frame[5]), 2 + 1 + 4 + 1 + 2);
+}
+
+#[test]
+fn pin_layouts() {
+    let default = PinConfig::default();
+    assert_eq!(default.q_pin(), Some(7));
+    assert_eq!(default.used().collect::<Vec<_>>(), [3, 6, 7, 8, 9, 10]);
+
+    // GPIO7 is free without the Q pin
+    let single_pin = PinConfig {
+        quadrature: false,
+        extra: [Some(7), None, None],
+        ..default
+    };
+    assert_eq!(single_pin.validate(), Ok(()));
+    assert_eq!(
+        PinConfig {
+            quadrature: true,
+            ..single_pin
+        }
+        .validate(),
+        Err(PinError::UsedTwice(7))
+    );
+
+    // the Q pin of GPIO29 would be GPIO30
+    let last = PinConfig {
+        antenna: 29,
+        extra: [None; 3],
+        ..default
+    };
+    assert_eq!(last.validate(), Err(PinError::OutOfRange(30)));
+    assert_eq!(
+        PinConfig {
+            quadrature: false,
+            ..last
+        }
+        .validate(),
+        Ok(())
+    );
+    assert_eq!(
+        PinConfig {
+            trigger: 6,
+            ..default
+        }
+        .validate(),
+        Err(PinError::UsedTwice(6))
+    );
+}
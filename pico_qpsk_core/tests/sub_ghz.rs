@@ -0,0 +1,105 @@
+//! The 868MHz and 915MHz PHYs: chips, timing and round trips
+
+use pico_qpsk_core::frame_gen::get_seq_frame_bytes;
+use pico_qpsk_core::pio_bytecode_decode::pio_run_lengths;
+use pico_qpsk_core::sub_ghz::{
+    decode_sub_ghz_bytecode, SubGhzChips, SubGhzPhy, BPSK_CHIPS, SUB_GHZ_CHIP_ARRAY,
+};
+use pico_qpsk_core::to_max_frame_size;
+use pico_qpsk_core::transmit_option::{Phy, StandardTransmitOption, TransmitOption};
+
+const MAX_PAYLOAD_SIZE: usize = 40;
+const MAX_FRAME_SIZE: usize = to_max_frame_size!(MAX_PAYLOAD_SIZE);
+
+fn options() -> Vec<TransmitOption> {
+    let mut options: Vec<TransmitOption> = StandardTransmitOption::ALL.into_iter().map(Into::into).collect();
+    options.push(TransmitOption::for_offset(3_000_000).unwrap());
+    options
+}
+
+#[test]
+fn every_phy_round_trips_on_every_option() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(12);
+    for option in options() {
+        let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+        for phy in SubGhzPhy::ALL {
+            let words: Vec<u32> = option.convert_phy(&frame, Phy::SubGhz(phy)).collect();
+            let decoded =
+                decode_sub_ghz_bytecode::<MAX_FRAME_SIZE>(&words, phy, quarter_cycles, quarters_per_chip)
+                    .unwrap_or_else(|err| panic!("{option:?} {phy:?}: {err:?}"));
+            assert_eq!(decoded.bytes, frame, "{option:?} {phy:?}");
+            assert_eq!(decoded.chip_errors, 0, "{option:?} {phy:?}");
+        }
+    }
+}
+
+#[test]
+fn a_frame_lasts_its_airtime() {
+    let frame = get_seq_frame_bytes::<MAX_PAYLOAD_SIZE, MAX_FRAME_SIZE>(20);
+    for option in options() {
+        let (quarter_cycles, quarters_per_chip) = option.quarter_timing();
+        // a quarter is quarter_cycles and there are quarters_per_chip of them in 0.5us
+        let pio_hz = u64::from(quarter_cycles) * u64::from(quarters_per_chip) * 2_000_000;
+        for phy in SubGhzPhy::ALL {
+            let words: Vec<u32> = option.convert_phy(&frame, Phy::SubGhz(phy)).collect();
+            // the leading run and the padding of the last word aren't part of the frame
+            let runs: Vec<u32> = pio_run_lengths(&words).skip(1).collect();
+            let padding = runs.iter().rev().take_while(|len| **len == 4).count() as u64 * 4;
+            let cycles = runs.iter().map(|len| u64::from(*len)).sum::<u64>() - padding;
+            let mut expected = u64::from(phy.airtime_us(frame.len())) * pio_hz / 1_000_000;
+            if !phy.is_bpsk() {
+                // the first middle chip is left out
+                expected -= pio_hz / u64::from(phy.chip_rate());
+            }
+            // the first and last runs can be off by a subcarrier period and the padding can eat a short last run
+            let slack = 4 * u64::from(quarter_cycles) + 8;
+            assert!(
+                cycles.abs_diff(expected) <= slack,
+                "{option:?} {phy:?}: {cycles} cycles, expected {expected}"
+            );
+        }
+    }
+}
+
+#[test]
+fn bpsk_chips_are_differentially_encoded() {
+    let chips: Vec<u8> = SubGhzChips::new(&[0b0000_0110], SubGhzPhy::Bpsk868MHz).collect();
+    let bits: Vec<Vec<u8>> = chips
+        .chunks(15)
+        .map(|chips| chips.iter().map(|chip| chip & 1).collect())
+        .collect();
+    let zero = BPSK_CHIPS.to_vec();
+    let one: Vec<u8> = BPSK_CHIPS.iter().map(|chip| chip ^ 1).collect();
+    // the raw bits 0, 1, 1, 0, ... encode to 0, 1, 0, 0, ...
+    assert_eq!(
+        bits,
+        [
+            zero.clone(),
+            one,
+            zero.clone(),
+            zero.clone(),
+            zero.clone(),
+            zero.clone(),
+            zero.clone(),
+            zero
+        ]
+    );
+    assert!(chips.iter().all(|chip| *chip == 0b00 || *chip == 0b11));
+}
+
+#[test]
+fn o_qpsk_chips_are_the_symbols_with_middle_chips() {
+    let chips: Vec<u8> = SubGhzChips::new(&[0x90], SubGhzPhy::OQpsk915MHz).collect();
+    // the low half first, every other chip is a middle chip
+    let symbols: Vec<u8> = SUB_GHZ_CHIP_ARRAY[0]
+        .iter()
+        .chain(&SUB_GHZ_CHIP_ARRAY[9])
+        .copied()
+        .collect();
+    assert_eq!(chips.len(), 2 * symbols.len() - 1);
+    assert!(chips.iter().step_by(2).eq(symbols.iter()));
+    for (idx, middle) in chips.iter().enumerate().skip(1).step_by(2) {
+        // Q of the chip before, I of the chip after
+        assert_eq!(*middle, chips[idx - 1] & 0b01 | chips[idx + 1] & 0b10);
+    }
+}